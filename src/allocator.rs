@@ -20,10 +20,21 @@ impl VariableAllocator {
         variables: &[VariableConfig],
         branch_name: &str,
         existing_worktrees: &HashMap<String, WorktreeConfig>,
+    ) -> Result<HashMap<String, String>> {
+        Self::allocate_values_reserving(variables, branch_name, existing_worktrees, &HashSet::new())
+    }
+
+    /// Allocate values like `allocate_values`, also avoiding ports held elsewhere,
+    /// such as by other repositories in the registry
+    pub fn allocate_values_reserving(
+        variables: &[VariableConfig],
+        branch_name: &str,
+        existing_worktrees: &HashMap<String, WorktreeConfig>,
+        reserved_ports: &HashSet<u16>,
     ) -> Result<HashMap<String, String>> {
         let mut allocated = HashMap::new();
         // Track ports allocated in this session to ensure uniqueness
-        let mut session_allocated_ports = HashSet::new();
+        let mut session_allocated_ports = reserved_ports.clone();
 
         for variable in variables {
             // Skip if we've already allocated this variable name (first match wins)
@@ -201,26 +212,25 @@ impl VariableAllocator {
 
     /// Extract all ports that are currently in use across all worktrees
     fn get_all_used_ports(existing_worktrees: &HashMap<String, WorktreeConfig>) -> HashSet<u16> {
-        let mut used = HashSet::new();
-        for worktree in existing_worktrees.values() {
-            for value in worktree.values.values() {
-                // Try to parse as simple port number
-                if let Ok(port) = value.parse::<u16>() {
-                    used.insert(port);
-                    continue;
-                }
+        existing_worktrees
+            .values()
+            .flat_map(|worktree| worktree.values.values())
+            .flat_map(|value| Self::numbers_in(value))
+            .collect()
+    }
 
-                // Also extract port numbers from template strings
-                // Example: "server_9000_v10" should extract 9000
-                // We use a regex to find all numbers that could be ports
-                for cap in NUMBER_REGEX.find_iter(value) {
-                    if let Ok(port) = cap.as_str().parse::<u16>() {
-                        used.insert(port);
-                    }
-                }
-            }
+    /// Numbers in a value that could be ports
+    ///
+    /// Template strings count too: "server_9000_v10" yields 9000 and 10.
+    pub(crate) fn numbers_in(value: &str) -> Vec<u16> {
+        // Try to parse as simple port number
+        if let Ok(port) = value.parse::<u16>() {
+            return vec![port];
         }
-        used
+        NUMBER_REGEX
+            .find_iter(value)
+            .filter_map(|cap| cap.as_str().parse::<u16>().ok())
+            .collect()
     }
 }
 
@@ -374,6 +384,7 @@ mod tests {
             "main".to_string(),
             WorktreeConfig {
                 values: main_values,
+                ..Default::default()
             },
         );
 
@@ -405,7 +416,13 @@ mod tests {
         let mut existing2 = HashMap::new();
         let mut values1 = HashMap::new();
         values1.insert("INSTANCE".to_string(), "1".to_string());
-        existing2.insert("branch1".to_string(), WorktreeConfig { values: values1 });
+        existing2.insert(
+            "branch1".to_string(),
+            WorktreeConfig {
+                values: values1,
+                ..Default::default()
+            },
+        );
         let allocated2 = VariableAllocator::allocate_values(&variables, "branch2", &existing2)?;
         assert_eq!(allocated2.get("INSTANCE"), Some(&"2".to_string()));
 
@@ -414,7 +431,13 @@ mod tests {
         let mut existing3 = HashMap::new();
         let mut values2 = HashMap::new();
         values2.insert("INSTANCE".to_string(), "2".to_string());
-        existing3.insert("branch2".to_string(), WorktreeConfig { values: values2 });
+        existing3.insert(
+            "branch2".to_string(),
+            WorktreeConfig {
+                values: values2,
+                ..Default::default()
+            },
+        );
         let allocated3 = VariableAllocator::allocate_values(&variables, "branch3", &existing3)?;
         assert_eq!(
             allocated3.get("INSTANCE"),
@@ -426,10 +449,22 @@ mod tests {
         let mut existing4 = HashMap::new();
         let mut values2b = HashMap::new();
         values2b.insert("INSTANCE".to_string(), "2".to_string());
-        existing4.insert("branch2".to_string(), WorktreeConfig { values: values2b.clone() });
+        existing4.insert(
+            "branch2".to_string(),
+            WorktreeConfig {
+                values: values2b.clone(),
+                ..Default::default()
+            },
+        );
         let mut values3 = HashMap::new();
         values3.insert("INSTANCE".to_string(), "1".to_string());
-        existing4.insert("branch3".to_string(), WorktreeConfig { values: values3 });
+        existing4.insert(
+            "branch3".to_string(),
+            WorktreeConfig {
+                values: values3,
+                ..Default::default()
+            },
+        );
         let allocated4 = VariableAllocator::allocate_values(&variables, "branch4", &existing4)?;
         assert_eq!(
             allocated4.get("INSTANCE"),
@@ -476,7 +511,13 @@ mod tests {
         let mut values1 = HashMap::new();
         values1.insert("PORT_A".to_string(), port_a1.to_string());
         values1.insert("PORT_B".to_string(), port_b1.to_string());
-        existing2.insert("main".to_string(), WorktreeConfig { values: values1 });
+        existing2.insert(
+            "main".to_string(),
+            WorktreeConfig {
+                values: values1,
+                ..Default::default()
+            },
+        );
 
         let allocated2 = VariableAllocator::allocate_values(&variables, "feature", &existing2)?;
 
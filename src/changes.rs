@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+use crate::config::VibeTreeConfig;
+use crate::git::GitManager;
+use crate::output::{MoveChangesResult, VibeTreeError};
+
+/// How `add --carry` and `move-changes` take uncommitted changes along
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CarryOptions {
+    /// Also carry untracked files, not just changes to tracked ones
+    pub include_untracked: bool,
+}
+
+/// Uncommitted changes stashed from a worktree, on their way to another
+///
+/// The stash stays in the stash list until the changes were applied, so
+/// they can't be lost if vibetree is interrupted.
+pub struct CarriedChanges {
+    source: PathBuf,
+    stash: String,
+}
+
+impl CarriedChanges {
+    /// Stash the changes in `source`, leaving it clean; `None` if there were none
+    pub fn take(source: &Path, options: CarryOptions, message: &str) -> Result<Option<Self>> {
+        let stash = GitManager::stash_changes(source, options.include_untracked, message)?;
+        Ok(stash.map(|stash| Self {
+            source: source.to_path_buf(),
+            stash,
+        }))
+    }
+
+    /// Whether `source` has changes that `take` would stash
+    pub fn pending(source: &Path, options: CarryOptions) -> Result<bool> {
+        use std::process::Command;
+
+        let untracked = if options.include_untracked {
+            "--untracked-files=all"
+        } else {
+            "--untracked-files=no"
+        };
+        let output = Command::new("git")
+            .args(["status", "--porcelain", untracked])
+            .current_dir(source)
+            .output()
+            .context("Failed to execute git status")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Failed to get status of {}: {}", source.display(), stderr);
+        }
+        // The vibetree directory is never carried
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.get(3..))
+            .any(|path| !path.starts_with(".vibetree")))
+    }
+
+    /// Apply the changes to `target`
+    ///
+    /// On failure `target` is cleaned up again and the changes are put back
+    /// where they came from.
+    pub fn apply_to(self, target: &Path) -> Result<()> {
+        let Err(e) = GitManager::apply_stash(target, &self.stash) else {
+            return GitManager::drop_stash(&self.source, &self.stash);
+        };
+
+        if let Err(discard) = GitManager::discard_changes(target) {
+            warn!("Failed to clean up {}: {}", target.display(), discard);
+        }
+        self.restore()?;
+        Err(e)
+    }
+
+    /// Put the changes back into the worktree they were taken from
+    pub fn restore(self) -> Result<()> {
+        GitManager::apply_stash(&self.source, &self.stash).with_context(|| {
+            format!(
+                "Failed to restore changes to {}; they are kept in stash {}",
+                self.source.display(),
+                self.stash
+            )
+        })?;
+        GitManager::drop_stash(&self.source, &self.stash)
+    }
+}
+
+pub struct ChangesManager<'a> {
+    config: &'a VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> ChangesManager<'a> {
+    pub fn new(config: &'a VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Move the uncommitted changes of one worktree into another, clean one
+    pub fn move_changes(
+        &self,
+        from: &str,
+        to: &str,
+        options: CarryOptions,
+    ) -> Result<MoveChangesResult> {
+        if from == to {
+            anyhow::bail!("Cannot move changes from '{}' to itself", from);
+        }
+        let source = self.existing_worktree(from)?;
+        let target = self.existing_worktree(to)?;
+
+        if GitManager::has_uncommitted_changes(&target)? {
+            anyhow::bail!(VibeTreeError::dirty_tree(format!(
+                "Worktree '{}' has uncommitted changes; commit or stash them first",
+                to
+            )));
+        }
+
+        let message = format!("vibetree: moving changes from {} to {}", from, to);
+        let Some(changes) = CarriedChanges::take(&source, options, &message)? else {
+            info!("No changes to move from '{}'", from);
+            return Ok(MoveChangesResult {
+                from: from.to_string(),
+                to: to.to_string(),
+                moved: false,
+            });
+        };
+
+        changes
+            .apply_to(&target)
+            .with_context(|| format!("Failed to move changes from '{}' to '{}'", from, to))?;
+        info!("Moved uncommitted changes from '{}' to '{}'", from, to);
+
+        Ok(MoveChangesResult {
+            from: from.to_string(),
+            to: to.to_string(),
+            moved: true,
+        })
+    }
+
+    fn existing_worktree(&self, name: &str) -> Result<PathBuf> {
+        let path = self.config.get_worktree_path(self.vibetree_parent, name);
+        let is_main = name == self.config.project_config.main_branch;
+        if !is_main && !self.config.branches_config.worktrees.contains_key(name) {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Worktree '{}' not found",
+                name
+            )));
+        }
+        if !GitManager::validate_worktree_state(&path)?.is_git_worktree {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Worktree '{}' is missing at {}",
+                name,
+                path.display()
+            )));
+        }
+        Ok(path)
+    }
+}
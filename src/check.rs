@@ -0,0 +1,347 @@
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::VibeTreeConfig;
+use crate::env::EnvFileGenerator;
+use crate::ports::PortManager;
+use crate::sync::SyncManager;
+use crate::validation::ConfigValidator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A safe repair that `check --fix` can apply
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Fix {
+    /// Reconcile the configuration with git, as `vibetree repair` does
+    Repair,
+    /// Rewrite a worktree's env file from its allocated values
+    WriteEnvFile(String),
+    /// Add `.vibetree/` to the repository's .gitignore
+    IgnoreVibetreeDir,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fix::Repair => write!(f, "run vibetree repair"),
+            Fix::WriteEnvFile(name) => write!(f, "regenerate the env file for '{}'", name),
+            Fix::IgnoreVibetreeDir => write!(f, "add .vibetree/ to .gitignore"),
+        }
+    }
+}
+
+/// A single problem found by `vibetree check`
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckIssue {
+    pub severity: Severity,
+    pub message: String,
+    /// Worktree the problem belongs to, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+    /// What `--fix` does about it, if it can be repaired safely
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+    pub fixed: bool,
+    #[serde(skip)]
+    action: Option<Fix>,
+}
+
+impl CheckIssue {
+    fn new(severity: Severity, worktree: Option<&str>, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            worktree: worktree.map(str::to_string),
+            fix: None,
+            fixed: false,
+            action: None,
+        }
+    }
+
+    fn with_fix(mut self, action: Fix) -> Self {
+        self.fix = Some(action.to_string());
+        self.action = Some(action);
+        self
+    }
+}
+
+/// Everything `vibetree check` found, and what it fixed
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    pub issues: Vec<CheckIssue>,
+}
+
+impl CheckReport {
+    /// Errors that are still present after any fixes
+    pub fn error_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error && !i.fixed)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning && !i.fixed)
+            .count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error_count() == 0
+    }
+
+    pub fn report(&self) {
+        if self.issues.is_empty() {
+            info!("No problems found");
+            return;
+        }
+
+        for issue in &self.issues {
+            let message = match &issue.worktree {
+                Some(name) => format!("{}: {}", name, issue.message),
+                None => issue.message.clone(),
+            };
+
+            if issue.fixed {
+                info!("Fixed: {}", message);
+                continue;
+            }
+
+            match issue.severity {
+                Severity::Error => error!("{}", message),
+                Severity::Warning => warn!("{}", message),
+            }
+            if let Some(fix) = &issue.fix {
+                info!("  Fixable with --fix: {}", fix);
+            }
+        }
+
+        info!(
+            "{} error(s), {} warning(s)",
+            self.error_count(),
+            self.warning_count()
+        );
+    }
+}
+
+pub struct CheckManager<'a> {
+    config: &'a mut VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> CheckManager<'a> {
+    pub fn new(config: &'a mut VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Validate the configuration and the state on disk, optionally fixing what is safe to fix
+    pub fn check(&mut self, fix: bool) -> Result<CheckReport> {
+        let mut issues = Vec::new();
+
+        let validation = ConfigValidator::validate_config_in(self.config, self.vibetree_parent)?;
+        for message in validation.errors {
+            issues.push(CheckIssue::new(Severity::Error, None, message));
+        }
+        for message in validation.warnings {
+            issues.push(CheckIssue::new(Severity::Warning, None, message));
+        }
+
+        self.check_gitignore(&mut issues)?;
+        self.check_worktrees(&mut issues);
+        self.check_git_worktrees(&mut issues)?;
+
+        if fix {
+            self.apply_fixes(&mut issues)?;
+        }
+
+        Ok(CheckReport { issues })
+    }
+
+    fn check_gitignore(&self, issues: &mut Vec<CheckIssue>) -> Result<()> {
+        if !EnvFileGenerator::suggest_gitignore_update(self.vibetree_parent)? {
+            issues.push(
+                CheckIssue::new(
+                    Severity::Warning,
+                    None,
+                    ".vibetree/ is not listed in .gitignore".to_string(),
+                )
+                .with_fix(Fix::IgnoreVibetreeDir),
+            );
+        }
+        Ok(())
+    }
+
+    /// Check each configured worktree's directory, env file and ports
+    fn check_worktrees(&self, issues: &mut Vec<CheckIssue>) {
+        let mut names: Vec<_> = self.config.branches_config.worktrees.keys().collect();
+        names.sort();
+
+        for name in names {
+            let worktree = &self.config.branches_config.worktrees[name];
+            let path = self.config.get_worktree_path(self.vibetree_parent, name);
+
+            if !path.exists() {
+                issues.push(
+                    CheckIssue::new(
+                        Severity::Error,
+                        Some(name),
+                        format!("Directory {} does not exist", path.display()),
+                    )
+                    .with_fix(Fix::Repair),
+                );
+                continue;
+            }
+
+            let env_file_path = self.config.get_env_file_path(&path);
+            if !env_file_path.exists() {
+                issues.push(
+                    CheckIssue::new(
+                        Severity::Error,
+                        Some(name),
+                        format!("Env file {} is missing", env_file_path.display()),
+                    )
+                    .with_fix(Fix::WriteEnvFile(name.clone())),
+                );
+            } else {
+                match EnvFileGenerator::read_env_file(&env_file_path) {
+                    Ok(env_values) if env_values == worktree.values => {}
+                    Ok(_) => issues.push(
+                        CheckIssue::new(
+                            Severity::Error,
+                            Some(name),
+                            format!(
+                                "Env file {} does not match the allocated values",
+                                env_file_path.display()
+                            ),
+                        )
+                        .with_fix(Fix::WriteEnvFile(name.clone())),
+                    ),
+                    Err(e) => issues.push(
+                        CheckIssue::new(
+                            Severity::Error,
+                            Some(name),
+                            format!("Failed to read env file: {}", e),
+                        )
+                        .with_fix(Fix::WriteEnvFile(name.clone())),
+                    ),
+                }
+            }
+
+            // Ports in use are expected while the worktree's services run, so
+            // these are only worth a warning
+            let mut ports: Vec<(&String, u16)> = worktree
+                .values
+                .iter()
+                .filter_map(|(variable, value)| value.parse::<u16>().ok().map(|p| (variable, p)))
+                .filter(|&(_, port)| port >= 1024)
+                .collect();
+            ports.sort();
+            for (variable, port) in ports {
+                if !PortManager::check_port_availability(port) {
+                    issues.push(CheckIssue::new(
+                        Severity::Warning,
+                        Some(name),
+                        format!("Port {} ({}) is in use", port, variable),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Compare git's worktrees with the configured ones
+    fn check_git_worktrees(&mut self, issues: &mut Vec<CheckIssue>) -> Result<()> {
+        let plan = SyncManager::new(self.config, self.vibetree_parent).plan()?;
+
+        for orphan in &plan.orphaned_worktrees {
+            issues.push(
+                CheckIssue::new(
+                    Severity::Warning,
+                    Some(&orphan.name),
+                    format!(
+                        "Git worktree at {} is not managed by vibetree",
+                        orphan.path.display()
+                    ),
+                )
+                .with_fix(Fix::Repair),
+            );
+        }
+
+        for name in &plan.missing_worktrees {
+            // Missing directories were already reported above
+            if issues
+                .iter()
+                .any(|i| i.worktree.as_ref() == Some(name) && i.action == Some(Fix::Repair))
+            {
+                continue;
+            }
+            issues.push(
+                CheckIssue::new(
+                    Severity::Error,
+                    Some(name),
+                    "Configured worktree is not a git worktree".to_string(),
+                )
+                .with_fix(Fix::Repair),
+            );
+        }
+
+        for pending in &plan.pending_resources {
+            issues.push(
+                CheckIssue::new(
+                    Severity::Warning,
+                    Some(&pending.worktree),
+                    format!("Resource '{}' has not been created", pending.resource),
+                )
+                .with_fix(Fix::Repair),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn apply_fixes(&mut self, issues: &mut [CheckIssue]) -> Result<()> {
+        // Repair rewrites every env file as well, so run it first and only once
+        if issues.iter().any(|i| i.action == Some(Fix::Repair)) {
+            let repair = SyncManager::new(self.config, self.vibetree_parent).sync(false)?;
+            let repaired = repair.errors.is_empty();
+            for issue in issues.iter_mut() {
+                if matches!(issue.action, Some(Fix::Repair | Fix::WriteEnvFile(_))) {
+                    issue.fixed = repaired;
+                }
+            }
+        }
+
+        for issue in issues.iter_mut().filter(|i| !i.fixed) {
+            match &issue.action {
+                Some(Fix::WriteEnvFile(name)) => {
+                    let Some(worktree) = self.config.branches_config.worktrees.get(name) else {
+                        continue;
+                    };
+                    let path = self.config.get_worktree_path(self.vibetree_parent, name);
+                    let env_file_path = self.config.get_env_file_path(&path);
+                    EnvFileGenerator::generate_env_file(&env_file_path, name, &worktree.values)
+                        .with_context(|| format!("Failed to regenerate env file for '{}'", name))?;
+                    issue.fixed = true;
+                }
+                Some(Fix::IgnoreVibetreeDir) => {
+                    EnvFileGenerator::add_to_gitignore(self.vibetree_parent)?;
+                    issue.fixed = true;
+                }
+                Some(Fix::Repair) | None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
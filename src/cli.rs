@@ -1,7 +1,10 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use serde::{Deserialize, Serialize};
 use std::ffi::OsStr;
 
+use crate::variables::VariableValue;
+
 /// Supported shells for completion generation
 #[derive(Clone, Debug, ValueEnum)]
 pub enum CompletionShell {
@@ -16,6 +19,16 @@ pub enum CompletionShell {
     Install,
 }
 
+/// Shells the `shell-init` integration supports
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    Nu,
+}
+
 /// Custom completer that returns existing worktree names
 fn complete_worktree_names(current: &OsStr) -> Vec<CompletionCandidate> {
     let current_str = current.to_str().unwrap_or("");
@@ -36,7 +49,7 @@ fn complete_worktree_names(current: &OsStr) -> Vec<CompletionCandidate> {
             stdout
                 .lines()
                 .filter(|name| name.starts_with(current_str))
-                .map(|name| CompletionCandidate::new(name))
+                .map(CompletionCandidate::new)
                 .collect()
         }
         _ => vec![],
@@ -53,6 +66,15 @@ pub struct Cli {
 
     #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputMode::Text,
+        help = "Emit a single JSON result object on stdout instead of log messages"
+    )]
+    pub output: OutputMode,
 }
 
 #[derive(Subcommand)]
@@ -65,6 +87,12 @@ pub enum Commands {
             value_delimiter = ','
         )]
         variables: Vec<String>,
+
+        #[arg(long, help = "Show what would be written without making changes")]
+        dry_run: bool,
+
+        #[arg(long, help = "Replace the variables already in vibetree.toml")]
+        reset: bool,
     },
 
     #[command(about = "Add new worktree with isolated environment")]
@@ -72,7 +100,11 @@ pub enum Commands {
         #[arg(help = "Name of the branch/worktree to add")]
         branch_name: String,
 
-        #[arg(long, help = "Add worktree from specific branch")]
+        #[arg(
+            long,
+            help = "Add worktree from specific branch",
+            conflicts_with = "carry"
+        )]
         from: Option<String>,
 
         #[arg(long, help = "Specify custom value assignments", value_delimiter = ',')]
@@ -83,6 +115,74 @@ pub enum Commands {
 
         #[arg(long, help = "Switch to the newly created worktree directory")]
         switch: bool,
+
+        #[arg(
+            long,
+            help = "Open the new worktree in a tmux window laid out with [layout]",
+            conflicts_with_all = ["switch", "zellij"]
+        )]
+        tmux: bool,
+
+        #[arg(
+            long,
+            help = "Open the new worktree in a zellij tab laid out with [layout]",
+            conflicts_with = "switch"
+        )]
+        zellij: bool,
+
+        #[arg(
+            long,
+            help = "Move the main worktree's uncommitted changes into the new worktree"
+        )]
+        carry: bool,
+
+        #[arg(long, help = "Also carry untracked files", requires = "carry")]
+        include_untracked: bool,
+    },
+
+    #[command(about = "Add a worktree that starts from another worktree's HEAD")]
+    Fork {
+        #[arg(help = "Worktree to fork", add = ArgValueCompleter::new(complete_worktree_names))]
+        source: String,
+
+        #[arg(help = "Name of the new branch/worktree")]
+        branch_name: String,
+
+        #[arg(
+            long,
+            help = "Copy the source's uncommitted changes, including untracked files"
+        )]
+        with_changes: bool,
+
+        #[arg(
+            long,
+            help = "Copy the source's gitignored files, like node_modules or target/"
+        )]
+        copy_ignored: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t,
+            help = "How to copy ignored files",
+            requires = "copy_ignored"
+        )]
+        copy_mode: CopyMode,
+
+        #[arg(long, help = "Don't run the [fork] hooks from vibetree.toml")]
+        no_hooks: bool,
+    },
+
+    #[command(about = "Move uncommitted changes from one worktree into another")]
+    MoveChanges {
+        #[arg(help = "Worktree to take the changes from", add = ArgValueCompleter::new(complete_worktree_names))]
+        from: String,
+
+        #[arg(help = "Worktree to apply the changes to", add = ArgValueCompleter::new(complete_worktree_names))]
+        to: String,
+
+        #[arg(long, help = "Also move untracked files")]
+        include_untracked: bool,
     },
 
     #[command(about = "Remove worktree and release allocations")]
@@ -99,12 +199,42 @@ pub enum Commands {
 
         #[arg(long, help = "Remove worktree but keep git branch")]
         keep_branch: bool,
+
+        #[arg(long, help = "Show what would be removed without making changes")]
+        dry_run: bool,
     },
 
     #[command(about = "List worktrees with their allocations")]
     List {
         #[arg(short, long, help = "Output format")]
         format: Option<OutputFormat>,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "COLUMNS",
+            help = "Table columns to show: name, branch, path, status, dirty, main, upstream, age, subject, pr, services, compose, values, or a variable name"
+        )]
+        columns: Option<Vec<String>>,
+
+        #[arg(long, value_enum, default_value_t = ListSort::Name, help = "Sort worktrees by")]
+        sort: ListSort,
+
+        #[arg(long, help = "Only show worktrees with uncommitted changes")]
+        dirty: bool,
+
+        #[arg(long, help = "Only show worktrees whose branch is merged into main")]
+        merged: bool,
+
+        #[arg(
+            long,
+            value_name = "REGEX",
+            help = "Only show worktrees whose name matches REGEX"
+        )]
+        filter: Option<String>,
+
+        #[arg(long, help = "Show each branch's pull request (queries the forge)")]
+        pr: bool,
     },
 
     #[command(about = "Repair configuration and discover orphaned worktrees")]
@@ -113,10 +243,72 @@ pub enum Commands {
         dry_run: bool,
     },
 
+    #[command(
+        about = "Check configuration and worktree state for problems",
+        visible_alias = "doctor"
+    )]
+    Check {
+        #[arg(long, help = "Apply safe fixes for the problems found")]
+        fix: bool,
+    },
+
+    #[command(about = "Remove merged, stale or missing worktrees")]
+    Prune {
+        #[arg(long, help = "Prune worktrees whose branch is merged into main")]
+        merged: bool,
+
+        #[arg(long, help = "Prune worktrees whose upstream branch was deleted")]
+        gone: bool,
+
+        #[arg(
+            long,
+            value_name = "DAYS",
+            help = "Prune worktrees with no commits in the last DAYS days"
+        )]
+        stale: Option<u64>,
+
+        #[arg(long, help = "Prune worktrees whose directory is missing")]
+        missing: bool,
+
+        #[arg(short, long, help = "Prune without asking for confirmation")]
+        yes: bool,
+
+        #[arg(long, help = "Show what would be pruned without making changes")]
+        dry_run: bool,
+    },
+
     #[command(about = "Switch to an existing worktree directory")]
     Switch {
         #[arg(help = "Name of the branch/worktree to switch to", add = ArgValueCompleter::new(complete_worktree_names))]
         branch_name: String,
+
+        #[arg(
+            long,
+            help = "Start a new shell in the worktree instead of changing the current shell's directory"
+        )]
+        subshell: bool,
+
+        #[arg(
+            long,
+            help = "Open the worktree in a tmux window laid out with [layout]",
+            conflicts_with_all = ["subshell", "zellij"]
+        )]
+        tmux: bool,
+
+        #[arg(
+            long,
+            help = "Open the worktree in a zellij tab laid out with [layout]",
+            conflicts_with = "subshell"
+        )]
+        zellij: bool,
+    },
+
+    #[command(
+        about = "Print the shell function that lets switch change the current shell's directory"
+    )]
+    ShellInit {
+        #[arg(help = "Shell to print the integration for (default: `shell` from the user config)")]
+        shell: Option<ShellKind>,
     },
 
     #[command(about = "Generate shell completions", hide = true)]
@@ -127,8 +319,12 @@ pub enum Commands {
 
     #[command(about = "Merge a worktree branch into target branch")]
     Merge {
-        #[arg(help = "Name of the branch/worktree to merge", add = ArgValueCompleter::new(complete_worktree_names))]
-        branch_name: String,
+        #[arg(
+            help = "Name of the branch/worktree to merge",
+            required_unless_present_any = ["continue_merge", "abort"],
+            add = ArgValueCompleter::new(complete_worktree_names)
+        )]
+        branch_name: Option<String>,
 
         #[arg(long, help = "Target branch to merge into (default: main)")]
         into: Option<String>,
@@ -141,10 +337,308 @@ pub enum Commands {
 
         #[arg(long, help = "Remove worktree after successful merge")]
         remove: bool,
+
+        #[arg(short, long, help = "Commit message for the merge or squash commit")]
+        message: Option<String>,
+
+        #[arg(
+            long,
+            help = "Edit the commit message in your git editor before committing"
+        )]
+        edit: bool,
+
+        #[arg(
+            long,
+            help = "Create a merge commit even when a fast-forward is possible",
+            conflicts_with = "squash"
+        )]
+        no_ff: bool,
+
+        #[arg(
+            long,
+            help = "On conflicts, leave them in place to resolve and continue the merge"
+        )]
+        keep_conflicts: bool,
+
+        #[arg(
+            long,
+            help = "Show what would be merged and whether it would conflict, without making changes",
+            conflicts_with_all = ["edit", "keep_conflicts"]
+        )]
+        dry_run: bool,
+
+        #[arg(
+            long = "continue",
+            help = "Finish a merge stopped by conflicts once they are resolved",
+            conflicts_with_all = ["into", "squash", "rebase", "message", "no_ff", "keep_conflicts", "dry_run", "abort"]
+        )]
+        continue_merge: bool,
+
+        #[arg(
+            long,
+            help = "Abandon a merge stopped by conflicts and restore the previous state",
+            conflicts_with_all = ["into", "squash", "rebase", "remove", "message", "edit", "no_ff", "keep_conflicts", "dry_run"]
+        )]
+        abort: bool,
+    },
+
+    #[command(about = "Push a worktree's branch and set its upstream")]
+    Push {
+        #[arg(help = "Name of the branch/worktree to push", add = ArgValueCompleter::new(complete_worktree_names))]
+        branch_name: String,
+
+        #[arg(
+            long,
+            help = "Overwrite the remote branch if it hasn't changed since the last fetch"
+        )]
+        force_with_lease: bool,
+    },
+
+    #[command(about = "Create a pull request for a worktree's branch, or show its open one")]
+    Pr {
+        #[arg(help = "Name of the branch/worktree", add = ArgValueCompleter::new(complete_worktree_names))]
+        branch_name: String,
+
+        #[arg(long, help = "Branch to merge into (default: main)")]
+        base: Option<String>,
+
+        #[arg(
+            long,
+            help = "Pull request title (default: the commit subject or branch name)"
+        )]
+        title: Option<String>,
+
+        #[arg(long, help = "Pull request body (default: the commit subjects)")]
+        body: Option<String>,
+
+        #[arg(long, help = "Create the pull request as a draft")]
+        draft: bool,
+
+        #[arg(long, help = "Open the pull request in the browser")]
+        web: bool,
+    },
+
+    #[command(about = "Merge or rebase the main branch into worktree branches")]
+    Update {
+        #[arg(
+            help = "Name of the branch/worktree to update",
+            required_unless_present = "all",
+            add = ArgValueCompleter::new(complete_worktree_names)
+        )]
+        branch_name: Option<String>,
+
+        #[arg(long, help = "Update every worktree", conflicts_with = "branch_name")]
+        all: bool,
+
+        #[arg(long, help = "Rebase onto the main branch instead of merging it")]
+        rebase: bool,
+
+        #[arg(long, help = "Don't fetch from the remote first")]
+        no_fetch: bool,
+
+        #[arg(long, help = "Predict the outcome without changing any worktree")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Start a worktree's [[services]] in the background")]
+    Up {
+        #[arg(help = "Name of the branch/worktree", add = ArgValueCompleter::new(complete_worktree_names))]
+        branch_name: String,
+
+        #[arg(help = "Services to start (default: all)")]
+        services: Vec<String>,
+    },
+
+    #[command(about = "Stop a worktree's [[services]]")]
+    Down {
+        #[arg(help = "Name of the branch/worktree", add = ArgValueCompleter::new(complete_worktree_names))]
+        branch_name: String,
+
+        #[arg(help = "Services to stop (default: all)")]
+        services: Vec<String>,
+    },
+
+    #[command(about = "Show the output of a worktree's [[services]]")]
+    Logs {
+        #[arg(help = "Name of the branch/worktree", add = ArgValueCompleter::new(complete_worktree_names))]
+        branch_name: String,
+
+        #[arg(help = "Service to show (default: all)")]
+        service: Option<String>,
+
+        #[arg(short, long, help = "Keep printing new output")]
+        follow: bool,
+
+        #[arg(short = 'n', long, help = "Only show the last N lines")]
+        lines: Option<usize>,
+    },
+
+    #[command(about = "Show the configuration merged from all its files")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    #[command(about = "Manage the registry of allocations across repositories")]
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommand,
+    },
+
+    #[command(about = "Manage the variables in vibetree.toml")]
+    Var {
+        #[command(subcommand)]
+        command: VarCommand,
+    },
+
+    #[command(about = "Migrate vibetree.toml and branches.toml to the current format")]
+    Migrate {
+        #[arg(long, help = "Show what would be migrated without changing any files")]
+        dry_run: bool,
     },
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    #[command(about = "Print the effective configuration")]
+    Show {
+        #[arg(long, help = "Show which file each value comes from")]
+        origin: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RegistryCommand {
+    #[command(about = "List the allocations of every repository")]
+    List,
+
+    #[command(about = "Remove allocations of repositories and worktrees that no longer exist")]
+    Gc {
+        #[arg(
+            long,
+            help = "Show what would be removed without changing the registry"
+        )]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VarCommand {
+    #[command(about = "List the variables in vibetree.toml")]
+    List,
+
+    #[command(about = "Add a variable to vibetree.toml")]
+    Add {
+        #[arg(help = "Environment variable name")]
+        name: String,
+
+        #[command(flatten)]
+        value: VarValueArgs,
+
+        #[arg(
+            long,
+            help = "Only define the variable for branches matching this regex"
+        )]
+        branch: Option<String>,
+
+        #[arg(short, long, help = "Repair outdated worktrees without asking")]
+        yes: bool,
+    },
+
+    #[command(about = "Change the value of a variable in vibetree.toml")]
+    Set {
+        #[arg(help = "Environment variable name")]
+        name: String,
+
+        #[command(flatten)]
+        value: VarValueArgs,
+
+        #[arg(
+            long,
+            help = "Only define the variable for branches matching this regex"
+        )]
+        branch: Option<String>,
+
+        #[arg(short, long, help = "Repair outdated worktrees without asking")]
+        yes: bool,
+    },
+
+    #[command(about = "Remove a variable from vibetree.toml")]
+    Remove {
+        #[arg(help = "Environment variable name")]
+        name: String,
+
+        #[arg(short, long, help = "Repair outdated worktrees without asking")]
+        yes: bool,
+    },
+}
+
+/// The value of `var add` and `var set`, exactly one of which is required
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct VarValueArgs {
+    #[arg(long, help = "Allocate a free port, starting from this one")]
+    port: Option<u16>,
+
+    #[arg(long, help = "Allocate a unique number, starting from this one")]
+    int: Option<u16>,
+
+    #[arg(long, help = "Template like 'app_{int:1}', or a static value")]
+    template: Option<String>,
+}
+
+impl VarValueArgs {
+    pub fn into_value(self) -> VariableValue {
+        match (self.port, self.int, self.template) {
+            (Some(port), _, _) => VariableValue::Port(port),
+            (_, Some(number), _) => VariableValue::Int(number),
+            (_, _, template) => VariableValue::Template(template.unwrap_or_default()),
+        }
+    }
+}
+
+impl Commands {
+    /// Subcommand name as typed on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Init { .. } => "init",
+            Commands::Add { .. } => "add",
+            Commands::Fork { .. } => "fork",
+            Commands::MoveChanges { .. } => "move-changes",
+            Commands::Remove { .. } => "remove",
+            Commands::List { .. } => "list",
+            Commands::Repair { .. } => "repair",
+            Commands::Check { .. } => "check",
+            Commands::Prune { .. } => "prune",
+            Commands::Switch { .. } => "switch",
+            Commands::ShellInit { .. } => "shell-init",
+            Commands::Completions { .. } => "completions",
+            Commands::Merge { .. } => "merge",
+            Commands::Push { .. } => "push",
+            Commands::Pr { .. } => "pr",
+            Commands::Update { .. } => "update",
+            Commands::Up { .. } => "up",
+            Commands::Down { .. } => "down",
+            Commands::Logs { .. } => "logs",
+            Commands::Config { .. } => "config",
+            Commands::Registry { .. } => "registry",
+            Commands::Var { .. } => "var",
+            Commands::Migrate { .. } => "migrate",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputMode {
+    /// Human-readable log messages
+    #[default]
+    Text,
+    /// One JSON result object per command
+    Json,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
     Table,
     Json,
@@ -152,3 +646,45 @@ pub enum OutputFormat {
     /// Output just branch names, one per line (useful for shell completions)
     Names,
 }
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyMode {
+    /// Copy-on-write clones where the filesystem supports them, plain copies otherwise
+    #[default]
+    Reflink,
+    /// Hard links - fast and small, but both worktrees then share the same files
+    Hardlink,
+    /// Plain copies
+    Copy,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    #[default]
+    Name,
+    /// Worktrees with the most recent commit first
+    Age,
+    Status,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_needs_a_branch_or_all() {
+        assert!(Cli::try_parse_from(["vibetree", "update"]).is_err());
+        assert!(Cli::try_parse_from(["vibetree", "update", "feature", "--all"]).is_err());
+
+        let cli = Cli::try_parse_from(["vibetree", "update", "--all"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Update {
+                branch_name: None,
+                all: true,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::Command;
+
+use crate::config::ComposeConfig;
+
+/// A container of a worktree's compose project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComposeContainer {
+    pub name: String,
+    pub service: String,
+    /// e.g. "running" or "exited"
+    pub state: String,
+}
+
+/// Runs the docker CLI against the compose project of a worktree, as set by
+/// the `[compose]` table
+pub struct ComposeManager<'a> {
+    config: &'a ComposeConfig,
+}
+
+impl<'a> ComposeManager<'a> {
+    pub fn new(config: &'a ComposeConfig) -> Self {
+        Self { config }
+    }
+
+    /// The worktree's compose project name: its value of the `[compose]` project variable
+    ///
+    /// None when `[compose]` is not configured or the worktree has no such value.
+    pub fn project_name(&self, values: &HashMap<String, String>) -> Option<String> {
+        let variable = self.config.project.as_ref()?;
+        values
+            .get(variable)
+            .filter(|name| !name.is_empty())
+            .cloned()
+    }
+
+    /// Containers of a project, stopped ones included
+    pub fn containers(&self, project: &str) -> Result<Vec<ComposeContainer>> {
+        let output = self.run(&["compose", "-p", project, "ps", "-a", "--format", "json"])?;
+        parse_containers(&output)
+    }
+
+    /// Volumes created for a project
+    pub fn volumes(&self, project: &str) -> Result<Vec<String>> {
+        let filter = format!("label=com.docker.compose.project={}", project);
+        let output = self.run(&["volume", "ls", "-q", "--filter", &filter])?;
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    /// Stop a project and delete its containers, networks and volumes
+    pub fn down(&self, project: &str) -> Result<()> {
+        self.run(&["compose", "-p", project, "down", "-v", "--remove-orphans"])?;
+        Ok(())
+    }
+
+    fn run(&self, args: &[&str]) -> Result<String> {
+        let docker = self.config.docker();
+        let output = Command::new(docker)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", docker))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} {} failed: {}",
+                docker,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// Parse `docker compose ps --format json`, which older versions print as one
+/// array and newer ones as one object per line
+fn parse_containers(output: &str) -> Result<Vec<ComposeContainer>> {
+    let objects: Vec<serde_json::Value> = if output.starts_with('[') {
+        serde_json::from_str(output).context("Failed to parse docker compose ps output")?
+    } else {
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .context("Failed to parse docker compose ps output")?
+    };
+
+    let field = |object: &serde_json::Value, key: &str| {
+        object[key].as_str().unwrap_or_default().to_string()
+    };
+    Ok(objects
+        .iter()
+        .map(|object| ComposeContainer {
+            name: field(object, "Name"),
+            service: field(object, "Service"),
+            state: field(object, "State"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_containers_lines_and_array() -> Result<()> {
+        let expected = vec![ComposeContainer {
+            name: "app_2-db-1".to_string(),
+            service: "db".to_string(),
+            state: "running".to_string(),
+        }];
+        let line = r#"{"Name":"app_2-db-1","Service":"db","State":"running"}"#;
+
+        assert_eq!(parse_containers(line)?, expected);
+        assert_eq!(parse_containers(&format!("[{}]", line))?, expected);
+        assert!(parse_containers("")?.is_empty());
+        Ok(())
+    }
+}
@@ -1,9 +1,16 @@
 use anyhow::{Context, Result};
+use log::warn;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::output::VibeTreeError;
+use crate::project_file::ProjectFile;
+use crate::registry::Registry;
+use crate::schema;
+use crate::settings::{LayeredConfig, Settings};
+
 /// Variable type for bare number values
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -13,7 +20,7 @@ pub enum VariableType {
 }
 
 /// Variable configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VariableConfig {
     pub name: String, // Environment variable name
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -31,10 +38,197 @@ pub struct VibeTreeProjectConfig {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub variables: Vec<VariableConfig>,
     pub main_branch: String,
-    #[serde(default = "default_branches_dir")]
+    /// Left out of the file while it's the default, so the user config can pick another
+    #[serde(
+        default = "default_branches_dir",
+        skip_serializing_if = "is_default_branches_dir"
+    )]
     pub branches_dir: String,
     #[serde(default = "default_env_file_path")]
     pub env_file_path: String,
+    #[serde(default, skip_serializing_if = "MergeConfig::is_empty")]
+    pub merge: MergeConfig,
+    #[serde(default, skip_serializing_if = "ForgeConfig::is_empty")]
+    pub forge: ForgeConfig,
+    #[serde(default, skip_serializing_if = "ForkConfig::is_empty")]
+    pub fork: ForkConfig,
+    #[serde(default, skip_serializing_if = "LayoutConfig::is_empty")]
+    pub layout: LayoutConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ServiceConfig>,
+    #[serde(default, skip_serializing_if = "ComposeConfig::is_empty")]
+    pub compose: ComposeConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<ResourceConfig>,
+}
+
+/// Settings for `vibetree merge` - the `[merge]` table in vibetree.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeConfig {
+    /// Trailer appended to merge commit messages, e.g.
+    /// `"Co-authored-by: {name} <{email}>"`. Author placeholders expand once per
+    /// author of the merged commits; `{branch}` and `{target}` are also available.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailer: Option<String>,
+}
+
+impl MergeConfig {
+    pub fn is_empty(&self) -> bool {
+        self.trailer.is_none()
+    }
+}
+
+/// How pull requests are created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeMethod {
+    /// The GitHub CLI, using its own authentication
+    Gh,
+    /// The GitHub REST API, authenticated with `GH_TOKEN` or `GITHUB_TOKEN`
+    Api,
+}
+
+/// Where branches are pushed and pull requests opened - the `[forge]` table in vibetree.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeConfig {
+    /// Chosen automatically when unset: the API when `api_url` or a token is set, otherwise gh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<ForgeMethod>,
+    /// Base URL of the REST API, e.g. for GitHub Enterprise (default: https://api.github.com)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Repository as "owner/name"; detected from the remote URL when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// Remote to push to (default: origin)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+impl ForgeConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn remote(&self) -> &str {
+        self.remote.as_deref().unwrap_or("origin")
+    }
+}
+
+/// Settings for `vibetree fork` - the `[fork]` table in vibetree.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkConfig {
+    /// Shell commands run in the new worktree after forking, e.g. to copy a
+    /// database. `${src:VAR}` expands to the source's value of a variable,
+    /// `${VAR}` or `${new:VAR}` to the fork's.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<String>,
+}
+
+impl ForkConfig {
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+/// Panes of the tmux window or zellij tab opened by `--tmux` and `--zellij` -
+/// the `[layout]` table in vibetree.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutConfig {
+    /// One shell in the worktree when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub panes: Vec<PaneConfig>,
+}
+
+impl LayoutConfig {
+    pub fn is_empty(&self) -> bool {
+        self.panes.is_empty()
+    }
+}
+
+/// The Docker Compose project of each worktree - the `[compose]` table in vibetree.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposeConfig {
+    /// Variable holding the worktree's compose project name, e.g. "COMPOSE_PROJECT_NAME"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Docker CLI to run (default: docker)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docker: Option<String>,
+}
+
+impl ComposeConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn docker(&self) -> &str {
+        self.docker.as_deref().unwrap_or("docker")
+    }
+}
+
+/// Something each worktree gets its own of, like a database - a `[[resources]]`
+/// entry in vibetree.toml
+///
+/// Commands run in the worktree with its values in their environment; `${VAR}`
+/// placeholders naming a value are filled in before they run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceConfig {
+    pub name: String,
+    /// Run when a worktree is added, e.g. `createdb app_${INSTANCE}`
+    pub create: String,
+    /// Run when a worktree is removed, e.g. `dropdb app_${INSTANCE}`
+    pub destroy: String,
+    /// Exits 0 when the resource already exists, so `create` is skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exists: Option<String>,
+}
+
+/// Whether a worktree's resource was created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceState {
+    Created,
+    /// `create` failed; `repair` tries again
+    Failed,
+}
+
+/// A dev service started by `vibetree up` - a `[[services]]` entry in vibetree.toml
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceConfig {
+    pub name: String,
+    /// Shell command, run with the worktree's values in its environment
+    pub command: String,
+    /// Directory to run in, relative to the worktree root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// How `up` tells the service has started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready: Option<ReadyCheck>,
+    /// Seconds to wait for the ready check (default: 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready_timeout: Option<u64>,
+}
+
+/// Readiness check of a service; `${VAR}` expands to the worktree's value of VAR
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadyCheck {
+    /// A TCP port, or host:port, accepts connections, e.g. `{ tcp = "${WEB_PORT}" }`
+    Tcp(String),
+    /// A URL answers with HTTP 200, e.g. `{ http = "http://localhost:${WEB_PORT}/health" }`
+    Http(String),
+}
+
+/// A `[[layout.panes]]` entry
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaneConfig {
+    /// Pane title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Command typed into the pane's shell, e.g. `docker compose up`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
 }
 
 /// Local worktree state - stored in .vibetree/branches.toml (not checked into git)
@@ -49,6 +243,8 @@ pub struct VibeTreeBranchesConfig {
 pub struct VibeTreeConfig {
     pub project_config: VibeTreeProjectConfig,
     pub branches_config: VibeTreeBranchesConfig,
+    /// Personal settings from the user config and vibetree.local.toml
+    pub settings: Settings,
     parent_override: Option<PathBuf>, // Track parent directory for saving
 }
 
@@ -56,24 +252,69 @@ fn default_branches_dir() -> String {
     ".vibetree/branches".to_string()
 }
 
+fn is_default_branches_dir(branches_dir: &String) -> bool {
+    *branches_dir == default_branches_dir()
+}
+
 fn default_env_file_path() -> String {
     ".vibetree/env".to_string()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct WorktreeConfig {
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub values: HashMap<String, String>, // env_var_name -> allocated value (stored as string)
+    /// State of each `[[resources]]` entry, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<String, ResourceState>,
+}
+
+/// Differences between a worktree's variable definitions and the main branch's
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigDrift {
+    /// Variables defined only in the worktree's vibetree.toml
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// Variables defined only in the main branch's vibetree.toml
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    /// Variables defined in both but with different values, types or branch patterns
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<String>,
+}
+
+impl ConfigDrift {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Compact one-line summary, e.g. "+ELASTIC_PORT, -REDIS_PORT, ~API_PORT"
+    pub fn summary(&self) -> String {
+        self.added
+            .iter()
+            .map(|name| format!("+{}", name))
+            .chain(self.removed.iter().map(|name| format!("-{}", name)))
+            .chain(self.changed.iter().map(|name| format!("~{}", name)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 impl Default for VibeTreeProjectConfig {
     fn default() -> Self {
         Self {
-            version: "1".to_string(),
+            version: schema::PROJECT.current().to_string(),
             variables: Vec::new(),
             main_branch: "main".to_string(),
             branches_dir: default_branches_dir(),
             env_file_path: default_env_file_path(),
+            merge: MergeConfig::default(),
+            forge: ForgeConfig::default(),
+            fork: ForkConfig::default(),
+            layout: LayoutConfig::default(),
+            services: Vec::new(),
+            compose: ComposeConfig::default(),
+            resources: Vec::new(),
         }
     }
 }
@@ -81,7 +322,7 @@ impl Default for VibeTreeProjectConfig {
 impl Default for VibeTreeBranchesConfig {
     fn default() -> Self {
         Self {
-            version: "1".to_string(),
+            version: schema::BRANCHES.current().to_string(),
             worktrees: HashMap::new(),
         }
     }
@@ -112,10 +353,10 @@ impl VibeTreeConfig {
         };
 
         if !project_config_path.exists() {
-            anyhow::bail!(
+            anyhow::bail!(VibeTreeError::not_found(format!(
                 "Vibetree configuration not found at {}. Run 'vibetree init' first.",
                 project_config_path.display()
-            );
+            )));
         }
 
         let project_config = Self::load_project_config(&project_config_path)?;
@@ -126,9 +367,11 @@ impl VibeTreeConfig {
             VibeTreeBranchesConfig::default()
         };
 
+        let settings = Self::load_settings(parent_override.as_deref())?;
         Ok(Self {
             project_config,
             branches_config,
+            settings,
             parent_override: parent_override.clone(),
         })
     }
@@ -160,13 +403,28 @@ impl VibeTreeConfig {
             VibeTreeBranchesConfig::default()
         };
 
+        let settings = Self::load_settings(parent_override.as_deref())?;
         Ok(Self {
             project_config,
             branches_config,
+            settings,
             parent_override: parent_override.clone(),
         })
     }
 
+    fn load_settings(parent_override: Option<&Path>) -> Result<Settings> {
+        let repo = match parent_override {
+            Some(parent) => parent.to_path_buf(),
+            None => Self::get_vibetree_parent()?,
+        };
+        LayeredConfig::load(&repo)?.settings()
+    }
+
+    /// Load the repository's vibetree.toml, migrating it in memory if it's from an
+    /// older vibetree
+    ///
+    /// The file is shared with teammates who may run older builds, so only
+    /// `vibetree migrate` rewrites it.
     fn load_project_config(config_path: &Path) -> Result<VibeTreeProjectConfig> {
         let content = fs::read_to_string(config_path).with_context(|| {
             format!(
@@ -174,36 +432,29 @@ impl VibeTreeConfig {
                 config_path.display()
             )
         })?;
-
-        let config: VibeTreeProjectConfig = toml::from_str(&content).with_context(|| {
-            format!(
-                "Failed to parse project config file: {}",
-                config_path.display()
-            )
-        })?;
-
-        Ok(config)
+        schema::PROJECT.parse(&content, &config_path.display().to_string())
     }
 
+    /// Load .vibetree/branches.toml, migrating it first if it's from an older vibetree
     fn load_branches_config(config_path: &Path) -> Result<VibeTreeBranchesConfig> {
-        let content = fs::read_to_string(config_path).with_context(|| {
-            format!(
-                "Failed to read branches config file: {}",
-                config_path.display()
-            )
-        })?;
-
-        let config: VibeTreeBranchesConfig = toml::from_str(&content).with_context(|| {
-            format!(
-                "Failed to parse branches config file: {}",
-                config_path.display()
-            )
-        })?;
-
-        Ok(config)
+        let backup_dir = config_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("backups");
+        schema::BRANCHES.load(config_path, &backup_dir)
     }
 
+    /// Write the project config, leaving an existing vibetree.toml untouched unless the
+    /// configuration changed, and then editing only what changed
     fn save_project_config(config: &VibeTreeProjectConfig, config_path: &Path) -> Result<()> {
+        if config_path.exists() {
+            let mut file = ProjectFile::load(config_path)?;
+            if file.update(config)? {
+                file.save()?;
+            }
+            return Ok(());
+        }
+
         if let Some(parent) = config_path.parent() {
             fs::create_dir_all(parent).with_context(|| {
                 format!("Failed to create config directory: {}", parent.display())
@@ -246,9 +497,89 @@ impl VibeTreeConfig {
             )
         })?;
 
+        self.update_registry();
         Ok(())
     }
 
+    /// Record this repository's allocations in the registry
+    ///
+    /// The registry only helps other repositories avoid our ports, so failing
+    /// to update it doesn't fail the command.
+    fn update_registry(&self) {
+        let Some(registry_path) = Registry::path() else {
+            return;
+        };
+        let result = self.repo_path().and_then(|repo| {
+            Registry::update(&registry_path, |registry| {
+                registry.record(&repo, &self.branches_config.worktrees)
+            })
+        });
+        if let Err(e) = result {
+            warn!(
+                "Failed to update registry {}: {:#}",
+                registry_path.display(),
+                e
+            );
+        }
+    }
+
+    /// Allocate a new worktree's values clear of `reserved` and of the ports
+    /// other repositories hold according to the registry
+    ///
+    /// With `reserve`, the values are recorded in the registry under the same
+    /// lock, so a repository allocating at the same time can't take them too.
+    fn allocate_clear_of_registry(
+        &self,
+        name: &str,
+        variables: &[VariableConfig],
+        existing_worktrees: &HashMap<String, WorktreeConfig>,
+        reserved: &HashSet<u16>,
+        reserve: bool,
+    ) -> Result<HashMap<String, String>> {
+        let allocate = |elsewhere: HashSet<u16>| {
+            let mut reserved = reserved.clone();
+            reserved.extend(elsewhere);
+            crate::allocator::VariableAllocator::allocate_values_reserving(
+                variables,
+                name,
+                existing_worktrees,
+                &reserved,
+            )
+        };
+        let Some(registry_path) = Registry::path() else {
+            return allocate(HashSet::new());
+        };
+        let result = self.repo_path().and_then(|repo| {
+            Registry::update(&registry_path, |registry| {
+                let values = allocate(registry.ports_used_outside(&repo))?;
+                if reserve {
+                    registry.reserve(&repo, name, &values);
+                }
+                Ok(values)
+            })
+        });
+        match result {
+            Ok(values) => values,
+            Err(e) => {
+                warn!(
+                    "Failed to read registry {}: {:#}",
+                    registry_path.display(),
+                    e
+                );
+                allocate(HashSet::new())
+            }
+        }
+    }
+
+    /// The repository root, as recorded in the registry
+    fn repo_path(&self) -> Result<PathBuf> {
+        let parent = match self.parent_override {
+            Some(ref parent) => parent.clone(),
+            None => Self::get_vibetree_parent()?,
+        };
+        Ok(fs::canonicalize(&parent).unwrap_or(parent))
+    }
+
     pub fn save(&self) -> Result<()> {
         let project_config_path = if let Some(ref parent) = self.parent_override {
             parent.join("vibetree.toml")
@@ -264,6 +595,18 @@ impl VibeTreeConfig {
         &mut self,
         name: String,
         custom_values: Option<HashMap<String, String>>,
+    ) -> Result<HashMap<String, String>> {
+        let variables = self.project_config.variables.clone();
+        self.add_or_update_worktree_with_variables(name, custom_values, &variables)
+    }
+
+    /// Add or update a worktree, allocating from the given variable definitions
+    /// instead of the root project config (used for worktrees with their own vibetree.toml)
+    pub fn add_or_update_worktree_with_variables(
+        &mut self,
+        name: String,
+        custom_values: Option<HashMap<String, String>>,
+        variables: &[VariableConfig],
     ) -> Result<HashMap<String, String>> {
         let values = if let Some(custom) = custom_values {
             // Check for conflicts with existing worktrees (excluding the one we're updating)
@@ -272,27 +615,36 @@ impl VibeTreeConfig {
                     if existing_name != &name
                         && existing_worktree.values.values().any(|p| p == value)
                     {
-                        anyhow::bail!(
+                        anyhow::bail!(VibeTreeError::conflict(format!(
                             "Value {} (for variable '{}') is already allocated to worktree '{}'",
-                            value,
-                            variable,
-                            existing_name
-                        );
+                            value, variable, existing_name
+                        )));
                     }
                 }
             }
 
             custom
-        } else if self.project_config.variables.is_empty() {
+        } else if variables.is_empty() {
             // No variables defined, no values needed
             HashMap::new()
         } else {
-            self.project_config
-                .allocate_values(&name, &self.branches_config.worktrees)?
+            crate::allocator::VariableAllocator::allocate_values(
+                variables,
+                &name,
+                &self.branches_config.worktrees,
+            )?
         };
 
+        // Updating the values leaves the worktree's resources alone
+        let resources = self
+            .branches_config
+            .worktrees
+            .get(&name)
+            .map(|worktree| worktree.resources.clone())
+            .unwrap_or_default();
         let worktree = WorktreeConfig {
             values: values.clone(),
+            resources,
         };
 
         self.branches_config.worktrees.insert(name, worktree);
@@ -305,8 +657,46 @@ impl VibeTreeConfig {
         name: String,
         custom_values: Option<HashMap<String, String>>,
     ) -> Result<HashMap<String, String>> {
-        if self.branches_config.worktrees.contains_key(&name) {
-            anyhow::bail!("Worktree '{}' already exists", name);
+        let variables = self.project_config.variables.clone();
+        self.add_worktree_with_variables(name, custom_values, &variables)
+    }
+
+    /// Add a worktree, allocating from the given variable definitions
+    /// instead of the root project config (used for worktrees with their own vibetree.toml)
+    pub fn add_worktree_with_variables(
+        &mut self,
+        name: String,
+        custom_values: Option<HashMap<String, String>>,
+        variables: &[VariableConfig],
+    ) -> Result<HashMap<String, String>> {
+        let values = self.new_worktree_values(&name, custom_values, variables, true)?;
+
+        let worktree = WorktreeConfig {
+            values: values.clone(),
+            ..Default::default()
+        };
+
+        self.branches_config.worktrees.insert(name, worktree);
+        self.save_branches_config()?; // Save changes to branches config
+        Ok(values)
+    }
+
+    /// Values a new worktree would be allocated, without adding it
+    ///
+    /// With `reserve`, allocated ports are also recorded in the registry right
+    /// away; dry runs leave it alone.
+    pub fn new_worktree_values(
+        &self,
+        name: &str,
+        custom_values: Option<HashMap<String, String>>,
+        variables: &[VariableConfig],
+        reserve: bool,
+    ) -> Result<HashMap<String, String>> {
+        if self.branches_config.worktrees.contains_key(name) {
+            anyhow::bail!(VibeTreeError::conflict(format!(
+                "Worktree '{}' already exists",
+                name
+            )));
         }
 
         let values = if let Some(custom) = custom_values {
@@ -314,37 +704,82 @@ impl VibeTreeConfig {
             for (variable, value) in custom.iter() {
                 for (existing_name, existing_worktree) in &self.branches_config.worktrees {
                     if existing_worktree.values.values().any(|p| p == value) {
-                        anyhow::bail!(
+                        anyhow::bail!(VibeTreeError::conflict(format!(
                             "Value {} (for variable '{}') is already allocated to worktree '{}'",
-                            value,
-                            variable,
-                            existing_name
-                        );
+                            value, variable, existing_name
+                        )));
                     }
                 }
             }
 
             custom
-        } else if self.project_config.variables.is_empty() {
+        } else if variables.is_empty() {
             // No variables defined, no values needed
             HashMap::new()
         } else {
-            self.project_config
-                .allocate_values(&name, &self.branches_config.worktrees)?
+            let avoided = self.settings.avoided_ports()?;
+            self.allocate_clear_of_registry(
+                name,
+                variables,
+                &self.branches_config.worktrees,
+                &avoided,
+                reserve,
+            )?
         };
+        Ok(values)
+    }
 
-        let worktree = WorktreeConfig {
-            values: values.clone(),
-        };
+    /// Fresh values for an existing worktree whose variables changed, clear of
+    /// other repositories' ports, which are reserved in the registry
+    pub fn reallocated_values(
+        &self,
+        name: &str,
+        variables: &[VariableConfig],
+    ) -> Result<HashMap<String, String>> {
+        if variables.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let avoided = self.settings.avoided_ports()?;
+        self.allocate_clear_of_registry(
+            name,
+            variables,
+            &self.branches_config.worktrees,
+            &avoided,
+            true,
+        )
+    }
 
-        self.branches_config.worktrees.insert(name, worktree);
-        self.save_branches_config()?; // Save changes to branches config
-        Ok(values)
+    /// Values for the main branch when `init` runs, clear of the repository's
+    /// other worktrees, which exist when init runs again, and of other
+    /// repositories' ports
+    pub fn main_branch_values(
+        &self,
+        main_branch: &str,
+        reserve: bool,
+    ) -> Result<HashMap<String, String>> {
+        let others: HashMap<_, _> = self
+            .branches_config
+            .worktrees
+            .iter()
+            .filter(|(name, _)| *name != main_branch)
+            .map(|(name, worktree)| (name.clone(), worktree.clone()))
+            .collect();
+        let avoided = self.settings.avoided_ports()?;
+        self.allocate_clear_of_registry(
+            main_branch,
+            &self.project_config.variables,
+            &others,
+            &avoided,
+            reserve,
+        )
     }
 
     pub fn remove_worktree(&mut self, name: &str) -> Result<()> {
         if !self.branches_config.worktrees.contains_key(name) {
-            anyhow::bail!("Worktree '{}' does not exist", name);
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Worktree '{}' does not exist",
+                name
+            )));
         }
 
         self.branches_config.worktrees.remove(name);
@@ -372,6 +807,145 @@ impl VibeTreeConfig {
     pub fn get_env_file_path(&self, worktree_path: &Path) -> PathBuf {
         worktree_path.join(&self.project_config.env_file_path)
     }
+
+    /// Directory worktrees are created in, relative to the repo root
+    ///
+    /// vibetree.local.toml, then vibetree.toml, then the user config decide it.
+    pub fn branches_dir(&self) -> &str {
+        self.settings
+            .branches_dir
+            .as_deref()
+            .unwrap_or(&self.project_config.branches_dir)
+    }
+
+    /// Get the directory a worktree lives in: the repo root for the main branch,
+    /// the branches directory for everything else
+    pub fn get_worktree_path(&self, vibetree_parent: &Path, name: &str) -> PathBuf {
+        if name == self.project_config.main_branch {
+            vibetree_parent.to_path_buf()
+        } else {
+            vibetree_parent.join(self.branches_dir()).join(name)
+        }
+    }
+
+    /// Variable definitions that apply to a worktree, read from the vibetree.toml in its
+    /// own checkout. Falls back to the root config when the checkout has no vibetree.toml.
+    /// Only the variables are taken from the branch - layout settings always come from root.
+    pub fn worktree_variables(
+        &self,
+        vibetree_parent: &Path,
+        name: &str,
+    ) -> Result<Vec<VariableConfig>> {
+        if name == self.project_config.main_branch {
+            return Ok(self.project_config.variables.clone());
+        }
+
+        self.variables_in_checkout(&self.get_worktree_path(vibetree_parent, name))
+    }
+
+    /// Variable definitions from the vibetree.toml checked out at a worktree path,
+    /// falling back to the root config when there is none
+    pub fn variables_in_checkout(&self, worktree_path: &Path) -> Result<Vec<VariableConfig>> {
+        let config_path = worktree_path.join("vibetree.toml");
+        if !config_path.exists() {
+            return Ok(self.project_config.variables.clone());
+        }
+
+        // Another branch's file is only migrated in memory
+        let content = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let config: VibeTreeProjectConfig =
+            schema::PROJECT.parse(&content, &config_path.display().to_string())?;
+        Ok(config.variables)
+    }
+
+    /// Variable definitions committed at a revision, which is what a new worktree
+    /// created from that revision will see. Falls back to the root config when the
+    /// revision has no vibetree.toml.
+    pub fn variables_at_revision(
+        &self,
+        repo_path: &Path,
+        revision: &str,
+    ) -> Result<Vec<VariableConfig>> {
+        let content = crate::git::GitManager::read_file_at_revision(
+            repo_path,
+            revision,
+            Path::new("vibetree.toml"),
+        )?;
+
+        match content {
+            Some(content) => {
+                let config: VibeTreeProjectConfig =
+                    schema::PROJECT.parse(&content, &format!("vibetree.toml at '{}'", revision))?;
+                Ok(config.variables)
+            }
+            None => Ok(self.project_config.variables.clone()),
+        }
+    }
+
+    /// Compare each worktree's own variable definitions with the main branch's
+    ///
+    /// Worktrees whose vibetree.toml can't be read are reported as errors instead.
+    pub fn config_drift(&self, vibetree_parent: &Path) -> Vec<(String, Result<ConfigDrift>)> {
+        let mut names: Vec<_> = self.branches_config.worktrees.keys().collect();
+        names.sort();
+
+        names
+            .into_iter()
+            .filter(|name| **name != self.project_config.main_branch)
+            .filter_map(|name| {
+                let drift = self
+                    .worktree_variables(vibetree_parent, name)
+                    .map(|variables| variable_drift(&self.project_config.variables, &variables));
+                match drift {
+                    Ok(drift) if drift.is_empty() => None,
+                    drift => Some((name.clone(), drift)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Compare two sets of variable definitions by name
+///
+/// A name can appear several times with different branch patterns, so all
+/// definitions sharing a name are compared together.
+pub fn variable_drift(base: &[VariableConfig], other: &[VariableConfig]) -> ConfigDrift {
+    fn group(variables: &[VariableConfig]) -> Vec<(&str, Vec<&VariableConfig>)> {
+        let mut groups: Vec<(&str, Vec<&VariableConfig>)> = Vec::new();
+        for variable in variables {
+            match groups.iter_mut().find(|(name, _)| *name == variable.name) {
+                Some((_, definitions)) => definitions.push(variable),
+                None => groups.push((&variable.name, vec![variable])),
+            }
+        }
+        groups
+    }
+
+    let base_groups = group(base);
+    let other_groups = group(other);
+    let mut drift = ConfigDrift::default();
+
+    for (name, definitions) in &other_groups {
+        match base_groups.iter().find(|(base_name, _)| base_name == name) {
+            None => drift.added.push(name.to_string()),
+            Some((_, base_definitions)) if base_definitions != definitions => {
+                drift.changed.push(name.to_string())
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (name, _) in &base_groups {
+        if !other_groups
+            .iter()
+            .any(|(other_name, _)| other_name == name)
+        {
+            drift.removed.push(name.to_string());
+        }
+    }
+
+    drift
 }
 
 impl VibeTreeProjectConfig {
@@ -409,6 +983,7 @@ mod tests {
         let mut config = VibeTreeConfig {
             project_config: VibeTreeProjectConfig::default(),
             branches_config: VibeTreeBranchesConfig::default(),
+            settings: Settings::default(),
             parent_override: Some(temp_dir.path().to_path_buf()),
         };
         config.add_worktree("test-branch".to_string(), None)?;
@@ -429,6 +1004,7 @@ mod tests {
         let mut config = VibeTreeConfig {
             project_config: VibeTreeProjectConfig::default(),
             branches_config: VibeTreeBranchesConfig::default(),
+            settings: Settings::default(),
             parent_override: Some(temp_dir.path().to_path_buf()),
         };
 
@@ -451,13 +1027,13 @@ mod tests {
 
         // Verify values start from the configured starting value
         for variable in &config.project_config.variables {
-            if let Some(toml::Value::Integer(base_value)) = variable.value {
-                if let Ok(base_u16) = u16::try_from(base_value) {
-                    let value1: u16 = values1[&variable.name].parse().unwrap();
-                    let value2: u16 = values2[&variable.name].parse().unwrap();
-                    assert!(value1 >= base_u16);
-                    assert!(value2 >= base_u16);
-                }
+            if let Some(toml::Value::Integer(base_value)) = variable.value
+                && let Ok(base_u16) = u16::try_from(base_value)
+            {
+                let value1: u16 = values1[&variable.name].parse().unwrap();
+                let value2: u16 = values2[&variable.name].parse().unwrap();
+                assert!(value1 >= base_u16);
+                assert!(value2 >= base_u16);
             }
         }
 
@@ -471,6 +1047,7 @@ mod tests {
         let mut config = VibeTreeConfig {
             project_config: VibeTreeProjectConfig::default(),
             branches_config: VibeTreeBranchesConfig::default(),
+            settings: Settings::default(),
             parent_override: Some(temp_dir.path().to_path_buf()),
         };
 
@@ -506,6 +1083,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_variable_drift() {
+        let port = |name: &str, value: i64| VariableConfig {
+            name: name.to_string(),
+            value: Some(toml::Value::Integer(value)),
+            r#type: Some(VariableType::Port),
+            branch: None,
+        };
+
+        let main = vec![port("POSTGRES_PORT", 5432), port("REDIS_PORT", 6379)];
+        let branch = vec![
+            port("POSTGRES_PORT", 5500),
+            port("REDIS_PORT", 6379),
+            port("ELASTIC_PORT", 9200),
+        ];
+
+        let drift = variable_drift(&main, &branch);
+        assert_eq!(drift.added, vec!["ELASTIC_PORT"]);
+        assert!(drift.removed.is_empty());
+        assert_eq!(drift.changed, vec!["POSTGRES_PORT"]);
+        assert_eq!(drift.summary(), "+ELASTIC_PORT, ~POSTGRES_PORT");
+
+        let drift = variable_drift(&branch, &main);
+        assert_eq!(drift.removed, vec!["ELASTIC_PORT"]);
+
+        assert!(variable_drift(&main, &main).is_empty());
+    }
+
     #[test]
     fn test_duplicate_worktree_error() {
         use tempfile::TempDir;
@@ -513,6 +1118,7 @@ mod tests {
         let mut config = VibeTreeConfig {
             project_config: VibeTreeProjectConfig::default(),
             branches_config: VibeTreeBranchesConfig::default(),
+            settings: Settings::default(),
             parent_override: Some(temp_dir.path().to_path_buf()),
         };
 
@@ -1,22 +1,109 @@
 use anyhow::{Context, Result};
+use log::warn;
+use regex::Regex;
 use serde::Serialize;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::OutputFormat;
-use crate::config::VibeTreeConfig;
+use crate::compose::{ComposeContainer, ComposeManager};
+use crate::config::{ConfigDrift, VibeTreeConfig};
+use crate::forge::{self, PullRequest};
 use crate::git::GitManager;
+use crate::merge::MergeOperations;
+use crate::output::ServiceStatus;
+use crate::services::ServiceManager;
+use crate::{ListSort, OutputFormat};
+
+/// Columns shown in the table when `--columns` is not given
+const DEFAULT_COLUMNS: &[&str] = &[
+    "name", "status", "dirty", "main", "upstream", "age", "subject", "values",
+];
+
+/// Columns that are always available, in addition to one per variable
+const KNOWN_COLUMNS: &[&str] = &[
+    "name", "branch", "path", "status", "dirty", "main", "upstream", "age", "subject", "pr",
+    "services", "compose", "values",
+];
+
+/// Longest commit subject shown in the table before it is truncated
+const MAX_SUBJECT_WIDTH: usize = 50;
 
 /// Helper struct for formatting worktree data across different output formats
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct WorktreeDisplayData {
     pub name: String,
     pub status: String,
+    /// Branch checked out in the worktree, if it exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub path: PathBuf,
+    /// Whether the worktree has uncommitted changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dirty: Option<bool>,
+    /// Whether the branch has commits that are all contained in the main branch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ahead_main: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub behind_main: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ahead_upstream: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub behind_upstream: Option<usize>,
+    /// Time of the last commit as seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_commit_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_commit_subject: Option<String>,
+    /// Pull request for the branch, when requested with `--pr`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<PullRequest>,
+    /// Merge of this worktree stopped by conflicts, e.g. "merging into main"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// Whether each of the `[[services]]` is running
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ServiceStatus>,
+    /// Containers of the worktree's `[compose]` project
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub containers: Vec<ComposeContainer>,
     pub values: HashMap<String, String>,
+    /// How this worktree's vibetree.toml differs from the main branch's, if at all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_drift: Option<ConfigDrift>,
     #[serde(skip)]
     pub values_display: String,
 }
 
+/// Filtering, sorting and column selection for `vibetree list`
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// Table columns to show; `None` uses the default set
+    pub columns: Option<Vec<String>>,
+    pub sort: ListSort,
+    /// Only include worktrees with uncommitted changes
+    pub dirty: bool,
+    /// Only include worktrees merged into the main branch
+    pub merged: bool,
+    /// Only include worktrees whose name matches this regex
+    pub filter: Option<String>,
+    /// Look up each branch's pull request on the forge
+    pub pr: bool,
+}
+
+impl ListOptions {
+    /// Whether pull requests were asked for, by flag or by column
+    fn wants_pull_requests(&self) -> bool {
+        self.pr
+            || self
+                .columns
+                .as_ref()
+                .is_some_and(|columns| columns.iter().any(|c| c == "pr"))
+    }
+}
+
 pub struct DisplayManager<'a> {
     config: &'a VibeTreeConfig,
     vibetree_parent: &'a PathBuf,
@@ -31,42 +118,78 @@ impl<'a> DisplayManager<'a> {
     }
 
     /// List all worktrees and their configurations
-    pub fn list_worktrees(&self, format: Option<OutputFormat>) -> Result<()> {
+    pub fn list_worktrees(
+        &self,
+        format: Option<OutputFormat>,
+        options: &ListOptions,
+    ) -> Result<()> {
         let format = format.unwrap_or(OutputFormat::Table);
 
         match format {
-            OutputFormat::Table => self.list_worktrees_table(),
-            OutputFormat::Json => self.list_worktrees_json(),
-            OutputFormat::Yaml => self.list_worktrees_yaml(),
-            OutputFormat::Names => self.list_worktrees_names(),
+            OutputFormat::Table => self.list_worktrees_table(options),
+            OutputFormat::Json => self.list_worktrees_json(options),
+            OutputFormat::Yaml => self.list_worktrees_yaml(options),
+            OutputFormat::Names => self.list_worktrees_names(options),
         }
     }
 
-    fn list_worktrees_table(&self) -> Result<()> {
-        let worktree_data = self.collect_worktree_data()?;
+    fn list_worktrees_table(&self, options: &ListOptions) -> Result<()> {
+        let columns = self.resolve_columns(options)?;
+        let worktree_data = self.select_worktree_data(options)?;
 
         if worktree_data.is_empty() {
             println!("No worktrees configured");
             return Ok(());
         }
 
-        println!("{:<20} {:<10} {:<15}", "Name", "Status", "Values");
-        println!("{}", "-".repeat(45));
+        let now = now_seconds();
+        let headers: Vec<String> = columns.iter().map(|c| column_header(c)).collect();
+        let rows: Vec<Vec<String>> = worktree_data
+            .iter()
+            .map(|data| columns.iter().map(|c| column_cell(data, c, now)).collect())
+            .collect();
 
-        for data in worktree_data {
-            let values_summary = if data.values.is_empty() {
-                "none".to_string()
-            } else {
-                format!("{} configured", data.values.len())
-            };
-            println!("{:<20} {:<10} {:<15}", data.name, data.status, values_summary);
+        let widths: Vec<usize> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .chain(std::iter::once(header.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        println!("{}", format_row(&headers, &widths));
+        println!(
+            "{}",
+            "-".repeat(widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1))
+        );
+        for row in &rows {
+            println!("{}", format_row(row, &widths));
+        }
+
+        let drifted: Vec<_> = worktree_data
+            .iter()
+            .filter_map(|data| data.config_drift.as_ref().map(|drift| (&data.name, drift)))
+            .collect();
+        if !drifted.is_empty() {
+            println!();
+            println!(
+                "vibetree.toml differs from '{}':",
+                self.config.project_config.main_branch
+            );
+            for (name, drift) in drifted {
+                println!("  {:<20} {}", name, drift.summary());
+            }
         }
 
         Ok(())
     }
 
-    fn list_worktrees_json(&self) -> Result<()> {
-        let worktree_data = self.collect_worktree_data()?;
+    fn list_worktrees_json(&self, options: &ListOptions) -> Result<()> {
+        let worktree_data = self.select_worktree_data(options)?;
 
         let output: HashMap<&str, &WorktreeDisplayData> = worktree_data
             .iter()
@@ -79,8 +202,8 @@ impl<'a> DisplayManager<'a> {
         Ok(())
     }
 
-    fn list_worktrees_yaml(&self) -> Result<()> {
-        let worktree_data = self.collect_worktree_data()?;
+    fn list_worktrees_yaml(&self, options: &ListOptions) -> Result<()> {
+        let worktree_data = self.select_worktree_data(options)?;
 
         let output: HashMap<&str, &WorktreeDisplayData> = worktree_data
             .iter()
@@ -93,8 +216,26 @@ impl<'a> DisplayManager<'a> {
         Ok(())
     }
 
-    fn list_worktrees_names(&self) -> Result<()> {
-        for name in self.config.branches_config.worktrees.keys() {
+    fn list_worktrees_names(&self, options: &ListOptions) -> Result<()> {
+        // Plain name listings back shell completion, so skip git inspection
+        // unless a filter actually needs it
+        if options.dirty || options.merged {
+            for data in self.select_worktree_data(options)? {
+                println!("{}", data.name);
+            }
+            return Ok(());
+        }
+
+        let filter = compile_filter(options)?;
+        let mut names: Vec<_> = self
+            .config
+            .branches_config
+            .worktrees
+            .keys()
+            .filter(|name| filter.as_ref().is_none_or(|re| re.is_match(name)))
+            .collect();
+        names.sort();
+        for name in names {
             println!("{}", name);
         }
         Ok(())
@@ -102,18 +243,36 @@ impl<'a> DisplayManager<'a> {
 
     /// Collect worktree data with validation status for display
     pub fn collect_worktree_data(&self) -> Result<Vec<WorktreeDisplayData>> {
-        let mut data = Vec::new();
+        self.select_worktree_data(&ListOptions::default())
+    }
+
+    /// Collect, filter and sort worktree data according to the list options
+    pub fn select_worktree_data(&self, options: &ListOptions) -> Result<Vec<WorktreeDisplayData>> {
+        let filter = compile_filter(options)?;
+        let repo_path = GitManager::find_repo_root(self.vibetree_parent).ok();
+        let mut drift: HashMap<String, ConfigDrift> = self
+            .config
+            .config_drift(self.vibetree_parent)
+            .into_iter()
+            .filter_map(|(name, drift)| drift.ok().map(|drift| (name, drift)))
+            .collect();
+        let operations = MergeOperations::load(self.vibetree_parent).unwrap_or_else(|e| {
+            warn!("Could not read merges in progress: {}", e);
+            MergeOperations::default()
+        });
 
+        let services = ServiceManager::new(self.config, self.vibetree_parent);
+        let compose = ComposeManager::new(&self.config.project_config.compose);
+
+        let mut data = Vec::new();
         for (name, worktree) in &self.config.branches_config.worktrees {
-            let worktree_path = if *name == self.config.project_config.main_branch {
-                // Main branch lives at repo root
-                self.vibetree_parent.clone()
-            } else {
-                // Other branches live in branches directory
-                self.vibetree_parent
-                    .join(&self.config.project_config.branches_dir)
-                    .join(name)
-            };
+            if let Some(re) = &filter
+                && !re.is_match(name)
+            {
+                continue;
+            }
+
+            let worktree_path = self.config.get_worktree_path(self.vibetree_parent, name);
             let validation = GitManager::validate_worktree_state(&worktree_path)?;
 
             let status = if !validation.exists {
@@ -126,21 +285,367 @@ impl<'a> DisplayManager<'a> {
                 "OK"
             };
 
-            let values_display = worktree
-                .values
+            let mut values: Vec<_> = worktree.values.iter().collect();
+            values.sort();
+            let values_display = values
                 .iter()
                 .map(|(service, port)| format!("{}:{}", service, port))
                 .collect::<Vec<_>>()
                 .join(", ");
 
-            data.push(WorktreeDisplayData {
+            let mut entry = WorktreeDisplayData {
                 name: name.clone(),
                 status: status.to_string(),
+                branch: None,
+                path: worktree_path.clone(),
+                dirty: None,
+                merged: None,
+                ahead_main: None,
+                behind_main: None,
+                ahead_upstream: None,
+                behind_upstream: None,
+                last_commit_time: None,
+                last_commit_subject: None,
+                pull_request: None,
+                operation: operations.merges.get(name).map(|state| state.describe()),
+                services: services.status(name),
+                containers: Vec::new(),
                 values: worktree.values.clone(),
+                config_drift: drift.remove(name),
                 values_display,
-            });
+            };
+
+            if let Some(project) = compose.project_name(&worktree.values) {
+                entry.containers = compose.containers(&project).unwrap_or_else(|e| {
+                    warn!("Could not list compose project '{}': {}", project, e);
+                    Vec::new()
+                });
+            }
+            if validation.exists && validation.is_git_worktree {
+                entry.dirty = GitManager::has_uncommitted_changes(&worktree_path).ok();
+            }
+            if let Some(repo_path) = &repo_path {
+                let branch = validation.branch_name.clone().or_else(|| {
+                    GitManager::branch_exists(repo_path, name)
+                        .unwrap_or(false)
+                        .then(|| name.clone())
+                });
+                if let Some(branch) = branch {
+                    self.fill_branch_status(&mut entry, repo_path, &branch);
+                    entry.branch = Some(branch);
+                }
+            }
+
+            if options.dirty && entry.dirty != Some(true) {
+                continue;
+            }
+            if options.merged && entry.merged != Some(true) {
+                continue;
+            }
+
+            data.push(entry);
+        }
+
+        if options.wants_pull_requests()
+            && let Some(repo_path) = &repo_path
+        {
+            self.fill_pull_requests(&mut data, repo_path);
+        }
+
+        match options.sort {
+            ListSort::Name => data.sort_by(|a, b| a.name.cmp(&b.name)),
+            ListSort::Age => data.sort_by(|a, b| {
+                b.last_commit_time
+                    .cmp(&a.last_commit_time)
+                    .then_with(|| a.name.cmp(&b.name))
+            }),
+            ListSort::Status => {
+                data.sort_by(|a, b| a.status.cmp(&b.status).then_with(|| a.name.cmp(&b.name)))
+            }
         }
 
         Ok(data)
     }
+
+    /// Fill in commit and ahead/behind information for a worktree's branch
+    ///
+    /// Git errors leave the fields empty rather than failing the whole listing.
+    fn fill_branch_status(&self, entry: &mut WorktreeDisplayData, repo_path: &Path, branch: &str) {
+        let main_branch = &self.config.project_config.main_branch;
+
+        if let Ok(commit) = GitManager::last_commit(repo_path, branch) {
+            entry.last_commit_time = Some(commit.time);
+            entry.last_commit_subject = Some(commit.subject);
+        }
+
+        if branch != main_branch
+            && let Ok((ahead, behind)) = GitManager::ahead_behind(repo_path, branch, main_branch)
+        {
+            entry.ahead_main = Some(ahead);
+            entry.behind_main = Some(behind);
+            // Same rule as prune: a branch that never moved has no work to be merged
+            entry.merged =
+                Some(ahead == 0 && GitManager::branch_has_moved(repo_path, branch).unwrap_or(true));
+        }
+
+        if let Ok(Some((ahead, behind))) = GitManager::upstream_ahead_behind(repo_path, branch) {
+            entry.ahead_upstream = Some(ahead);
+            entry.behind_upstream = Some(behind);
+        }
+    }
+
+    /// Look up each branch's pull request, leaving it empty when the forge can't be reached
+    fn fill_pull_requests(&self, data: &mut [WorktreeDisplayData], repo_path: &Path) {
+        let forge = match forge::forge_for(&self.config.project_config.forge, repo_path) {
+            Ok(forge) => forge,
+            Err(e) => {
+                warn!("Cannot look up pull requests: {}", e);
+                return;
+            }
+        };
+
+        for entry in data.iter_mut() {
+            let Some(branch) = &entry.branch else {
+                continue;
+            };
+            match forge.find_pull_request(branch) {
+                Ok(pr) => entry.pull_request = pr,
+                Err(e) => warn!("Cannot look up pull request for '{}': {}", branch, e),
+            }
+        }
+    }
+
+    /// Validate the requested columns, falling back to the defaults
+    fn resolve_columns(&self, options: &ListOptions) -> Result<Vec<String>> {
+        let Some(columns) = &options.columns else {
+            let mut columns: Vec<String> = DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect();
+            if options.pr {
+                columns.insert(columns.len() - 1, "pr".to_string());
+            }
+            if !self.config.project_config.services.is_empty() {
+                columns.insert(columns.len() - 1, "services".to_string());
+            }
+            if self.config.project_config.compose.project.is_some() {
+                columns.insert(columns.len() - 1, "compose".to_string());
+            }
+            return Ok(columns);
+        };
+
+        for column in columns {
+            let is_variable = self
+                .config
+                .project_config
+                .variables
+                .iter()
+                .any(|v| v.name == *column);
+            if !is_variable && !KNOWN_COLUMNS.contains(&column.as_str()) {
+                anyhow::bail!(
+                    "Unknown column '{}'. Use one of: {}, or a variable name",
+                    column,
+                    KNOWN_COLUMNS.join(", ")
+                );
+            }
+        }
+
+        Ok(columns.clone())
+    }
+}
+
+fn compile_filter(options: &ListOptions) -> Result<Option<Regex>> {
+    options
+        .filter
+        .as_deref()
+        .map(|pattern| {
+            Regex::new(pattern).with_context(|| format!("Invalid filter regex: {}", pattern))
+        })
+        .transpose()
+}
+
+fn now_seconds() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Built-in columns are lowercase, so variables like PATH or NAME can still be shown
+fn column_header(column: &str) -> String {
+    match column {
+        "name" => "Name".to_string(),
+        "branch" => "Branch".to_string(),
+        "path" => "Path".to_string(),
+        "status" => "Status".to_string(),
+        "dirty" => "Dirty".to_string(),
+        "main" => "Main".to_string(),
+        "upstream" => "Upstream".to_string(),
+        "age" => "Age".to_string(),
+        "subject" => "Subject".to_string(),
+        "pr" => "PR".to_string(),
+        "services" => "Services".to_string(),
+        "compose" => "Compose".to_string(),
+        "values" => "Values".to_string(),
+        _ => column.to_string(),
+    }
+}
+
+fn column_cell(data: &WorktreeDisplayData, column: &str, now: i64) -> String {
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+
+    match column {
+        "name" => data.name.clone(),
+        "branch" => or_dash(data.branch.clone()),
+        "path" => data.path.display().to_string(),
+        "status" => match &data.operation {
+            Some(operation) => format!("{} ({})", data.status, operation),
+            None => data.status.clone(),
+        },
+        "dirty" => or_dash(
+            data.dirty
+                .map(|d| if d { "dirty" } else { "clean" }.to_string()),
+        ),
+        "main" => or_dash(format_ahead_behind(data.ahead_main, data.behind_main)),
+        "upstream" => or_dash(format_ahead_behind(
+            data.ahead_upstream,
+            data.behind_upstream,
+        )),
+        "age" => or_dash(data.last_commit_time.map(|t| format_age(now - t))),
+        "subject" => or_dash(data.last_commit_subject.as_deref().map(truncate_subject)),
+        "pr" => or_dash(
+            data.pull_request
+                .as_ref()
+                .map(|pr| format!("#{} {}", pr.number, pr.state)),
+        ),
+        "services" if !data.services.is_empty() => data
+            .services
+            .iter()
+            .map(|service| {
+                let state = if service.running {
+                    "running"
+                } else {
+                    "stopped"
+                };
+                format!("{}:{}", service.name, state)
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        "services" => "-".to_string(),
+        "compose" if !data.containers.is_empty() => {
+            let running = data
+                .containers
+                .iter()
+                .filter(|container| container.state == "running")
+                .count();
+            format!("{}/{} running", running, data.containers.len())
+        }
+        "compose" => "-".to_string(),
+        "values" if !data.values.is_empty() => data.values_display.clone(),
+        "values" => "none".to_string(),
+        _ => or_dash(data.values.get(column).cloned()),
+    }
+}
+
+fn format_row(cells: &[String], widths: &[usize]) -> String {
+    let line = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join("  ");
+    line.trim_end().to_string()
+}
+
+fn format_ahead_behind(ahead: Option<usize>, behind: Option<usize>) -> Option<String> {
+    match (ahead, behind) {
+        (Some(0), Some(0)) => Some("=".to_string()),
+        (Some(ahead), Some(behind)) => Some(format!("+{} -{}", ahead, behind)),
+        _ => None,
+    }
+}
+
+/// Format a duration in seconds as a compact age like `5m`, `3h` or `2w`
+fn format_age(seconds: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+
+    let seconds = seconds.max(0);
+    if seconds < MINUTE {
+        "now".to_string()
+    } else if seconds < HOUR {
+        format!("{}m", seconds / MINUTE)
+    } else if seconds < DAY {
+        format!("{}h", seconds / HOUR)
+    } else if seconds < 14 * DAY {
+        format!("{}d", seconds / DAY)
+    } else if seconds < 60 * DAY {
+        format!("{}w", seconds / (7 * DAY))
+    } else if seconds < 365 * DAY {
+        format!("{}mo", seconds / (30 * DAY))
+    } else {
+        format!("{}y", seconds / (365 * DAY))
+    }
+}
+
+fn truncate_subject(subject: &str) -> String {
+    if subject.chars().count() <= MAX_SUBJECT_WIDTH {
+        subject.to_string()
+    } else {
+        let truncated: String = subject.chars().take(MAX_SUBJECT_WIDTH - 3).collect();
+        format!("{}...", truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(-5), "now");
+        assert_eq!(format_age(30), "now");
+        assert_eq!(format_age(5 * 60), "5m");
+        assert_eq!(format_age(3 * 60 * 60), "3h");
+        assert_eq!(format_age(3 * 24 * 60 * 60), "3d");
+        assert_eq!(format_age(21 * 24 * 60 * 60), "3w");
+        assert_eq!(format_age(90 * 24 * 60 * 60), "3mo");
+        assert_eq!(format_age(800 * 24 * 60 * 60), "2y");
+    }
+
+    #[test]
+    fn test_format_row_pads_to_widths() {
+        let cells = vec!["a".to_string(), "bb".to_string(), "c".to_string()];
+        assert_eq!(format_row(&cells, &[3, 4, 5]), "a    bb    c");
+        assert_eq!(
+            format_ahead_behind(Some(2), Some(1)).as_deref(),
+            Some("+2 -1")
+        );
+        assert_eq!(format_ahead_behind(Some(0), Some(0)).as_deref(), Some("="));
+        assert_eq!(format_ahead_behind(None, None), None);
+    }
+
+    #[test]
+    fn test_variables_named_like_builtin_columns() {
+        let data = WorktreeDisplayData {
+            name: "feature".to_string(),
+            path: PathBuf::from("/repo/.vibetree/branches/feature"),
+            values: HashMap::from([
+                ("PATH".to_string(), "/opt/bin".to_string()),
+                ("NAME".to_string(), "app".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(column_cell(&data, "name", 0), "feature");
+        assert_eq!(column_cell(&data, "NAME", 0), "app");
+        assert_eq!(column_cell(&data, "PATH", 0), "/opt/bin");
+        assert_eq!(column_header("PATH"), "PATH");
+        assert_eq!(column_header("path"), "Path");
+
+        // Only the built-in column looks up pull requests
+        let options = |column: &str| ListOptions {
+            columns: Some(vec![column.to_string()]),
+            ..Default::default()
+        };
+        assert!(options("pr").wants_pull_requests());
+        assert!(!options("PR").wants_pull_requests());
+    }
 }
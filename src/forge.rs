@@ -0,0 +1,472 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::{ForgeConfig, ForgeMethod};
+use crate::git::GitManager;
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrState {
+    Open,
+    Closed,
+    Merged,
+}
+
+impl fmt::Display for PrState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrState::Open => write!(f, "open"),
+            PrState::Closed => write!(f, "closed"),
+            PrState::Merged => write!(f, "merged"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub url: String,
+    pub title: String,
+    pub state: PrState,
+    pub draft: bool,
+}
+
+/// A pull request to be created
+#[derive(Debug, Clone)]
+pub struct NewPullRequest {
+    pub head: String,
+    pub base: String,
+    pub title: String,
+    pub body: String,
+    pub draft: bool,
+}
+
+/// A code host that pull requests can be opened on
+pub trait Forge {
+    /// The most relevant pull request for a branch, preferring open ones
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>>;
+
+    fn create_pull_request(&self, request: &NewPullRequest) -> Result<PullRequest>;
+}
+
+/// Pick the forge for a repository from the `[forge]` configuration
+pub fn forge_for(config: &ForgeConfig, repo_path: &Path) -> Result<Box<dyn Forge>> {
+    let token = std::env::var("GH_TOKEN")
+        .or_else(|_| std::env::var("GITHUB_TOKEN"))
+        .ok()
+        .filter(|token| !token.is_empty());
+
+    // Branches are pushed to the remote, which may be a fork of the configured repository
+    let remote_repository = GitManager::remote_url(repo_path, config.remote())?
+        .as_deref()
+        .and_then(parse_github_repository);
+    let repository = config.repository.clone().or(remote_repository.clone());
+
+    let method = config
+        .method
+        .unwrap_or(if config.api_url.is_some() || token.is_some() {
+            ForgeMethod::Api
+        } else {
+            ForgeMethod::Gh
+        });
+
+    match method {
+        ForgeMethod::Gh => Ok(Box::new(GhCli {
+            repo_path: repo_path.to_path_buf(),
+            repository,
+        })),
+        ForgeMethod::Api => {
+            let repository = repository.with_context(|| {
+                format!(
+                    "Cannot determine the GitHub repository from remote '{}'; set repository in [forge]",
+                    config.remote()
+                )
+            })?;
+            let mut api = GitHubApi::new(
+                config.api_url.as_deref().unwrap_or(DEFAULT_API_URL),
+                &repository,
+                token,
+            );
+            if let Some(owner) = remote_repository
+                .as_deref()
+                .and_then(|remote| remote.split_once('/'))
+                .map(|(owner, _)| owner)
+            {
+                api = api.with_head_owner(owner);
+            }
+            Ok(Box::new(api))
+        }
+    }
+}
+
+/// Extract "owner/name" from a GitHub remote URL
+pub fn parse_github_repository(url: &str) -> Option<String> {
+    let url = url.trim().trim_end_matches('/');
+    let path = if let Some((_, rest)) = url.split_once("://") {
+        // https://github.com/owner/name, ssh://git@github.com/owner/name
+        rest.split_once('/')?.1
+    } else {
+        // git@github.com:owner/name
+        url.split_once(':')?.1
+    };
+
+    let path = path.trim_end_matches(".git");
+    let mut parts = path.rsplitn(3, '/');
+    let name = parts.next().filter(|s| !s.is_empty())?;
+    let owner = parts.next().filter(|s| !s.is_empty())?;
+    Some(format!("{}/{}", owner, name))
+}
+
+/// Open a URL in the user's browser
+pub fn open_in_browser(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    let status = Command::new(opener)
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run '{}'", opener))?;
+
+    if !status.success() {
+        anyhow::bail!("'{}' failed to open {}", opener, url);
+    }
+    Ok(())
+}
+
+/// GitHub through the `gh` command-line tool
+pub struct GhCli {
+    repo_path: PathBuf,
+    repository: Option<String>,
+}
+
+impl GhCli {
+    fn gh(&self, args: &[&str]) -> Result<std::process::Output> {
+        let mut command = Command::new("gh");
+        command.args(args).current_dir(&self.repo_path);
+        if let Some(repository) = &self.repository {
+            command.args(["--repo", repository]);
+        }
+        command
+            .output()
+            .context("Failed to run gh; install the GitHub CLI or set method = \"api\" in [forge]")
+    }
+}
+
+impl Forge for GhCli {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let output = self.gh(&[
+            "pr",
+            "view",
+            branch,
+            "--json",
+            "number,url,title,state,isDraft",
+        ])?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("no pull requests found") {
+                return Ok(None);
+            }
+            anyhow::bail!("gh pr view failed: {}", stderr.trim());
+        }
+
+        let json: Value =
+            serde_json::from_slice(&output.stdout).context("Failed to parse gh pr view output")?;
+        let state = match json["state"].as_str().unwrap_or_default() {
+            "MERGED" => PrState::Merged,
+            "CLOSED" => PrState::Closed,
+            _ => PrState::Open,
+        };
+
+        Ok(Some(PullRequest {
+            number: json["number"].as_u64().unwrap_or_default(),
+            url: json["url"].as_str().unwrap_or_default().to_string(),
+            title: json["title"].as_str().unwrap_or_default().to_string(),
+            state,
+            draft: json["isDraft"].as_bool().unwrap_or(false),
+        }))
+    }
+
+    fn create_pull_request(&self, request: &NewPullRequest) -> Result<PullRequest> {
+        let mut args = vec![
+            "pr",
+            "create",
+            "--head",
+            &request.head,
+            "--base",
+            &request.base,
+            "--title",
+            &request.title,
+            "--body",
+            &request.body,
+        ];
+        if request.draft {
+            args.push("--draft");
+        }
+
+        let output = self.gh(&args)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("gh pr create failed: {}", stderr.trim());
+        }
+
+        self.find_pull_request(&request.head)?
+            .context("Pull request was created but could not be found")
+    }
+}
+
+/// GitHub through its REST API, using curl for HTTP
+///
+/// The base URL is configurable for GitHub Enterprise, and so tests can point
+/// it at a local server.
+pub struct GitHubApi {
+    base_url: String,
+    /// "owner/name"
+    repository: String,
+    /// Owner of the repository branches are pushed to, when it is a fork
+    head_owner: Option<String>,
+    token: Option<String>,
+}
+
+impl GitHubApi {
+    pub fn new(base_url: &str, repository: &str, token: Option<String>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            repository: repository.to_string(),
+            head_owner: None,
+            token,
+        }
+    }
+
+    /// Look for pull requests from branches pushed to `owner`'s fork
+    pub fn with_head_owner(mut self, owner: &str) -> Self {
+        self.head_owner = Some(owner.to_string());
+        self
+    }
+
+    fn owner(&self) -> &str {
+        self.repository.split('/').next().unwrap_or_default()
+    }
+
+    /// "owner:branch", as the API names the head of a pull request
+    fn qualified_head(&self, branch: &str) -> String {
+        format!(
+            "{}:{}",
+            self.head_owner.as_deref().unwrap_or(self.owner()),
+            branch
+        )
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value> {
+        let url = format!("{}{}", self.base_url, path);
+
+        let mut command = Command::new("curl");
+        command.args([
+            "--silent",
+            "--show-error",
+            "--request",
+            method,
+            "--header",
+            "Accept: application/vnd.github+json",
+            "--header",
+            "X-GitHub-Api-Version: 2022-11-28",
+            "--header",
+            "Expect:",
+            // The token goes through stdin so it never shows up in the process list
+            "--config",
+            "-",
+            "--write-out",
+            "\n%{http_code}",
+        ]);
+        if let Some(body) = body {
+            command.args([
+                "--header",
+                "Content-Type: application/json",
+                "--data-binary",
+                &body.to_string(),
+            ]);
+        }
+        command
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = command.spawn().context("Failed to run curl")?;
+        {
+            let mut stdin = child.stdin.take().context("Failed to open curl stdin")?;
+            if let Some(token) = &self.token {
+                writeln!(stdin, "header = \"Authorization: Bearer {}\"", token)
+                    .context("Failed to pass token to curl")?;
+            }
+        }
+        let output = child.wait_with_output().context("Failed to run curl")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Request to {} failed: {}", url, stderr.trim());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        let status: u16 = status.trim().parse().unwrap_or(0);
+        let json: Value = if body.trim().is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(body)
+                .with_context(|| format!("Invalid JSON in response from {}", url))?
+        };
+
+        if !(200..300).contains(&status) {
+            let message = json["message"].as_str().unwrap_or("unexpected response");
+            anyhow::bail!("GitHub API returned {} for {}: {}", status, url, message);
+        }
+
+        Ok(json)
+    }
+}
+
+impl Forge for GitHubApi {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let json = self.request(
+            "GET",
+            &format!(
+                "/repos/{}/pulls?state=all&head={}",
+                self.repository,
+                encode_query(&self.qualified_head(branch))
+            ),
+            None,
+        )?;
+
+        let mut pulls: Vec<PullRequest> = json
+            .as_array()
+            .map(|pulls| pulls.iter().map(parse_api_pull_request).collect())
+            .unwrap_or_default();
+
+        // Prefer an open pull request over older closed ones
+        pulls.sort_by_key(|pr| (pr.state != PrState::Open, std::cmp::Reverse(pr.number)));
+        Ok(pulls.into_iter().next())
+    }
+
+    fn create_pull_request(&self, request: &NewPullRequest) -> Result<PullRequest> {
+        // A branch in a fork has to be qualified with the fork's owner
+        let head = match &self.head_owner {
+            Some(owner) if owner != self.owner() => self.qualified_head(&request.head),
+            _ => request.head.clone(),
+        };
+        let body = serde_json::json!({
+            "title": request.title,
+            "head": head,
+            "base": request.base,
+            "body": request.body,
+            "draft": request.draft,
+        });
+        let json = self.request(
+            "POST",
+            &format!("/repos/{}/pulls", self.repository),
+            Some(&body),
+        )?;
+        Ok(parse_api_pull_request(&json))
+    }
+}
+
+fn parse_api_pull_request(json: &Value) -> PullRequest {
+    let state = if !json["merged_at"].is_null() {
+        PrState::Merged
+    } else if json["state"].as_str() == Some("closed") {
+        PrState::Closed
+    } else {
+        PrState::Open
+    };
+
+    PullRequest {
+        number: json["number"].as_u64().unwrap_or_default(),
+        url: json["html_url"].as_str().unwrap_or_default().to_string(),
+        title: json["title"].as_str().unwrap_or_default().to_string(),
+        state,
+        draft: json["draft"].as_bool().unwrap_or(false),
+    }
+}
+
+/// Percent-encode a query parameter value
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b':' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_github_repository() {
+        for url in [
+            "git@github.com:acme/widgets.git",
+            "https://github.com/acme/widgets",
+            "https://github.com/acme/widgets.git/",
+            "ssh://git@github.com/acme/widgets.git",
+        ] {
+            assert_eq!(
+                parse_github_repository(url).as_deref(),
+                Some("acme/widgets"),
+                "{}",
+                url
+            );
+        }
+        assert_eq!(parse_github_repository("/srv/git/widgets"), None);
+    }
+
+    #[test]
+    fn test_parse_api_pull_request_state() {
+        let open = serde_json::json!({
+            "number": 3, "html_url": "https://github.com/acme/widgets/pull/3",
+            "title": "Add parser", "state": "open", "draft": true, "merged_at": null
+        });
+        let pr = parse_api_pull_request(&open);
+        assert_eq!(pr.number, 3);
+        assert_eq!(pr.state, PrState::Open);
+        assert!(pr.draft);
+
+        let merged = serde_json::json!({
+            "number": 4, "state": "closed", "merged_at": "2024-01-01T00:00:00Z"
+        });
+        assert_eq!(parse_api_pull_request(&merged).state, PrState::Merged);
+
+        let closed = serde_json::json!({ "number": 5, "state": "closed", "merged_at": null });
+        assert_eq!(parse_api_pull_request(&closed).state, PrState::Closed);
+    }
+
+    #[test]
+    fn test_head_of_fork_pull_requests() {
+        let api = GitHubApi::new(DEFAULT_API_URL, "acme/widgets", None);
+        assert_eq!(api.qualified_head("feature"), "acme:feature");
+
+        let fork = api.with_head_owner("alice");
+        assert_eq!(fork.qualified_head("feature"), "alice:feature");
+    }
+
+    #[test]
+    fn test_encode_query() {
+        assert_eq!(encode_query("acme:feature/x"), "acme:feature/x");
+        assert_eq!(encode_query("acme:fix #1&2"), "acme:fix%20%231%262");
+    }
+}
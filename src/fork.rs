@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::CopyMode;
+use crate::git::GitManager;
+
+/// Options for `vibetree fork`
+#[derive(Debug, Clone, Default)]
+pub struct ForkOptions {
+    /// Copy the source's uncommitted changes, including untracked files
+    pub with_changes: bool,
+    /// Copy the source's gitignored files, like node_modules or target/
+    pub copy_ignored: bool,
+    pub copy_mode: CopyMode,
+    /// Skip the `[fork]` hooks
+    pub no_hooks: bool,
+}
+
+/// Copy the uncommitted changes of `source` into `target` without touching `source`
+///
+/// Tracked changes keep what was staged; untracked files are copied as they are.
+/// Returns whether there was anything to copy.
+pub fn copy_changes(source: &Path, target: &Path) -> Result<bool> {
+    let mut copied = false;
+    if let Some(stash) = GitManager::stash_create(source)? {
+        GitManager::apply_stash(target, &stash)?;
+        copied = true;
+    }
+
+    for file in GitManager::untracked_files(source, false)? {
+        if is_excluded(&file, &[".vibetree"]) {
+            continue;
+        }
+        copy_path(&source.join(&file), &target.join(&file), CopyMode::Copy)?;
+        copied = true;
+    }
+    Ok(copied)
+}
+
+/// The gitignored files and directories of `source`, skipping paths under any of `exclude`
+pub fn ignored_paths(source: &Path, exclude: &[&str]) -> Result<Vec<String>> {
+    Ok(GitManager::untracked_files(source, true)?
+        .into_iter()
+        .map(|entry| entry.trim_end_matches('/').to_string())
+        .filter(|entry| !is_excluded(entry, exclude))
+        .collect())
+}
+
+/// Copy `paths`, relative to `source`, into `target`
+pub fn copy_ignored(source: &Path, target: &Path, mode: CopyMode, paths: &[String]) -> Result<()> {
+    for path in paths {
+        debug!("Copying {}", path);
+        copy_path(&source.join(path), &target.join(path), mode)?;
+    }
+    Ok(())
+}
+
+fn is_excluded(path: &str, exclude: &[&str]) -> bool {
+    let path = path.trim_end_matches('/');
+    exclude.iter().any(|excluded| {
+        let excluded = excluded.trim_end_matches('/');
+        path == excluded
+            || path.starts_with(&format!("{}/", excluded))
+            || excluded.starts_with(&format!("{}/", path))
+    })
+}
+
+/// Copy a file or directory tree, creating the destination's parent directories
+pub fn copy_path(from: &Path, to: &Path, mode: CopyMode) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    match mode {
+        CopyMode::Reflink => reflink(from, to),
+        CopyMode::Hardlink | CopyMode::Copy => copy_recursive(from, to, mode),
+    }
+}
+
+/// Clone with copy-on-write where the filesystem supports it, letting `cp` fall back to copying
+fn reflink(from: &Path, to: &Path) -> Result<()> {
+    let mut cp = Command::new("cp");
+    if cfg!(target_os = "macos") {
+        cp.args(["-c", "-R"]);
+    } else {
+        cp.args(["-R", "--reflink=auto"]);
+    }
+    let output = cp
+        .arg(from)
+        .arg(to)
+        .output()
+        .context("Failed to execute cp")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Failed to copy {}: {}", from.display(), stderr);
+    }
+    Ok(())
+}
+
+fn copy_recursive(from: &Path, to: &Path, mode: CopyMode) -> Result<()> {
+    let metadata =
+        fs::symlink_metadata(from).with_context(|| format!("Failed to read {}", from.display()))?;
+
+    if metadata.is_dir() {
+        fs::create_dir_all(to)
+            .with_context(|| format!("Failed to create directory {}", to.display()))?;
+        for entry in
+            fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))?
+        {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()), mode)?;
+        }
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    if metadata.file_type().is_symlink() {
+        let link = fs::read_link(from)
+            .with_context(|| format!("Failed to read link {}", from.display()))?;
+        return std::os::unix::fs::symlink(&link, to)
+            .with_context(|| format!("Failed to create link {}", to.display()));
+    }
+
+    match mode {
+        CopyMode::Hardlink => {
+            fs::hard_link(from, to).with_context(|| format!("Failed to link {}", from.display()))
+        }
+        _ => fs::copy(from, to)
+            .map(|_| ())
+            .with_context(|| format!("Failed to copy {}", from.display())),
+    }
+}
+
+/// Run a fork hook with the shell in the new worktree
+pub fn run_hook(command: &str, dir: &Path) -> Result<()> {
+    info!("Running fork hook: {}", command);
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to run fork hook: {}", command))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Fork hook failed: {}\n{}", command, stderr.trim_end());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_path_modes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(source.join("nested"))?;
+        fs::write(source.join("nested").join("file.txt"), "contents")?;
+
+        for mode in [CopyMode::Reflink, CopyMode::Hardlink, CopyMode::Copy] {
+            let target = temp_dir.path().join(format!("{:?}", mode)).join("copy");
+            copy_path(&source, &target, mode)?;
+            assert_eq!(
+                fs::read_to_string(target.join("nested").join("file.txt"))?,
+                "contents"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let exclude = [".vibetree", "branches/"];
+        assert!(is_excluded(".vibetree/", &exclude));
+        assert!(is_excluded(".vibetree/env", &exclude));
+        assert!(is_excluded("branches/feature", &exclude));
+        assert!(!is_excluded("node_modules/", &exclude));
+        assert!(!is_excluded(".vibetreerc", &exclude));
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use git2::Repository;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
 pub struct GitManager;
@@ -54,9 +55,453 @@ impl GitManager {
 
     /// Test if a merge would succeed without conflicts
     pub fn can_merge_cleanly(repo_path: &Path, branch: &str, target: &str) -> Result<bool> {
+        Ok(Self::predict_merge_conflicts(repo_path, branch, target)?.is_clean())
+    }
+
+    /// Test if a squash merge would succeed without conflicts
+    ///
+    /// A squash merge combines the same trees as a regular merge, so it conflicts
+    /// exactly when the merge would.
+    pub fn can_squash_cleanly(repo_path: &Path, branch: &str, target: &str) -> Result<bool> {
+        Self::can_merge_cleanly(repo_path, branch, target)
+    }
+
+    /// Test if a rebase would succeed without conflicts
+    pub fn can_rebase_cleanly(repo_path: &Path, branch: &str, target: &str) -> Result<bool> {
+        Ok(Self::predict_rebase_conflicts(repo_path, branch, target)?.is_clean())
+    }
+
+    /// Merge `branch` into `target` in memory and report any conflicting paths
+    ///
+    /// Neither the working tree nor any ref is touched.
+    pub fn predict_merge_conflicts(
+        repo_path: &Path,
+        branch: &str,
+        target: &str,
+    ) -> Result<ConflictPrediction> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+
+        let ours = Self::peel_commit(&repo, target)?;
+        let theirs = Self::peel_commit(&repo, branch)?;
+
+        let index = repo.merge_commits(&ours, &theirs, None).with_context(|| {
+            format!("Failed to simulate merging '{}' into '{}'", branch, target)
+        })?;
+
+        Ok(ConflictPrediction {
+            paths: Self::conflicting_paths(&index)?,
+            commit: None,
+        })
+    }
+
+    /// Replay each commit of `branch` onto `target` in memory, as a rebase would,
+    /// and report the first commit that conflicts
+    ///
+    /// Merge commits, and commits whose change is already upstream under another
+    /// id (like cherry-picks), are skipped like `git rebase` does by default. Only
+    /// tree objects are written to the object database; the working tree and refs
+    /// are never touched.
+    pub fn predict_rebase_conflicts(
+        repo_path: &Path,
+        branch: &str,
+        target: &str,
+    ) -> Result<ConflictPrediction> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+
+        let branch_commit = Self::peel_commit(&repo, branch)?;
+        let target_commit = Self::peel_commit(&repo, target)?;
+
+        let mut revwalk = repo.revwalk().context("Failed to walk commits")?;
+        revwalk.push(branch_commit.id())?;
+        revwalk.hide(target_commit.id())?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+        let upstream = Self::patch_ids(&repo, &target_commit, &branch_commit)?;
+
+        let mut onto = target_commit.tree()?;
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            if commit.parent_count() > 1
+                || (!upstream.is_empty() && upstream.contains(&Self::patch_id(&repo, &commit)?))
+            {
+                continue;
+            }
+
+            let base = match commit.parent(0) {
+                Ok(parent) => parent.tree()?,
+                Err(_) => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+            };
+
+            // Cherry-picking a commit is a three-way merge against its parent
+            let mut index = repo
+                .merge_trees(&base, &onto, &commit.tree()?, None)
+                .with_context(|| format!("Failed to simulate applying {}", commit.id()))?;
+
+            let paths = Self::conflicting_paths(&index)?;
+            if !paths.is_empty() {
+                return Ok(ConflictPrediction {
+                    paths,
+                    commit: Some(format!(
+                        "{} {}",
+                        &commit.id().to_string()[..7],
+                        commit.summary().unwrap_or_default()
+                    )),
+                });
+            }
+
+            let tree_id = index
+                .write_tree_to(&repo)
+                .context("Failed to write simulated tree")?;
+            onto = repo.find_tree(tree_id)?;
+        }
+
+        Ok(ConflictPrediction::default())
+    }
+
+    /// Patch ids of the non-merge commits on `from` that are not on `hide`, like `git cherry`
+    fn patch_ids(
+        repo: &Repository,
+        from: &git2::Commit,
+        hide: &git2::Commit,
+    ) -> Result<HashSet<git2::Oid>> {
+        let mut revwalk = repo.revwalk().context("Failed to walk commits")?;
+        revwalk.push(from.id())?;
+        revwalk.hide(hide.id())?;
+
+        let mut ids = HashSet::new();
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            if commit.parent_count() <= 1 {
+                ids.insert(Self::patch_id(repo, &commit)?);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Id of a commit's change, the same for every commit that makes the same change
+    fn patch_id(repo: &Repository, commit: &git2::Commit) -> Result<git2::Oid> {
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)
+            .with_context(|| format!("Failed to diff {}", commit.id()))?;
+        diff.patchid(None)
+            .with_context(|| format!("Failed to compute patch id of {}", commit.id()))
+    }
+
+    fn peel_commit<'r>(repo: &'r Repository, revision: &str) -> Result<git2::Commit<'r>> {
+        repo.revparse_single(revision)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("Failed to resolve '{}'", revision))
+    }
+
+    fn conflicting_paths(index: &git2::Index) -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for conflict in index
+            .conflicts()
+            .context("Failed to read merge conflicts")?
+        {
+            let conflict = conflict.context("Failed to read merge conflict")?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+
+    /// Find the worktree where a branch is checked out, if any
+    pub fn find_worktree_for_branch(repo_path: &Path, branch: &str) -> Result<Option<PathBuf>> {
+        Ok(Self::discover_worktrees(repo_path)?
+            .into_iter()
+            .find(|wt| !wt.is_bare && wt.path.exists() && wt.branch.as_deref() == Some(branch))
+            .map(|wt| wt.path))
+    }
+
+    /// Merge `branch` into `target` by updating the target ref directly, for
+    /// targets that aren't checked out anywhere
+    ///
+    /// Fast-forwards when possible unless `no_ff` is set, otherwise writes a merge
+    /// commit built in memory. With `squash`, always writes a single-parent commit.
+    pub fn merge_into_ref(
+        repo_path: &Path,
+        branch: &str,
+        target: &str,
+        message: &str,
+        squash: bool,
+        no_ff: bool,
+    ) -> Result<()> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+
+        let target_ref = format!("refs/heads/{}", target);
+        let target_commit = Self::peel_commit(&repo, &target_ref)?;
+        let branch_commit = Self::peel_commit(&repo, branch)?;
+
+        let fast_forward = repo
+            .graph_descendant_of(branch_commit.id(), target_commit.id())
+            .context("Failed to compare commit graphs")?;
+
+        let new_id = if fast_forward && !squash && !no_ff {
+            branch_commit.id()
+        } else {
+            let mut index = repo
+                .merge_commits(&target_commit, &branch_commit, None)
+                .with_context(|| format!("Failed to merge '{}' into '{}'", branch, target))?;
+            if index.has_conflicts() {
+                anyhow::bail!("Merge failed: '{}' conflicts with '{}'", branch, target);
+            }
+
+            let tree = index
+                .write_tree_to(&repo)
+                .context("Failed to write merged tree")?;
+
+            if squash {
+                Self::create_commit(&repo, repo_path, tree, &[&target_commit], message)?
+            } else {
+                Self::create_commit(
+                    &repo,
+                    repo_path,
+                    tree,
+                    &[&target_commit, &branch_commit],
+                    message,
+                )?
+            }
+        };
+
+        Self::update_branch_ref(&repo, target, new_id, target_commit.id(), branch)
+    }
+
+    /// Write a commit without updating any ref
+    ///
+    /// git2 can't sign commits, so when `commit.gpgsign` is set this goes through
+    /// `git commit-tree`, which signs the same way `git commit` would.
+    fn create_commit(
+        repo: &Repository,
+        repo_path: &Path,
+        tree: git2::Oid,
+        parents: &[&git2::Commit],
+        message: &str,
+    ) -> Result<git2::Oid> {
+        if Self::commit_signing_enabled(repo) {
+            let mut args = vec!["commit-tree".to_string(), tree.to_string()];
+            for parent in parents {
+                args.push("-p".to_string());
+                args.push(parent.id().to_string());
+            }
+            args.push("-m".to_string());
+            args.push(message.to_string());
+
+            let output = std::process::Command::new("git")
+                .args(&args)
+                .current_dir(repo_path)
+                .output()
+                .context("Failed to execute git commit-tree")?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!("Failed to create signed commit: {}", stderr);
+            }
+
+            let id = String::from_utf8_lossy(&output.stdout);
+            return git2::Oid::from_str(id.trim())
+                .context("Invalid commit id from git commit-tree");
+        }
+
+        let tree = repo.find_tree(tree)?;
+        let signature = repo
+            .signature()
+            .context("Failed to determine commit author from git config")?;
+        repo.commit(None, &signature, &signature, message, &tree, parents)
+            .context("Failed to create merge commit")
+    }
+
+    /// Whether the user's git config asks for commits to be signed
+    fn commit_signing_enabled(repo: &Repository) -> bool {
+        repo.config()
+            .and_then(|config| config.get_bool("commit.gpgsign"))
+            .unwrap_or(false)
+    }
+
+    /// Whether merging `branch` into `target` can fast-forward
+    pub fn can_fast_forward(repo_path: &Path, branch: &str, target: &str) -> Result<bool> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+
+        let target_commit = Self::peel_commit(&repo, &format!("refs/heads/{}", target))?;
+        let branch_commit = Self::peel_commit(&repo, branch)?;
+
+        repo.graph_descendant_of(branch_commit.id(), target_commit.id())
+            .context("Failed to compare commit graphs")
+    }
+
+    /// Commits on `branch` that are not on `target`, oldest first
+    pub fn branch_commits(
+        repo_path: &Path,
+        branch: &str,
+        target: &str,
+    ) -> Result<Vec<BranchCommit>> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+
+        let mut revwalk = repo.revwalk().context("Failed to walk commits")?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        revwalk.push(Self::peel_commit(&repo, branch)?.id())?;
+        revwalk.hide(Self::peel_commit(&repo, &format!("refs/heads/{}", target))?.id())?;
+
+        let mut commits = Vec::new();
+        for id in revwalk {
+            let commit = repo.find_commit(id.context("Failed to walk commits")?)?;
+            let author = commit.author();
+            commits.push(BranchCommit {
+                subject: commit.summary().unwrap_or_default().to_string(),
+                author_name: author.name().unwrap_or_default().to_string(),
+                author_email: author.email().unwrap_or_default().to_string(),
+            });
+        }
+        Ok(commits)
+    }
+
+    /// Email of the user committing in this repository, from git config
+    pub fn user_email(repo_path: &Path) -> Option<String> {
+        let repo = Repository::open(repo_path).ok()?;
+        let signature = repo.signature().ok()?;
+        signature.email().map(str::to_string)
+    }
+
+    /// Open the user's git editor on a commit message and return the edited text
+    ///
+    /// Runs `editor` when given, otherwise respects `GIT_EDITOR`, `core.editor`,
+    /// `VISUAL` and `EDITOR`, like git does. Lines starting with `#` are dropped
+    /// from the result.
+    pub fn edit_message(repo_path: &Path, initial: &str, editor: Option<&str>) -> Result<String> {
+        use std::process::{Command, Stdio};
+
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+        let message_path = repo.path().join("VIBETREE_MERGE_MSG");
+
+        let mut contents = initial.trim_end().to_string();
+        contents.push_str(
+            "\n\n# Please enter the commit message for the merge. Lines starting\n\
+             # with '#' will be ignored, and an empty message aborts the merge.\n",
+        );
+        std::fs::write(&message_path, contents)
+            .with_context(|| format!("Failed to write {}", message_path.display()))?;
+
+        let editor = match editor {
+            Some(editor) => editor.to_string(),
+            None => {
+                let output = Command::new("git")
+                    .args(["var", "GIT_EDITOR"])
+                    .current_dir(repo_path)
+                    .output()
+                    .context("Failed to determine git editor")?;
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    anyhow::bail!("Failed to determine git editor: {}", stderr);
+                }
+                String::from_utf8_lossy(&output.stdout).trim().to_string()
+            }
+        };
+
+        // Run through the shell, as git does, so editors with arguments work
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", editor))
+            .arg(&editor)
+            .arg(&message_path)
+            .current_dir(repo_path)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .with_context(|| format!("Failed to run editor '{}'", editor))?;
+
+        let edited = std::fs::read_to_string(&message_path)
+            .with_context(|| format!("Failed to read {}", message_path.display()));
+        let _ = std::fs::remove_file(&message_path);
+
+        if !status.success() {
+            anyhow::bail!("Editor '{}' exited with {}", editor, status);
+        }
+
+        Ok(edited?
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string())
+    }
+
+    /// Fast-forward a branch that isn't checked out anywhere to another branch's tip
+    pub fn fast_forward_ref(repo_path: &Path, target: &str, branch: &str) -> Result<()> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+
+        let target_commit = Self::peel_commit(&repo, &format!("refs/heads/{}", target))?;
+        let branch_commit = Self::peel_commit(&repo, branch)?;
+
+        if !repo
+            .graph_descendant_of(branch_commit.id(), target_commit.id())
+            .context("Failed to compare commit graphs")?
+        {
+            anyhow::bail!(
+                "Fast-forward merge failed: '{}' is not a descendant of '{}'",
+                branch,
+                target
+            );
+        }
+
+        Self::update_branch_ref(
+            &repo,
+            target,
+            branch_commit.id(),
+            target_commit.id(),
+            branch,
+        )
+    }
+
+    /// Move a branch ref, failing if it changed since `expected` was read
+    fn update_branch_ref(
+        repo: &Repository,
+        target: &str,
+        new_id: git2::Oid,
+        expected: git2::Oid,
+        branch: &str,
+    ) -> Result<()> {
+        repo.reference_matching(
+            &format!("refs/heads/{}", target),
+            new_id,
+            true,
+            expected,
+            &format!("merge {}: updated by vibetree", branch),
+        )
+        .with_context(|| format!("Failed to update branch '{}'", target))?;
+        Ok(())
+    }
+
+    /// Execute a merge
+    ///
+    /// `repo_path` is where the merge runs; pass the worktree that has `target`
+    /// checked out so the checkout is a no-op. `message` is used when a merge
+    /// commit is created.
+    pub fn merge_branch(
+        repo_path: &Path,
+        branch: &str,
+        target: &str,
+        message: &str,
+        no_ff: bool,
+    ) -> Result<()> {
         use std::process::Command;
 
-        // First, checkout the target branch
+        // Checkout target branch
         let checkout = Command::new("git")
             .args(["checkout", target])
             .current_dir(repo_path)
@@ -68,29 +513,34 @@ impl GitManager {
             anyhow::bail!("Failed to checkout {}: {}", target, stderr);
         }
 
-        // Try a merge with --no-commit --no-ff to test
+        // Execute merge
+        let mut args = vec!["merge", "--no-edit", "-m", message];
+        if no_ff {
+            args.push("--no-ff");
+        }
+        args.push(branch);
         let merge = Command::new("git")
-            .args(["merge", "--no-commit", "--no-ff", branch])
+            .args(&args)
             .current_dir(repo_path)
             .output()
-            .context("Failed to execute test merge")?;
-
-        let can_merge = merge.status.success();
+            .context("Failed to execute merge")?;
 
-        // Always abort/reset to clean up
-        let _ = Command::new("git")
-            .args(["merge", "--abort"])
-            .current_dir(repo_path)
-            .output();
+        if !merge.status.success() {
+            let stderr = String::from_utf8_lossy(&merge.stderr);
+            anyhow::bail!("Merge failed: {}", stderr);
+        }
 
-        Ok(can_merge)
+        Ok(())
     }
 
-    /// Test if a squash merge would succeed without conflicts
-    pub fn can_squash_cleanly(repo_path: &Path, branch: &str, target: &str) -> Result<bool> {
+    /// Stage a squash merge of `branch` into `target` without committing it
+    ///
+    /// Like [`GitManager::merge_branch`], this runs in the worktree at `repo_path`.
+    /// Finish with [`GitManager::commit_merge`].
+    pub fn stage_squash_merge(repo_path: &Path, branch: &str, target: &str) -> Result<()> {
         use std::process::Command;
 
-        // First, checkout the target branch
+        // Checkout target branch
         let checkout = Command::new("git")
             .args(["checkout", target])
             .current_dir(repo_path)
@@ -102,299 +552,679 @@ impl GitManager {
             anyhow::bail!("Failed to checkout {}: {}", target, stderr);
         }
 
-        // Try a squash merge with --no-commit to test
+        // Execute squash merge
         let merge = Command::new("git")
-            .args(["merge", "--squash", "--no-commit", branch])
+            .args(["merge", "--squash", branch])
+            .current_dir(repo_path)
+            .output()
+            .context("Failed to execute squash merge")?;
+
+        if !merge.status.success() {
+            let stderr = String::from_utf8_lossy(&merge.stderr);
+            anyhow::bail!("Squash merge failed: {}", stderr);
+        }
+
+        Ok(())
+    }
+
+    /// Rebase `branch` onto `target`, aborting the rebase if it fails
+    ///
+    /// Runs in the branch's worktree, or checks the branch out at the repo root
+    /// and restores the previous checkout when it has no worktree.
+    pub fn rebase_branch(
+        repo_path: &Path,
+        branch: &str,
+        target: &str,
+        worktree_path: Option<&Path>,
+    ) -> Result<()> {
+        use std::process::Command;
+
+        if let Some(worktree_path) = worktree_path {
+            // Worktree case - run rebase directly in the worktree
+            return Self::run_rebase(worktree_path, target);
+        }
+
+        // Non-worktree case - checkout first
+        let checkout = Command::new("git")
+            .args(["checkout", branch])
             .current_dir(repo_path)
             .output()
-            .context("Failed to execute test squash merge")?;
+            .context("Failed to checkout branch for rebase")?;
+
+        if !checkout.status.success() {
+            let stderr = String::from_utf8_lossy(&checkout.stderr);
+            anyhow::bail!("Failed to checkout {}: {}", branch, stderr);
+        }
 
-        let can_merge = merge.status.success();
+        let rebase = Self::run_rebase(repo_path, target);
 
-        // Reset to clean up (squash doesn't leave merge state, so use reset)
-        let _ = Command::new("git")
-            .args(["reset", "--hard", "HEAD"])
+        // Return to whatever was checked out before, which may be the target
+        let restore = Command::new("git")
+            .args(["checkout", "-"])
             .current_dir(repo_path)
-            .output();
+            .output()
+            .context("Failed to restore checkout after rebase")?;
 
-        Ok(can_merge)
+        rebase?;
+        if !restore.status.success() {
+            let stderr = String::from_utf8_lossy(&restore.stderr);
+            anyhow::bail!("Failed to restore checkout after rebase: {}", stderr);
+        }
+        Ok(())
     }
 
-    /// Test if a rebase would succeed without conflicts
+    fn run_rebase(path: &Path, target: &str) -> Result<()> {
+        use std::process::Command;
+
+        let rebase = Command::new("git")
+            .args(["rebase", target])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute rebase")?;
+
+        if !rebase.status.success() {
+            // Abort the failed rebase
+            let _ = Command::new("git")
+                .args(["rebase", "--abort"])
+                .current_dir(path)
+                .output();
+            let stderr = String::from_utf8_lossy(&rebase.stderr);
+            anyhow::bail!("Rebase failed: {}", stderr);
+        }
+        Ok(())
+    }
+
+    /// Bring a branch that was rebased onto `target` into it
     ///
-    /// For worktrees, the rebase must be run from the worktree directory since
-    /// git doesn't allow checking out a branch that's already checked out elsewhere.
-    pub fn can_rebase_cleanly(
+    /// Fast-forwards in `target_worktree`, or moves the ref when the target isn't
+    /// checked out. With `no_ff_message`, creates a merge commit instead.
+    pub fn merge_rebased_branch(
         repo_path: &Path,
         branch: &str,
         target: &str,
-        worktree_path: Option<&Path>,
-    ) -> Result<bool> {
+        target_worktree: Option<&Path>,
+        no_ff_message: Option<&str>,
+    ) -> Result<()> {
         use std::process::Command;
 
-        // Determine where to run the rebase
-        let rebase_dir = worktree_path.unwrap_or(repo_path);
+        let Some(target_worktree) = target_worktree else {
+            return match no_ff_message {
+                Some(message) => {
+                    Self::merge_into_ref(repo_path, branch, target, message, false, true)
+                }
+                None => Self::fast_forward_ref(repo_path, target, branch),
+            };
+        };
 
-        // For worktree case, the branch is already checked out there
-        // For non-worktree case, we need to checkout first (but this shouldn't happen in practice)
-        if worktree_path.is_none() {
-            // Remember current branch to restore later
-            let current_branch = Self::get_current_branch(repo_path)?;
+        if let Some(message) = no_ff_message {
+            return Self::merge_branch(target_worktree, branch, target, message, true);
+        }
 
-            // Checkout the branch to rebase
-            let checkout = Command::new("git")
-                .args(["checkout", branch])
-                .current_dir(repo_path)
+        // Fast-forward merge where the target is checked out
+        let merge = Command::new("git")
+            .args(["merge", "--ff-only", branch])
+            .current_dir(target_worktree)
+            .output()
+            .context("Failed to execute fast-forward merge")?;
+
+        if !merge.status.success() {
+            let stderr = String::from_utf8_lossy(&merge.stderr);
+            anyhow::bail!("Fast-forward merge failed: {}", stderr);
+        }
+
+        Ok(())
+    }
+
+    /// Start a merge or squash merge in `path` and leave any conflicts in place
+    ///
+    /// Nothing is committed; finish with [`GitManager::commit_merge`] or undo
+    /// with [`GitManager::abort_merge`]. Returns the conflicting paths.
+    pub fn start_merge(path: &Path, branch: &str, squash: bool) -> Result<Vec<String>> {
+        use std::process::Command;
+
+        let args: &[&str] = if squash {
+            &["merge", "--squash", branch]
+        } else {
+            &["merge", "--no-commit", "--no-ff", branch]
+        };
+        let merge = Command::new("git")
+            .args(args)
+            .current_dir(path)
+            .output()
+            .context("Failed to execute merge")?;
+
+        let conflicts = Self::unmerged_paths(path)?;
+        if !merge.status.success() && conflicts.is_empty() {
+            let stderr = String::from_utf8_lossy(&merge.stderr);
+            anyhow::bail!("Merge failed: {}", stderr);
+        }
+        Ok(conflicts)
+    }
+
+    /// Start rebasing the branch checked out at `path` and leave any conflicts in place
+    ///
+    /// Returns the conflicting paths; empty when the rebase completed.
+    pub fn start_rebase(path: &Path, target: &str) -> Result<Vec<String>> {
+        use std::process::Command;
+
+        let rebase = Command::new("git")
+            .args(["rebase", target])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute rebase")?;
+
+        let conflicts = Self::unmerged_paths(path)?;
+        if !rebase.status.success() && conflicts.is_empty() {
+            let _ = Command::new("git")
+                .args(["rebase", "--abort"])
+                .current_dir(path)
+                .output();
+            let stderr = String::from_utf8_lossy(&rebase.stderr);
+            anyhow::bail!("Rebase failed: {}", stderr);
+        }
+        Ok(conflicts)
+    }
+
+    /// Continue a rebase stopped by conflicts that have been resolved
+    ///
+    /// Returns the conflicts of the next commit if the rebase stops again.
+    pub fn continue_rebase(path: &Path) -> Result<Vec<String>> {
+        use std::process::Command;
+
+        let rebase = Command::new("git")
+            .args(["rebase", "--continue"])
+            // Keep the original commit messages instead of opening an editor
+            .env("GIT_EDITOR", "true")
+            .current_dir(path)
+            .output()
+            .context("Failed to continue rebase")?;
+
+        let conflicts = Self::unmerged_paths(path)?;
+        if !rebase.status.success() && conflicts.is_empty() {
+            let stderr = String::from_utf8_lossy(&rebase.stderr);
+            anyhow::bail!("Failed to continue rebase: {}", stderr);
+        }
+        Ok(conflicts)
+    }
+
+    /// Whether a rebase is stopped in the worktree at `path`
+    pub fn rebase_in_progress(path: &Path) -> Result<bool> {
+        let repo = Repository::open(path)
+            .with_context(|| format!("Failed to open git repository at {}", path.display()))?;
+        Ok(matches!(
+            repo.state(),
+            git2::RepositoryState::Rebase
+                | git2::RepositoryState::RebaseInteractive
+                | git2::RepositoryState::RebaseMerge
+        ))
+    }
+
+    /// Whether a merge, rebase, cherry-pick or similar is stopped in the worktree at `path`
+    pub fn operation_in_progress(path: &Path) -> Result<bool> {
+        let repo = Repository::open(path)
+            .with_context(|| format!("Failed to open git repository at {}", path.display()))?;
+        Ok(repo.state() != git2::RepositoryState::Clean)
+    }
+
+    /// Files with unresolved conflicts in the worktree at `path`
+    pub fn unmerged_paths(path: &Path) -> Result<Vec<String>> {
+        let repo = Repository::open(path)
+            .with_context(|| format!("Failed to open git repository at {}", path.display()))?;
+        let index = repo.index().context("Failed to read index")?;
+        if !index.has_conflicts() {
+            return Ok(Vec::new());
+        }
+        Self::conflicting_paths(&index)
+    }
+
+    /// Commit a merge or squash merge whose conflicts have been resolved
+    pub fn commit_merge(path: &Path, message: &str) -> Result<()> {
+        use std::process::Command;
+
+        let commit = Command::new("git")
+            .args(["commit", "--no-edit", "-m", message])
+            .current_dir(path)
+            .output()
+            .context("Failed to commit merge")?;
+
+        if !commit.status.success() {
+            let stdout = String::from_utf8_lossy(&commit.stdout);
+            let stderr = String::from_utf8_lossy(&commit.stderr);
+            anyhow::bail!("Failed to commit merge: {}{}", stderr, stdout);
+        }
+        Ok(())
+    }
+
+    /// Undo a merge or squash merge started with [`GitManager::start_merge`]
+    pub fn abort_merge(path: &Path) -> Result<()> {
+        use std::process::Command;
+
+        // Unlike `merge --abort`, this also works for squash merges, which leave no MERGE_HEAD
+        let reset = Command::new("git")
+            .args(["reset", "--merge"])
+            .current_dir(path)
+            .output()
+            .context("Failed to abort merge")?;
+
+        if !reset.status.success() {
+            let stderr = String::from_utf8_lossy(&reset.stderr);
+            anyhow::bail!("Failed to abort merge: {}", stderr);
+        }
+        Ok(())
+    }
+
+    /// Undo a rebase stopped by conflicts
+    pub fn abort_rebase(path: &Path) -> Result<()> {
+        use std::process::Command;
+
+        let abort = Command::new("git")
+            .args(["rebase", "--abort"])
+            .current_dir(path)
+            .output()
+            .context("Failed to abort rebase")?;
+
+        if !abort.status.success() {
+            let stderr = String::from_utf8_lossy(&abort.stderr);
+            anyhow::bail!("Failed to abort rebase: {}", stderr);
+        }
+        Ok(())
+    }
+
+    /// Stash the uncommitted changes in the worktree at `path`, leaving it clean
+    ///
+    /// The `.vibetree` directory is never stashed. Returns the stash commit, or
+    /// `None` when there was nothing to stash.
+    pub fn stash_changes(
+        path: &Path,
+        include_untracked: bool,
+        message: &str,
+    ) -> Result<Option<String>> {
+        use std::process::Command;
+
+        let before = Self::stash_head(path)?;
+
+        let mut args = vec!["stash", "push", "-m", message];
+        if include_untracked {
+            args.push("--include-untracked");
+        }
+        // Git refuses pathspecs naming ignored paths, and ignored paths are never stashed anyway
+        if !Self::is_ignored(path, ".vibetree")? {
+            args.extend(["--", ".", ":(exclude).vibetree"]);
+        }
+        let stash = Command::new("git")
+            .args(&args)
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git stash")?;
+
+        if !stash.status.success() {
+            let stderr = String::from_utf8_lossy(&stash.stderr);
+            anyhow::bail!("Failed to stash changes: {}", stderr);
+        }
+
+        let after = Self::stash_head(path)?;
+        Ok(if after != before { after } else { None })
+    }
+
+    /// Whether `.gitignore` rules ignore a path in the worktree at `path`
+    fn is_ignored(path: &Path, relative: &str) -> Result<bool> {
+        use std::process::Command;
+
+        let output = Command::new("git")
+            .args(["check-ignore", "--quiet", relative])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git check-ignore")?;
+
+        // Exit code 0 means ignored, 1 means not ignored
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!("git check-ignore failed: {}", stderr);
+            }
+        }
+    }
+
+    fn stash_head(path: &Path) -> Result<Option<String>> {
+        use std::process::Command;
+
+        let output = Command::new("git")
+            .args(["rev-parse", "--quiet", "--verify", "refs/stash"])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git rev-parse")?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    }
+
+    /// Record the tracked changes in the worktree at `path` as a stash commit
+    /// without touching the worktree; `None` when there are none
+    pub fn stash_create(path: &Path) -> Result<Option<String>> {
+        use std::process::Command;
+
+        let output = Command::new("git")
+            .args(["stash", "create"])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git stash create")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Failed to record changes: {}", stderr);
+        }
+
+        let stash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((!stash.is_empty()).then_some(stash))
+    }
+
+    /// Untracked files in the worktree at `path`, or with `ignored` the
+    /// gitignored ones, where wholly ignored directories are listed once
+    /// with a trailing slash
+    pub fn untracked_files(path: &Path, ignored: bool) -> Result<Vec<String>> {
+        use std::process::Command;
+
+        let mut args = vec!["ls-files", "--others", "--exclude-standard", "-z"];
+        if ignored {
+            args.extend(["--ignored", "--directory"]);
+        }
+        let output = Command::new("git")
+            .args(&args)
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git ls-files")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Failed to list untracked files: {}", stderr);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter(|file| !file.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Apply a stash commit, including what was staged, to the worktree at `path`
+    pub fn apply_stash(path: &Path, stash: &str) -> Result<()> {
+        use std::process::Command;
+
+        let apply = Command::new("git")
+            .args(["stash", "apply", "--index", stash])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git stash apply")?;
+
+        if !apply.status.success() {
+            let stderr = String::from_utf8_lossy(&apply.stderr);
+            anyhow::bail!("Failed to apply stashed changes: {}", stderr);
+        }
+        Ok(())
+    }
+
+    /// Remove a stash commit from the stash list, if it is still there
+    pub fn drop_stash(path: &Path, stash: &str) -> Result<()> {
+        use std::process::Command;
+
+        let list = Command::new("git")
+            .args(["stash", "list", "--format=%H"])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git stash list")?;
+        let list = String::from_utf8_lossy(&list.stdout);
+        let Some(index) = list.lines().position(|oid| oid == stash) else {
+            return Ok(());
+        };
+
+        let drop = Command::new("git")
+            .args(["stash", "drop", "--quiet", &format!("stash@{{{}}}", index)])
+            .current_dir(path)
+            .output()
+            .context("Failed to execute git stash drop")?;
+
+        if !drop.status.success() {
+            let stderr = String::from_utf8_lossy(&drop.stderr);
+            anyhow::bail!("Failed to drop stash: {}", stderr);
+        }
+        Ok(())
+    }
+
+    /// Throw away uncommitted changes and untracked files, except in `.vibetree`
+    pub fn discard_changes(path: &Path) -> Result<()> {
+        use std::process::Command;
+
+        for args in [
+            &["reset", "--hard", "--quiet"][..],
+            &["clean", "-d", "--force", "--quiet", "-e", ".vibetree"][..],
+        ] {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(path)
                 .output()
-                .context("Failed to checkout branch for rebase test")?;
+                .context("Failed to execute git")?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!("Failed to discard changes: {}", stderr);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if a branch exists
+    pub fn branch_exists(repo_path: &Path, branch: &str) -> Result<bool> {
+        use std::process::Command;
+
+        let output = Command::new("git")
+            .args(["rev-parse", "--verify", &format!("refs/heads/{}", branch)])
+            .current_dir(repo_path)
+            .output()
+            .context("Failed to check branch existence")?;
+
+        Ok(output.status.success())
+    }
+
+    /// Read a file as committed at the given revision, or None if it isn't tracked there
+    pub fn read_file_at_revision(
+        repo_path: &Path,
+        revision: &str,
+        file_path: &Path,
+    ) -> Result<Option<String>> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-            if !checkout.status.success() {
-                let stderr = String::from_utf8_lossy(&checkout.stderr);
-                anyhow::bail!("Failed to checkout {}: {}", branch, stderr);
-            }
+        let tree = repo
+            .revparse_single(revision)
+            .and_then(|object| object.peel_to_tree())
+            .with_context(|| format!("Failed to resolve revision '{}'", revision))?;
 
-            // Try the rebase
-            let rebase = Command::new("git")
-                .args(["rebase", target])
-                .current_dir(repo_path)
-                .output()
-                .context("Failed to execute test rebase")?;
+        let entry = match tree.get_path(file_path) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to look up file in tree"),
+        };
 
-            let can_rebase = rebase.status.success();
+        let blob = entry
+            .to_object(&repo)
+            .and_then(|object| object.peel_to_blob())
+            .with_context(|| format!("'{}' is not a file", file_path.display()))?;
 
-            // Abort rebase if it failed
-            if !can_rebase {
-                let _ = Command::new("git")
-                    .args(["rebase", "--abort"])
-                    .current_dir(repo_path)
-                    .output();
-            }
+        Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+    }
 
-            // If rebase succeeded, undo it
-            if can_rebase {
-                let _ = Command::new("git")
-                    .args(["reset", "--hard", &format!("{}@{{1}}", branch)])
-                    .current_dir(repo_path)
-                    .output();
-            }
+    /// Check if a branch has moved since it was created, according to its reflog
+    ///
+    /// Branches without a reflog are assumed to have moved.
+    pub fn branch_has_moved(repo_path: &Path, branch: &str) -> Result<bool> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-            // Return to original branch
-            let _ = Command::new("git")
-                .args(["checkout", &current_branch])
-                .current_dir(repo_path)
-                .output();
+        let reflog = repo
+            .reflog(&format!("refs/heads/{}", branch))
+            .with_context(|| format!("Failed to read reflog for '{}'", branch))?;
 
-            Ok(can_rebase)
-        } else {
-            // Worktree case - run rebase directly in the worktree
-            let rebase = Command::new("git")
-                .args(["rebase", target])
-                .current_dir(rebase_dir)
-                .output()
-                .context("Failed to execute test rebase")?;
+        Ok(reflog.len() != 1)
+    }
 
-            let can_rebase = rebase.status.success();
+    /// Get the commit time of a branch's tip as seconds since the Unix epoch
+    pub fn last_commit_time(repo_path: &Path, branch: &str) -> Result<i64> {
+        Ok(Self::last_commit(repo_path, branch)?.time)
+    }
 
-            // Abort rebase if it failed
-            if !can_rebase {
-                let _ = Command::new("git")
-                    .args(["rebase", "--abort"])
-                    .current_dir(rebase_dir)
-                    .output();
-            }
+    /// Get the time and subject line of a branch's tip commit
+    pub fn last_commit(repo_path: &Path, branch: &str) -> Result<CommitSummary> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-            // If rebase succeeded, undo it using reflog
-            if can_rebase {
-                let _ = Command::new("git")
-                    .args(["reset", "--hard", &format!("{}@{{1}}", branch)])
-                    .current_dir(rebase_dir)
-                    .output();
-            }
+        let commit = repo
+            .find_branch(branch, git2::BranchType::Local)
+            .and_then(|branch| branch.get().peel_to_commit())
+            .with_context(|| format!("Failed to find branch '{}'", branch))?;
 
-            Ok(can_rebase)
-        }
+        Ok(CommitSummary {
+            time: commit.time().seconds(),
+            subject: commit.summary().unwrap_or_default().to_string(),
+        })
     }
 
-    /// Execute a merge
-    pub fn merge_branch(repo_path: &Path, branch: &str, target: &str) -> Result<()> {
-        use std::process::Command;
+    /// Count commits on `branch` that are not on `base`, and on `base` that are not on `branch`
+    pub fn ahead_behind(repo_path: &Path, branch: &str, base: &str) -> Result<(usize, usize)> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-        // Checkout target branch
-        let checkout = Command::new("git")
-            .args(["checkout", target])
-            .current_dir(repo_path)
-            .output()
-            .context("Failed to checkout target branch")?;
+        let local = repo
+            .revparse_single(branch)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("Failed to resolve '{}'", branch))?;
+        let base = repo
+            .revparse_single(base)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("Failed to resolve '{}'", base))?;
+
+        repo.graph_ahead_behind(local.id(), base.id())
+            .context("Failed to compare commit graphs")
+    }
 
-        if !checkout.status.success() {
-            let stderr = String::from_utf8_lossy(&checkout.stderr);
-            anyhow::bail!("Failed to checkout {}: {}", target, stderr);
-        }
+    /// Count commits ahead of and behind a branch's upstream
+    ///
+    /// Returns `None` when the branch has no upstream or the upstream is gone.
+    pub fn upstream_ahead_behind(repo_path: &Path, branch: &str) -> Result<Option<(usize, usize)>> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-        // Execute merge
-        let merge = Command::new("git")
-            .args(["merge", branch])
-            .current_dir(repo_path)
-            .output()
-            .context("Failed to execute merge")?;
+        let local = repo
+            .find_branch(branch, git2::BranchType::Local)
+            .with_context(|| format!("Failed to find branch '{}'", branch))?;
+        let Ok(upstream) = local.upstream() else {
+            return Ok(None);
+        };
 
-        if !merge.status.success() {
-            let stderr = String::from_utf8_lossy(&merge.stderr);
-            anyhow::bail!("Merge failed: {}", stderr);
-        }
+        let (Some(local_oid), Some(upstream_oid)) = (local.get().target(), upstream.get().target())
+        else {
+            return Ok(None);
+        };
 
-        Ok(())
+        let counts = repo
+            .graph_ahead_behind(local_oid, upstream_oid)
+            .context("Failed to compare commit graphs")?;
+        Ok(Some(counts))
     }
 
-    /// Execute a squash merge with a custom commit message
-    pub fn squash_merge_branch(
-        repo_path: &Path,
-        branch: &str,
-        target: &str,
-        message: &str,
-    ) -> Result<()> {
-        use std::process::Command;
+    /// Short name of a branch's upstream, e.g. "origin/main", if it has one
+    pub fn upstream_branch(repo_path: &Path, branch: &str) -> Result<Option<String>> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-        // Checkout target branch
-        let checkout = Command::new("git")
-            .args(["checkout", target])
-            .current_dir(repo_path)
-            .output()
-            .context("Failed to checkout target branch")?;
+        let local = repo
+            .find_branch(branch, git2::BranchType::Local)
+            .with_context(|| format!("Failed to find branch '{}'", branch))?;
+        let Ok(upstream) = local.upstream() else {
+            return Ok(None);
+        };
+        Ok(upstream.name()?.map(str::to_string))
+    }
 
-        if !checkout.status.success() {
-            let stderr = String::from_utf8_lossy(&checkout.stderr);
-            anyhow::bail!("Failed to checkout {}: {}", target, stderr);
-        }
+    /// Fetch from a remote, updating its remote-tracking branches
+    pub fn fetch(repo_path: &Path, remote: &str) -> Result<()> {
+        use std::process::Command;
 
-        // Execute squash merge
-        let merge = Command::new("git")
-            .args(["merge", "--squash", branch])
+        let fetch = Command::new("git")
+            .args(["fetch", remote])
             .current_dir(repo_path)
             .output()
-            .context("Failed to execute squash merge")?;
+            .context("Failed to execute git fetch")?;
 
-        if !merge.status.success() {
-            let stderr = String::from_utf8_lossy(&merge.stderr);
-            anyhow::bail!("Squash merge failed: {}", stderr);
+        if !fetch.status.success() {
+            let stderr = String::from_utf8_lossy(&fetch.stderr);
+            anyhow::bail!("Failed to fetch from {}: {}", remote, stderr);
         }
+        Ok(())
+    }
 
-        // Commit with the provided message
-        let commit = Command::new("git")
-            .args(["commit", "-m", message])
-            .current_dir(repo_path)
-            .output()
-            .context("Failed to commit squash merge")?;
+    /// URL of a remote, if the remote exists
+    pub fn remote_url(repo_path: &Path, remote: &str) -> Result<Option<String>> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-        if !commit.status.success() {
-            let stderr = String::from_utf8_lossy(&commit.stderr);
-            anyhow::bail!("Failed to commit squash merge: {}", stderr);
+        match repo.find_remote(remote) {
+            Ok(remote) => Ok(remote.url().map(str::to_string)),
+            Err(_) => Ok(None),
         }
-
-        Ok(())
     }
 
-    /// Execute a rebase and fast-forward merge
-    ///
-    /// For worktrees, the rebase must be run from the worktree directory since
-    /// git doesn't allow checking out a branch that's already checked out elsewhere.
-    pub fn rebase_and_merge(
+    /// Push a branch to a remote and set it as the branch's upstream
+    pub fn push_branch(
         repo_path: &Path,
+        remote: &str,
         branch: &str,
-        target: &str,
-        worktree_path: Option<&Path>,
+        force_with_lease: bool,
     ) -> Result<()> {
         use std::process::Command;
 
-        // Determine where to run the rebase
-        let rebase_dir = worktree_path.unwrap_or(repo_path);
-
-        if worktree_path.is_some() {
-            // Worktree case - run rebase directly in the worktree
-            let rebase = Command::new("git")
-                .args(["rebase", target])
-                .current_dir(rebase_dir)
-                .output()
-                .context("Failed to execute rebase")?;
-
-            if !rebase.status.success() {
-                // Abort the failed rebase
-                let _ = Command::new("git")
-                    .args(["rebase", "--abort"])
-                    .current_dir(rebase_dir)
-                    .output();
-                let stderr = String::from_utf8_lossy(&rebase.stderr);
-                anyhow::bail!("Rebase failed: {}", stderr);
-            }
-        } else {
-            // Non-worktree case - checkout first
-            let checkout = Command::new("git")
-                .args(["checkout", branch])
-                .current_dir(repo_path)
-                .output()
-                .context("Failed to checkout branch for rebase")?;
-
-            if !checkout.status.success() {
-                let stderr = String::from_utf8_lossy(&checkout.stderr);
-                anyhow::bail!("Failed to checkout {}: {}", branch, stderr);
-            }
-
-            // Execute rebase
-            let rebase = Command::new("git")
-                .args(["rebase", target])
-                .current_dir(repo_path)
-                .output()
-                .context("Failed to execute rebase")?;
-
-            if !rebase.status.success() {
-                // Abort the failed rebase
-                let _ = Command::new("git")
-                    .args(["rebase", "--abort"])
-                    .current_dir(repo_path)
-                    .output();
-                let stderr = String::from_utf8_lossy(&rebase.stderr);
-                anyhow::bail!("Rebase failed: {}", stderr);
-            }
-        }
-
-        // Checkout target (in main repo) and fast-forward merge
-        let checkout_target = Command::new("git")
-            .args(["checkout", target])
-            .current_dir(repo_path)
-            .output()
-            .context("Failed to checkout target after rebase")?;
-
-        if !checkout_target.status.success() {
-            let stderr = String::from_utf8_lossy(&checkout_target.stderr);
-            anyhow::bail!("Failed to checkout {}: {}", target, stderr);
+        let mut args = vec!["push", "--set-upstream"];
+        if force_with_lease {
+            args.push("--force-with-lease");
         }
+        args.extend([remote, branch]);
 
-        // Fast-forward merge
-        let merge = Command::new("git")
-            .args(["merge", "--ff-only", branch])
+        let output = Command::new("git")
+            .args(&args)
             .current_dir(repo_path)
             .output()
-            .context("Failed to execute fast-forward merge")?;
+            .context("Failed to execute git push")?;
 
-        if !merge.status.success() {
-            let stderr = String::from_utf8_lossy(&merge.stderr);
-            anyhow::bail!("Fast-forward merge failed: {}", stderr);
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Failed to push '{}' to '{}': {}", branch, remote, stderr);
         }
 
         Ok(())
     }
 
-    /// Check if a branch exists
-    pub fn branch_exists(repo_path: &Path, branch: &str) -> Result<bool> {
-        use std::process::Command;
+    /// Check if a branch tracks an upstream that no longer exists
+    ///
+    /// Branches without any configured upstream are not considered gone.
+    pub fn is_upstream_gone(repo_path: &Path, branch: &str) -> Result<bool> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
 
-        let output = Command::new("git")
-            .args(["rev-parse", "--verify", &format!("refs/heads/{}", branch)])
-            .current_dir(repo_path)
-            .output()
-            .context("Failed to check branch existence")?;
+        let config = repo.config().context("Failed to read git config")?;
+        if config
+            .get_string(&format!("branch.{}.merge", branch))
+            .is_err()
+        {
+            return Ok(false);
+        }
 
-        Ok(output.status.success())
+        let local = repo
+            .find_branch(branch, git2::BranchType::Local)
+            .with_context(|| format!("Failed to find branch '{}'", branch))?;
+
+        Ok(local.upstream().is_err())
     }
 
     /// Check if vibetree is already configured (has vibetree.toml)
@@ -448,42 +1278,102 @@ impl GitManager {
         }
     }
 
-    pub fn create_worktree(
+    /// Create a branch at `base`, or at HEAD when unset
+    pub fn create_branch(repo_path: &Path, branch_name: &str, base: Option<&str>) -> Result<()> {
+        use std::process::Command;
+
+        let mut args = vec!["branch", branch_name];
+        args.extend(base);
+        let output = Command::new("git")
+            .args(&args)
+            .current_dir(repo_path)
+            .output()
+            .context("Failed to execute git branch")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Failed to create branch {}: {}", branch_name, stderr);
+        }
+        Ok(())
+    }
+
+    /// Check out an existing branch in a new worktree at `worktree_path`
+    pub fn checkout_worktree(
         repo_path: &Path,
         worktree_path: &Path,
         branch_name: &str,
-        base_branch: Option<&str>,
     ) -> Result<()> {
         use std::process::Command;
 
         // Use git command line for worktree creation to avoid git2 reference conflicts
-        let mut cmd = Command::new("git");
-        cmd.args(["worktree", "add"]);
-
-        // Add the worktree path
-        cmd.arg(worktree_path);
+        let output = Command::new("git")
+            .args(["worktree", "add"])
+            .arg(worktree_path)
+            .arg(branch_name)
+            .current_dir(repo_path)
+            .output()
+            .context("Failed to execute git worktree command")?;
 
-        // Add branch creation arguments
-        if let Some(base) = base_branch {
-            // Create new branch from base
-            cmd.args(["-b", branch_name, base]);
-        } else {
-            // Create new branch from HEAD
-            cmd.args(["-b", branch_name]);
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Git worktree creation failed: {}", stderr);
         }
+        Ok(())
+    }
+
+    /// Commit a branch points at
+    pub fn branch_oid(repo_path: &Path, branch: &str) -> Result<String> {
+        let repo = Repository::open(repo_path)
+            .with_context(|| format!("Failed to open git repository at {}", repo_path.display()))?;
+        Ok(Self::peel_commit(&repo, &format!("refs/heads/{}", branch))?
+            .id()
+            .to_string())
+    }
 
-        // Set working directory to the repo
-        cmd.current_dir(repo_path);
+    /// Point a branch at `oid`, creating it if needed
+    ///
+    /// Where the branch is checked out in `worktree`, the worktree is reset to
+    /// match, which throws away uncommitted changes there.
+    pub fn set_branch(
+        repo_path: &Path,
+        branch: &str,
+        oid: &str,
+        worktree: Option<&Path>,
+    ) -> Result<()> {
+        use std::process::Command;
 
-        let output = cmd
+        let refname = format!("refs/heads/{}", branch);
+        let (dir, args) = match worktree {
+            Some(worktree) => (worktree, vec!["reset", "--hard", "--quiet", oid]),
+            None => (repo_path, vec!["update-ref", refname.as_str(), oid]),
+        };
+        let output = Command::new("git")
+            .args(&args)
+            .current_dir(dir)
             .output()
-            .context("Failed to execute git worktree command")?;
+            .context("Failed to execute git")?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Git worktree creation failed: {}", stderr);
+            anyhow::bail!("Failed to reset branch {}: {}", branch, stderr);
         }
+        Ok(())
+    }
+
+    /// Delete a branch ref, even if a worktree still lists it as checked out
+    pub fn delete_branch(repo_path: &Path, branch: &str) -> Result<()> {
+        use std::process::Command;
+
+        let output = Command::new("git")
+            .args(["update-ref", "-d", &format!("refs/heads/{}", branch)])
+            .current_dir(repo_path)
+            .output()
+            .context("Failed to execute git update-ref")?;
 
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Failed to delete branch {}: {}", branch, stderr);
+        }
         Ok(())
     }
 
@@ -507,12 +1397,12 @@ impl GitManager {
         worktree.prune(None).context("Failed to prune worktree")?;
 
         // Remove branch if requested
-        if !keep_branch {
-            if let Ok(mut branch) = repo.find_branch(worktree_name, git2::BranchType::Local) {
-                branch
-                    .delete()
-                    .with_context(|| format!("Failed to delete branch: {}", worktree_name))?;
-            }
+        if !keep_branch
+            && let Ok(mut branch) = repo.find_branch(worktree_name, git2::BranchType::Local)
+        {
+            branch
+                .delete()
+                .with_context(|| format!("Failed to delete branch: {}", worktree_name))?;
         }
 
         Ok(())
@@ -610,10 +1500,10 @@ impl GitManager {
                 if let Some(ref mut wt) = current_worktree {
                     wt.is_bare = true;
                 }
-            } else if line == "detached" {
-                if let Some(ref mut wt) = current_worktree {
-                    wt.is_detached = true;
-                }
+            } else if line == "detached"
+                && let Some(ref mut wt) = current_worktree
+            {
+                wt.is_detached = true;
             }
         }
 
@@ -635,6 +1525,35 @@ pub struct WorktreeValidation {
     pub branch_name: Option<String>,
 }
 
+/// Paths that would conflict when combining two branches
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConflictPrediction {
+    pub paths: Vec<String>,
+    /// For rebases, the commit that would stop with conflicts
+    pub commit: Option<String>,
+}
+
+impl ConflictPrediction {
+    pub fn is_clean(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitSummary {
+    /// Commit time as seconds since the Unix epoch
+    pub time: i64,
+    pub subject: String,
+}
+
+/// A commit being merged, as used for merge messages and trailers
+#[derive(Debug, Clone)]
+pub struct BranchCommit {
+    pub subject: String,
+    pub author_name: String,
+    pub author_email: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct DiscoveredWorktree {
     pub path: PathBuf,
@@ -728,6 +1647,163 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_file_at_revision() -> Result<()> {
+        let (_temp_dir, repo_path) = create_test_repo()?;
+
+        // Uncommitted edits are not visible at HEAD
+        fs::write(repo_path.join("README.md"), "# Changed")?;
+
+        let content =
+            GitManager::read_file_at_revision(&repo_path, "HEAD", Path::new("README.md"))?;
+        assert_eq!(content.as_deref(), Some("# Test Repo"));
+
+        let missing =
+            GitManager::read_file_at_revision(&repo_path, "HEAD", Path::new("vibetree.toml"))?;
+        assert!(missing.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ahead_behind_and_last_commit() -> Result<()> {
+        let (_temp_dir, repo_path) = create_test_repo()?;
+        let repo = Repository::open(&repo_path)?;
+        let base = GitManager::get_current_branch(&repo_path)?;
+
+        let head = repo.head()?.peel_to_commit()?;
+        repo.branch("feature", &head, false)?;
+
+        let signature = git2::Signature::now("Test User", "test@example.com")?;
+        let tree = head.tree()?;
+        repo.commit(
+            Some("refs/heads/feature"),
+            &signature,
+            &signature,
+            "Add feature\n\nWith a body",
+            &tree,
+            &[&head],
+        )?;
+
+        assert_eq!(
+            GitManager::ahead_behind(&repo_path, "feature", &base)?,
+            (1, 0)
+        );
+        assert_eq!(
+            GitManager::ahead_behind(&repo_path, &base, "feature")?,
+            (0, 1)
+        );
+        assert_eq!(
+            GitManager::upstream_ahead_behind(&repo_path, "feature")?,
+            None
+        );
+        assert_eq!(
+            GitManager::last_commit(&repo_path, "feature")?.subject,
+            "Add feature"
+        );
+
+        Ok(())
+    }
+
+    /// Commit a single top-level file onto `parent` without touching the working tree
+    fn commit_file<'r>(
+        repo: &'r Repository,
+        refname: &str,
+        parent: &git2::Commit<'r>,
+        path: &str,
+        content: &str,
+    ) -> Result<git2::Commit<'r>> {
+        let blob = repo.blob(content.as_bytes())?;
+        let mut builder = repo.treebuilder(Some(&parent.tree()?))?;
+        builder.insert(path, blob, 0o100644)?;
+        let tree = repo.find_tree(builder.write()?)?;
+
+        let signature = git2::Signature::now("Test User", "test@example.com")?;
+        let oid = repo.commit(
+            Some(refname),
+            &signature,
+            &signature,
+            &format!("Change {}", path),
+            &tree,
+            &[parent],
+        )?;
+        Ok(repo.find_commit(oid)?)
+    }
+
+    #[test]
+    fn test_predict_conflicts_in_memory() -> Result<()> {
+        let (_temp_dir, repo_path) = create_test_repo()?;
+        let repo = Repository::open(&repo_path)?;
+        let base = GitManager::get_current_branch(&repo_path)?;
+        let root = repo.head()?.peel_to_commit()?;
+
+        repo.branch("clean", &root, false)?;
+        repo.branch("conflicting", &root, false)?;
+        let on_base = commit_file(
+            &repo,
+            &format!("refs/heads/{}", base),
+            &root,
+            "shared.txt",
+            "base",
+        )?;
+        commit_file(&repo, "refs/heads/clean", &root, "other.txt", "clean")?;
+        let first = commit_file(&repo, "refs/heads/conflicting", &root, "notes.txt", "notes")?;
+        commit_file(
+            &repo,
+            "refs/heads/conflicting",
+            &first,
+            "shared.txt",
+            "feature",
+        )?;
+
+        assert!(GitManager::predict_merge_conflicts(&repo_path, "clean", &base)?.is_clean());
+        assert!(GitManager::predict_rebase_conflicts(&repo_path, "clean", &base)?.is_clean());
+
+        let merge = GitManager::predict_merge_conflicts(&repo_path, "conflicting", &base)?;
+        assert_eq!(merge.paths, vec!["shared.txt".to_string()]);
+        assert!(!GitManager::can_squash_cleanly(
+            &repo_path,
+            "conflicting",
+            &base
+        )?);
+
+        // The first commit applies cleanly, the second one stops the rebase
+        let rebase = GitManager::predict_rebase_conflicts(&repo_path, "conflicting", &base)?;
+        assert_eq!(rebase.paths, vec!["shared.txt".to_string()]);
+        assert!(rebase.commit.unwrap().ends_with("Change shared.txt"));
+
+        // Nothing outside the object database changed
+        assert_eq!(repo.head()?.peel_to_commit()?.id(), on_base.id());
+        assert_eq!(
+            fs::read_to_string(repo_path.join("README.md"))?,
+            "# Test Repo"
+        );
+        assert!(!repo_path.join("shared.txt").exists());
+        assert!(!repo_path.join("notes.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predict_rebase_skips_commits_already_upstream() -> Result<()> {
+        let (_temp_dir, repo_path) = create_test_repo()?;
+        let repo = Repository::open(&repo_path)?;
+        let base = GitManager::get_current_branch(&repo_path)?;
+        let base_ref = format!("refs/heads/{}", base);
+        let root = repo.head()?.peel_to_commit()?;
+
+        // The feature's first change was cherry-picked onto base, then changed again there
+        repo.branch("picked", &root, false)?;
+        let notes = commit_file(&repo, "refs/heads/picked", &root, "notes.txt", "notes")?;
+        commit_file(&repo, "refs/heads/picked", &notes, "shared.txt", "v1")?;
+        let on_base = commit_file(&repo, &base_ref, &root, "shared.txt", "v1")?;
+        commit_file(&repo, &base_ref, &on_base, "shared.txt", "v2")?;
+
+        assert!(GitManager::predict_rebase_conflicts(&repo_path, "picked", &base)?.is_clean());
+
+        Ok(())
+    }
+
     #[test]
     fn test_find_repo_root_not_in_git() {
         let temp_dir = TempDir::new().unwrap();
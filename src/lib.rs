@@ -7,41 +7,118 @@
 //! - Configuration management and state reconciliation
 
 pub mod allocator;
+pub mod changes;
+pub mod check;
 pub mod cli;
 pub mod completions;
+pub mod compose;
 pub mod config;
 pub mod display;
 pub mod env;
+pub mod forge;
+pub mod fork;
 pub mod git;
+pub mod lock;
+pub mod merge;
+pub mod multiplexer;
+pub mod output;
+pub mod placeholders;
+pub mod plan;
 pub mod ports;
+pub mod pr;
+pub mod project_file;
+pub mod prune;
+pub mod registry;
+pub mod resources;
+pub mod schema;
+pub mod services;
+pub mod settings;
+pub mod shell;
 pub mod sync;
 pub mod template;
+pub mod transaction;
+pub mod update;
 pub mod validation;
+pub mod variables;
 
 /// Current version of vibetree from Cargo.toml
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 // Re-export public types for external use
-pub use cli::{Cli, Commands, CompletionShell, OutputFormat};
+pub use changes::{CarriedChanges, CarryOptions};
+pub use check::{CheckIssue, CheckReport};
+pub use cli::{
+    Cli, Commands, CompletionShell, ConfigCommand, CopyMode, ListSort, OutputFormat, OutputMode,
+    RegistryCommand, ShellKind, VarCommand,
+};
 pub use completions::generate_completions;
-pub use config::{VariableConfig, VibeTreeConfig, WorktreeConfig};
-pub use display::WorktreeDisplayData;
+pub use compose::{ComposeContainer, ComposeManager};
+pub use config::{
+    ComposeConfig, ConfigDrift, LayoutConfig, PaneConfig, ReadyCheck, ResourceConfig,
+    ResourceState, ServiceConfig, VariableConfig, VibeTreeConfig, WorktreeConfig,
+};
+pub use display::{ListOptions, WorktreeDisplayData};
 pub use env::EnvFileGenerator;
-pub use git::{DiscoveredWorktree, GitManager, WorktreeValidation};
+pub use forge::{Forge, PrState, PullRequest};
+pub use fork::ForkOptions;
+pub use git::{ConflictPrediction, DiscoveredWorktree, GitManager, WorktreeValidation};
+pub use merge::{MergeOperations, MergeOptions, MergeState};
+pub use multiplexer::{Multiplexer, WorkspaceCloser};
+pub use output::{
+    AddResult, CommandOutput, ConfigShowResult, ErrorKind, ForkResult, InitResult,
+    MergeAbortResult, MergeResult, MergeStrategy, MigrateResult, MoveChangesResult, PruneResult,
+    PullRequestResult, PushResult, RegistryGcResult, RegistryResult, RemoveResult, RepairResult,
+    ServiceStatus, ServicesResult, UpdateResult, UpdateStatus, VariableEditResult,
+    VariableListResult, VibeTreeError, WorktreeUpdate,
+};
+pub use plan::{Action, Plan};
+pub use pr::PullRequestOptions;
+pub use project_file::ProjectFile;
+pub use prune::{PruneCandidate, PruneCriteria, PruneReason};
+pub use registry::{Allocation, Registry};
+pub use resources::ResourceManager;
+pub use schema::{FileMigration, Migration, Schema};
+pub use services::ServiceManager;
+pub use settings::{AddDefaults, LayeredConfig, Origin, Settings};
+pub use sync::SyncPlan;
+pub use update::UpdateOptions;
 pub use validation::{ConfigValidator, ValidationResult};
+pub use variables::{VariableManager, VariableValue};
 
 use anyhow::{Context, Result};
 use log::{debug, info, warn};
 use std::collections::HashMap;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use plan::PlanContext;
 
 pub use ports::PortManager;
 
+/// How `vibetree init` runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InitOptions {
+    /// Only show what would change
+    pub dry_run: bool,
+    /// Replace the variables already in vibetree.toml instead of keeping them
+    pub reset: bool,
+}
+
+/// How `vibetree remove` runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveOptions {
+    /// Don't ask for confirmation
+    pub force: bool,
+    /// Keep the worktree's git branch
+    pub keep_branch: bool,
+    /// Only show what would be removed
+    pub dry_run: bool,
+}
+
 /// Main application context for vibetree operations
 pub struct VibeTreeApp {
     config: VibeTreeConfig,
     vibetree_parent: PathBuf,
+    /// Closes the windows, tabs and sessions of removed worktrees
+    closer: Box<dyn WorkspaceCloser>,
 }
 
 impl VibeTreeApp {
@@ -61,9 +138,16 @@ impl VibeTreeApp {
         Ok(Self {
             config,
             vibetree_parent,
+            closer: Box::new(multiplexer::Multiplexers),
         })
     }
 
+    /// Close removed worktrees' windows, tabs and sessions with `closer` instead
+    /// of in tmux and zellij
+    pub fn set_workspace_closer(&mut self, closer: Box<dyn WorkspaceCloser>) {
+        self.closer = closer;
+    }
+
     /// Create a VibeTreeApp instance that only loads existing configuration (doesn't create new files)
     pub fn load_existing() -> Result<Self> {
         let vibetree_parent = VibeTreeConfig::get_vibetree_parent()
@@ -80,19 +164,49 @@ impl VibeTreeApp {
         Ok(Self {
             config,
             vibetree_parent,
+            closer: Box::new(multiplexer::Multiplexers),
         })
     }
 
     /// Initialize vibetree configuration
-    pub fn init(&mut self, variables: Vec<String>) -> Result<()> {
+    pub fn init(&mut self, variables: Vec<String>) -> Result<InitResult> {
+        self.init_with_options(variables, InitOptions::default())
+    }
+
+    /// Initialize vibetree configuration, or with `dry_run` only show what would change
+    pub fn init_with_options(
+        &mut self,
+        variables: Vec<String>,
+        options: InitOptions,
+    ) -> Result<InitResult> {
         info!("Initializing vibetree configuration");
 
-        // Clear existing configuration to start fresh
-        self.config.project_config.variables.clear();
+        // Existing variables are kept unless asked to start fresh
+        if options.reset {
+            self.config.project_config.variables.clear();
+        }
 
         // Parse and update variables if provided
         if !variables.is_empty() {
             for variable_spec in &variables {
+                let name = variable_spec
+                    .split_once(':')
+                    .map_or(variable_spec.as_str(), |(name, _)| name)
+                    .to_uppercase();
+                if self
+                    .config
+                    .project_config
+                    .variables
+                    .iter()
+                    .any(|v| v.name == name)
+                {
+                    warn!(
+                        "Variable '{}' is already configured; use --reset to replace it",
+                        name
+                    );
+                    continue;
+                }
+
                 if let Some((variable, port_str)) = variable_spec.split_once(':') {
                     let port = port_str.parse::<u16>().with_context(|| {
                         format!("Invalid port '{}' for variable '{}'", port_str, variable)
@@ -123,38 +237,70 @@ impl VibeTreeApp {
             }
         }
 
+        let config_path = self.vibetree_parent.join("vibetree.toml");
+        let mut plan = Plan::new();
+        plan.push(Action::WriteConfig {
+            path: config_path.clone(),
+        });
+
         // Add or update the main branch to branches configuration if variables are configured
+        let mut main_values = HashMap::new();
         if !self.config.project_config.variables.is_empty() {
             // Always use the configured main_branch, regardless of current git branch
             let main_branch = self.config.project_config.main_branch.clone();
 
-            // Allocate values for the main branch using the new allocator
-            let existing_worktrees = HashMap::new(); // Empty since this is init
-            let main_branch_values = self.config.project_config
-                .allocate_values(&main_branch, &existing_worktrees)?;
-
-            // Add or update main branch with the allocated values to branches.toml
-            self.config
-                .add_or_update_worktree(main_branch.clone(), Some(main_branch_values.clone()))?;
+            main_values = self
+                .config
+                .main_branch_values(&main_branch, !options.dry_run)?;
+
+            plan.push(Action::Allocate {
+                worktree: main_branch.clone(),
+                values: main_values.clone().into_iter().collect(),
+            });
+            plan.push(Action::WriteEnvFile {
+                path: self.config.get_env_file_path(&self.vibetree_parent),
+                worktree: main_branch,
+            });
+        }
 
-            // Generate env file for the main worktree
-            let env_file_path = self.config.get_env_file_path(&self.vibetree_parent);
-            EnvFileGenerator::generate_env_file(&env_file_path, &main_branch, &main_branch_values)
-                .context("Failed to generate environment file for main worktree")?;
+        // Update .gitignore to include .vibetree directory
+        let gitignore_path = self.vibetree_parent.join(".gitignore");
+        if plan::gitignore_needs_rule(&gitignore_path)? {
+            plan.push(Action::UpdateGitignore {
+                path: gitignore_path,
+            });
         }
 
-        self.save_config()?;
+        if options.dry_run {
+            info!("Dry run - would initialize vibetree:");
+            plan.report();
+            let repair = self.repair(true)?;
+            return Ok(InitResult {
+                config_path,
+                variables: self.config.project_config.variables.clone(),
+                values: main_values,
+                repair,
+                dry_run: true,
+                plan,
+            });
+        }
 
-        // Update .gitignore to include .vibetree directory
-        self.update_gitignore(&self.vibetree_parent)?;
+        let variables = self.config.project_config.variables.clone();
+        let context = PlanContext {
+            command: "init",
+            repo_path: None,
+            vibetree_parent: &self.vibetree_parent,
+            variables: &variables,
+        };
+        plan.execute(&mut self.config, &context)?;
 
         // Automatically repair to update all discovered worktrees with new configuration
         info!("Running repair to update all worktree configurations");
-        self.repair(false)?;
+        let repair = self.repair(false)?;
 
         info!(
             "Initialized vibetree configuration at {}",
-            self.vibetree_parent.join("vibetree.toml").display()
+            config_path.display()
         );
         info!(
             "Configured variables: {}",
@@ -183,55 +329,80 @@ impl VibeTreeApp {
             );
         }
 
-        Ok(())
+        Ok(InitResult {
+            config_path,
+            variables: self.config.project_config.variables.clone(),
+            values: main_values,
+            repair,
+            dry_run: false,
+            plan,
+        })
     }
 
+    /// Add a new worktree with isolated environment
+    pub fn add_worktree(
+        &mut self,
+        branch_name: String,
+        from_branch: Option<String>,
+        custom_values: Option<Vec<String>>,
+        dry_run: bool,
+        switch: bool,
+    ) -> Result<AddResult> {
+        self.add_worktree_carrying(
+            branch_name,
+            from_branch,
+            custom_values,
+            dry_run,
+            switch,
+            None,
+        )
+    }
 
-    /// Update .gitignore to include .vibetree directory
-    fn update_gitignore(&self, repo_root: &std::path::Path) -> Result<()> {
-        let gitignore_path = repo_root.join(".gitignore");
-        let vibetree_rule = ".vibetree/";
-
-        // Read existing .gitignore or create empty content
-        let mut content = if gitignore_path.exists() {
-            std::fs::read_to_string(&gitignore_path).with_context(|| {
-                format!("Failed to read .gitignore: {}", gitignore_path.display())
-            })?
-        } else {
-            String::new()
-        };
-
-        // Check if rule already exists
-        if content.lines().any(|line| line.trim() == vibetree_rule) {
-            debug!(".gitignore already contains {} rule", vibetree_rule);
-            return Ok(());
-        }
+    /// Add a worktree, optionally moving the main worktree's uncommitted changes into it
+    pub fn add_worktree_carrying(
+        &mut self,
+        branch_name: String,
+        from_branch: Option<String>,
+        custom_values: Option<Vec<String>>,
+        dry_run: bool,
+        switch: bool,
+        carry: Option<CarryOptions>,
+    ) -> Result<AddResult> {
+        let added = self.add_worktree_planned(
+            branch_name,
+            from_branch,
+            custom_values,
+            dry_run,
+            carry,
+            |_, _| Ok(Vec::new()),
+        )?;
 
-        // Add the rule
-        if !content.is_empty() && !content.ends_with('\n') {
-            content.push('\n');
+        // Handle switch flag
+        if switch && !dry_run && !self.enter_directory(&added.path, false)? {
+            shell::print_integration_hint();
         }
-        content.push_str(&format!("{}\n", vibetree_rule));
-
-        std::fs::write(&gitignore_path, content).with_context(|| {
-            format!("Failed to update .gitignore: {}", gitignore_path.display())
-        })?;
-
-        info!("Added {} to .gitignore", vibetree_rule);
-        Ok(())
+        Ok(added)
     }
 
-    /// Add a new worktree with isolated environment
-    pub fn add_worktree(
+    /// Add a worktree, with `extend` planning further actions to run as part of
+    /// the same transaction once the worktree exists
+    ///
+    /// `extend` gets the worktree's path and allocated values.
+    fn add_worktree_planned(
         &mut self,
         branch_name: String,
         from_branch: Option<String>,
         custom_values: Option<Vec<String>>,
         dry_run: bool,
-        switch: bool,
-    ) -> Result<()> {
+        carry: Option<CarryOptions>,
+        extend: impl FnOnce(&Path, &HashMap<String, String>) -> Result<Vec<Action>>,
+    ) -> Result<AddResult> {
         info!("Adding worktree: {}", branch_name);
 
+        if carry.is_some() && from_branch.is_some() {
+            anyhow::bail!("Carried changes can only be applied to a worktree created from HEAD");
+        }
+
         // Validate input
         if branch_name.is_empty() {
             anyhow::bail!("Branch name cannot be empty");
@@ -243,42 +414,40 @@ impl VibeTreeApp {
             .worktrees
             .contains_key(&branch_name)
         {
-            anyhow::bail!("Worktree '{}' already exists", branch_name);
+            anyhow::bail!(VibeTreeError::conflict(format!(
+                "Worktree '{}' already exists",
+                branch_name
+            )));
         }
 
         // Find git repository
         let repo_path = GitManager::find_repo_root(&self.vibetree_parent)
             .context("Not inside a git repository")?;
 
-        let branches_dir = self
-            .vibetree_parent
-            .join(&self.config.project_config.branches_dir);
+        let branches_dir = self.vibetree_parent.join(self.config.branches_dir());
         let worktree_path = branches_dir.join(&branch_name);
 
-        // Create branches directory if it doesn't exist
-        if !branches_dir.exists() {
-            std::fs::create_dir_all(&branches_dir).with_context(|| {
-                format!(
-                    "Failed to create branches directory: {}",
-                    branches_dir.display()
-                )
-            })?;
-        }
-
         if worktree_path.exists() {
-            anyhow::bail!("Directory '{}' already exists", worktree_path.display());
+            anyhow::bail!(VibeTreeError::conflict(format!(
+                "Directory '{}' already exists",
+                worktree_path.display()
+            )));
         }
 
+        // The new worktree sees the vibetree.toml committed at its base revision,
+        // which may define a different set of variables than the root config
+        let variables = self
+            .config
+            .variables_at_revision(&repo_path, from_branch.as_deref().unwrap_or("HEAD"))?;
+
         // Convert custom values Vec to HashMap if provided
         let custom_value_map = if let Some(custom) = custom_values {
             // Validate value count matches variable count
-            if custom.len() != self.config.project_config.variables.len() {
+            if custom.len() != variables.len() {
                 anyhow::bail!(
                     "Expected {} values for variables: {}",
-                    self.config.project_config.variables.len(),
-                    self.config
-                        .project_config
-                        .variables
+                    variables.len(),
+                    variables
                         .iter()
                         .map(|v| v.name.clone())
                         .collect::<Vec<_>>()
@@ -287,13 +456,7 @@ impl VibeTreeApp {
             }
 
             let mut value_map = HashMap::new();
-            for (variable, value) in self
-                .config
-                .project_config
-                .variables
-                .iter()
-                .zip(custom.iter())
-            {
+            for (variable, value) in variables.iter().zip(custom.iter()) {
                 value_map.insert(variable.name.clone(), value.clone());
             }
             Some(value_map)
@@ -301,77 +464,77 @@ impl VibeTreeApp {
             None
         };
 
-        // First, add the worktree to configuration (this handles port allocation and validation)
+        // Work out the values first (this handles port allocation and validation)
         let values = self
             .config
-            .add_worktree(branch_name.clone(), custom_value_map)?;
-
-        // Validate that allocated values that are ports are actually available on the system
-        // Only validate values that look like user ports (>= 1024), to avoid false positives
-        // from integer values like INSTANCE_ID that happen to be < 1024
-        // Skip this check during tests (VIBETREE_TESTING env var) since concurrent tests
-        // may temporarily occupy ports that would otherwise be available
-        if std::env::var("VIBETREE_TESTING").is_err() {
-            let port_values: Vec<u16> = values
-                .values()
-                .filter_map(|v| v.parse::<u16>().ok())
-                .filter(|&port| port >= 1024)
-                .collect();
-
-            if !port_values.is_empty() {
-                let availability = PortManager::check_ports_availability(&port_values);
-                let unavailable: Vec<u16> = availability
-                    .iter()
-                    .filter_map(|(&value, &available)| if !available { Some(value) } else { None })
-                    .collect();
-
-                if !unavailable.is_empty() {
-                    // Remove the worktree from config since value validation failed
-                    self.config.remove_worktree(&branch_name)?;
-                    anyhow::bail!(
-                        "The following ports are not available: {}",
-                        unavailable
-                            .iter()
-                            .map(|p| p.to_string())
-                            .collect::<Vec<_>>()
-                            .join(", ")
-                    );
-                }
+            .new_worktree_values(&branch_name, custom_value_map, &variables, !dry_run)?;
+        check_ports_available(&values)?;
+
+        let mut plan = Plan::new();
+        plan.push(Action::Allocate {
+            worktree: branch_name.clone(),
+            values: values.clone().into_iter().collect(),
+        });
+        plan.push(Action::CreateBranch {
+            name: branch_name.clone(),
+            from: from_branch.clone(),
+        });
+        plan.push(Action::CreateDir {
+            path: worktree_path.clone(),
+            branch: branch_name.clone(),
+        });
+        if let Some(options) = carry {
+            if CarriedChanges::pending(&repo_path, options)? {
+                plan.push(Action::CarryChanges {
+                    from: repo_path.clone(),
+                    to: worktree_path.clone(),
+                    include_untracked: options.include_untracked,
+                });
+            } else {
+                info!("No uncommitted changes to carry");
             }
         }
+        plan.push(Action::WriteEnvFile {
+            path: self.config.get_env_file_path(&worktree_path),
+            worktree: branch_name.clone(),
+        });
+        for resource in &self.config.project_config.resources {
+            plan.push(Action::CreateResource {
+                worktree: branch_name.clone(),
+                resource: resource.name.clone(),
+                command: ResourceManager::new(resource, &values).create_command(),
+            });
+        }
+        plan.actions.extend(extend(&worktree_path, &values)?);
+        let carried = plan
+            .actions
+            .iter()
+            .any(|action| matches!(action, Action::CarryChanges { .. }));
 
         if dry_run {
-            // Remove from configuration since this was just a dry run
-            self.config.remove_worktree(&branch_name)?;
-
-            info!("Dry run - would add worktree '{}' with:", branch_name);
-            info!("  Path: {}", worktree_path.display());
-            info!(
-                "  Base branch: {}",
-                from_branch.as_deref().unwrap_or("HEAD")
-            );
-            info!("  Values:");
-            for (variable, value) in &values {
-                info!("    {} → {}", variable, value);
-            }
-            return Ok(());
+            info!("Dry run - would add worktree '{}':", branch_name);
+            plan.report();
+            return Ok(AddResult {
+                name: branch_name,
+                path: worktree_path,
+                from: from_branch,
+                values,
+                // Nothing has moved yet; the plan lists the carry that would happen
+                carried: false,
+                dry_run: true,
+                plan,
+            });
         }
 
-        // Create git worktree
-        GitManager::create_worktree(
-            &repo_path,
-            &worktree_path,
-            &branch_name,
-            from_branch.as_deref(),
-        )
-        .context("Failed to create git worktree")?;
-
-        // Configuration was already updated by add_worktree above
-
-        // Generate environment file
-        let env_file_path = self.config.get_env_file_path(&worktree_path);
-        EnvFileGenerator::generate_env_file(&env_file_path, &branch_name, &values)
-            .context("Failed to generate environment file")?;
+        // Every action registers how to undo it, so a failure part way leaves the
+        // allocations, branches and worktrees as they were
+        let context = PlanContext {
+            command: "add",
+            repo_path: Some(&repo_path),
+            vibetree_parent: &self.vibetree_parent,
+            variables: &variables,
+        };
+        plan.execute(&mut self.config, &context)?;
 
         // Check and suggest .gitignore update
         if !EnvFileGenerator::suggest_gitignore_update(&worktree_path)? {
@@ -381,9 +544,6 @@ impl VibeTreeApp {
             );
         }
 
-        // Save configuration
-        self.save_config()?;
-
         info!(
             "Added worktree '{}' at {}",
             branch_name,
@@ -398,12 +558,124 @@ impl VibeTreeApp {
             "Use with process orchestrators like: docker compose --env-file .vibetree/env up"
         );
 
-        // Handle switch flag
-        if switch {
-            self.spawn_shell_in_directory(&worktree_path)?;
+        Ok(AddResult {
+            name: branch_name,
+            path: worktree_path,
+            from: from_branch,
+            values,
+            carried,
+            dry_run: false,
+            plan,
+        })
+    }
+
+    /// Add a worktree whose branch starts at another worktree's HEAD, optionally
+    /// copying its uncommitted changes and gitignored files and running the
+    /// `[fork]` hooks
+    pub fn fork_worktree(
+        &mut self,
+        source: String,
+        branch_name: String,
+        options: ForkOptions,
+    ) -> Result<ForkResult> {
+        let is_main = source == self.config.project_config.main_branch;
+        let Some(source_values) = self
+            .config
+            .branches_config
+            .worktrees
+            .get(&source)
+            .map(|worktree| worktree.values.clone())
+            .or_else(|| is_main.then(HashMap::new))
+        else {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Worktree '{}' not found",
+                source
+            )));
+        };
+
+        let source_path = self
+            .config
+            .get_worktree_path(&self.vibetree_parent, &source);
+        let validation = GitManager::validate_worktree_state(&source_path)?;
+        if !validation.is_git_worktree {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Worktree '{}' is missing at {}",
+                source,
+                source_path.display()
+            )));
         }
+        let Some(base) = validation.branch_name else {
+            anyhow::bail!("Worktree '{}' has no branch checked out", source);
+        };
 
-        Ok(())
+        info!("Forking worktree '{}' as '{}'", source, branch_name);
+
+        let copy_changes =
+            options.with_changes && GitManager::has_uncommitted_changes(&source_path)?;
+        let copied_ignored = if options.copy_ignored {
+            // Never copy vibetree's own state, or other worktrees when forking main
+            let branches_dir = self.config.branches_dir().to_string();
+            fork::ignored_paths(&source_path, &[".vibetree", branches_dir.as_str()])?
+        } else {
+            Vec::new()
+        };
+        let hooks = if options.no_hooks {
+            Vec::new()
+        } else {
+            self.config.project_config.fork.hooks.clone()
+        };
+
+        // The copies and hooks are part of the add, so when one fails the new
+        // worktree, its branch and its allocations are rolled back as well
+        let extend = |path: &Path, values: &HashMap<String, String>| -> Result<Vec<Action>> {
+            let mut actions = Vec::new();
+            if copy_changes {
+                actions.push(Action::CopyChanges {
+                    from: source_path.clone(),
+                    to: path.to_path_buf(),
+                });
+            }
+            if !copied_ignored.is_empty() {
+                actions.push(Action::CopyIgnored {
+                    from: source_path.clone(),
+                    to: path.to_path_buf(),
+                    paths: copied_ignored.clone(),
+                    mode: options.copy_mode,
+                });
+            }
+            for hook in &hooks {
+                actions.push(Action::RunHook {
+                    command: placeholders::expand_hook(hook, &source_values, values)?,
+                    dir: path.to_path_buf(),
+                });
+            }
+            Ok(actions)
+        };
+        let added =
+            self.add_worktree_planned(branch_name.clone(), Some(base), None, false, None, extend)?;
+
+        info!("Forked '{}' as '{}'", source, branch_name);
+        Ok(ForkResult {
+            name: branch_name,
+            from: source,
+            path: added.path,
+            values: added.values,
+            copied_changes: copy_changes,
+            copied_ignored,
+            hooks_run: hooks.len(),
+        })
+    }
+
+    /// Move uncommitted changes from one worktree into another
+    pub fn move_changes(
+        &self,
+        from: &str,
+        to: &str,
+        options: CarryOptions,
+    ) -> Result<MoveChangesResult> {
+        let changes_manager =
+            crate::changes::ChangesManager::new(&self.config, &self.vibetree_parent);
+        changes_manager.move_changes(from, to, options)
     }
 
     /// Remove a worktree and clean up resources
@@ -412,18 +684,38 @@ impl VibeTreeApp {
         branch_name: String,
         force: bool,
         keep_branch: bool,
-    ) -> Result<()> {
-        self.remove_worktree_with_confirmation(branch_name, force, keep_branch, true)
+    ) -> Result<RemoveResult> {
+        self.remove_worktree_with_options(
+            branch_name,
+            RemoveOptions {
+                force,
+                keep_branch,
+                dry_run: false,
+            },
+        )
+    }
+
+    /// Remove a worktree, or with `dry_run` only show what would be removed
+    pub fn remove_worktree_with_options(
+        &mut self,
+        branch_name: String,
+        options: RemoveOptions,
+    ) -> Result<RemoveResult> {
+        self.remove_worktree_with_confirmation(branch_name, options, true)
     }
 
     /// Remove a worktree and clean up resources with optional confirmation
     fn remove_worktree_with_confirmation(
         &mut self,
         branch_name: String,
-        force: bool,
-        keep_branch: bool,
+        options: RemoveOptions,
         prompt_for_confirmation: bool,
-    ) -> Result<()> {
+    ) -> Result<RemoveResult> {
+        let RemoveOptions {
+            force,
+            keep_branch,
+            dry_run,
+        } = options;
         info!("Removing worktree: {}", branch_name);
 
         if !self
@@ -432,68 +724,219 @@ impl VibeTreeApp {
             .worktrees
             .contains_key(&branch_name)
         {
-            anyhow::bail!("Worktree '{}' does not exist in configuration", branch_name);
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Worktree '{}' does not exist in configuration",
+                branch_name
+            )));
         }
 
-        let worktree_path = self
-            .vibetree_parent
-            .join(&self.config.project_config.branches_dir)
-            .join(&branch_name);
+        let repo_path = GitManager::find_repo_root(&self.vibetree_parent).ok();
+        let (worktree_path, plan) =
+            self.removal_plan(&branch_name, keep_branch, repo_path.as_deref());
+
+        // --force is for removing without questions, so don't wait on the forge either
+        let open_pull_request = if force {
+            None
+        } else {
+            crate::pr::PullRequestManager::new(&self.config, &self.vibetree_parent)
+                .open_pull_request(&branch_name)
+        };
+        if let Some(pr) = &open_pull_request {
+            warn!(
+                "Pull request #{} for '{}' is still open: {}",
+                pr.number, branch_name, pr.url
+            );
+        }
+
+        if dry_run {
+            info!("Dry run - would remove worktree '{}':", branch_name);
+            plan.report();
+            return Ok(RemoveResult {
+                name: branch_name,
+                path: worktree_path,
+                removed: false,
+                kept_branch: keep_branch,
+                dry_run: true,
+                plan,
+                open_pull_request,
+            });
+        }
 
         if !force && prompt_for_confirmation {
             warn!(
                 "Make sure no important processes are using the allocated ports before removing"
             );
-            print!(
-                "Are you sure you want to remove worktree '{}'? (y/N): ",
-                branch_name
+            let open = open_pull_request
+                .as_ref()
+                .map(|pr| format!(" with open pull request #{}", pr.number))
+                .unwrap_or_default();
+            let question = format!(
+                "Are you sure you want to remove worktree '{}'{}?",
+                branch_name, open
             );
-            io::stdout().flush().context("Failed to flush stdout")?;
-
-            let mut input = String::new();
-            io::stdin()
-                .read_line(&mut input)
-                .context("Failed to read confirmation input")?;
-
-            let input = input.trim().to_lowercase();
-            if input != "y" && input != "yes" {
+            if !output::confirm(&question, "--force")? {
                 info!("Cancelled removal of worktree '{}'", branch_name);
-                return Ok(());
+                return Ok(RemoveResult {
+                    name: branch_name,
+                    path: worktree_path,
+                    removed: false,
+                    kept_branch: true,
+                    dry_run: false,
+                    plan,
+                    open_pull_request,
+                });
             }
         }
 
-        // Find git repository and remove worktree
-        if let Ok(repo_path) = GitManager::find_repo_root(&self.vibetree_parent) {
-            if let Err(e) = GitManager::remove_worktree(&repo_path, &branch_name, keep_branch) {
-                warn!("Failed to remove git worktree: {}", e);
-                // Continue with cleanup even if git removal fails
-            }
-        }
+        // Services would otherwise keep running from a deleted directory
+        ServiceManager::new(&self.config, &self.vibetree_parent).stop_all(&branch_name);
 
-        // Remove from configuration
-        self.config.remove_worktree(&branch_name)?;
-        self.save_config()?;
+        // Every action registers how to undo it, so a failure part way leaves the
+        // worktree, its allocations and its branch as they were
+        let context = PlanContext {
+            command: "remove",
+            repo_path: repo_path.as_deref(),
+            vibetree_parent: &self.vibetree_parent,
+            variables: &[],
+        };
+        plan.execute(&mut self.config, &context)?;
 
-        // Remove directory if it still exists
-        if worktree_path.exists() {
-            std::fs::remove_dir_all(&worktree_path).with_context(|| {
-                format!("Failed to remove directory: {}", worktree_path.display())
-            })?;
+        let trash_path = plan::trash_path(&self.vibetree_parent, &branch_name);
+        // The removal is done, so the moved directory and git's record of the
+        // worktree can go
+        if trash_path.exists()
+            && let Err(e) = std::fs::remove_dir_all(&trash_path)
+        {
+            warn!("Failed to remove {}: {}", trash_path.display(), e);
         }
+        ServiceManager::new(&self.config, &self.vibetree_parent).forget(&branch_name);
+        if let Some(repo_path) = &repo_path
+            && let Err(e) = GitManager::remove_worktree(repo_path, &branch_name, true)
+        {
+            warn!("Failed to remove git worktree: {}", e);
+        }
+
+        self.closer.close(&branch_name, &worktree_path);
 
         info!("Removed worktree '{}'", branch_name);
         if keep_branch {
             info!("Kept git branch '{}'", branch_name);
         }
 
-        Ok(())
+        Ok(RemoveResult {
+            name: branch_name,
+            path: worktree_path,
+            removed: true,
+            kept_branch: keep_branch,
+            dry_run: false,
+            plan,
+            open_pull_request,
+        })
+    }
+
+    /// What removing a worktree changes: its directory, its allocation and,
+    /// unless kept, its branch
+    fn removal_plan(
+        &self,
+        branch_name: &str,
+        keep_branch: bool,
+        repo_path: Option<&std::path::Path>,
+    ) -> (PathBuf, Plan) {
+        let worktree_path = self
+            .vibetree_parent
+            .join(self.config.branches_dir())
+            .join(branch_name);
+
+        let mut plan = Plan::new();
+        if worktree_path.exists() {
+            plan.push(Action::RemoveDir {
+                path: worktree_path.clone(),
+                worktree: branch_name.to_string(),
+            });
+        }
+        let values = self
+            .config
+            .branches_config
+            .worktrees
+            .get(branch_name)
+            .map(|worktree| worktree.values.clone().into_iter().collect())
+            .unwrap_or_default();
+        plan.push(Action::Release {
+            worktree: branch_name.to_string(),
+            values,
+        });
+        if !keep_branch
+            && let Some(repo_path) = repo_path
+            && GitManager::branch_exists(repo_path, branch_name).unwrap_or(false)
+        {
+            plan.push(Action::DeleteBranch {
+                name: branch_name.to_string(),
+            });
+        }
+        // Destroyed resources and compose volumes can't be brought back, so they
+        // go after every step that could still fail and roll the removal back.
+        // Only resources that were created are destroyed
+        if let Some(worktree) = self.config.branches_config.worktrees.get(branch_name) {
+            for resource in &self.config.project_config.resources {
+                if worktree.resources.get(&resource.name) == Some(&ResourceState::Created) {
+                    plan.push(Action::DestroyResource {
+                        worktree: branch_name.to_string(),
+                        resource: resource.name.clone(),
+                        command: ResourceManager::new(resource, &worktree.values).destroy_command(),
+                        values: worktree.values.clone().into_iter().collect(),
+                    });
+                }
+            }
+        }
+        // Last, as resources may live in the compose project
+        if let Some(action) = self.compose_down_action(branch_name) {
+            plan.push(action);
+        }
+        (worktree_path, plan)
+    }
+
+    /// Take down the worktree's compose project, if it has containers or volumes
+    ///
+    /// Docker errors leave the project alone rather than blocking the removal.
+    fn compose_down_action(&self, branch_name: &str) -> Option<Action> {
+        let compose = ComposeManager::new(&self.config.project_config.compose);
+        let worktree = self.config.branches_config.worktrees.get(branch_name)?;
+        let project = compose.project_name(&worktree.values)?;
+        let listed = compose
+            .containers(&project)
+            .and_then(|containers| Ok((containers, compose.volumes(&project)?)));
+        let (containers, volumes) = match listed {
+            Ok(listed) => listed,
+            Err(e) => {
+                warn!("Could not list compose project '{}': {}", project, e);
+                return None;
+            }
+        };
+        if containers.is_empty() && volumes.is_empty() {
+            return None;
+        }
+        Some(Action::ComposeDown {
+            project,
+            containers: containers.into_iter().map(|c| c.name).collect(),
+            volumes,
+        })
     }
 
     /// List all worktrees and their configurations
     pub fn list_worktrees(&self, format: Option<OutputFormat>) -> Result<()> {
+        self.list_worktrees_with_options(format, &ListOptions::default())
+    }
+
+    /// List worktrees with column selection, sorting and filtering
+    pub fn list_worktrees_with_options(
+        &self,
+        format: Option<OutputFormat>,
+        options: &ListOptions,
+    ) -> Result<()> {
         let display_manager =
             crate::display::DisplayManager::new(&self.config, &self.vibetree_parent);
-        display_manager.list_worktrees(format)
+        let format = format.or_else(|| self.config.settings.format.clone());
+        display_manager.list_worktrees(format, options)
     }
 
     /// Collect worktree data with validation status for display
@@ -503,8 +946,11 @@ impl VibeTreeApp {
         display_manager.collect_worktree_data()
     }
 
-    fn save_config(&self) -> Result<()> {
-        self.config.save().context("Failed to save configuration")
+    /// Collect worktree data filtered and sorted by the list options
+    pub fn select_worktree_data(&self, options: &ListOptions) -> Result<Vec<WorktreeDisplayData>> {
+        let display_manager =
+            crate::display::DisplayManager::new(&self.config, &self.vibetree_parent);
+        display_manager.select_worktree_data(options)
     }
 
     // Getter methods to allow tests to access private fields
@@ -516,6 +962,61 @@ impl VibeTreeApp {
         &self.config.branches_config.worktrees
     }
 
+    /// Personal settings from the user config and vibetree.local.toml
+    pub fn settings(&self) -> &Settings {
+        &self.config.settings
+    }
+
+    /// The configuration merged from the defaults, the user config, vibetree.toml
+    /// and vibetree.local.toml, optionally with where each value came from
+    pub fn show_config(&self, origin: bool) -> Result<ConfigShowResult> {
+        let layers = LayeredConfig::load(&self.vibetree_parent)?;
+        let (config, origins) = layers.merged();
+        Ok(ConfigShowResult {
+            files: layers
+                .layers
+                .iter()
+                .filter_map(|layer| Some((layer.origin, layer.path.clone()?)))
+                .collect(),
+            config,
+            origins: origin.then_some(origins),
+        })
+    }
+
+    /// The variables defined in vibetree.toml
+    pub fn list_variables(&self) -> VariableListResult {
+        VariableListResult {
+            variables: self.config.project_config.variables.clone(),
+        }
+    }
+
+    /// Define a variable in vibetree.toml, then offer to repair the worktrees it affects
+    pub fn add_variable(
+        &mut self,
+        name: &str,
+        value: VariableValue,
+        branch: Option<String>,
+        yes: bool,
+    ) -> Result<VariableEditResult> {
+        VariableManager::new(&mut self.config, &self.vibetree_parent).add(name, value, branch, yes)
+    }
+
+    /// Change a variable in vibetree.toml, then offer to repair the worktrees it affects
+    pub fn set_variable(
+        &mut self,
+        name: &str,
+        value: VariableValue,
+        branch: Option<String>,
+        yes: bool,
+    ) -> Result<VariableEditResult> {
+        VariableManager::new(&mut self.config, &self.vibetree_parent).set(name, value, branch, yes)
+    }
+
+    /// Remove a variable from vibetree.toml, then offer to repair the worktrees it affected
+    pub fn remove_variable(&mut self, name: &str, yes: bool) -> Result<VariableEditResult> {
+        VariableManager::new(&mut self.config, &self.vibetree_parent).remove(name, yes)
+    }
+
     /// Get mutable access to config for testing
     #[doc(hidden)]
     pub fn get_config_mut(&mut self) -> &mut VibeTreeConfig {
@@ -523,12 +1024,120 @@ impl VibeTreeApp {
     }
 
     /// Repair configuration and discover orphaned worktrees
-    pub fn repair(&mut self, dry_run: bool) -> Result<()> {
+    pub fn repair(&mut self, dry_run: bool) -> Result<RepairResult> {
         let mut sync_manager =
             crate::sync::SyncManager::new(&mut self.config, &self.vibetree_parent);
         sync_manager.sync(dry_run)
     }
 
+    /// Check configuration and worktree state, fixing safe problems when asked
+    pub fn check(&mut self, fix: bool) -> Result<CheckReport> {
+        let mut check_manager =
+            crate::check::CheckManager::new(&mut self.config, &self.vibetree_parent);
+        check_manager.check(fix)
+    }
+
+    /// Push a worktree's branch and set its upstream
+    pub fn push_worktree(&self, branch_name: &str, force_with_lease: bool) -> Result<PushResult> {
+        let pr_manager = crate::pr::PullRequestManager::new(&self.config, &self.vibetree_parent);
+        pr_manager.push(branch_name, force_with_lease)
+    }
+
+    /// Show the open pull request for a worktree's branch, or create one
+    pub fn pull_request(
+        &self,
+        branch_name: &str,
+        options: PullRequestOptions,
+    ) -> Result<PullRequestResult> {
+        let pr_manager = crate::pr::PullRequestManager::new(&self.config, &self.vibetree_parent);
+        pr_manager.pull_request(branch_name, options)
+    }
+
+    /// Merge or rebase the main branch into one or all worktree branches
+    pub fn update(&self, options: UpdateOptions) -> Result<UpdateResult> {
+        let update_manager = crate::update::UpdateManager::new(&self.config, &self.vibetree_parent);
+        update_manager.update(options)
+    }
+
+    /// Start a worktree's services in the background and wait until they are ready
+    pub fn services_up(&self, branch_name: &str, services: &[String]) -> Result<ServicesResult> {
+        ServiceManager::new(&self.config, &self.vibetree_parent).up(branch_name, services)
+    }
+
+    /// Stop a worktree's services
+    pub fn services_down(&self, branch_name: &str, services: &[String]) -> Result<ServicesResult> {
+        ServiceManager::new(&self.config, &self.vibetree_parent).down(branch_name, services)
+    }
+
+    /// Print the output of a worktree's services
+    pub fn service_logs(
+        &self,
+        branch_name: &str,
+        service: Option<&str>,
+        follow: bool,
+        lines: Option<usize>,
+    ) -> Result<()> {
+        ServiceManager::new(&self.config, &self.vibetree_parent).logs(
+            branch_name,
+            service,
+            follow,
+            lines,
+        )
+    }
+
+    /// Remove merged, stale or missing worktrees after confirmation
+    ///
+    /// Each worktree goes through the same removal plan as `remove`, so its
+    /// resources, compose project, services and windows go with it.
+    pub fn prune(
+        &mut self,
+        criteria: PruneCriteria,
+        yes: bool,
+        dry_run: bool,
+    ) -> Result<PruneResult> {
+        let candidates = crate::prune::PruneManager::new(&self.config, &self.vibetree_parent)
+            .candidates(criteria)?;
+        let mut result = PruneResult {
+            dry_run,
+            candidates,
+            removals: Vec::new(),
+        };
+        if result.candidates.is_empty() {
+            return Ok(result);
+        }
+
+        if !dry_run && !yes {
+            let question = format!("Prune {} worktree(s)?", result.candidates.len());
+            if !output::confirm(&question, "--yes")? {
+                info!("Cancelled pruning");
+                return Ok(result);
+            }
+        }
+
+        for candidate in &result.candidates {
+            let removal = self.remove_worktree_with_confirmation(
+                candidate.name.clone(),
+                RemoveOptions {
+                    force: true,
+                    keep_branch: !candidate.delete_branch,
+                    dry_run,
+                },
+                false,
+            );
+            match removal {
+                Ok(removal) => result.removals.push(removal),
+                Err(e) => warn!("Failed to prune '{}': {:#}", candidate.name, e),
+            }
+        }
+
+        if dry_run {
+            info!("Dry run - no changes made");
+        } else {
+            info!("Pruned {} worktree(s)", result.removals.len());
+        }
+        Ok(result)
+    }
+
     /// Merge a worktree branch into target branch
     pub fn merge_worktree(
         &mut self,
@@ -537,11 +1146,51 @@ impl VibeTreeApp {
         squash: bool,
         rebase: bool,
         remove_after: bool,
-    ) -> Result<()> {
+    ) -> Result<MergeResult> {
+        self.merge_worktree_with_options(
+            branch_name,
+            MergeOptions {
+                into,
+                squash,
+                rebase,
+                remove_after,
+                ..MergeOptions::default()
+            },
+        )
+    }
+
+    /// Merge a worktree branch, with control over the commit message and fast-forwarding
+    pub fn merge_worktree_with_options(
+        &mut self,
+        branch_name: String,
+        options: MergeOptions,
+    ) -> Result<MergeResult> {
         info!("Merging worktree: {}", branch_name);
 
+        if MergeOperations::load(&self.vibetree_parent)?
+            .merges
+            .contains_key(&branch_name)
+        {
+            anyhow::bail!(VibeTreeError::conflict(format!(
+                "A merge of '{}' is already in progress; run 'vibetree merge --continue' or 'vibetree merge --abort'",
+                branch_name
+            )));
+        }
+
+        let (squash, rebase, remove_after) = (options.squash, options.rebase, options.remove_after);
+
         // Determine target branch
-        let target_branch = into.unwrap_or_else(|| self.config.project_config.main_branch.clone());
+        let target_branch = options
+            .into
+            .clone()
+            .unwrap_or_else(|| self.config.project_config.main_branch.clone());
+        let strategy = if rebase {
+            MergeStrategy::Rebase
+        } else if squash {
+            MergeStrategy::Squash
+        } else {
+            MergeStrategy::Merge
+        };
 
         // Validate: can't merge main into itself
         if branch_name == target_branch {
@@ -562,15 +1211,18 @@ impl VibeTreeApp {
 
         // Check if target branch exists
         if !GitManager::branch_exists(&repo_path, &target_branch)? {
-            anyhow::bail!("Target branch '{}' does not exist", target_branch);
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Target branch '{}' does not exist",
+                target_branch
+            )));
         }
 
         // Check if source branch exists
         if !GitManager::branch_exists(&repo_path, &branch_name)? {
-            anyhow::bail!(
+            anyhow::bail!(VibeTreeError::not_found(format!(
                 "Branch '{}' does not exist in git. Run 'vibetree repair' to sync configuration.",
                 branch_name
-            );
+            )));
         }
 
         // Check if already merged
@@ -580,37 +1232,75 @@ impl VibeTreeApp {
                 branch_name, target_branch
             );
 
+            if options.dry_run {
+                let mut plan = Plan::new();
+                if remove_after {
+                    plan = self.removal_plan(&branch_name, false, Some(&repo_path)).1;
+                    info!("Dry run - would remove worktree '{}':", branch_name);
+                    plan.report();
+                }
+                return Ok(MergeResult {
+                    name: branch_name,
+                    into: target_branch,
+                    strategy,
+                    already_merged: true,
+                    removed: false,
+                    message: None,
+                    dry_run: true,
+                    plan,
+                    conflicts: Vec::new(),
+                });
+            }
+
             // Handle cleanup
-            if remove_after {
-                return self.handle_post_merge_cleanup(&branch_name);
+            let removed = if remove_after {
+                self.handle_post_merge_cleanup(&branch_name)?
             } else {
                 info!("To clean up the worktree, run:");
                 info!("  vibetree remove {}", branch_name);
-                return Ok(());
-            }
+                false
+            };
+            return Ok(MergeResult {
+                name: branch_name,
+                into: target_branch,
+                strategy,
+                already_merged: true,
+                removed,
+                message: None,
+                dry_run: false,
+                plan: Plan::new(),
+                conflicts: Vec::new(),
+            });
         }
 
         // Get worktree path for checking uncommitted changes
         let worktree_path = self
             .vibetree_parent
-            .join(&self.config.project_config.branches_dir)
+            .join(self.config.branches_dir())
             .join(&branch_name);
 
         // Check for uncommitted changes in worktree (if it exists as a directory)
-        if worktree_path.exists() {
-            if GitManager::has_uncommitted_changes(&worktree_path)? {
-                eprintln!("Cannot merge: worktree '{}' has uncommitted changes.", branch_name);
-                eprintln!();
-                eprintln!("To resolve:");
-                eprintln!("  vibetree switch {}", branch_name);
-                eprintln!("  git add . && git commit -m \"your message\"");
-                eprintln!("  vibetree merge {}", branch_name);
-                anyhow::bail!("Uncommitted changes in worktree");
-            }
+        if worktree_path.exists() && GitManager::has_uncommitted_changes(&worktree_path)? {
+            eprintln!(
+                "Cannot merge: worktree '{}' has uncommitted changes.",
+                branch_name
+            );
+            eprintln!();
+            eprintln!("To resolve:");
+            eprintln!("  vibetree switch {}", branch_name);
+            eprintln!("  git add . && git commit -m \"your message\"");
+            eprintln!("  vibetree merge {}", branch_name);
+            anyhow::bail!(VibeTreeError::dirty_tree("Uncommitted changes in worktree"));
         }
 
-        // Check for uncommitted changes in target (main worktree)
-        if GitManager::has_uncommitted_changes(&self.vibetree_parent)? {
+        // The target may be checked out at the repo root, in another worktree, or
+        // nowhere; in the last case the merge updates the branch ref directly
+        let target_worktree = GitManager::find_worktree_for_branch(&repo_path, &target_branch)?;
+
+        // Check for uncommitted changes where the target is checked out
+        if let Some(target_path) = &target_worktree
+            && GitManager::has_uncommitted_changes(target_path)?
+        {
             eprintln!(
                 "Cannot merge: target branch '{}' has uncommitted changes.",
                 target_branch
@@ -620,9 +1310,61 @@ impl VibeTreeApp {
             eprintln!("  vibetree switch {}", target_branch);
             eprintln!("  git add . && git commit -m \"your message\"");
             eprintln!("  vibetree merge {}", branch_name);
-            anyhow::bail!("Uncommitted changes in target");
+            anyhow::bail!(VibeTreeError::dirty_tree("Uncommitted changes in target"));
+        }
+
+        let merge_plan = |message: Option<String>| {
+            let mut plan = Plan::new();
+            plan.push(Action::Merge {
+                branch: branch_name.clone(),
+                into: target_branch.clone(),
+                strategy,
+                message,
+                no_ff: options.no_ff,
+            });
+            plan
+        };
+
+        if options.dry_run {
+            let mut plan = merge_plan(None);
+            let prediction = if rebase {
+                GitManager::predict_rebase_conflicts(&repo_path, &branch_name, &target_branch)?
+            } else {
+                GitManager::predict_merge_conflicts(&repo_path, &branch_name, &target_branch)?
+            };
+            if remove_after && prediction.is_clean() {
+                let removal = self.removal_plan(&branch_name, false, Some(&repo_path)).1;
+                plan.actions.extend(removal.actions);
+            }
+            info!("Dry run - would merge '{}':", branch_name);
+            plan.report();
+            if !prediction.is_clean() {
+                eprintln!("The merge would stop with conflicts.");
+                print_conflicts(&prediction);
+            }
+            return Ok(MergeResult {
+                name: branch_name,
+                into: target_branch,
+                strategy,
+                already_merged: false,
+                removed: false,
+                message: None,
+                dry_run: true,
+                plan,
+                conflicts: prediction.paths,
+            });
         }
 
+        // Message of the commit created by the merge, if one is created
+        let message;
+        let plan;
+        let context = PlanContext {
+            command: "merge",
+            repo_path: Some(&repo_path),
+            vibetree_parent: &self.vibetree_parent,
+            variables: &[],
+        };
+
         // Test if operation would succeed and execute
         if rebase {
             // For rebase, we need to run from the worktree directory where the branch is checked out
@@ -632,15 +1374,54 @@ impl VibeTreeApp {
                 None
             };
 
-            // Try the rebase - if it fails with conflicts, we abort and provide guidance
-            match GitManager::rebase_and_merge(&repo_path, &branch_name, &target_branch, wt_path) {
-                Ok(()) => {
+            // Replay the commits in memory first so a conflicting rebase never starts.
+            // If the real rebase still fails with conflicts, it aborts and we give guidance.
+            let prediction =
+                GitManager::predict_rebase_conflicts(&repo_path, &branch_name, &target_branch)?;
+            let result = if prediction.is_clean() {
+                // Rebasing always allows a fast-forward, so only --no-ff makes a commit
+                let no_ff_message = if options.no_ff {
+                    Some(self.merge_commit_message(
+                        &repo_path,
+                        &branch_name,
+                        &target_branch,
+                        &options,
+                    )?)
+                } else {
+                    warn_unused_message(&options);
+                    None
+                };
+                let rebase_plan = merge_plan(no_ff_message.clone());
+                rebase_plan
+                    .execute(&mut self.config, &context)
+                    .map(|()| (rebase_plan, no_ff_message))
+            } else {
+                Err(anyhow::anyhow!("Rebase would have conflicts"))
+            };
+            match result {
+                Ok(executed) => {
                     // Success - continue below
+                    (plan, message) = executed;
                 }
                 Err(e) => {
                     let err_str = e.to_string();
-                    if err_str.contains("conflict") || err_str.contains("CONFLICT") || err_str.contains("Rebase failed") {
+                    if !prediction.is_clean()
+                        || err_str.contains("conflict")
+                        || err_str.contains("CONFLICT")
+                        || err_str.contains("Rebase failed")
+                    {
+                        if options.keep_conflicts {
+                            return self.keep_conflicts(
+                                &repo_path,
+                                &branch_name,
+                                &target_branch,
+                                strategy,
+                                wt_path,
+                                &options,
+                            );
+                        }
                         eprintln!("Cannot rebase cleanly - conflicts would occur.");
+                        print_conflicts(&prediction);
                         eprintln!();
                         eprintln!("To resolve manually:");
                         eprintln!("  vibetree switch {}", branch_name);
@@ -650,8 +1431,13 @@ impl VibeTreeApp {
                         eprintln!();
                         eprintln!("  vibetree switch {}", target_branch);
                         eprintln!("  git merge --ff-only {}", branch_name);
-                        eprintln!("  vibetree merge {}   # Detects merge complete, offers cleanup", branch_name);
-                        anyhow::bail!("Rebase would have conflicts");
+                        eprintln!(
+                            "  vibetree merge {}   # Detects merge complete, offers cleanup",
+                            branch_name
+                        );
+                        eprintln!();
+                        eprintln!("Or leave the conflicts in place with --keep-conflicts.");
+                        return Err(conflict_error("Rebase would have conflicts", &prediction));
                     } else {
                         return Err(e);
                     }
@@ -662,55 +1448,101 @@ impl VibeTreeApp {
                 branch_name, target_branch
             );
         } else if squash {
-            // Test squash
-            if !GitManager::can_squash_cleanly(&repo_path, &branch_name, &target_branch)? {
+            // Test squash in memory - it combines the same trees as a regular merge
+            let prediction =
+                GitManager::predict_merge_conflicts(&repo_path, &branch_name, &target_branch)?;
+            if !prediction.is_clean() {
+                if options.keep_conflicts {
+                    return self.keep_conflicts(
+                        &repo_path,
+                        &branch_name,
+                        &target_branch,
+                        strategy,
+                        target_worktree.as_deref(),
+                        &options,
+                    );
+                }
                 eprintln!("Cannot squash merge cleanly - conflicts detected.");
+                print_conflicts(&prediction);
                 eprintln!();
                 eprintln!("To resolve manually:");
                 eprintln!("  vibetree switch {}", target_branch);
                 eprintln!("  git merge --squash {}", branch_name);
                 eprintln!("  # Resolve conflicts, then:");
                 eprintln!("  git add . && git commit -m \"your message\"");
-                eprintln!("  vibetree merge {}   # Detects merge complete, offers cleanup", branch_name);
-                anyhow::bail!("Squash merge would have conflicts");
+                eprintln!(
+                    "  vibetree merge {}   # Detects merge complete, offers cleanup",
+                    branch_name
+                );
+                eprintln!();
+                eprintln!("Or leave the conflicts in place with --keep-conflicts.");
+                return Err(conflict_error(
+                    "Squash merge would have conflicts",
+                    &prediction,
+                ));
             }
 
-            // Prompt for commit message
-            print!("Enter commit message for squash merge: ");
-            io::stdout().flush().context("Failed to flush stdout")?;
-
-            let mut message = String::new();
-            io::stdin()
-                .read_line(&mut message)
-                .context("Failed to read commit message")?;
-
-            let message = message.trim();
-            if message.is_empty() {
-                anyhow::bail!("Commit message cannot be empty");
-            }
+            let squash_message =
+                self.merge_commit_message(&repo_path, &branch_name, &target_branch, &options)?;
 
             // Execute squash merge
-            GitManager::squash_merge_branch(&repo_path, &branch_name, &target_branch, message)?;
+            message = Some(squash_message);
+            plan = merge_plan(message.clone());
+            plan.execute(&mut self.config, &context)?;
             info!(
                 "Successfully squash merged '{}' into '{}'",
                 branch_name, target_branch
             );
         } else {
-            // Test regular merge
-            if !GitManager::can_merge_cleanly(&repo_path, &branch_name, &target_branch)? {
+            // Test regular merge in memory
+            let prediction =
+                GitManager::predict_merge_conflicts(&repo_path, &branch_name, &target_branch)?;
+            if !prediction.is_clean() {
+                if options.keep_conflicts {
+                    return self.keep_conflicts(
+                        &repo_path,
+                        &branch_name,
+                        &target_branch,
+                        strategy,
+                        target_worktree.as_deref(),
+                        &options,
+                    );
+                }
                 eprintln!("Cannot merge cleanly - conflicts detected.");
+                print_conflicts(&prediction);
                 eprintln!();
                 eprintln!("To resolve manually:");
                 eprintln!("  vibetree switch {}", target_branch);
                 eprintln!("  git merge {}", branch_name);
                 eprintln!("  # Resolve conflicts, then:");
                 eprintln!("  git add . && git commit");
-                eprintln!("  vibetree merge {}   # Detects merge complete, offers cleanup", branch_name);
-                anyhow::bail!("Merge would have conflicts");
+                eprintln!(
+                    "  vibetree merge {}   # Detects merge complete, offers cleanup",
+                    branch_name
+                );
+                eprintln!();
+                eprintln!("Or leave the conflicts in place with --keep-conflicts.");
+                return Err(conflict_error("Merge would have conflicts", &prediction));
             }
 
+            // A fast-forward creates no commit, so there's no message to write
+            let fast_forward = !options.no_ff
+                && GitManager::can_fast_forward(&repo_path, &branch_name, &target_branch)?;
+            message = if fast_forward {
+                warn_unused_message(&options);
+                None
+            } else {
+                Some(self.merge_commit_message(
+                    &repo_path,
+                    &branch_name,
+                    &target_branch,
+                    &options,
+                )?)
+            };
+
             // Execute merge
-            GitManager::merge_branch(&repo_path, &branch_name, &target_branch)?;
+            plan = merge_plan(message.clone());
+            plan.execute(&mut self.config, &context)?;
             info!(
                 "Successfully merged '{}' into '{}'",
                 branch_name, target_branch
@@ -718,34 +1550,295 @@ impl VibeTreeApp {
         }
 
         // Handle cleanup
-        if remove_after {
-            self.handle_post_merge_cleanup(&branch_name)?;
+        let removed = if remove_after {
+            self.handle_post_merge_cleanup(&branch_name)?
         } else {
             info!("To clean up the worktree, run:");
             info!("  vibetree remove {}", branch_name);
+            false
+        };
+
+        Ok(MergeResult {
+            name: branch_name,
+            into: target_branch,
+            strategy,
+            already_merged: false,
+            removed,
+            message,
+            dry_run: false,
+            plan,
+            conflicts: Vec::new(),
+        })
+    }
+
+    /// Build the commit message for a merge: the given or default message, plus
+    /// configured trailers, optionally edited by the user
+    fn merge_commit_message(
+        &self,
+        repo_path: &std::path::Path,
+        branch_name: &str,
+        target_branch: &str,
+        options: &MergeOptions,
+    ) -> Result<String> {
+        let message =
+            self.unedited_commit_message(repo_path, branch_name, target_branch, options)?;
+        if options.edit {
+            edit_commit_message(repo_path, &message, self.settings().editor.as_deref())
+        } else {
+            Ok(message)
         }
+    }
 
-        Ok(())
+    /// The given or default commit message for a merge, plus configured trailers
+    fn unedited_commit_message(
+        &self,
+        repo_path: &std::path::Path,
+        branch_name: &str,
+        target_branch: &str,
+        options: &MergeOptions,
+    ) -> Result<String> {
+        let commits = GitManager::branch_commits(repo_path, branch_name, target_branch)?;
+
+        let message = match &options.message {
+            Some(message) => message.clone(),
+            None if options.squash => {
+                merge::default_squash_message(branch_name, target_branch, &commits)
+            }
+            None => merge::default_merge_message(branch_name, target_branch),
+        };
+
+        let message = match &self.config.project_config.merge.trailer {
+            Some(template) => {
+                let trailers = merge::expand_trailer(
+                    template,
+                    branch_name,
+                    target_branch,
+                    &commits,
+                    GitManager::user_email(repo_path).as_deref(),
+                );
+                merge::append_trailers(&message, &trailers)
+            }
+            None => message,
+        };
+
+        Ok(message)
     }
 
-    /// Handle worktree cleanup after a successful merge
-    fn handle_post_merge_cleanup(&mut self, branch_name: &str) -> Result<()> {
+    /// Actually start a conflicting merge or rebase, leaving the conflicts in
+    /// place and recording the operation so `merge --continue` can finish it
+    fn keep_conflicts(
+        &mut self,
+        repo_path: &std::path::Path,
+        branch_name: &str,
+        target_branch: &str,
+        strategy: MergeStrategy,
+        location: Option<&std::path::Path>,
+        options: &MergeOptions,
+    ) -> Result<MergeResult> {
+        let Some(location) = location else {
+            if strategy == MergeStrategy::Rebase {
+                anyhow::bail!(
+                    "Cannot keep conflicts: '{}' has no worktree to rebase in",
+                    branch_name
+                );
+            }
+            anyhow::bail!(
+                "Cannot keep conflicts: '{}' is not checked out in any worktree",
+                target_branch
+            );
+        };
+
+        // Rebasing only creates a commit of its own with --no-ff
+        let message = if strategy == MergeStrategy::Rebase && !options.no_ff {
+            None
+        } else {
+            Some(self.unedited_commit_message(repo_path, branch_name, target_branch, options)?)
+        };
+
+        let conflicts = if strategy == MergeStrategy::Rebase {
+            GitManager::start_rebase(location, target_branch)?
+        } else {
+            GitManager::start_merge(location, branch_name, strategy == MergeStrategy::Squash)?
+        };
+
+        let mut operations = MergeOperations::load(&self.vibetree_parent)?;
+        operations.merges.insert(
+            branch_name.to_string(),
+            MergeState {
+                into: target_branch.to_string(),
+                strategy,
+                path: location.to_path_buf(),
+                message,
+                edit: options.edit,
+                remove_after: options.remove_after,
+                no_ff: strategy == MergeStrategy::Merge || options.no_ff,
+            },
+        );
+        operations.save(&self.vibetree_parent)?;
+
+        // The prediction was pessimistic; nothing is left to resolve
+        if conflicts.is_empty() {
+            return self.continue_merge(Some(branch_name.to_string()), false, false);
+        }
+
+        eprintln!("Conflicts left in {}:", location.display());
+        for path in &conflicts {
+            eprintln!("  {}", path);
+        }
+        eprintln!();
+        eprintln!("Resolve them and stage the results with git add, then run:");
+        eprintln!("  vibetree merge --continue {}", branch_name);
+        eprintln!("To give up and restore the previous state:");
+        eprintln!("  vibetree merge --abort {}", branch_name);
+
+        Err(
+            VibeTreeError::conflict(format!("Merge of '{}' stopped with conflicts", branch_name))
+                .with_details(serde_json::json!({
+                    "conflicts": conflicts,
+                    "path": location,
+                    "in_progress": true,
+                }))
+                .into(),
+        )
+    }
+
+    /// Finish a merge that was stopped by conflicts once they are resolved
+    pub fn continue_merge(
+        &mut self,
+        branch_name: Option<String>,
+        remove_after: bool,
+        edit: bool,
+    ) -> Result<MergeResult> {
+        let mut operations = MergeOperations::load(&self.vibetree_parent)?;
+        let (branch_name, state) = operations.resolve(branch_name)?;
+        info!("Continuing {} of '{}'", state.describe(), branch_name);
+
+        let repo_path = GitManager::find_repo_root(&self.vibetree_parent)
+            .context("Not inside a git repository")?;
+
+        let conflicts = GitManager::unmerged_paths(&state.path)?;
+        if !conflicts.is_empty() {
+            return Err(unresolved_error(&branch_name, &conflicts));
+        }
+
+        let edit = edit || state.edit;
+        let message = match &state.message {
+            Some(message) if edit => Some(edit_commit_message(
+                &repo_path,
+                message,
+                self.settings().editor.as_deref(),
+            )?),
+            message => message.clone(),
+        };
+
+        let message = if state.strategy == MergeStrategy::Rebase {
+            message
+        } else {
+            Some(message.unwrap_or_else(|| merge::default_merge_message(&branch_name, &state.into)))
+        };
+        let plan = Plan {
+            actions: vec![Action::Merge {
+                branch: branch_name.clone(),
+                into: state.into.clone(),
+                strategy: state.strategy,
+                message: message.clone(),
+                no_ff: state.no_ff,
+            }],
+        };
+
+        if state.strategy == MergeStrategy::Rebase {
+            if GitManager::rebase_in_progress(&state.path)? {
+                let conflicts = GitManager::continue_rebase(&state.path)?;
+                if !conflicts.is_empty() {
+                    eprintln!("The rebase stopped at another conflicting commit.");
+                    return Err(unresolved_error(&branch_name, &conflicts));
+                }
+            }
+            // The branch is now on top of the target, so the plan only has to
+            // merge it, and undoes that if it fails
+            let context = PlanContext {
+                command: "merge",
+                repo_path: Some(&repo_path),
+                vibetree_parent: &self.vibetree_parent,
+                variables: &[],
+            };
+            plan.execute(&mut self.config, &context)?;
+        } else {
+            // The merge itself already ran when it stopped; committing finishes it
+            let message = message.as_deref().context("A merge needs a commit message")?;
+            GitManager::commit_merge(&state.path, message)?;
+        }
+
+        operations.merges.remove(&branch_name);
+        operations.save(&self.vibetree_parent)?;
+        info!(
+            "Successfully merged '{}' into '{}'",
+            branch_name, state.into
+        );
+
+        let removed = if remove_after || state.remove_after {
+            self.handle_post_merge_cleanup(&branch_name)?
+        } else {
+            info!("To clean up the worktree, run:");
+            info!("  vibetree remove {}", branch_name);
+            false
+        };
+
+        Ok(MergeResult {
+            name: branch_name,
+            into: state.into,
+            strategy: state.strategy,
+            already_merged: false,
+            removed,
+            message,
+            dry_run: false,
+            plan,
+            conflicts: Vec::new(),
+        })
+    }
+
+    /// Abandon a merge that was stopped by conflicts and restore the previous state
+    pub fn abort_merge(&mut self, branch_name: Option<String>) -> Result<MergeAbortResult> {
+        let mut operations = MergeOperations::load(&self.vibetree_parent)?;
+        let (branch_name, state) = operations.resolve(branch_name)?;
+
+        if state.strategy == MergeStrategy::Rebase {
+            if GitManager::rebase_in_progress(&state.path)? {
+                GitManager::abort_rebase(&state.path)?;
+            }
+        } else {
+            GitManager::abort_merge(&state.path)?;
+        }
+
+        operations.merges.remove(&branch_name);
+        operations.save(&self.vibetree_parent)?;
+        info!("Aborted {} of '{}'", state.describe(), branch_name);
+
+        Ok(MergeAbortResult {
+            name: branch_name,
+            into: state.into,
+            strategy: state.strategy,
+        })
+    }
+
+    /// Handle worktree cleanup after a successful merge, returning whether it was removed
+    fn handle_post_merge_cleanup(&mut self, branch_name: &str) -> Result<bool> {
         // Check if we're currently in the worktree being removed
         let worktree_path = self
             .vibetree_parent
-            .join(&self.config.project_config.branches_dir)
+            .join(self.config.branches_dir())
             .join(branch_name);
 
-        if let Ok(current_dir) = std::env::current_dir() {
-            if current_dir.starts_with(&worktree_path) {
-                warn!(
-                    "You are currently in the worktree '{}'. Cannot remove current directory.",
-                    branch_name
-                );
-                info!("Switch to another worktree first, then run:");
-                info!("  vibetree remove {}", branch_name);
-                return Ok(());
-            }
+        if let Ok(current_dir) = std::env::current_dir()
+            && current_dir.starts_with(&worktree_path)
+        {
+            warn!(
+                "You are currently in the worktree '{}'. Cannot remove current directory.",
+                branch_name
+            );
+            info!("Switch to another worktree first, then run:");
+            info!("  vibetree remove {}", branch_name);
+            return Ok(false);
         }
 
         // Check if worktree exists in config
@@ -753,8 +1846,11 @@ impl VibeTreeApp {
             // Use the internal method with force=true to skip prompts
             self.remove_worktree_with_confirmation(
                 branch_name.to_string(),
-                true,  // force
-                false, // don't keep branch
+                RemoveOptions {
+                    force: true,
+                    keep_branch: false,
+                    dry_run: false,
+                },
                 false, // don't prompt for confirmation
             )?;
             info!("Removed worktree '{}'", branch_name);
@@ -768,30 +1864,61 @@ impl VibeTreeApp {
             info!("Cleaned up branch '{}'", branch_name);
         }
 
-        Ok(())
+        Ok(true)
     }
 
     /// Switch to an existing worktree directory
-    pub fn switch_to_worktree(&self, branch_name: String) -> Result<()> {
+    ///
+    /// Through the `shell-init` wrapper this changes the calling shell's directory
+    /// and loads the worktree's env file; without it the path is printed. With
+    /// `subshell` a new shell is started in the worktree instead.
+    pub fn switch_to_worktree(&self, branch_name: String, subshell: bool) -> Result<()> {
         info!("Switching to worktree: {}", branch_name);
 
-        // Determine target directory
-        let target_path = if branch_name == self.config.project_config.main_branch {
+        let target_path = self.switch_target(&branch_name)?;
+
+        if !self.enter_directory(&target_path, subshell)? {
+            shell::print_integration_hint();
+            println!("{}", target_path.display());
+        }
+        Ok(())
+    }
+
+    /// Open a worktree in a tmux window or zellij tab of its own, laid out with
+    /// the `[layout]` panes and its env file loaded in every pane
+    pub fn open_in_multiplexer(&self, branch_name: &str, multiplexer: Multiplexer) -> Result<()> {
+        let path = self.switch_target(branch_name)?;
+        let env = EnvFileGenerator::read_env_file(&self.config.get_env_file_path(&path))?
+            .into_iter()
+            .collect();
+        multiplexer.open(&multiplexer::Workspace {
+            name: branch_name,
+            path: &path,
+            env: &env,
+            layout: &self.config.project_config.layout,
+        })
+    }
+
+    /// Directory of the worktree `switch` moves to: the repository root for the
+    /// main branch
+    fn switch_target(&self, branch_name: &str) -> Result<PathBuf> {
+        Ok(if branch_name == self.config.project_config.main_branch {
             // Switching to main branch - use root directory
             self.vibetree_parent.clone()
         } else {
             // Switching to a worktree branch
             let worktree_path = self
                 .vibetree_parent
-                .join(&self.config.project_config.branches_dir)
-                .join(&branch_name);
+                .join(self.config.branches_dir())
+                .join(branch_name);
 
             if !worktree_path.exists() {
-                return Err(anyhow::anyhow!(
+                return Err(VibeTreeError::not_found(format!(
                     "Worktree '{}' does not exist at {}",
                     branch_name,
                     worktree_path.display()
-                ));
+                ))
+                .into());
             }
 
             // Check if it's actually a git worktree
@@ -815,10 +1942,27 @@ impl VibeTreeApp {
             }
 
             worktree_path
-        };
+        })
+    }
 
-        // Spawn a shell in the target directory
-        self.spawn_shell_in_directory(&target_path)
+    /// Move the user into `path`, returning false when there was no way to
+    ///
+    /// Without `subshell` this needs the shell integration, since a child process
+    /// can't change its parent shell's directory.
+    fn enter_directory(&self, path: &std::path::Path, subshell: bool) -> Result<bool> {
+        if subshell {
+            self.spawn_shell_in_directory(path)?;
+            return Ok(true);
+        }
+
+        let env_file = self.config.get_env_file_path(path);
+        let directive = shell::Directive {
+            cd: path.to_path_buf(),
+            env: EnvFileGenerator::read_env_file(&env_file)?
+                .into_iter()
+                .collect(),
+        };
+        directive.send()
     }
 
     /// Spawn a new shell in the specified directory
@@ -849,8 +1993,8 @@ impl VibeTreeApp {
             
             // Use exec to replace the current shell process with a new one in main directory
             let shell_env = std::env::var("SHELL").unwrap_or_default();
-            let shell_name = shell_env.split('/').last().unwrap_or("bash");
-            
+            let shell_name = shell_env.split('/').next_back().unwrap_or("bash");
+
             // Get the actual main directory path from stored environment or config
             let main_path = std::env::var("VIBETREE_PREV_DIR")
                 .map(std::path::PathBuf::from)
@@ -913,8 +2057,8 @@ impl VibeTreeApp {
         
         // For nushell, we need to handle it differently since it doesn't support all features
         let shell_env = std::env::var("SHELL").unwrap_or_default();
-        let shell_name = shell_env.split('/').last().unwrap_or("bash");
-        
+        let shell_name = shell_env.split('/').next_back().unwrap_or("bash");
+
         // Spawn the shell in the target directory with environment variables
         let mut cmd = Command::new(&shell);
         cmd.current_dir(path)
@@ -965,36 +2109,35 @@ impl VibeTreeApp {
             if let Ok(output) = std::process::Command::new("ps")
                 .args(["-p", &pid.to_string(), "-o", "comm="])
                 .output()
+                && output.status.success()
             {
-                if output.status.success() {
-                    let process_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                    
-                    // Check if this is a shell process (common shell names)
-                    if process_name.ends_with("sh") || process_name.ends_with("zsh") || 
-                       process_name.ends_with("bash") || process_name.ends_with("fish") ||
-                       process_name.contains("nu") {
-                        shells.push((pid, process_name.clone()));
-                    }
-                    
-                    // For any process, try to get its parent and continue walking up
-                    if let Ok(parent_output) = std::process::Command::new("ps")
-                        .args(["-p", &pid.to_string(), "-o", "ppid="])
-                        .output()
-                    {
-                        if parent_output.status.success() {
-                            if let Ok(parent_pid) = String::from_utf8_lossy(&parent_output.stdout)
-                                .trim()
-                                .parse::<i32>()
-                            {
-                                pid = parent_pid;
-                                depth += 1;
-                                continue;
-                            }
-                        }
-                    }
+                let process_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+                // Check if this is a shell process (common shell names)
+                if process_name.ends_with("sh")
+                    || process_name.ends_with("zsh")
+                    || process_name.ends_with("bash")
+                    || process_name.ends_with("fish")
+                    || process_name.contains("nu")
+                {
+                    shells.push((pid, process_name.clone()));
+                }
+
+                // For any process, try to get its parent and continue walking up
+                if let Ok(parent_output) = std::process::Command::new("ps")
+                    .args(["-p", &pid.to_string(), "-o", "ppid="])
+                    .output()
+                    && parent_output.status.success()
+                    && let Ok(parent_pid) = String::from_utf8_lossy(&parent_output.stdout)
+                        .trim()
+                        .parse::<i32>()
+                {
+                    pid = parent_pid;
+                    depth += 1;
+                    continue;
                 }
             }
-            
+
             break;
         }
         
@@ -1028,10 +2171,10 @@ impl VibeTreeApp {
                     // Check if .envrc is allowed (allowed level 0 or 1, denied is 2+)
                     if let Some(line) = stdout.lines().find(|line| line.contains("Found RC allowed")) {
                         // Extract the number after "Found RC allowed"
-                        if let Some(allowed_str) = line.split("Found RC allowed").nth(1) {
-                            if let Ok(level) = allowed_str.trim().parse::<u32>() {
-                                return level <= 1; // 0 = allowed, 1 = allowed, 2+ = denied
-                            }
+                        if let Some(allowed_str) = line.split("Found RC allowed").nth(1)
+                            && let Ok(level) = allowed_str.trim().parse::<u32>()
+                        {
+                            return level <= 1; // 0 = allowed, 1 = allowed, 2+ = denied
                         }
                     }
                     false
@@ -1078,8 +2221,16 @@ impl VibeTreeApp {
         branch_name: String,
         force: bool,
         keep_branch: bool,
-    ) -> Result<()> {
-        self.remove_worktree_with_confirmation(branch_name, force, keep_branch, false)
+    ) -> Result<RemoveResult> {
+        self.remove_worktree_with_confirmation(
+            branch_name,
+            RemoveOptions {
+                force,
+                keep_branch,
+                dry_run: false,
+            },
+            false,
+        )
     }
 }
 
@@ -1089,6 +2240,103 @@ impl Default for VibeTreeApp {
     }
 }
 
+/// Validate that allocated values that are ports are actually available on the system
+///
+/// Only validates values that look like user ports (>= 1024), to avoid false positives
+/// from integer values like INSTANCE_ID that happen to be < 1024.
+/// Skipped during tests (VIBETREE_TESTING env var) since concurrent tests
+/// may temporarily occupy ports that would otherwise be available.
+fn check_ports_available(values: &HashMap<String, String>) -> Result<()> {
+    if std::env::var("VIBETREE_TESTING").is_ok() {
+        return Ok(());
+    }
+
+    let port_values: Vec<u16> = values
+        .values()
+        .filter_map(|v| v.parse::<u16>().ok())
+        .filter(|&port| port >= 1024)
+        .collect();
+    if port_values.is_empty() {
+        return Ok(());
+    }
+
+    let availability = PortManager::check_ports_availability(&port_values);
+    let unavailable: Vec<u16> = availability
+        .iter()
+        .filter_map(|(&value, &available)| if !available { Some(value) } else { None })
+        .collect();
+
+    if !unavailable.is_empty() {
+        anyhow::bail!(VibeTreeError::port_unavailable(format!(
+            "The following ports are not available: {}",
+            unavailable
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Warn that a fast-forward merge ignores --message and --edit
+fn warn_unused_message(options: &MergeOptions) {
+    if options.message.is_some() || options.edit {
+        warn!("Fast-forward merge creates no commit; use --no-ff to apply the commit message");
+    }
+}
+
+/// Let the user edit a commit message, refusing an empty result
+fn edit_commit_message(
+    repo_path: &std::path::Path,
+    message: &str,
+    editor: Option<&str>,
+) -> Result<String> {
+    let message = GitManager::edit_message(repo_path, message, editor)?;
+    if message.is_empty() {
+        anyhow::bail!("Aborting merge due to empty commit message");
+    }
+    Ok(message)
+}
+
+/// Error for `merge --continue` while conflicts are still unresolved
+fn unresolved_error(branch_name: &str, conflicts: &[String]) -> anyhow::Error {
+    eprintln!("Unresolved conflicts:");
+    for path in conflicts {
+        eprintln!("  {}", path);
+    }
+    eprintln!();
+    eprintln!("Resolve them and stage the results with git add, then run:");
+    eprintln!("  vibetree merge --continue {}", branch_name);
+
+    VibeTreeError::conflict(format!(
+        "Merge of '{}' still has unresolved conflicts",
+        branch_name
+    ))
+    .with_details(serde_json::json!({ "conflicts": conflicts, "in_progress": true }))
+    .into()
+}
+
+/// List the files a predicted merge or rebase would conflict on
+fn print_conflicts(prediction: &ConflictPrediction) {
+    if let Some(commit) = &prediction.commit {
+        eprintln!("Stops at commit {}", commit);
+    }
+    eprintln!("Conflicting files:");
+    for path in &prediction.paths {
+        eprintln!("  {}", path);
+    }
+}
+
+fn conflict_error(message: &str, prediction: &ConflictPrediction) -> anyhow::Error {
+    VibeTreeError::conflict(message)
+        .with_details(serde_json::json!({
+            "conflicts": prediction.paths,
+            "commit": prediction.commit,
+        }))
+        .into()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Exclusive advisory lock on a file, released when dropped
+///
+/// Uses flock(2), so the lock is also released if the process dies.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Block until an exclusive lock on `path` is acquired, creating the file if needed
+    pub fn acquire(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create lock directory: {}", parent.display())
+            })?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file: {}", path.display()))?;
+
+        // SAFETY: the fd is owned by `file`, which outlives this call
+        let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to lock {}", path.display()));
+        }
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // SAFETY: the fd is still owned by `self.file`
+        unsafe {
+            libc::flock(self.file.as_raw_fd(), libc::LOCK_UN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_is_reacquirable_after_drop() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let lock_path = temp_dir.path().join(".vibetree").join("lock");
+
+        let lock = FileLock::acquire(&lock_path)?;
+        assert!(lock_path.exists());
+        drop(lock);
+
+        // Would block forever if the first lock had not been released
+        let _lock = FileLock::acquire(&lock_path)?;
+        Ok(())
+    }
+}
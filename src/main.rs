@@ -2,7 +2,13 @@ use anyhow::Context;
 use clap::{CommandFactory, Parser};
 use clap_complete::env::CompleteEnv;
 use log::error;
-use vibetree::{generate_completions, Cli, Commands, VibeTreeApp};
+use vibetree::output::{self, CommandOutput};
+use vibetree::{
+    CarryOptions, Cli, Commands, ConfigCommand, ErrorKind, ForkOptions, InitOptions, ListOptions,
+    MergeOptions, Multiplexer, OutputFormat, OutputMode, Plan, PruneCriteria, PullRequestOptions,
+    RegistryCommand, RemoveOptions, UpdateOptions, VarCommand, VibeTreeApp, VibeTreeError,
+    generate_completions,
+};
 
 fn main() {
     // Handle dynamic shell completions (if triggered by shell completion request)
@@ -11,31 +17,89 @@ fn main() {
     env_logger::init();
 
     let cli = Cli::parse();
+    let output_mode = cli.output;
+    let command = cli.command.name();
 
-    if let Err(e) = run(cli) {
-        error!("Error: {}", e);
+    match run(cli) {
+        Ok(result) => {
+            // Commands without a structured result keep their own stdout output
+            if output_mode == OutputMode::Json
+                && let Some(result) = result
+            {
+                print_json(&CommandOutput::success(command, Some(result)));
+            }
+        }
+        Err(e) => {
+            if output_mode == OutputMode::Json {
+                print_json(&CommandOutput::failure(command, &e));
+            } else {
+                error!("Error: {}", e);
 
-        // Print error chain
-        let mut current = e.source();
-        while let Some(err) = current {
-            error!("  Caused by: {}", err);
-            current = err.source();
+                // Print error chain
+                let mut current = e.source();
+                while let Some(err) = current {
+                    error!("  Caused by: {}", err);
+                    current = err.source();
+                }
+            }
+
+            std::process::exit(output::exit_code(&e));
         }
+    }
+}
 
-        std::process::exit(1);
+fn print_json(output: &CommandOutput) {
+    match serde_json::to_string_pretty(output) {
+        Ok(json) => println!("{}", json),
+        Err(e) => error!("Failed to serialize command output: {}", e),
     }
 }
 
-fn run(cli: Cli) -> anyhow::Result<()> {
+/// Print the plan of a dry run; under `--output json` it's part of the result instead
+fn print_plan(output: OutputMode, heading: &str, plan: &Plan) {
+    if output == OutputMode::Text {
+        println!("{}", heading);
+        print!("{}", plan);
+    }
+}
+
+/// The multiplexer chosen with `--tmux` or `--zellij`
+fn multiplexer(tmux: bool, zellij: bool) -> Option<Multiplexer> {
+    if tmux {
+        Some(Multiplexer::Tmux)
+    } else if zellij {
+        Some(Multiplexer::Zellij)
+    } else {
+        None
+    }
+}
+
+/// Run a command, returning its structured result for `--output json` if it has one
+fn run(cli: Cli) -> anyhow::Result<Option<serde_json::Value>> {
     if cli.verbose {
         log::set_max_level(log::LevelFilter::Debug);
     }
 
-    match cli.command {
-        Commands::Init { variables } => {
+    let result = match cli.command {
+        Commands::Init {
+            variables,
+            dry_run,
+            reset,
+        } => {
+            let config_path = std::env::current_dir()?.join("vibetree.toml");
+            let existed = config_path.exists();
             // Init command can create configuration if it doesn't exist
             let mut app = VibeTreeApp::new()?;
-            app.init(variables)?;
+            let result = app.init_with_options(variables, InitOptions { dry_run, reset })?;
+            if dry_run {
+                // Remove the config file created by VibeTreeApp::new() since nothing should change
+                if !existed && config_path.exists() {
+                    std::fs::remove_file(&config_path)
+                        .context("Failed to remove created config file")?;
+                }
+                print_plan(cli.output, "Would initialize vibetree:", &result.plan);
+            }
+            Some(serde_json::to_value(result)?)
         }
 
         Commands::Add {
@@ -44,12 +108,14 @@ fn run(cli: Cli) -> anyhow::Result<()> {
             ports,
             dry_run,
             switch,
+            tmux,
+            zellij,
+            carry,
+            include_untracked,
         } => {
             // Try to load existing config first, fall back to empty config for worktrees without variables
-            match VibeTreeApp::load_existing() {
-                Ok(mut app) => {
-                    app.add_worktree(branch_name, from, ports, dry_run, switch)?;
-                }
+            let (mut app, discovery) = match VibeTreeApp::load_existing() {
+                Ok(app) => (app, false),
                 Err(_) => {
                     // No main config exists - only allow creation if no variables are needed (no ports specified)
                     if ports.is_some() {
@@ -57,52 +123,146 @@ fn run(cli: Cli) -> anyhow::Result<()> {
                             "Cannot add worktree with custom values when no configuration exists. Run 'vibetree init' first to configure variables."
                         );
                     }
-                    let mut app = VibeTreeApp::new()?;
-                    app.add_worktree(branch_name, from, None, dry_run, switch)?;
-                    // Remove the config file created by VibeTreeApp::new() since we're in discovery mode
-                    let config_path = std::env::current_dir()?.join("vibetree.toml");
-                    if config_path.exists() {
-                        std::fs::remove_file(&config_path)
-                            .context("Failed to remove created config file")?;
-                    }
+                    (VibeTreeApp::new()?, true)
+                }
+            };
+
+            // The [add] settings only apply when the command line doesn't say how to open it
+            let defaults = app.settings().add.clone();
+            let (switch, tmux, zellij) = if switch || tmux || zellij {
+                (switch, tmux, zellij)
+            } else {
+                (defaults.switch, defaults.tmux, defaults.zellij)
+            };
+            let carry = carry || (defaults.carry && from.is_none());
+
+            let carry = carry.then_some(CarryOptions { include_untracked });
+            let multiplexer = multiplexer(tmux, zellij).filter(|_| !dry_run);
+            let result =
+                app.add_worktree_carrying(branch_name, from, ports, dry_run, switch, carry)?;
+            if let Some(multiplexer) = multiplexer {
+                app.open_in_multiplexer(&result.name, multiplexer)?;
+            }
+            if discovery {
+                // Remove the config file created by VibeTreeApp::new() since we're in discovery mode
+                let config_path = std::env::current_dir()?.join("vibetree.toml");
+                if config_path.exists() {
+                    std::fs::remove_file(&config_path)
+                        .context("Failed to remove created config file")?;
                 }
             }
+            if dry_run {
+                let heading = format!("Would add worktree '{}':", result.name);
+                print_plan(cli.output, &heading, &result.plan);
+            }
+            Some(serde_json::to_value(result)?)
+        }
+
+        Commands::Fork {
+            source,
+            branch_name,
+            with_changes,
+            copy_ignored,
+            copy_mode,
+            no_hooks,
+        } => {
+            let options = ForkOptions {
+                with_changes,
+                copy_ignored,
+                copy_mode,
+                no_hooks,
+            };
+            let mut app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(app.fork_worktree(
+                source,
+                branch_name,
+                options,
+            )?)?)
+        }
+
+        Commands::MoveChanges {
+            from,
+            to,
+            include_untracked,
+        } => {
+            let app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(app.move_changes(
+                &from,
+                &to,
+                CarryOptions { include_untracked },
+            )?)?)
         }
 
         Commands::Remove {
             branch_name,
             force,
             keep_branch,
+            dry_run,
         } => {
+            let options = RemoveOptions {
+                force,
+                keep_branch,
+                dry_run,
+            };
             // Try to load existing config first, fall back to discovery mode
-            match VibeTreeApp::load_existing() {
-                Ok(mut app) => {
-                    app.remove_worktree(branch_name, force, keep_branch)?;
-                }
+            let result = match VibeTreeApp::load_existing() {
+                Ok(mut app) => app.remove_worktree_with_options(branch_name, options)?,
                 Err(_) => {
                     // No main config exists - try to load branches config directly for removal
                     let mut app = VibeTreeApp::new()?;
-                    app.remove_worktree(branch_name, force, keep_branch)?;
+                    let result = app.remove_worktree_with_options(branch_name, options)?;
                     // Remove the config file created by VibeTreeApp::new() since we're in discovery mode
                     let config_path = std::env::current_dir()?.join("vibetree.toml");
                     if config_path.exists() {
                         std::fs::remove_file(&config_path)
                             .context("Failed to remove created config file")?;
                     }
+                    result
+                }
+            };
+            if dry_run {
+                let heading = format!("Would remove worktree '{}':", result.name);
+                print_plan(cli.output, &heading, &result.plan);
+                if cli.output == OutputMode::Text
+                    && let Some(pr) = &result.open_pull_request
+                {
+                    println!("Pull request #{} is still open: {}", pr.number, pr.url);
                 }
             }
+            Some(serde_json::to_value(result)?)
         }
 
-        Commands::List { format } => {
+        Commands::List {
+            format,
+            columns,
+            sort,
+            dirty,
+            merged,
+            filter,
+            pr,
+        } => {
+            let options = ListOptions {
+                columns,
+                sort,
+                dirty,
+                merged,
+                filter,
+                pr,
+            };
+            // Under --output json the listing itself is the structured result
+            let format = match (format, cli.output) {
+                (None, OutputMode::Json) => Some(OutputFormat::Json),
+                (format, _) => format,
+            };
             // Try to load existing configuration first, fall back to empty config
             match VibeTreeApp::load_existing() {
                 Ok(app) => {
-                    app.list_worktrees(format)?;
+                    app.list_worktrees_with_options(format, &options)?;
                 }
                 Err(_) => {
                     // No config exists - create temporary app to show empty list
                     let app = VibeTreeApp::new()?;
-                    app.list_worktrees(format)?;
+                    app.list_worktrees_with_options(format, &options)?;
                     // Remove any config file that might have been created
                     let config_path = std::env::current_dir()?.join("vibetree.toml");
                     if config_path.exists() {
@@ -111,38 +271,85 @@ fn run(cli: Cli) -> anyhow::Result<()> {
                     }
                 }
             }
+            None
         }
 
         Commands::Repair { dry_run } => {
             // Try to load existing configuration first
             match VibeTreeApp::load_existing() {
-                Ok(mut app) => {
-                    app.repair(dry_run)?;
-                }
+                Ok(mut app) => Some(serde_json::to_value(app.repair(dry_run)?)?),
                 Err(_) => {
                     // No config exists - run repair in discovery mode
                     let mut app = VibeTreeApp::new()?;
-                    app.repair(dry_run)?;
+                    let result = app.repair(dry_run)?;
                     // Remove the created config file since repair shouldn't create it
                     let config_path = std::env::current_dir()?.join("vibetree.toml");
                     if config_path.exists() {
                         std::fs::remove_file(&config_path)
                             .context("Failed to remove created config file")?;
                     }
+                    Some(serde_json::to_value(result)?)
                 }
             }
         }
 
-        Commands::Switch { branch_name } => {
+        Commands::Check { fix } => {
+            let mut app = VibeTreeApp::load_existing()?;
+            let report = app.check(fix)?;
+            if cli.output == OutputMode::Text {
+                report.report();
+            }
+
+            let result = serde_json::to_value(&report)?;
+            if !report.is_ok() {
+                return Err(VibeTreeError::new(
+                    ErrorKind::CheckFailed,
+                    format!("Check found {} error(s)", report.error_count()),
+                )
+                .with_details(result)
+                .into());
+            }
+            Some(result)
+        }
+
+        Commands::Prune {
+            merged,
+            gone,
+            stale,
+            missing,
+            yes,
+            dry_run,
+        } => {
+            let criteria = PruneCriteria {
+                merged,
+                gone,
+                stale_days: stale,
+                missing,
+            };
+            let mut app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(app.prune(criteria, yes, dry_run)?)?)
+        }
+
+        Commands::Switch {
+            branch_name,
+            subshell,
+            tmux,
+            zellij,
+        } => {
+            let multiplexer = multiplexer(tmux, zellij);
+            let switch = |app: &VibeTreeApp, branch_name: String| match multiplexer {
+                Some(multiplexer) => app.open_in_multiplexer(&branch_name, multiplexer),
+                None => app.switch_to_worktree(branch_name, subshell),
+            };
             // Try to load existing config, but fall back to simple directory navigation if none exists
             match VibeTreeApp::load_existing() {
                 Ok(app) => {
-                    app.switch_to_worktree(branch_name)?;
+                    switch(&app, branch_name)?;
                 }
                 Err(_) => {
                     // No config exists - try simple directory navigation
                     let app = VibeTreeApp::new()?;
-                    app.switch_to_worktree(branch_name)?;
+                    switch(&app, branch_name)?;
                     // Remove the config file created by VibeTreeApp::new() since we're in discovery mode
                     let config_path = std::env::current_dir()?.join("vibetree.toml");
                     if config_path.exists() {
@@ -151,10 +358,25 @@ fn run(cli: Cli) -> anyhow::Result<()> {
                     }
                 }
             }
+            None
+        }
+
+        Commands::ShellInit { shell } => {
+            let shell = match shell {
+                Some(shell) => shell,
+                None => VibeTreeApp::load_existing()
+                    .ok()
+                    .and_then(|app| app.settings().shell)
+                    .or_else(|| vibetree::settings::user_settings().ok()?.shell)
+                    .context("No shell given and no `shell` set in the user config")?,
+            };
+            print!("{}", vibetree::shell::shell_init(shell));
+            None
         }
 
         Commands::Completions { shell } => {
             generate_completions(shell);
+            None
         }
 
         Commands::Merge {
@@ -163,26 +385,209 @@ fn run(cli: Cli) -> anyhow::Result<()> {
             squash,
             rebase,
             remove,
+            message,
+            edit,
+            no_ff,
+            keep_conflicts,
+            dry_run,
+            continue_merge,
+            abort,
         } => {
+            if continue_merge {
+                let mut app = VibeTreeApp::load_existing()?;
+                return Ok(Some(serde_json::to_value(app.continue_merge(
+                    branch_name,
+                    remove,
+                    edit,
+                )?)?));
+            }
+            if abort {
+                let mut app = VibeTreeApp::load_existing()?;
+                return Ok(Some(serde_json::to_value(app.abort_merge(branch_name)?)?));
+            }
+
+            let branch_name = branch_name.context("A branch name is required")?;
+            let options = MergeOptions {
+                into,
+                squash,
+                rebase,
+                remove_after: remove,
+                message,
+                edit,
+                no_ff,
+                keep_conflicts,
+                dry_run,
+            };
+
             // Try to load existing config
-            match VibeTreeApp::load_existing() {
-                Ok(mut app) => {
-                    app.merge_worktree(branch_name, into, squash, rebase, remove)?;
-                }
+            let result = match VibeTreeApp::load_existing() {
+                Ok(mut app) => app.merge_worktree_with_options(branch_name, options)?,
                 Err(_) => {
                     // No config exists - try to create temporary app for merge
                     let mut app = VibeTreeApp::new()?;
-                    app.merge_worktree(branch_name, into, squash, rebase, remove)?;
+                    let result = app.merge_worktree_with_options(branch_name, options)?;
                     // Remove the config file created by VibeTreeApp::new() since we're in discovery mode
                     let config_path = std::env::current_dir()?.join("vibetree.toml");
                     if config_path.exists() {
                         std::fs::remove_file(&config_path)
                             .context("Failed to remove created config file")?;
                     }
+                    result
                 }
+            };
+            if dry_run {
+                let heading = format!("Would merge '{}' into '{}':", result.name, result.into);
+                print_plan(cli.output, &heading, &result.plan);
             }
+            Some(serde_json::to_value(result)?)
         }
-    }
 
-    Ok(())
+        Commands::Push {
+            branch_name,
+            force_with_lease,
+        } => {
+            let app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(
+                app.push_worktree(&branch_name, force_with_lease)?,
+            )?)
+        }
+
+        Commands::Pr {
+            branch_name,
+            base,
+            title,
+            body,
+            draft,
+            web,
+        } => {
+            let options = PullRequestOptions {
+                base,
+                title,
+                body,
+                draft,
+                web,
+            };
+            let app = VibeTreeApp::load_existing()?;
+            let result = app.pull_request(&branch_name, options)?;
+            if cli.output == OutputMode::Text {
+                println!("{}", result.pull_request.url);
+            }
+            Some(serde_json::to_value(result)?)
+        }
+
+        Commands::Update {
+            branch_name,
+            all,
+            rebase,
+            no_fetch,
+            dry_run,
+        } => {
+            let options = UpdateOptions {
+                // Parsing requires exactly one of BRANCH and --all
+                branch: branch_name.filter(|_| !all),
+                rebase,
+                no_fetch,
+                dry_run,
+            };
+            let app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(app.update(options)?)?)
+        }
+
+        Commands::Up {
+            branch_name,
+            services,
+        } => {
+            let app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(
+                app.services_up(&branch_name, &services)?,
+            )?)
+        }
+
+        Commands::Down {
+            branch_name,
+            services,
+        } => {
+            let app = VibeTreeApp::load_existing()?;
+            Some(serde_json::to_value(
+                app.services_down(&branch_name, &services)?,
+            )?)
+        }
+
+        Commands::Logs {
+            branch_name,
+            service,
+            follow,
+            lines,
+        } => {
+            let app = VibeTreeApp::load_existing()?;
+            app.service_logs(&branch_name, service.as_deref(), follow, lines)?;
+            None
+        }
+
+        Commands::Config {
+            command: ConfigCommand::Show { origin },
+        } => {
+            let app = VibeTreeApp::load_existing()?;
+            let result = app.show_config(origin)?;
+            if cli.output == OutputMode::Text {
+                print!("{}", result);
+            }
+            Some(serde_json::to_value(result)?)
+        }
+
+        Commands::Registry { command } => match command {
+            RegistryCommand::List => Some(serde_json::to_value(vibetree::registry::list()?)?),
+            RegistryCommand::Gc { dry_run } => {
+                Some(serde_json::to_value(vibetree::registry::gc(dry_run)?)?)
+            }
+        },
+
+        Commands::Migrate { dry_run } => {
+            let vibetree_parent = vibetree::config::VibeTreeConfig::get_vibetree_parent()?;
+            Some(serde_json::to_value(vibetree::schema::migrate(
+                &vibetree_parent,
+                dry_run,
+            )?)?)
+        }
+
+        Commands::Var { command } => {
+            let mut app = VibeTreeApp::load_existing()?;
+            match command {
+                VarCommand::List => {
+                    let result = app.list_variables();
+                    if cli.output == OutputMode::Text {
+                        print!("{}", result);
+                    }
+                    Some(serde_json::to_value(result)?)
+                }
+                VarCommand::Add {
+                    name,
+                    value,
+                    branch,
+                    yes,
+                } => Some(serde_json::to_value(app.add_variable(
+                    &name,
+                    value.into_value(),
+                    branch,
+                    yes,
+                )?)?),
+                VarCommand::Set {
+                    name,
+                    value,
+                    branch,
+                    yes,
+                } => Some(serde_json::to_value(app.set_variable(
+                    &name,
+                    value.into_value(),
+                    branch,
+                    yes,
+                )?)?),
+                VarCommand::Remove { name, yes } => {
+                    Some(serde_json::to_value(app.remove_variable(&name, yes)?)?)
+                }
+            }
+        }
+    };
+
+    Ok(result)
 }
@@ -0,0 +1,290 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::git::BranchCommit;
+use crate::output::{MergeStrategy, VibeTreeError};
+
+/// How `vibetree merge` should merge a worktree's branch
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// Target branch; the main branch when unset
+    pub into: Option<String>,
+    pub squash: bool,
+    pub rebase: bool,
+    /// Remove the worktree after a successful merge
+    pub remove_after: bool,
+    /// Commit message, instead of the generated default
+    pub message: Option<String>,
+    /// Open the git editor on the commit message before committing
+    pub edit: bool,
+    /// Create a merge commit even when the target could be fast-forwarded
+    pub no_ff: bool,
+    /// On conflicts, leave the merge or rebase in place to resolve and continue
+    pub keep_conflicts: bool,
+    /// Only show what would be merged and whether it would conflict
+    pub dry_run: bool,
+}
+
+/// A merge stopped by conflicts, waiting for `vibetree merge --continue` or `--abort`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeState {
+    pub into: String,
+    pub strategy: MergeStrategy,
+    /// Worktree where the conflicts are: the target's for merges, the branch's for rebases
+    pub path: PathBuf,
+    /// Commit message to use when continuing; for rebases only set with --no-ff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub edit: bool,
+    #[serde(default)]
+    pub remove_after: bool,
+    /// Whether the merge makes a commit even when it could fast-forward, as
+    /// merges left with conflicts always do
+    #[serde(default)]
+    pub no_ff: bool,
+}
+
+impl MergeState {
+    /// Short description for listings, e.g. "merging into main"
+    pub fn describe(&self) -> String {
+        let verb = match self.strategy {
+            MergeStrategy::Merge => "merging",
+            MergeStrategy::Squash => "squashing",
+            MergeStrategy::Rebase => "rebasing",
+        };
+        format!("{} into {}", verb, self.into)
+    }
+}
+
+/// Merges in progress, by worktree name - stored in .vibetree/operations.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MergeOperations {
+    #[serde(default)]
+    pub merges: BTreeMap<String, MergeState>,
+}
+
+impl MergeOperations {
+    fn path(vibetree_parent: &Path) -> PathBuf {
+        vibetree_parent.join(".vibetree").join("operations.toml")
+    }
+
+    pub fn load(vibetree_parent: &Path) -> Result<Self> {
+        let path = Self::path(vibetree_parent);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Write the operations, removing the file once none are left
+    pub fn save(&self, vibetree_parent: &Path) -> Result<()> {
+        let path = Self::path(vibetree_parent);
+        if self.merges.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize merge state")?;
+        fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The merge to continue or abort: the named one, or the only one in progress
+    pub fn resolve(&self, name: Option<String>) -> Result<(String, MergeState)> {
+        let name = match name {
+            Some(name) => name,
+            None => match self.merges.keys().collect::<Vec<_>>().as_slice() {
+                [name] => (*name).clone(),
+                [] => anyhow::bail!(VibeTreeError::not_found("No merge is in progress")),
+                names => anyhow::bail!(
+                    "Several merges are in progress ({}); name the one to use",
+                    names
+                        .iter()
+                        .map(|n| n.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+        };
+
+        match self.merges.get(&name) {
+            Some(state) => Ok((name, state.clone())),
+            None => anyhow::bail!(VibeTreeError::not_found(format!(
+                "No merge of '{}' is in progress",
+                name
+            ))),
+        }
+    }
+}
+
+/// Default message for a merge commit, matching git's
+pub fn default_merge_message(branch: &str, target: &str) -> String {
+    format!("Merge branch '{}' into {}", branch, target)
+}
+
+/// Default message for a squash merge, listing the squashed commits' subjects
+pub fn default_squash_message(branch: &str, target: &str, commits: &[BranchCommit]) -> String {
+    let mut message = format!("Squash merge branch '{}' into {}", branch, target);
+    if !commits.is_empty() {
+        message.push('\n');
+        for commit in commits {
+            message.push_str(&format!("\n* {}", commit.subject));
+        }
+    }
+    message
+}
+
+/// Expand the `[merge] trailer` template into trailer lines
+///
+/// A template using `{name}` or `{email}` yields one line per distinct author,
+/// skipping `self_email` so the person merging isn't credited as a co-author.
+pub fn expand_trailer(
+    template: &str,
+    branch: &str,
+    target: &str,
+    commits: &[BranchCommit],
+    self_email: Option<&str>,
+) -> Vec<String> {
+    let template = template
+        .replace("{branch}", branch)
+        .replace("{target}", target);
+
+    if !template.contains("{name}") && !template.contains("{email}") {
+        return vec![template];
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    for commit in commits {
+        if Some(commit.author_email.as_str()) == self_email {
+            continue;
+        }
+        let line = template
+            .replace("{name}", &commit.author_name)
+            .replace("{email}", &commit.author_email);
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// Append trailer lines to a commit message, skipping any it already contains
+pub fn append_trailers(message: &str, trailers: &[String]) -> String {
+    let mut message = message.trim_end().to_string();
+    let new: Vec<&str> = trailers
+        .iter()
+        .map(String::as_str)
+        .filter(|trailer| !message.lines().any(|line| line == *trailer))
+        .collect();
+
+    if !new.is_empty() {
+        message.push_str("\n\n");
+        message.push_str(&new.join("\n"));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(subject: &str, name: &str, email: &str) -> BranchCommit {
+        BranchCommit {
+            subject: subject.to_string(),
+            author_name: name.to_string(),
+            author_email: email.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_squash_message_lists_subjects() {
+        let commits = vec![
+            commit("Add parser", "Ann", "ann@example.com"),
+            commit("Fix parser", "Bob", "bob@example.com"),
+        ];
+        assert_eq!(
+            default_squash_message("feature", "main", &commits),
+            "Squash merge branch 'feature' into main\n\n* Add parser\n* Fix parser"
+        );
+        assert_eq!(
+            default_squash_message("feature", "main", &[]),
+            "Squash merge branch 'feature' into main"
+        );
+    }
+
+    #[test]
+    fn test_merge_operations_round_trip() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let parent = temp_dir.path();
+
+        let mut operations = MergeOperations::load(parent)?;
+        assert!(operations.resolve(None).is_err());
+
+        let state = MergeState {
+            into: "main".to_string(),
+            strategy: MergeStrategy::Squash,
+            path: parent.to_path_buf(),
+            message: Some("Squash".to_string()),
+            edit: false,
+            remove_after: true,
+            no_ff: false,
+        };
+        operations
+            .merges
+            .insert("feature".to_string(), state.clone());
+        operations.save(parent)?;
+
+        let loaded = MergeOperations::load(parent)?;
+        assert_eq!(loaded.resolve(None)?, ("feature".to_string(), state));
+        assert!(loaded.resolve(Some("other".to_string())).is_err());
+
+        operations.merges.clear();
+        operations.save(parent)?;
+        assert!(!parent.join(".vibetree/operations.toml").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailers_per_author() {
+        let commits = vec![
+            commit("One", "Ann", "ann@example.com"),
+            commit("Two", "Bob", "bob@example.com"),
+            commit("Three", "Ann", "ann@example.com"),
+            commit("Four", "Me", "me@example.com"),
+        ];
+        let trailers = expand_trailer(
+            "Co-authored-by: {name} <{email}>",
+            "feature",
+            "main",
+            &commits,
+            Some("me@example.com"),
+        );
+        assert_eq!(
+            trailers,
+            vec![
+                "Co-authored-by: Ann <ann@example.com>".to_string(),
+                "Co-authored-by: Bob <bob@example.com>".to_string(),
+            ]
+        );
+
+        let trailers = expand_trailer("Merged-from: {branch}", "feature", "main", &commits, None);
+        assert_eq!(trailers, vec!["Merged-from: feature".to_string()]);
+
+        let message = append_trailers("Subject\n", &trailers);
+        assert_eq!(message, "Subject\n\nMerged-from: feature");
+        assert_eq!(append_trailers(&message, &trailers), message);
+    }
+}
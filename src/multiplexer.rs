@@ -0,0 +1,553 @@
+//! tmux and zellij integration: open a worktree in a window, tab or session of
+//! its own, laid out with the `[layout]` panes
+
+use anyhow::{Context, Result, bail};
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::LayoutConfig;
+
+/// tmux window option marking the windows vibetree opened, set to the worktree path
+const WINDOW_OPTION: &str = "@vibetree";
+
+/// Prefix of the zellij tabs and sessions vibetree opens, which has no way to
+/// mark them otherwise, so `remove` leaves the user's own alone
+const ZELLIJ_PREFIX: &str = "vibetree-";
+
+/// Terminal multiplexers `switch --tmux` and `switch --zellij` open worktrees in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    Tmux,
+    Zellij,
+}
+
+/// A worktree to open, with the environment every pane starts with
+pub struct Workspace<'a> {
+    pub name: &'a str,
+    pub path: &'a Path,
+    pub env: &'a BTreeMap<String, String>,
+    pub layout: &'a LayoutConfig,
+}
+
+impl Workspace<'_> {
+    /// tmux window and session name
+    fn title(&self) -> String {
+        title(self.name)
+    }
+}
+
+/// A worktree name without the characters tmux and zellij reserve
+fn title(name: &str) -> String {
+    name.replace(['.', ':', '/'], "-")
+}
+
+/// Name of the zellij tab or session opened for worktree `name`
+fn zellij_title(name: &str) -> String {
+    format!("{}{}", ZELLIJ_PREFIX, title(name))
+}
+
+impl Multiplexer {
+    /// Select the worktree's window or tab, creating it with the layout's panes
+    /// when there is none
+    ///
+    /// Inside the multiplexer this opens a window in the current session,
+    /// otherwise a session named after the worktree, which is then attached.
+    pub fn open(self, workspace: &Workspace) -> Result<()> {
+        match self {
+            Multiplexer::Tmux => Tmux::default().open(workspace),
+            Multiplexer::Zellij => Zellij.open(workspace),
+        }
+    }
+
+    /// Close the windows, tabs and sessions opened for a worktree
+    ///
+    /// Best effort: multiplexers that aren't installed or running are skipped.
+    pub fn close_all(name: &str, path: &Path) {
+        match Tmux::default().close(path) {
+            Ok(0) => {}
+            Ok(closed) => info!("Closed {} tmux window(s) for '{}'", closed, name),
+            Err(e) => debug!("Could not close tmux windows: {}", e),
+        }
+        let title = zellij_title(name);
+        match Zellij.close(&title) {
+            Ok(false) => {}
+            Ok(true) => info!("Closed zellij tab or session '{}'", title),
+            Err(e) => debug!("Could not close zellij tab or session: {}", e),
+        }
+    }
+}
+
+/// Closes what was opened for a worktree once it is removed
+pub trait WorkspaceCloser {
+    /// Close the windows, tabs and sessions opened for worktree `name` at `path`
+    fn close(&self, name: &str, path: &Path);
+}
+
+/// Closes tmux windows and zellij tabs and sessions, see [`Multiplexer::close_all`]
+pub struct Multiplexers;
+
+impl WorkspaceCloser for Multiplexers {
+    fn close(&self, name: &str, path: &Path) {
+        Multiplexer::close_all(name, path);
+    }
+}
+
+fn skip_attach() -> bool {
+    std::env::var("VIBETREE_SKIP_SHELL").is_ok()
+}
+
+fn run(mut command: Command, program: &str) -> Result<String> {
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run an interactive command such as an attach, handing it the terminal
+fn attach(mut command: Command, program: &str) -> Result<()> {
+    if skip_attach() {
+        info!("Skipping {} attach (VIBETREE_SKIP_SHELL is set)", program);
+        return Ok(());
+    }
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Tmux {
+    /// Server socket name (`tmux -L`), the default server when unset
+    socket: Option<String>,
+}
+
+impl Tmux {
+    fn command(&self) -> Command {
+        let mut command = Command::new("tmux");
+        if let Some(socket) = &self.socket {
+            command.args(["-L", socket]);
+        }
+        command
+    }
+
+    fn run<S: AsRef<std::ffi::OsStr>>(&self, args: &[S]) -> Result<String> {
+        let mut command = self.command();
+        command.args(args);
+        run(command, "tmux")
+    }
+
+    fn open(&self, workspace: &Workspace) -> Result<()> {
+        let existing = self.windows_for(workspace.path).into_iter().next();
+        let inside = std::env::var_os("TMUX").is_some() && self.socket.is_none();
+
+        let session = match existing {
+            Some((session, window)) => {
+                info!("Selecting tmux window '{}'", workspace.title());
+                self.run(&["select-window", "-t", &window])?;
+                session
+            }
+            None if inside => {
+                self.new_window(None, workspace)?;
+                return Ok(());
+            }
+            None => self.new_session(workspace)?,
+        };
+
+        if inside {
+            self.run(&["switch-client", "-t", &session])?;
+            return Ok(());
+        }
+        let mut command = self.command();
+        command.args(["attach-session", "-t", &session]);
+        attach(command, "tmux")
+    }
+
+    /// Windows opened for the worktree at `path`, as (session id, window id)
+    fn windows_for(&self, path: &Path) -> Vec<(String, String)> {
+        let format = format!("#{{session_id}}\t#{{window_id}}\t#{{{}}}", WINDOW_OPTION);
+        // Fails when no server is running, in which case there are no windows
+        let Ok(list) = self.run(&["list-windows", "-a", "-F", &format]) else {
+            return Vec::new();
+        };
+        let path = path.to_string_lossy();
+        list.lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let (session, window, marker) = (fields.next()?, fields.next()?, fields.next()?);
+                (marker == path).then(|| (session.to_string(), window.to_string()))
+            })
+            .collect()
+    }
+
+    /// Open a window in `session`, or the current session when `None`
+    fn new_window(&self, session: Option<&str>, workspace: &Workspace) -> Result<()> {
+        info!("Opening tmux window '{}'", workspace.title());
+        let mut args = vec![
+            "new-window".to_string(),
+            "-P".to_string(),
+            "-F".to_string(),
+            "#{window_id}\t#{pane_id}".to_string(),
+        ];
+        if let Some(session) = session {
+            args.extend(["-t".to_string(), format!("{}:", session)]);
+        }
+        args.extend(self.window_args(workspace));
+        let ids = self.run(&args)?;
+        let (window, pane) = ids.split_once('\t').context("Unexpected tmux output")?;
+        self.lay_out(window, pane, workspace)
+    }
+
+    /// Start a detached session named after the worktree, returning its id
+    fn new_session(&self, workspace: &Workspace) -> Result<String> {
+        info!("Starting tmux session '{}'", workspace.title());
+        let mut args = vec![
+            "new-session".to_string(),
+            "-d".to_string(),
+            "-P".to_string(),
+            "-F".to_string(),
+            "#{session_id}\t#{window_id}\t#{pane_id}".to_string(),
+            "-s".to_string(),
+            workspace.title(),
+        ];
+        args.extend(self.window_args(workspace));
+        let ids = self.run(&args)?;
+        let mut ids = ids.split('\t');
+        let (Some(session), Some(window), Some(pane)) = (ids.next(), ids.next(), ids.next()) else {
+            bail!("Unexpected tmux output");
+        };
+        self.lay_out(window, pane, workspace)?;
+        Ok(session.to_string())
+    }
+
+    /// Name, directory and environment of a new window or session
+    fn window_args(&self, workspace: &Workspace) -> Vec<String> {
+        let mut args = vec![
+            "-n".to_string(),
+            workspace.title(),
+            "-c".to_string(),
+            workspace.path.to_string_lossy().to_string(),
+        ];
+        args.extend(Self::env_args(workspace));
+        args
+    }
+
+    fn env_args(workspace: &Workspace) -> Vec<String> {
+        workspace
+            .env
+            .iter()
+            .flat_map(|(name, value)| ["-e".to_string(), format!("{}={}", name, value)])
+            .collect()
+    }
+
+    /// Mark the window as the worktree's and split it into the layout's panes
+    fn lay_out(&self, window: &str, first_pane: &str, workspace: &Workspace) -> Result<()> {
+        let path = workspace.path.to_string_lossy().to_string();
+        self.run(&["set-option", "-w", "-t", window, WINDOW_OPTION, &path])?;
+
+        for (index, pane) in workspace.layout.panes.iter().enumerate() {
+            let pane_id = if index == 0 {
+                first_pane.to_string()
+            } else {
+                let mut args = vec![
+                    "split-window".to_string(),
+                    "-t".to_string(),
+                    window.to_string(),
+                    "-c".to_string(),
+                    path.clone(),
+                    "-P".to_string(),
+                    "-F".to_string(),
+                    "#{pane_id}".to_string(),
+                ];
+                args.extend(Self::env_args(workspace));
+                let pane_id = self.run(&args)?;
+                // Re-tile after every split so later splits have room
+                self.run(&["select-layout", "-t", window, "tiled"])?;
+                pane_id
+            };
+            if let Some(name) = &pane.name {
+                self.run(&["select-pane", "-t", &pane_id, "-T", name])?;
+            }
+            if let Some(command) = &pane.command {
+                self.run(&["send-keys", "-t", &pane_id, "-l", command])?;
+                self.run(&["send-keys", "-t", &pane_id, "Enter"])?;
+            }
+        }
+        self.run(&["select-pane", "-t", first_pane])?;
+        Ok(())
+    }
+
+    /// Kill the windows opened for the worktree at `path`, returning how many
+    fn close(&self, path: &Path) -> Result<usize> {
+        let windows = self.windows_for(path);
+        for (_, window) in &windows {
+            self.run(&["kill-window", "-t", window])?;
+        }
+        Ok(windows.len())
+    }
+}
+
+struct Zellij;
+
+impl Zellij {
+    fn run(&self, args: &[&str]) -> Result<String> {
+        let mut command = Command::new("zellij");
+        command.args(args);
+        run(command, "zellij")
+    }
+
+    fn inside() -> bool {
+        std::env::var_os("ZELLIJ").is_some()
+    }
+
+    fn sessions(&self) -> Vec<String> {
+        self.run(&["list-sessions", "--short"])
+            .map(|list| list.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn tabs(&self) -> Vec<String> {
+        self.run(&["action", "query-tab-names"])
+            .map(|list| list.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn open(&self, workspace: &Workspace) -> Result<()> {
+        let title = zellij_title(workspace.name);
+
+        if Self::inside() {
+            if self.tabs().contains(&title) {
+                info!("Selecting zellij tab '{}'", title);
+                self.run(&["action", "go-to-tab-name", &title])?;
+            } else {
+                info!("Opening zellij tab '{}'", title);
+                let layout = write_layout(workspace)?;
+                let cwd = workspace.path.to_string_lossy();
+                let layout_path = layout.to_string_lossy();
+                self.run(&[
+                    "action",
+                    "new-tab",
+                    "--name",
+                    &title,
+                    "--cwd",
+                    &cwd,
+                    "--layout",
+                    &layout_path,
+                ])?;
+            }
+            return Ok(());
+        }
+
+        let mut command = Command::new("zellij");
+        if self.sessions().contains(&title) {
+            info!("Attaching to zellij session '{}'", title);
+            command.args(["attach", &title]);
+        } else {
+            info!("Starting zellij session '{}'", title);
+            let layout = write_layout(workspace)?;
+            command
+                .args(["--session", &title, "--layout"])
+                .arg(layout)
+                .current_dir(workspace.path);
+        }
+        attach(command, "zellij")
+    }
+
+    /// Close the tab or kill the session named `title`, returning whether there was one
+    fn close(&self, title: &str) -> Result<bool> {
+        let mut closed = false;
+        if Self::inside() && self.tabs().iter().any(|tab| tab == title) {
+            self.run(&["action", "go-to-tab-name", title])?;
+            self.run(&["action", "close-tab"])?;
+            closed = true;
+        }
+        if self.sessions().iter().any(|session| session == title) {
+            self.run(&["kill-session", title])?;
+            closed = true;
+        }
+        Ok(closed)
+    }
+}
+
+/// Write the zellij layout for `workspace` to `.vibetree/layout.kdl` in the
+/// worktree, which goes away with it
+fn write_layout(workspace: &Workspace) -> Result<std::path::PathBuf> {
+    let dir = workspace.path.join(".vibetree");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join("layout.kdl");
+    std::fs::write(&path, zellij_layout(workspace))
+        .with_context(|| format!("Failed to write zellij layout to {}", path.display()))?;
+    Ok(path)
+}
+
+/// A zellij layout running each pane's command, then a shell, with the worktree's environment
+///
+/// Zellij layouts can't set environment variables, so every pane starts
+/// through `env`.
+fn zellij_layout(workspace: &Workspace) -> String {
+    let default_pane = [Default::default()];
+    let panes = if workspace.layout.panes.is_empty() {
+        &default_pane[..]
+    } else {
+        &workspace.layout.panes[..]
+    };
+
+    let mut layout = format!(
+        "layout {{\n    cwd {}\n",
+        kdl_quote(&workspace.path.to_string_lossy())
+    );
+    for pane in panes {
+        let script = match &pane.command {
+            Some(command) => format!("{}; exec \"${{SHELL:-sh}}\"", command),
+            None => "exec \"${SHELL:-sh}\"".to_string(),
+        };
+        let mut args: Vec<String> = workspace
+            .env
+            .iter()
+            .map(|(name, value)| kdl_quote(&format!("{}={}", name, value)))
+            .collect();
+        args.extend([
+            "\"sh\"".to_string(),
+            "\"-c\"".to_string(),
+            kdl_quote(&script),
+        ]);
+
+        layout.push_str("    pane");
+        if let Some(name) = &pane.name {
+            layout.push_str(&format!(" name={}", kdl_quote(name)));
+        }
+        layout.push_str(&format!(
+            " command=\"env\" {{\n        args {}\n    }}\n",
+            args.join(" ")
+        ));
+    }
+    layout.push_str("}\n");
+    layout
+}
+
+fn kdl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PaneConfig;
+    use std::time::{Duration, Instant};
+
+    fn layout() -> LayoutConfig {
+        LayoutConfig {
+            panes: vec![
+                PaneConfig {
+                    name: Some("editor".to_string()),
+                    command: None,
+                },
+                PaneConfig {
+                    name: None,
+                    command: Some("docker compose up".to_string()),
+                },
+            ],
+        }
+    }
+
+    /// Wait up to a few seconds for `check` to hold, as a loaded tmux server
+    /// may not have settled when the command that changed it returns
+    fn eventually(mut check: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if check() {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn test_zellij_layout_starts_panes_with_env() {
+        let env = BTreeMap::from([("PGPORT".to_string(), "5433".to_string())]);
+        let layout = layout();
+        let workspace = Workspace {
+            name: "feature/x",
+            path: Path::new("/repo/.vibetree/branches/feature/x"),
+            env: &env,
+            layout: &layout,
+        };
+
+        assert_eq!(workspace.title(), "feature-x");
+        assert_eq!(zellij_title(workspace.name), "vibetree-feature-x");
+        assert_eq!(
+            zellij_layout(&workspace),
+            "layout {\n    cwd \"/repo/.vibetree/branches/feature/x\"\n    \
+             pane name=\"editor\" command=\"env\" {\n        \
+             args \"PGPORT=5433\" \"sh\" \"-c\" \"exec \\\"${SHELL:-sh}\\\"\"\n    }\n    \
+             pane command=\"env\" {\n        \
+             args \"PGPORT=5433\" \"sh\" \"-c\" \"docker compose up; exec \\\"${SHELL:-sh}\\\"\"\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    #[ignore = "talks to a live tmux server; run with --ignored"]
+    fn test_tmux_window_lays_out_panes_and_closes() -> Result<()> {
+        let tmux = Tmux {
+            socket: Some(format!("vibetree-test-{}", std::process::id())),
+        };
+        // Needs tmux, which isn't installed everywhere. Panes run `cat` rather
+        // than a shell, which could retitle them after the layout names them
+        if tmux
+            .run(&["new-session", "-d", "-s", "base", "cat"])
+            .is_err()
+        {
+            return Ok(());
+        }
+        tmux.run(&["set-option", "-g", "default-command", "cat"])?;
+
+        let dir = tempfile::TempDir::new()?;
+        let env = BTreeMap::from([("PGPORT".to_string(), "5433".to_string())]);
+        let layout = layout();
+        let workspace = Workspace {
+            name: "feature",
+            path: dir.path(),
+            env: &env,
+            layout: &layout,
+        };
+        // Titles of the panes of the worktree's only window
+        let pane_titles = || -> Option<Vec<String>> {
+            let windows = tmux.windows_for(dir.path());
+            let [(_, window)] = windows.as_slice() else {
+                return None;
+            };
+            let titles = tmux
+                .run(&["list-panes", "-t", window, "-F", "#{pane_title}"])
+                .ok()?;
+            Some(titles.lines().map(str::to_string).collect())
+        };
+        let result = (|| -> Result<()> {
+            tmux.new_window(Some("base"), &workspace)?;
+            let laid_out = eventually(|| {
+                pane_titles()
+                    .is_some_and(|titles| titles.len() == 2 && titles.iter().any(|t| t == "editor"))
+            });
+            assert!(laid_out, "panes: {:?}", pane_titles());
+
+            assert_eq!(tmux.close(dir.path())?, 1);
+            assert!(eventually(|| tmux.windows_for(dir.path()).is_empty()));
+            Ok(())
+        })();
+        let _ = tmux.run(&["kill-server"]);
+        result
+    }
+}
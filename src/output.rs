@@ -0,0 +1,517 @@
+//! Structured command results and exit codes for `--output json`
+//!
+//! Exit codes are part of the CLI contract:
+//!
+//! | Code | Meaning                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | Success                                                  |
+//! | 1    | Any other error                                          |
+//! | 2    | Invalid command-line usage                               |
+//! | 3    | Worktree, branch or configuration not found              |
+//! | 4    | Conflict: merge conflicts, or a name or value is taken   |
+//! | 5    | Uncommitted changes prevent the operation                |
+//! | 6    | An allocated port is not available                      |
+//! | 7    | `check` found problems that were not fixed               |
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+
+use crate::config::{VariableConfig, VariableType};
+use crate::forge::PullRequest;
+use crate::plan::Plan;
+use crate::prune::PruneCandidate;
+use crate::registry::Allocation;
+use crate::schema::FileMigration;
+use crate::settings::{self, Origin};
+use crate::sync::SyncPlan;
+
+/// Exit code for errors without a more specific kind
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Categories of failure that scripts can tell apart by exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    NotFound,
+    Conflict,
+    DirtyTree,
+    PortUnavailable,
+    CheckFailed,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::NotFound => 3,
+            ErrorKind::Conflict => 4,
+            ErrorKind::DirtyTree => 5,
+            ErrorKind::PortUnavailable => 6,
+            ErrorKind::CheckFailed => 7,
+        }
+    }
+}
+
+/// An error with a known kind, carried inside `anyhow::Error`
+#[derive(Debug)]
+pub struct VibeTreeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Structured data to include with the error under `--output json`
+    pub details: Option<serde_json::Value>,
+}
+
+impl VibeTreeError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Conflict, message)
+    }
+
+    pub fn dirty_tree(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::DirtyTree, message)
+    }
+
+    pub fn port_unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::PortUnavailable, message)
+    }
+}
+
+impl fmt::Display for VibeTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for VibeTreeError {}
+
+fn find_error(error: &anyhow::Error) -> Option<&VibeTreeError> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<VibeTreeError>())
+}
+
+/// Find the kind of an error anywhere in its context chain
+pub fn error_kind(error: &anyhow::Error) -> Option<ErrorKind> {
+    find_error(error).map(|e| e.kind)
+}
+
+/// Process exit code for an error
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error_kind(error).map_or(EXIT_FAILURE, ErrorKind::exit_code)
+}
+
+/// Ask a yes/no question on stderr, keeping stdout for the command's result
+///
+/// Fails when stdin isn't a terminal, so scripts get an error instead of a
+/// hang; they pass `skip_with`, such as `--yes`, to go ahead without asking.
+pub fn confirm(question: &str, skip_with: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Can't ask '{}' without a terminal; pass {} to go ahead",
+            question,
+            skip_with
+        );
+    }
+    eprint!("{} (y/N): ", question);
+    io::stderr().flush().context("Failed to flush stderr")?;
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .context("Failed to read confirmation input")?;
+
+    let input = input.trim().to_lowercase();
+    Ok(input == "y" || input == "yes")
+}
+
+/// The single JSON object printed for a command under `--output json`
+#[derive(Debug, Serialize)]
+pub struct CommandOutput {
+    pub command: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorOutput>,
+}
+
+impl CommandOutput {
+    pub fn success(command: &str, result: Option<serde_json::Value>) -> Self {
+        Self {
+            command: command.to_string(),
+            success: true,
+            result,
+            error: None,
+        }
+    }
+
+    pub fn failure(command: &str, error: &anyhow::Error) -> Self {
+        Self {
+            command: command.to_string(),
+            success: false,
+            result: None,
+            error: Some(ErrorOutput::from_error(error)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorOutput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ErrorKind>,
+    pub exit_code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ErrorOutput {
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        Self {
+            kind: error_kind(error),
+            exit_code: exit_code(error),
+            message: error.to_string(),
+            causes: error.chain().skip(1).map(|c| c.to_string()).collect(),
+            details: find_error(error).and_then(|e| e.details.clone()),
+        }
+    }
+}
+
+/// Result of `vibetree init`
+#[derive(Debug, Serialize)]
+pub struct InitResult {
+    pub config_path: PathBuf,
+    pub variables: Vec<VariableConfig>,
+    /// Values allocated to the main worktree
+    pub values: HashMap<String, String>,
+    pub repair: RepairResult,
+    pub dry_run: bool,
+    /// Changes made, or that would be made in a dry run
+    pub plan: Plan,
+}
+
+/// Result of `vibetree add`
+#[derive(Debug, Serialize)]
+pub struct AddResult {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    pub values: HashMap<String, String>,
+    /// Uncommitted changes of the main worktree were moved into the new one
+    pub carried: bool,
+    pub dry_run: bool,
+    /// Changes made, or that would be made in a dry run
+    pub plan: Plan,
+}
+
+/// Result of `vibetree fork`
+#[derive(Debug, Serialize)]
+pub struct ForkResult {
+    pub name: String,
+    /// Worktree that was forked
+    pub from: String,
+    pub path: PathBuf,
+    pub values: HashMap<String, String>,
+    /// The source's uncommitted changes were copied
+    pub copied_changes: bool,
+    /// Gitignored paths copied from the source
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub copied_ignored: Vec<String>,
+    /// Number of `[fork]` hooks that ran
+    pub hooks_run: usize,
+}
+
+/// Result of `vibetree move-changes`
+#[derive(Debug, Serialize)]
+pub struct MoveChangesResult {
+    pub from: String,
+    pub to: String,
+    /// False when there were no changes to move
+    pub moved: bool,
+}
+
+/// Result of `vibetree remove`
+#[derive(Debug, Serialize)]
+pub struct RemoveResult {
+    pub name: String,
+    pub path: PathBuf,
+    /// False when the removal was cancelled at the confirmation prompt or a dry run
+    pub removed: bool,
+    pub kept_branch: bool,
+    pub dry_run: bool,
+    /// Changes made, or that would be made in a dry run
+    pub plan: Plan,
+    /// The branch's pull request if it is still open; not looked up with --force
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_pull_request: Option<PullRequest>,
+}
+
+/// Result of `vibetree prune`
+#[derive(Debug, Serialize)]
+pub struct PruneResult {
+    pub dry_run: bool,
+    /// Worktrees selected, with why
+    pub candidates: Vec<PruneCandidate>,
+    /// Removals of the candidates, or what they would change in a dry run;
+    /// empty when pruning was cancelled
+    pub removals: Vec<RemoveResult>,
+}
+
+/// How a branch was merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    Merge,
+    Squash,
+    Rebase,
+}
+
+/// Result of `vibetree merge`
+#[derive(Debug, Serialize)]
+pub struct MergeResult {
+    pub name: String,
+    pub into: String,
+    pub strategy: MergeStrategy,
+    /// The branch was already contained in the target, so nothing was merged
+    pub already_merged: bool,
+    /// The worktree was removed after merging
+    pub removed: bool,
+    /// Message of the commit the merge created; none for fast-forwards
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub dry_run: bool,
+    /// Changes made, or that would be made in a dry run
+    pub plan: Plan,
+    /// Paths a dry run predicts would conflict
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+}
+
+/// Result of `vibetree merge --abort`
+#[derive(Debug, Serialize)]
+pub struct MergeAbortResult {
+    pub name: String,
+    pub into: String,
+    pub strategy: MergeStrategy,
+}
+
+/// Result of `vibetree push`
+#[derive(Debug, Serialize)]
+pub struct PushResult {
+    pub name: String,
+    pub remote: String,
+    /// Remote-tracking branch now set as the upstream, e.g. "origin/feature"
+    pub upstream: String,
+}
+
+/// Result of `vibetree pr`
+#[derive(Debug, Serialize)]
+pub struct PullRequestResult {
+    pub name: String,
+    /// False when an open pull request already existed
+    pub created: bool,
+    pub pull_request: PullRequest,
+}
+
+/// Outcome of updating one worktree with `vibetree update`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    /// The branch already contains the base
+    UpToDate,
+    /// The base was merged or rebased onto, or would be in a dry run
+    Updated,
+    /// The update conflicted and was aborted, or would conflict in a dry run
+    Conflicts,
+    /// The worktree was left alone, see `reason`
+    Skipped,
+}
+
+/// Result of updating one worktree
+#[derive(Debug, Serialize)]
+pub struct WorktreeUpdate {
+    pub name: String,
+    pub status: UpdateStatus,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+    /// Why the worktree was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Result of `vibetree update`
+#[derive(Debug, Serialize)]
+pub struct UpdateResult {
+    /// Branch the worktrees were updated from, e.g. "origin/main"
+    pub base: String,
+    pub strategy: MergeStrategy,
+    pub dry_run: bool,
+    pub worktrees: Vec<WorktreeUpdate>,
+}
+
+/// State of one of a worktree's `[[services]]`
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    pub log: PathBuf,
+}
+
+/// Result of `vibetree up` and `vibetree down`
+#[derive(Debug, Serialize)]
+pub struct ServicesResult {
+    pub name: String,
+    pub services: Vec<ServiceStatus>,
+}
+
+/// Result of `vibetree config show`
+#[derive(Debug, Serialize)]
+pub struct ConfigShowResult {
+    /// The files that were merged, lowest precedence first
+    pub files: Vec<(Origin, PathBuf)>,
+    pub config: toml::Table,
+    /// Origin of each value by dotted key, with `--origin`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origins: Option<BTreeMap<String, Origin>>,
+}
+
+impl fmt::Display for ConfigShowResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.origins {
+            Some(origins) => {
+                for (origin, path) in &self.files {
+                    writeln!(f, "# {}: {}", origin, path.display())?;
+                }
+                write!(
+                    f,
+                    "{}",
+                    settings::render_with_origins(&self.config, origins)
+                )
+            }
+            None => write!(
+                f,
+                "{}",
+                toml::to_string_pretty(&self.config).map_err(|_| fmt::Error)?
+            ),
+        }
+    }
+}
+
+/// Result of `vibetree registry list`
+#[derive(Debug, Serialize)]
+pub struct RegistryResult {
+    pub path: PathBuf,
+    pub allocations: Vec<Allocation>,
+}
+
+/// Result of `vibetree registry gc`
+#[derive(Debug, Serialize)]
+pub struct RegistryGcResult {
+    pub dry_run: bool,
+    /// Allocations whose repository or worktree no longer exists
+    pub removed: Vec<Allocation>,
+}
+
+/// Result of `vibetree migrate`
+#[derive(Debug, Serialize)]
+pub struct MigrateResult {
+    pub dry_run: bool,
+    pub files: Vec<FileMigration>,
+}
+
+/// Result of `vibetree var list`
+#[derive(Debug, Serialize)]
+pub struct VariableListResult {
+    pub variables: Vec<VariableConfig>,
+}
+
+impl fmt::Display for VariableListResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for variable in &self.variables {
+            let value = match &variable.value {
+                Some(toml::Value::String(template)) => format!("{:?}", template),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            };
+            write!(f, "{} = {}", variable.name, value)?;
+            match variable.r#type {
+                Some(VariableType::Port) => write!(f, " (port)")?,
+                Some(VariableType::Int) => write!(f, " (int)")?,
+                None => {}
+            }
+            if let Some(branch) = &variable.branch {
+                write!(f, " [branch {}]", branch)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of `vibetree var add`, `var set` and `var remove`
+#[derive(Debug, Serialize)]
+pub struct VariableEditResult {
+    /// The variables in vibetree.toml after the edit
+    pub variables: Vec<VariableConfig>,
+    /// Worktrees whose values no longer match the variables
+    pub outdated: Vec<String>,
+    /// The repair of the outdated worktrees, unless it was declined
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair: Option<RepairResult>,
+}
+
+/// Result of `vibetree repair`
+#[derive(Debug, Serialize)]
+pub struct RepairResult {
+    pub dry_run: bool,
+    pub plan: SyncPlan,
+    /// Problems encountered while applying the plan
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_code_found_through_context() {
+        let error = Err::<(), _>(VibeTreeError::dirty_tree("Uncommitted changes"))
+            .context("Failed to merge")
+            .unwrap_err();
+        assert_eq!(error_kind(&error), Some(ErrorKind::DirtyTree));
+        assert_eq!(exit_code(&error), 5);
+
+        let output = ErrorOutput::from_error(&error);
+        assert_eq!(output.message, "Failed to merge");
+        assert_eq!(output.causes, vec!["Uncommitted changes".to_string()]);
+
+        let plain = anyhow::anyhow!("Something else");
+        assert_eq!(exit_code(&plain), EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,124 @@
+//! `${VAR}` placeholders in the commands and checks of vibetree.toml, filled in
+//! with a worktree's values
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\$\{(?:(src|new):)?([A-Za-z_][A-Za-z0-9_]*)\}")
+        .expect("Failed to compile placeholder regex")
+});
+
+/// Replace `${VAR}` placeholders naming one of `values`, leaving the rest, such
+/// as the shell's own variables, as they are
+pub fn expand(template: &str, values: &HashMap<String, String>) -> String {
+    substitute(template, values, None).0
+}
+
+/// Replace `${VAR}` placeholders like [`expand`], failing on any that name no
+/// value, for templates no shell sees, such as the ready checks of `[[services]]`
+pub fn expand_all(template: &str, values: &HashMap<String, String>, what: &str) -> Result<String> {
+    let (expanded, missing) = substitute(template, values, None);
+    if let Some((placeholder, _)) = missing.first() {
+        anyhow::bail!("Unknown variable {} in {}: {}", placeholder, what, template);
+    }
+    Ok(expanded)
+}
+
+/// Replace the placeholders of a `[fork]` hook: `${VAR}` and `${new:VAR}` name
+/// the fork's values, `${src:VAR}` the source's
+///
+/// Plain placeholders naming no value are left to the shell; `src:` and `new:`
+/// ones mean nothing to it, so they fail.
+pub fn expand_hook(
+    template: &str,
+    source: &HashMap<String, String>,
+    values: &HashMap<String, String>,
+) -> Result<String> {
+    let (expanded, missing) = substitute(template, values, Some(source));
+    if let Some((placeholder, _)) = missing.iter().find(|(_, scoped)| *scoped) {
+        anyhow::bail!(
+            "Unknown variable {} in fork hook: {}",
+            placeholder,
+            template
+        );
+    }
+    Ok(expanded)
+}
+
+/// Replace the placeholders that name a value, returning the ones that don't,
+/// each with whether it had a `src:` or `new:` scope
+fn substitute(
+    template: &str,
+    values: &HashMap<String, String>,
+    source: Option<&HashMap<String, String>>,
+) -> (String, Vec<(String, bool)>) {
+    let mut missing = Vec::new();
+    let expanded = PLACEHOLDER.replace_all(template, |caps: &regex::Captures| {
+        let scope = caps.get(1).map(|scope| scope.as_str());
+        let values = match scope {
+            Some("src") => source,
+            _ => Some(values),
+        };
+        match values.and_then(|values| values.get(&caps[2])) {
+            Some(value) => value.clone(),
+            None => {
+                missing.push((caps[0].to_string(), scope.is_some()));
+                caps[0].to_string()
+            }
+        }
+    });
+    (expanded.into_owned(), missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_leaves_unknown_placeholders_to_shell() {
+        let values = HashMap::from([("INSTANCE".to_string(), "2".to_string())]);
+        assert_eq!(
+            expand("createdb -U ${PGUSER} app_${INSTANCE}", &values),
+            "createdb -U ${PGUSER} app_2"
+        );
+    }
+
+    #[test]
+    fn test_expand_all_fails_on_unknown_placeholders() -> Result<()> {
+        let values = HashMap::from([("WEB_PORT".to_string(), "8080".to_string())]);
+        assert_eq!(
+            expand_all(
+                "http://localhost:${WEB_PORT}/health",
+                &values,
+                "ready check"
+            )?,
+            "http://localhost:8080/health"
+        );
+        let err = expand_all("${DB_PORT}", &values, "ready check").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown variable ${DB_PORT} in ready check: ${DB_PORT}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_hook_scopes() -> Result<()> {
+        let src = HashMap::from([("PG_PORT".to_string(), "5432".to_string())]);
+        let new = HashMap::from([("PG_PORT".to_string(), "5433".to_string())]);
+
+        assert_eq!(
+            expand_hook(
+                "pg_dump -p ${src:PG_PORT} app | psql -p ${new:PG_PORT} app_${PG_PORT} ${HOME}",
+                &src,
+                &new
+            )?,
+            "pg_dump -p 5432 app | psql -p 5433 app_5433 ${HOME}"
+        );
+        assert!(expand_hook("echo ${new:REDIS_PORT}", &src, &new).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,777 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::changes::{CarriedChanges, CarryOptions};
+use crate::cli::CopyMode;
+use crate::compose::ComposeManager;
+use crate::config::{ResourceState, VariableConfig, VibeTreeConfig};
+use crate::env::EnvFileGenerator;
+use crate::fork;
+use crate::git::GitManager;
+use crate::merge;
+use crate::output::MergeStrategy;
+use crate::resources::{self, ResourceManager};
+use crate::transaction::{Transaction, fail_point};
+
+/// One change a mutating command makes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Write vibetree.toml
+    WriteConfig {
+        path: PathBuf,
+    },
+    /// Record a worktree's allocated values in branches.toml
+    Allocate {
+        worktree: String,
+        values: BTreeMap<String, String>,
+    },
+    /// Drop a worktree and its values from branches.toml
+    Release {
+        worktree: String,
+        values: BTreeMap<String, String>,
+    },
+    /// Create a branch at `from`, or at HEAD when unset
+    CreateBranch {
+        name: String,
+        from: Option<String>,
+    },
+    /// Check a branch out in a new worktree directory
+    CreateDir {
+        path: PathBuf,
+        branch: String,
+    },
+    /// Move uncommitted changes from one worktree to another
+    CarryChanges {
+        from: PathBuf,
+        to: PathBuf,
+        include_untracked: bool,
+    },
+    /// Copy uncommitted changes from one worktree to another, leaving the source as it is
+    CopyChanges {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Copy gitignored files and directories from one worktree to another
+    CopyIgnored {
+        from: PathBuf,
+        to: PathBuf,
+        paths: Vec<String>,
+        mode: CopyMode,
+    },
+    /// Run a `[fork]` hook in a worktree
+    RunHook {
+        command: String,
+        dir: PathBuf,
+    },
+    /// Write a worktree's env file from its allocated values
+    WriteEnvFile {
+        path: PathBuf,
+        worktree: String,
+    },
+    /// Add the `.vibetree/` rule to a .gitignore
+    UpdateGitignore {
+        path: PathBuf,
+    },
+    /// Create one of a worktree's `[[resources]]` by running `command`
+    CreateResource {
+        worktree: String,
+        resource: String,
+        command: String,
+    },
+    /// Destroy one of a worktree's `[[resources]]` by running `command`
+    ///
+    /// Carries the worktree's values, which are released before it runs.
+    DestroyResource {
+        worktree: String,
+        resource: String,
+        command: String,
+        values: BTreeMap<String, String>,
+    },
+    /// Stop a worktree's compose project and delete its containers and volumes
+    ComposeDown {
+        project: String,
+        containers: Vec<String>,
+        volumes: Vec<String>,
+    },
+    /// Remove a worktree directory
+    RemoveDir {
+        path: PathBuf,
+        worktree: String,
+    },
+    DeleteBranch {
+        name: String,
+    },
+    /// Merge a branch into another with the given strategy
+    Merge {
+        branch: String,
+        into: String,
+        strategy: MergeStrategy,
+        /// Message of the commit the merge creates; unset for a fast-forward,
+        /// and in dry runs, where it isn't written yet
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        /// Create a merge commit even when the branch could be fast-forwarded
+        no_ff: bool,
+    },
+}
+
+impl Action {
+    /// Short name of the step, used for its fail point
+    fn step(&self) -> &'static str {
+        match self {
+            Action::WriteConfig { .. } => "config",
+            Action::Allocate { .. } => "allocate",
+            Action::Release { .. } => "release",
+            Action::CreateBranch { .. } => "branch",
+            Action::CreateDir { .. } => "worktree",
+            Action::CarryChanges { .. } => "carry",
+            Action::CopyChanges { .. } => "copy_changes",
+            Action::CopyIgnored { .. } => "copy_ignored",
+            Action::RunHook { .. } => "hook",
+            Action::WriteEnvFile { .. } => "env",
+            Action::UpdateGitignore { .. } => "gitignore",
+            Action::CreateResource { .. } | Action::DestroyResource { .. } => "resource",
+            Action::ComposeDown { .. } => "compose",
+            Action::RemoveDir { .. } => "move_dir",
+            Action::DeleteBranch { .. } => "branch",
+            Action::Merge { .. } => "merge",
+        }
+    }
+}
+
+fn format_values(values: &BTreeMap<String, String>) -> String {
+    values
+        .iter()
+        .map(|(variable, value)| format!("{}={}", variable, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::WriteConfig { path } => write!(f, "write {}", path.display()),
+            Action::Allocate { worktree, values } if values.is_empty() => {
+                write!(f, "register worktree '{}' (no values)", worktree)
+            }
+            Action::Allocate { worktree, values } => {
+                write!(f, "allocate {} to '{}'", format_values(values), worktree)
+            }
+            Action::Release { worktree, values } if values.is_empty() => {
+                write!(f, "unregister worktree '{}'", worktree)
+            }
+            Action::Release { worktree, values } => {
+                write!(f, "release {} from '{}'", format_values(values), worktree)
+            }
+            Action::CreateBranch { name, from } => write!(
+                f,
+                "create branch '{}' from {}",
+                name,
+                from.as_deref().unwrap_or("HEAD")
+            ),
+            Action::CreateDir { path, branch } => {
+                write!(f, "create worktree {} for '{}'", path.display(), branch)
+            }
+            Action::CarryChanges {
+                from,
+                to,
+                include_untracked,
+            } => write!(
+                f,
+                "move uncommitted changes{} from {} to {}",
+                if *include_untracked {
+                    " and untracked files"
+                } else {
+                    ""
+                },
+                from.display(),
+                to.display()
+            ),
+            Action::CopyChanges { from, to } => write!(
+                f,
+                "copy uncommitted changes from {} to {}",
+                from.display(),
+                to.display()
+            ),
+            Action::CopyIgnored {
+                from, paths, mode, ..
+            } => write!(
+                f,
+                "copy ignored {} from {} ({:?})",
+                paths.join(", "),
+                from.display(),
+                mode
+            ),
+            Action::RunHook { command, .. } => write!(f, "run fork hook: {}", command),
+            Action::WriteEnvFile { path, .. } => write!(f, "write {}", path.display()),
+            Action::UpdateGitignore { path } => {
+                write!(f, "add '.vibetree/' to {}", path.display())
+            }
+            Action::CreateResource {
+                resource, command, ..
+            } => write!(f, "create resource '{}': {}", resource, command),
+            Action::DestroyResource {
+                resource, command, ..
+            } => write!(f, "destroy resource '{}': {}", resource, command),
+            Action::ComposeDown {
+                project,
+                containers,
+                volumes,
+            } => {
+                write!(f, "take down compose project '{}'", project)?;
+                if !containers.is_empty() {
+                    write!(f, ", deleting containers {}", containers.join(", "))?;
+                }
+                if !volumes.is_empty() {
+                    write!(f, ", deleting volumes {}", volumes.join(", "))?;
+                }
+                Ok(())
+            }
+            Action::RemoveDir { path, .. } => write!(f, "remove directory {}", path.display()),
+            Action::DeleteBranch { name } => write!(f, "delete branch '{}'", name),
+            Action::Merge {
+                branch,
+                into,
+                strategy,
+                ..
+            } => match strategy {
+                MergeStrategy::Merge => write!(f, "merge '{}' into '{}'", branch, into),
+                MergeStrategy::Squash => write!(f, "squash merge '{}' into '{}'", branch, into),
+                MergeStrategy::Rebase => write!(
+                    f,
+                    "rebase '{}' onto '{}' and merge it into '{}'",
+                    branch, into, into
+                ),
+            },
+        }
+    }
+}
+
+/// The ordered changes a command makes, built before anything changes
+///
+/// `--dry-run` shows the plan; otherwise the same plan is executed. `init`,
+/// `add` (and `fork`), `remove` and `merge` go through a plan; `update`,
+/// `prune`, `repair`, `move-changes` and `sync` keep their own reports.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+}
+
+impl Plan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Log the plan, one action per line
+    pub fn report(&self) {
+        for action in &self.actions {
+            info!("  {}", action);
+        }
+    }
+
+    /// Carry out the actions in order as one transaction, then save the configuration
+    ///
+    /// When an action fails, the ones before it are undone. Fail points are named
+    /// `<command>:<step>`, e.g. `add:branch`.
+    pub fn execute(&self, config: &mut VibeTreeConfig, context: &PlanContext) -> Result<()> {
+        Transaction::run(config, |config, tx| {
+            for action in &self.actions {
+                execute_action(action, config, tx, context)?;
+                fail_point(&format!("{}:{}", context.command, action.step()))?;
+            }
+            config.save().context("Failed to save configuration")
+        })
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "  {}", action)?;
+        }
+        Ok(())
+    }
+}
+
+/// What executing a plan needs besides the actions
+pub struct PlanContext<'a> {
+    /// Command the plan belongs to, the first half of fail point names
+    pub command: &'static str,
+    /// Repository the git actions run in; unset for plans without any
+    pub repo_path: Option<&'a Path>,
+    pub vibetree_parent: &'a Path,
+    /// Variables the allocated values are validated against
+    pub variables: &'a [VariableConfig],
+}
+
+impl<'a> PlanContext<'a> {
+    fn repo(&self) -> Result<&'a Path> {
+        self.repo_path.context("Not inside a git repository")
+    }
+}
+
+/// Where a removed worktree's directory waits until the removal completes
+pub fn trash_path(vibetree_parent: &Path, worktree: &str) -> PathBuf {
+    vibetree_parent
+        .join(".vibetree")
+        .join("trash")
+        .join(worktree)
+}
+
+/// Replace a file's contents on rollback with what it held before
+fn restore_file_on_rollback<'a>(tx: &mut Transaction<'a>, path: &'a Path) -> Result<()> {
+    let previous = if path.exists() {
+        Some(std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?)
+    } else {
+        None
+    };
+    tx.on_rollback(format!("write {}", path.display()), move |_| {
+        match previous {
+            Some(contents) => std::fs::write(path, contents),
+            None => std::fs::remove_file(path),
+        }
+        .with_context(|| format!("Failed to restore {}", path.display()))
+    });
+    Ok(())
+}
+
+fn execute_action<'a>(
+    action: &'a Action,
+    config: &mut VibeTreeConfig,
+    tx: &mut Transaction<'a>,
+    context: &PlanContext<'a>,
+) -> Result<()> {
+    match action {
+        Action::WriteConfig { path } => {
+            restore_file_on_rollback(tx, path)?;
+            config.save().context("Failed to save configuration")?;
+        }
+        Action::Allocate { worktree, values } => {
+            let previous = config.branches_config.worktrees.get(worktree).cloned();
+            let values: HashMap<_, _> = values.clone().into_iter().collect();
+            config.add_or_update_worktree_with_variables(
+                worktree.clone(),
+                Some(values),
+                context.variables,
+            )?;
+            tx.on_rollback("allocation", move |config| match previous {
+                Some(previous) => config
+                    .add_or_update_worktree(worktree.clone(), Some(previous.values))
+                    .map(|_| ()),
+                None => config.remove_worktree(worktree),
+            });
+        }
+        Action::Release { worktree, values } => {
+            let released = config.branches_config.worktrees.get(worktree).cloned();
+            config.remove_worktree(worktree)?;
+            tx.on_rollback("release", move |config| {
+                // Put back the resource states with the values, which updating
+                // the worktree's values then leaves alone
+                if let Some(released) = released {
+                    config
+                        .branches_config
+                        .worktrees
+                        .insert(worktree.clone(), released);
+                }
+                let values = values.clone().into_iter().collect();
+                config
+                    .add_or_update_worktree(worktree.clone(), Some(values))
+                    .map(|_| ())
+            });
+        }
+        Action::CreateBranch { name, from } => {
+            let repo = context.repo()?;
+            GitManager::create_branch(repo, name, from.as_deref())
+                .context("Failed to create git branch")?;
+            tx.on_rollback("branch", move |_| GitManager::delete_branch(repo, name));
+        }
+        Action::CreateDir { path, branch } => {
+            let repo = context.repo()?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            GitManager::checkout_worktree(repo, path, branch)
+                .context("Failed to create git worktree")?;
+            tx.on_rollback("worktree", move |_| {
+                GitManager::remove_worktree(repo, branch, true)
+            });
+        }
+        Action::CarryChanges {
+            from,
+            to,
+            include_untracked,
+        } => {
+            let options = CarryOptions {
+                include_untracked: *include_untracked,
+            };
+            let message = format!("vibetree: carrying changes to {}", to.display());
+            let Some(changes) = CarriedChanges::take(from, options, &message)? else {
+                info!("No uncommitted changes to carry");
+                return Ok(());
+            };
+            changes
+                .apply_to(to)
+                .context("Failed to carry uncommitted changes")?;
+            tx.on_rollback("carried changes", move |_| {
+                // Everything uncommitted in the target came from the source
+                let options = CarryOptions {
+                    include_untracked: true,
+                };
+                let message = format!("vibetree: returning changes to {}", from.display());
+                match CarriedChanges::take(to, options, &message)? {
+                    Some(changes) => changes.apply_to(from),
+                    None => Ok(()),
+                }
+            });
+        }
+        // Both copy into the new worktree, so undoing its creation undoes them too
+        Action::CopyChanges { from, to } => {
+            fork::copy_changes(from, to).context("Failed to copy uncommitted changes")?;
+        }
+        Action::CopyIgnored {
+            from,
+            to,
+            paths,
+            mode,
+        } => {
+            fork::copy_ignored(from, to, *mode, paths).context("Failed to copy ignored files")?;
+        }
+        Action::RunHook { command, dir } => fork::run_hook(command, dir)?,
+        Action::WriteEnvFile { path, worktree } => {
+            let values = config
+                .branches_config
+                .worktrees
+                .get(worktree)
+                .map(|worktree| worktree.values.clone())
+                .unwrap_or_default();
+            restore_file_on_rollback(tx, path)?;
+            EnvFileGenerator::generate_env_file(path, worktree, &values)
+                .context("Failed to generate environment file")?;
+        }
+        Action::UpdateGitignore { path } => {
+            restore_file_on_rollback(tx, path)?;
+            add_gitignore_rule(path)?;
+        }
+        Action::CreateResource {
+            worktree,
+            resource,
+            command,
+        } => create_resource(config, tx, context, worktree, resource, command)?,
+        Action::DestroyResource {
+            worktree,
+            resource,
+            command,
+            values,
+        } => {
+            // Removals run this once the worktree's directory was moved aside
+            let cwd = [
+                config.get_worktree_path(context.vibetree_parent, worktree),
+                trash_path(context.vibetree_parent, worktree),
+            ]
+            .into_iter()
+            .find(|dir| dir.exists())
+            .unwrap_or_else(|| context.vibetree_parent.to_path_buf());
+            let values = values.clone().into_iter().collect();
+            resources::run(command, &values, &cwd)
+                .with_context(|| format!("Failed to destroy resource '{}'", resource))?;
+            info!("Destroyed resource '{}'", resource);
+        }
+        Action::ComposeDown { project, .. } => {
+            // Deleted volumes can't be brought back, so removals run this last
+            ComposeManager::new(&config.project_config.compose)
+                .down(project)
+                .with_context(|| format!("Failed to stop compose project '{}'", project))?;
+        }
+        Action::RemoveDir { path, worktree } => {
+            // Move the directory aside rather than deleting it, so it can be put back
+            let trash = trash_path(context.vibetree_parent, worktree);
+            if trash.exists() {
+                std::fs::remove_dir_all(&trash)
+                    .with_context(|| format!("Failed to remove {}", trash.display()))?;
+            }
+            if let Some(parent) = trash.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::rename(path, &trash)
+                .with_context(|| format!("Failed to move directory: {}", path.display()))?;
+            tx.on_rollback("worktree directory", move |_| {
+                std::fs::rename(&trash, path)
+                    .with_context(|| format!("Failed to restore {}", path.display()))
+            });
+        }
+        Action::DeleteBranch { name } => {
+            let repo = context.repo()?;
+            let oid = GitManager::branch_oid(repo, name)?;
+            GitManager::delete_branch(repo, name)?;
+            tx.on_rollback("branch", move |_| {
+                GitManager::set_branch(repo, name, &oid, None)
+            });
+        }
+        Action::Merge {
+            branch,
+            into,
+            strategy,
+            message,
+            no_ff,
+        } => execute_merge(
+            tx,
+            context,
+            branch,
+            into,
+            *strategy,
+            message.as_deref(),
+            *no_ff,
+        )?,
+    }
+    Ok(())
+}
+
+/// Merge `branch` into `into` where each is checked out, or by moving the branch
+/// ref when it isn't, undoing it by moving the branches back
+///
+/// Callers check for conflicts beforehand, so a conflict here is unexpected.
+fn execute_merge<'a>(
+    tx: &mut Transaction<'a>,
+    context: &PlanContext<'a>,
+    branch: &'a str,
+    into: &'a str,
+    strategy: MergeStrategy,
+    message: Option<&'a str>,
+    no_ff: bool,
+) -> Result<()> {
+    let repo = context.repo()?;
+    let target_oid = GitManager::branch_oid(repo, into)?;
+    let target_wt = GitManager::find_worktree_for_branch(repo, into)?;
+
+    // Registered up front so that a merge failing part-way, with the squash
+    // staged or the ref already moved, is undone too
+    let reset_wt = target_wt.clone();
+    tx.on_rollback("merge", move |_| {
+        GitManager::set_branch(repo, into, &target_oid, reset_wt.as_deref())
+    });
+
+    match strategy {
+        MergeStrategy::Rebase => {
+            let branch_oid = GitManager::branch_oid(repo, branch)?;
+            let branch_wt = GitManager::find_worktree_for_branch(repo, branch)?;
+            GitManager::rebase_branch(repo, branch, into, branch_wt.as_deref())?;
+            tx.on_rollback("rebase", move |_| {
+                GitManager::set_branch(repo, branch, &branch_oid, branch_wt.as_deref())
+            });
+            fail_point("merge:rebase")?;
+
+            // Rebasing always allows a fast-forward, so only --no-ff passes a message
+            GitManager::merge_rebased_branch(repo, branch, into, target_wt.as_deref(), message)?;
+        }
+        MergeStrategy::Squash => {
+            let message = message.context("A squash merge needs a commit message")?;
+            match target_wt.as_deref() {
+                Some(target_path) => {
+                    GitManager::stage_squash_merge(target_path, branch, into)?;
+                    fail_point("merge:commit")?;
+                    GitManager::commit_merge(target_path, message)?;
+                }
+                None => GitManager::merge_into_ref(repo, branch, into, message, true, false)?,
+            }
+        }
+        MergeStrategy::Merge => {
+            let message = message
+                .map(str::to_string)
+                .unwrap_or_else(|| merge::default_merge_message(branch, into));
+            match target_wt.as_deref() {
+                Some(target_path) => {
+                    GitManager::merge_branch(target_path, branch, into, &message, no_ff)?
+                }
+                None => GitManager::merge_into_ref(repo, branch, into, &message, false, no_ff)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn worktree_values(config: &VibeTreeConfig, worktree: &str) -> HashMap<String, String> {
+    config
+        .branches_config
+        .worktrees
+        .get(worktree)
+        .map(|worktree| worktree.values.clone())
+        .unwrap_or_default()
+}
+
+/// Create a resource, recording a failure instead of failing the plan so that
+/// `repair` can retry it
+fn create_resource<'a>(
+    config: &mut VibeTreeConfig,
+    tx: &mut Transaction<'a>,
+    context: &PlanContext<'a>,
+    worktree: &'a str,
+    name: &'a str,
+    command: &str,
+) -> Result<()> {
+    let resource = config
+        .project_config
+        .resources
+        .iter()
+        .find(|resource| resource.name == name)
+        .cloned()
+        .with_context(|| format!("Resource '{}' is not configured", name))?;
+    let values = worktree_values(config, worktree);
+    let cwd = config.get_worktree_path(context.vibetree_parent, worktree);
+    let manager = ResourceManager::new(&resource, &values);
+
+    let state = match manager.exists(&cwd) {
+        Ok(true) => {
+            info!("Resource '{}' already exists", name);
+            ResourceState::Created
+        }
+        Ok(false) => match resources::run(command, &values, &cwd) {
+            Ok(()) => {
+                info!("Created resource '{}'", name);
+                let destroy = manager.destroy_command();
+                tx.on_rollback(format!("resource '{}'", name), move |_| {
+                    resources::run(&destroy, &values, &cwd)
+                });
+                ResourceState::Created
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to create resource '{}' for '{}': {:#}. Run 'vibetree repair' to retry",
+                    name, worktree, e
+                );
+                ResourceState::Failed
+            }
+        },
+        Err(e) => {
+            warn!("Failed to check resource '{}': {:#}", name, e);
+            ResourceState::Failed
+        }
+    };
+    if let Some(entry) = config.branches_config.worktrees.get_mut(worktree) {
+        entry.resources.insert(name.to_string(), state);
+    }
+    Ok(())
+}
+
+/// Add the `.vibetree/` rule to a .gitignore, creating it if needed
+fn add_gitignore_rule(gitignore_path: &Path) -> Result<()> {
+    let vibetree_rule = ".vibetree/";
+
+    // Read existing .gitignore or create empty content
+    let mut content = if gitignore_path.exists() {
+        std::fs::read_to_string(gitignore_path)
+            .with_context(|| format!("Failed to read .gitignore: {}", gitignore_path.display()))?
+    } else {
+        String::new()
+    };
+
+    // Add the rule
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&format!("{}\n", vibetree_rule));
+
+    std::fs::write(gitignore_path, content)
+        .with_context(|| format!("Failed to update .gitignore: {}", gitignore_path.display()))?;
+
+    info!("Added {} to .gitignore", vibetree_rule);
+    Ok(())
+}
+
+/// Whether a .gitignore still needs the `.vibetree/` rule
+pub fn gitignore_needs_rule(gitignore_path: &Path) -> Result<bool> {
+    if !gitignore_path.exists() {
+        return Ok(true);
+    }
+    let content = std::fs::read_to_string(gitignore_path)
+        .with_context(|| format!("Failed to read .gitignore: {}", gitignore_path.display()))?;
+    Ok(!content.lines().any(|line| line.trim() == ".vibetree/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_renders_one_action_per_line() {
+        let mut plan = Plan::new();
+        plan.push(Action::Allocate {
+            worktree: "feature".to_string(),
+            values: BTreeMap::from([
+                ("POSTGRES".to_string(), "5433".to_string()),
+                ("API".to_string(), "8001".to_string()),
+            ]),
+        });
+        plan.push(Action::CreateBranch {
+            name: "feature".to_string(),
+            from: None,
+        });
+        plan.push(Action::Merge {
+            branch: "feature".to_string(),
+            into: "main".to_string(),
+            strategy: MergeStrategy::Squash,
+            message: Some("Add feature".to_string()),
+            no_ff: false,
+        });
+
+        assert_eq!(
+            plan.to_string(),
+            "  allocate API=8001, POSTGRES=5433 to 'feature'\n  \
+             create branch 'feature' from HEAD\n  \
+             squash merge 'feature' into 'main'\n"
+        );
+    }
+
+    #[test]
+    fn test_plan_serializes_as_tagged_actions() {
+        let mut plan = Plan::new();
+        plan.push(Action::DeleteBranch {
+            name: "feature".to_string(),
+        });
+        plan.push(Action::Merge {
+            branch: "feature".to_string(),
+            into: "main".to_string(),
+            strategy: MergeStrategy::Rebase,
+            message: None,
+            no_ff: false,
+        });
+
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"action": "delete_branch", "name": "feature"},
+                {"action": "merge", "branch": "feature", "into": "main", "strategy": "rebase", "no_ff": false},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_gitignore_needs_rule() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join(".gitignore");
+        assert!(gitignore_needs_rule(&path)?);
+
+        std::fs::write(&path, "target/")?;
+        assert!(gitignore_needs_rule(&path)?);
+        add_gitignore_rule(&path)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "target/\n.vibetree/\n");
+        assert!(!gitignore_needs_rule(&path)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::VibeTreeConfig;
+use crate::forge::{self, NewPullRequest, PrState, PullRequest};
+use crate::git::GitManager;
+use crate::output::{PullRequestResult, PushResult, VibeTreeError};
+
+/// How long removal waits for the forge to say whether a pull request is open
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Options for `vibetree pr`
+#[derive(Debug, Clone, Default)]
+pub struct PullRequestOptions {
+    /// Branch to merge into; the main branch when unset
+    pub base: Option<String>,
+    /// Title, instead of the commit subject or branch name
+    pub title: Option<String>,
+    /// Body, instead of the list of commit subjects
+    pub body: Option<String>,
+    pub draft: bool,
+    /// Open the pull request in the browser
+    pub web: bool,
+}
+
+pub struct PullRequestManager<'a> {
+    config: &'a VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> PullRequestManager<'a> {
+    pub fn new(config: &'a VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Push a branch to the configured remote with upstream tracking
+    pub fn push(&self, branch_name: &str, force_with_lease: bool) -> Result<PushResult> {
+        let repo_path = self.repo_path_for(branch_name)?;
+        let remote = self.config.project_config.forge.remote();
+
+        if GitManager::remote_url(&repo_path, remote)?.is_none() {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Remote '{}' does not exist",
+                remote
+            )));
+        }
+
+        GitManager::push_branch(&repo_path, remote, branch_name, force_with_lease)?;
+        info!("Pushed '{}' to '{}'", branch_name, remote);
+
+        Ok(PushResult {
+            name: branch_name.to_string(),
+            remote: remote.to_string(),
+            upstream: format!("{}/{}", remote, branch_name),
+        })
+    }
+
+    /// Show the branch's open pull request, or push the branch and create one
+    pub fn pull_request(
+        &self,
+        branch_name: &str,
+        options: PullRequestOptions,
+    ) -> Result<PullRequestResult> {
+        let repo_path = self.repo_path_for(branch_name)?;
+        let base = options
+            .base
+            .clone()
+            .unwrap_or_else(|| self.config.project_config.main_branch.clone());
+        if base == branch_name {
+            anyhow::bail!("Cannot open a pull request from '{}' into itself", base);
+        }
+
+        let forge = forge::forge_for(&self.config.project_config.forge, &repo_path)?;
+
+        let existing = forge
+            .find_pull_request(branch_name)?
+            .filter(|pr| pr.state == PrState::Open);
+        let (pull_request, created) = match existing {
+            Some(pr) => {
+                info!("Pull request #{} is already open: {}", pr.number, pr.url);
+                (pr, false)
+            }
+            None => {
+                // The forge can only see pushed commits
+                self.push(branch_name, false)?;
+                let request = self.new_pull_request(&repo_path, branch_name, &base, &options)?;
+                let pr = forge.create_pull_request(&request)?;
+                info!("Created pull request #{}: {}", pr.number, pr.url);
+                (pr, true)
+            }
+        };
+
+        if options.web {
+            forge::open_in_browser(&pull_request.url)?;
+        }
+
+        Ok(PullRequestResult {
+            name: branch_name.to_string(),
+            created,
+            pull_request,
+        })
+    }
+
+    /// The branch's pull request, if it is still open
+    ///
+    /// Only branches that were pushed are looked up, and lookup failures are
+    /// ignored so removal never depends on the forge being reachable. A forge
+    /// that doesn't answer within [`LOOKUP_TIMEOUT`] is given up on.
+    pub fn open_pull_request(&self, branch_name: &str) -> Option<PullRequest> {
+        let repo_path = GitManager::find_repo_root(self.vibetree_parent).ok()?;
+        if !matches!(
+            GitManager::upstream_ahead_behind(&repo_path, branch_name),
+            Ok(Some(_))
+        ) {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let forge_config = self.config.project_config.forge.clone();
+        let branch = branch_name.to_string();
+        thread::spawn(move || {
+            let found = forge::forge_for(&forge_config, &repo_path)
+                .and_then(|forge| forge.find_pull_request(&branch));
+            let _ = sender.send(found);
+        });
+
+        match receiver.recv_timeout(LOOKUP_TIMEOUT) {
+            Ok(Ok(Some(pr))) if pr.state == PrState::Open => Some(pr),
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => {
+                debug!(
+                    "Could not look up pull request for '{}': {}",
+                    branch_name, e
+                );
+                None
+            }
+            Err(_) => {
+                debug!(
+                    "Gave up looking up pull request for '{}' after {}s",
+                    branch_name,
+                    LOOKUP_TIMEOUT.as_secs()
+                );
+                None
+            }
+        }
+    }
+
+    fn repo_path_for(&self, branch_name: &str) -> Result<PathBuf> {
+        let repo_path = GitManager::find_repo_root(self.vibetree_parent)
+            .context("Not inside a git repository")?;
+        if !GitManager::branch_exists(&repo_path, branch_name)? {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Branch '{}' does not exist",
+                branch_name
+            )));
+        }
+        Ok(repo_path)
+    }
+
+    /// Title from the only commit's subject or the branch name, body from the commit subjects
+    fn new_pull_request(
+        &self,
+        repo_path: &std::path::Path,
+        branch_name: &str,
+        base: &str,
+        options: &PullRequestOptions,
+    ) -> Result<NewPullRequest> {
+        let commits = if GitManager::branch_exists(repo_path, base)? {
+            GitManager::branch_commits(repo_path, branch_name, base)?
+        } else {
+            Vec::new()
+        };
+
+        let title = match (&options.title, commits.as_slice()) {
+            (Some(title), _) => title.clone(),
+            (None, [commit]) => commit.subject.clone(),
+            (None, _) => branch_name.to_string(),
+        };
+        let body = match &options.body {
+            Some(body) => body.clone(),
+            None if commits.len() > 1 => commits
+                .iter()
+                .map(|commit| format!("* {}", commit.subject))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        };
+
+        Ok(NewPullRequest {
+            head: branch_name.to_string(),
+            base: base.to_string(),
+            title,
+            body,
+            draft: options.draft,
+        })
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, TableLike, Value};
+
+use crate::config::{VariableConfig, VariableType, VibeTreeProjectConfig};
+use crate::schema;
+
+/// vibetree.toml as an editable document
+///
+/// Edits only touch the keys they change, so the comments, ordering and
+/// formatting the team wrote are kept.
+pub struct ProjectFile {
+    path: PathBuf,
+    document: DocumentMut,
+    /// Whether the file on disk is from an older vibetree and the document was
+    /// migrated in memory
+    migrated: bool,
+}
+
+impl ProjectFile {
+    /// Read vibetree.toml at `path`, migrating it in memory if it's from an older
+    /// vibetree; a missing file is an empty document
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut document = if path.exists() {
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read project config file: {}", path.display()))?
+                .parse::<DocumentMut>()
+                .with_context(|| {
+                    format!("Failed to parse project config file: {}", path.display())
+                })?
+        } else {
+            DocumentMut::new()
+        };
+        let migrated = !document.is_empty()
+            && !schema::PROJECT
+                .upgrade(&mut document, &path.display().to_string())?
+                .is_empty();
+        Ok(Self {
+            path: path.to_path_buf(),
+            document,
+            migrated,
+        })
+    }
+
+    /// The configuration the document holds
+    pub fn config(&self) -> Result<VibeTreeProjectConfig> {
+        toml::from_str(&self.document.to_string()).with_context(|| {
+            format!(
+                "Failed to parse project config file: {}",
+                self.path.display()
+            )
+        })
+    }
+
+    /// Bring the document in line with `config`, changing only the values that differ
+    ///
+    /// Returns whether anything changed, so callers can leave the file alone otherwise.
+    pub fn update(&mut self, config: &VibeTreeProjectConfig) -> Result<bool> {
+        let old = if self.document.is_empty() {
+            toml::Table::new()
+        } else {
+            toml::Table::try_from(self.config()?)
+                .context("Failed to serialize project config to TOML")?
+        };
+        let new =
+            toml::Table::try_from(config).context("Failed to serialize project config to TOML")?;
+        if old == new {
+            return Ok(false);
+        }
+        update_table(self.document.as_table_mut(), &old, &new, false);
+        Ok(true)
+    }
+
+    /// Append a `[[variables]]` entry
+    pub fn add_variable(&mut self, variable: &VariableConfig) -> Result<()> {
+        let variables = self
+            .document
+            .entry("variables")
+            .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .context("'variables' in vibetree.toml is not an array of tables")?;
+
+        let mut table = Table::new();
+        set_value(&mut table, "name", variable.name.as_str().into());
+        set_variable_fields(&mut table, variable)?;
+        variables.push(table);
+        Ok(())
+    }
+
+    /// Replace the value, type and branch of the `[[variables]]` entry named like
+    /// `variable`, keeping anything else in it
+    pub fn update_variable(&mut self, variable: &VariableConfig) -> Result<bool> {
+        let Some(table) = self
+            .variables_mut()
+            .and_then(|variables| variables.iter_mut().find(|t| is_named(t, &variable.name)))
+        else {
+            return Ok(false);
+        };
+        set_variable_fields(table, variable)?;
+        Ok(true)
+    }
+
+    /// Remove the `[[variables]]` entries named `name`, returning whether there were any
+    pub fn remove_variable(&mut self, name: &str) -> bool {
+        let Some(variables) = self.variables_mut() else {
+            return false;
+        };
+        let before = variables.len();
+        variables.retain(|table| !is_named(table, name));
+        let removed = variables.len() != before;
+        if variables.is_empty() {
+            self.document.remove("variables");
+        }
+        removed
+    }
+
+    pub fn save(&self) -> Result<()> {
+        // Writing the migrated document would lock out teammates on older vibetrees
+        if self.migrated {
+            anyhow::bail!(
+                "{} is from an older vibetree. Run `vibetree migrate` before changing it.",
+                self.path.display()
+            );
+        }
+        fs::write(&self.path, self.document.to_string()).with_context(|| {
+            format!(
+                "Failed to write project config file: {}",
+                self.path.display()
+            )
+        })
+    }
+
+    fn variables_mut(&mut self) -> Option<&mut ArrayOfTables> {
+        self.document
+            .get_mut("variables")
+            .and_then(Item::as_array_of_tables_mut)
+    }
+}
+
+fn is_named(table: &Table, name: &str) -> bool {
+    table.get("name").and_then(Item::as_str) == Some(name)
+}
+
+/// Write `value`, `type` and `branch` of a variable into its table
+fn set_variable_fields(table: &mut Table, variable: &VariableConfig) -> Result<()> {
+    match &variable.value {
+        Some(toml::Value::Integer(number)) => set_value(table, "value", (*number).into()),
+        Some(toml::Value::String(string)) => set_value(table, "value", string.as_str().into()),
+        Some(other) => anyhow::bail!(
+            "Variable '{}' has unsupported value type: {}",
+            variable.name,
+            other
+        ),
+        None => drop(table.remove("value")),
+    }
+    match &variable.r#type {
+        Some(VariableType::Port) => set_value(table, "type", "port".into()),
+        Some(VariableType::Int) => set_value(table, "type", "int".into()),
+        None => drop(table.remove("type")),
+    }
+    match &variable.branch {
+        Some(branch) => set_value(table, "branch", branch.as_str().into()),
+        None => drop(table.remove("branch")),
+    }
+    Ok(())
+}
+
+/// Set `key` to `value`, keeping the comments and spacing around a value it replaces
+fn set_value(table: &mut Table, key: &str, value: Value) {
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(existing) => replace_value(existing, value),
+        None => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+fn replace_value(existing: &mut Value, mut value: Value) {
+    *value.decor_mut() = existing.decor().clone();
+    *existing = value;
+}
+
+/// Apply the difference between `old`, what `table` holds, and `new` to `table`
+///
+/// Keys whose value didn't change are left exactly as written, including keys
+/// vibetree doesn't know about.
+fn update_table(table: &mut dyn TableLike, old: &toml::Table, new: &toml::Table, inline: bool) {
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        table.remove(key);
+    }
+    for (key, value) in new {
+        let previous = old.get(key);
+        if previous == Some(value) {
+            continue;
+        }
+        let Some(item) = table.get_mut(key) else {
+            table.insert(key, to_item(value, inline));
+            continue;
+        };
+        match (previous, value) {
+            (Some(toml::Value::Table(previous)), toml::Value::Table(value))
+                if item.is_table_like() =>
+            {
+                let inline = item.is_inline_table();
+                if let Some(item) = item.as_table_like_mut() {
+                    update_table(item, previous, value, inline);
+                }
+            }
+            (Some(toml::Value::Array(previous)), toml::Value::Array(value))
+                if item.is_array_of_tables() && is_array_of_tables(value) =>
+            {
+                if let Some(tables) = item.as_array_of_tables_mut() {
+                    update_array_of_tables(tables, previous, value);
+                }
+            }
+            _ => match item.as_value_mut() {
+                Some(existing) => replace_value(existing, to_value(value)),
+                None => *item = to_item(value, inline),
+            },
+        }
+    }
+}
+
+/// Update `[[...]]` entries in place, appending or dropping entries at the end
+fn update_array_of_tables(tables: &mut ArrayOfTables, old: &[toml::Value], new: &[toml::Value]) {
+    let empty = toml::Table::new();
+    for (index, value) in new.iter().enumerate() {
+        let toml::Value::Table(value) = value else {
+            continue;
+        };
+        let previous = match old.get(index) {
+            Some(toml::Value::Table(previous)) => previous,
+            _ => &empty,
+        };
+        match tables.get_mut(index) {
+            Some(table) => update_table(table, previous, value, false),
+            None => tables.push(to_table(value)),
+        }
+    }
+    while tables.len() > new.len() {
+        tables.remove(tables.len() - 1);
+    }
+}
+
+fn is_array_of_tables(values: &[toml::Value]) -> bool {
+    !values.is_empty() && values.iter().all(toml::Value::is_table)
+}
+
+/// A new item laid out like `toml::to_string_pretty` would
+fn to_item(value: &toml::Value, inline: bool) -> Item {
+    match value {
+        _ if inline => Item::Value(to_value(value)),
+        toml::Value::Table(table) => Item::Table(to_table(table)),
+        toml::Value::Array(values) if is_array_of_tables(values) => Item::ArrayOfTables(
+            values
+                .iter()
+                .filter_map(toml::Value::as_table)
+                .map(to_table)
+                .collect(),
+        ),
+        _ => Item::Value(to_value(value)),
+    }
+}
+
+fn to_table(table: &toml::Table) -> Table {
+    let mut result = Table::new();
+    for (key, value) in table {
+        result.insert(key, to_item(value, false));
+    }
+    result
+}
+
+fn to_value(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => string.as_str().into(),
+        toml::Value::Integer(number) => (*number).into(),
+        toml::Value::Float(number) => (*number).into(),
+        toml::Value::Boolean(boolean) => (*boolean).into(),
+        toml::Value::Datetime(datetime) => (*datetime).into(),
+        toml::Value::Array(values) => Value::Array(values.iter().map(to_value).collect()),
+        toml::Value::Table(table) => Value::InlineTable(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), to_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"# Shared by the whole team
+version = "1"
+main_branch = "main"
+
+# Database for each worktree
+[[variables]]
+name = "POSTGRES_PORT"
+value = 5432 # the default port
+type = "port"
+"#;
+
+    #[test]
+    fn test_variable_edits_keep_comments() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("vibetree.toml");
+        fs::write(&path, CONFIG)?;
+
+        let mut file = ProjectFile::load(&path)?;
+        file.add_variable(&VariableConfig {
+            name: "DB_NAME".to_string(),
+            value: Some(toml::Value::String("app_{int:1}".to_string())),
+            r#type: None,
+            branch: Some("^feature/".to_string()),
+        })?;
+        file.save()?;
+
+        let written = fs::read_to_string(&path)?;
+        assert!(written.starts_with(CONFIG));
+        assert!(written.ends_with(
+            "\n[[variables]]\nname = \"DB_NAME\"\nvalue = \"app_{int:1}\"\nbranch = \"^feature/\"\n"
+        ));
+
+        let mut file = ProjectFile::load(&path)?;
+        assert!(file.remove_variable("DB_NAME"));
+        assert!(!file.remove_variable("DB_NAME"));
+        assert!(file.update_variable(&VariableConfig {
+            name: "POSTGRES_PORT".to_string(),
+            value: Some(toml::Value::Integer(6543)),
+            r#type: Some(VariableType::Port),
+            branch: None,
+        })?);
+        file.save()?;
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            CONFIG.replace("value = 5432", "value = 6543")
+        );
+        assert_eq!(file.config()?.variables.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_only_touches_changed_values() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("vibetree.toml");
+        fs::write(&path, CONFIG)?;
+
+        let mut file = ProjectFile::load(&path)?;
+        let mut config = file.config()?;
+        assert!(!file.update(&config)?);
+
+        config.main_branch = "trunk".to_string();
+        config.variables[0].value = Some(toml::Value::Integer(6543));
+        config.variables.push(VariableConfig {
+            name: "REDIS_PORT".to_string(),
+            value: Some(toml::Value::Integer(6379)),
+            r#type: Some(VariableType::Port),
+            branch: None,
+        });
+        assert!(file.update(&config)?);
+        file.save()?;
+
+        let expected = CONFIG
+            .replace("\"main\"", "\"trunk\"")
+            .replace("value = 5432", "value = 6543");
+        let written = fs::read_to_string(&path)?;
+        assert!(written.starts_with(&expected));
+        assert!(written.ends_with("name = \"REDIS_PORT\"\nvalue = 6379\ntype = \"port\"\n"));
+
+        config.variables.truncate(1);
+        assert!(file.update(&config)?);
+        file.save()?;
+        assert_eq!(fs::read_to_string(&path)?, expected);
+        Ok(())
+    }
+}
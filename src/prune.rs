@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::VibeTreeConfig;
+use crate::git::GitManager;
+use crate::ports::PortManager;
+
+/// Which kinds of worktrees `prune` should consider
+#[derive(Debug, Clone, Default)]
+pub struct PruneCriteria {
+    /// Branch is fully merged into the main branch
+    pub merged: bool,
+    /// Branch tracks an upstream that has been deleted
+    pub gone: bool,
+    /// Branch has had no commits for this many days
+    pub stale_days: Option<u64>,
+    /// Worktree directory no longer exists
+    pub missing: bool,
+}
+
+impl PruneCriteria {
+    /// Use merged, gone and missing when no criteria were selected explicitly
+    pub fn or_default(self) -> Self {
+        if self.merged || self.gone || self.missing || self.stale_days.is_some() {
+            self
+        } else {
+            Self {
+                merged: true,
+                gone: true,
+                stale_days: None,
+                missing: true,
+            }
+        }
+    }
+}
+
+/// Why a worktree was selected for pruning
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruneReason {
+    Merged,
+    UpstreamGone,
+    Stale(u64),
+    Missing,
+}
+
+impl fmt::Display for PruneReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PruneReason::Merged => write!(f, "merged"),
+            PruneReason::UpstreamGone => write!(f, "upstream gone"),
+            PruneReason::Stale(days) => write!(f, "no commits for {} days", days),
+            PruneReason::Missing => write!(f, "directory missing"),
+        }
+    }
+}
+
+/// A worktree `prune` selected, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneCandidate {
+    pub name: String,
+    pub path: PathBuf,
+    pub reasons: Vec<PruneReason>,
+    /// Whether the branch goes too, which is only when its work is on main
+    pub delete_branch: bool,
+}
+
+pub struct PruneManager<'a> {
+    config: &'a VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> PruneManager<'a> {
+    pub fn new(config: &'a VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Find and list the worktrees matching the criteria
+    pub fn candidates(&self, criteria: PruneCriteria) -> Result<Vec<PruneCandidate>> {
+        let criteria = criteria.or_default();
+        let repo_path = GitManager::find_repo_root(self.vibetree_parent)
+            .context("Not inside a git repository")?;
+
+        let candidates = self.find_candidates(&repo_path, &criteria)?;
+        if candidates.is_empty() {
+            info!("No worktrees to prune");
+            return Ok(candidates);
+        }
+
+        info!("Worktrees to prune:");
+        for candidate in &candidates {
+            let reasons = candidate
+                .reasons
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            info!("  {} ({})", candidate.name, reasons);
+        }
+        Ok(candidates)
+    }
+
+    fn find_candidates(
+        &self,
+        repo_path: &Path,
+        criteria: &PruneCriteria,
+    ) -> Result<Vec<PruneCandidate>> {
+        let main_branch = &self.config.project_config.main_branch;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let mut names: Vec<_> = self.config.branches_config.worktrees.keys().collect();
+        names.sort();
+
+        let mut candidates = Vec::new();
+        for name in names {
+            if name == main_branch {
+                continue;
+            }
+
+            let path = self.config.get_worktree_path(self.vibetree_parent, name);
+            let mut reasons = Vec::new();
+
+            if criteria.missing && !path.exists() {
+                reasons.push(PruneReason::Missing);
+            }
+
+            let mut on_main = false;
+            if GitManager::branch_exists(repo_path, name)? {
+                on_main = GitManager::is_ancestor(repo_path, name, main_branch)?;
+                // A branch that never moved since it was created has no work of its
+                // own, so it only counts as merged once it has had commits
+                if criteria.merged && on_main && GitManager::branch_has_moved(repo_path, name)? {
+                    reasons.push(PruneReason::Merged);
+                }
+
+                if criteria.gone && GitManager::is_upstream_gone(repo_path, name)? {
+                    reasons.push(PruneReason::UpstreamGone);
+                }
+
+                if let Some(days) = criteria.stale_days {
+                    match Self::last_activity(repo_path, name, &path) {
+                        Ok(Some(last_activity))
+                            if now - last_activity > days as i64 * 24 * 60 * 60 =>
+                        {
+                            reasons.push(PruneReason::Stale(days));
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Skipping staleness check for '{}': {:#}", name, e),
+                    }
+                }
+            }
+
+            if reasons.is_empty() {
+                continue;
+            }
+
+            if path.exists() && GitManager::has_uncommitted_changes(&path).unwrap_or(true) {
+                warn!("Skipping '{}': worktree has uncommitted changes", name);
+                continue;
+            }
+
+            let live_ports = self.live_ports(name);
+            if !live_ports.is_empty() {
+                warn!(
+                    "Skipping '{}': allocated ports are in use: {}",
+                    name,
+                    live_ports
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                continue;
+            }
+
+            // A gone upstream doesn't mean the work was merged; commits that are
+            // only on the branch would be lost with it
+            candidates.push(PruneCandidate {
+                name: name.clone(),
+                path,
+                delete_branch: on_main
+                    && reasons
+                        .iter()
+                        .any(|r| matches!(r, PruneReason::Merged | PruneReason::UpstreamGone)),
+                reasons,
+            });
+        }
+
+        Ok(candidates)
+    }
+
+    /// When work last happened on a worktree, as seconds since the Unix epoch
+    ///
+    /// Uncommitted edits disqualify a worktree anyway, so once the branch has
+    /// commits of its own the last one is the last change that matters. Before
+    /// that its tip is whatever it was created from, which says nothing about the
+    /// worktree, so the time its directory was last modified is used instead.
+    fn last_activity(repo_path: &Path, branch: &str, path: &Path) -> Result<Option<i64>> {
+        if GitManager::branch_has_moved(repo_path, branch)? {
+            return GitManager::last_commit_time(repo_path, branch).map(Some);
+        }
+        if !path.exists() {
+            return Ok(None);
+        }
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to read modification time of {}", path.display()))?;
+        Ok(modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .ok())
+    }
+
+    /// Allocated port values that something is currently listening on
+    fn live_ports(&self, name: &str) -> Vec<u16> {
+        let Some(worktree) = self.config.branches_config.worktrees.get(name) else {
+            return Vec::new();
+        };
+
+        let mut ports: Vec<u16> = worktree
+            .values
+            .values()
+            .filter_map(|v| v.parse::<u16>().ok())
+            .filter(|&port| port >= 1024)
+            .filter(|&port| !PortManager::check_port_availability(port))
+            .collect();
+        ports.sort();
+        ports
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::allocator::VariableAllocator;
+use crate::config::{VibeTreeBranchesConfig, WorktreeConfig};
+use crate::lock::FileLock;
+use crate::output::{RegistryGcResult, RegistryResult};
+
+/// Environment variable naming another registry file, or `off` to not use one
+pub const REGISTRY_ENV: &str = "VIBETREE_REGISTRY";
+
+/// One worktree's values, as recorded in the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub repo: PathBuf,
+    pub worktree: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, String>,
+}
+
+/// Allocations of every repository on this machine, so that two repos starting
+/// from the same base port don't hand out the same ports
+///
+/// Lives in `~/.local/state/vibetree/registry.toml`; every read-modify-write
+/// holds the lock next to it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    #[serde(default)]
+    pub allocations: Vec<Allocation>,
+}
+
+impl Registry {
+    /// Path of the registry file, or None when the registry is turned off
+    ///
+    /// Tests don't use the registry unless `VIBETREE_REGISTRY` points at one.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var(REGISTRY_ENV) {
+            Ok(value) if value == "off" => None,
+            Ok(value) if !value.is_empty() => Some(PathBuf::from(value)),
+            _ if cfg!(test) || std::env::var("VIBETREE_TESTING").is_ok() => None,
+            _ => {
+                let state_dir = dirs::state_dir()
+                    .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("state")))?;
+                Some(state_dir.join("vibetree").join("registry.toml"))
+            }
+        }
+    }
+
+    /// Read the registry at `path`; a missing file is an empty registry
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read registry: {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse registry: {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create registry directory: {}", parent.display())
+            })?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize registry")?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write registry: {}", path.display()))
+    }
+
+    /// Run `f` on the registry at `path` while holding its lock, saving it afterwards
+    pub fn update<T>(path: &Path, f: impl FnOnce(&mut Registry) -> T) -> Result<T> {
+        let _lock = FileLock::acquire(&path.with_extension("lock"))?;
+        let mut registry = Self::load(path)?;
+        let result = f(&mut registry);
+        registry.save(path)?;
+        Ok(result)
+    }
+
+    /// Replace the allocations recorded for `repo` with its current worktrees
+    pub fn record(&mut self, repo: &Path, worktrees: &HashMap<String, WorktreeConfig>) {
+        self.allocations
+            .retain(|allocation| allocation.repo != repo);
+        self.allocations
+            .extend(worktrees.iter().map(|(name, worktree)| Allocation {
+                repo: repo.to_path_buf(),
+                worktree: name.clone(),
+                values: worktree.values.clone().into_iter().collect(),
+            }));
+        self.allocations
+            .sort_by(|a, b| (&a.repo, &a.worktree).cmp(&(&b.repo, &b.worktree)));
+    }
+
+    /// Record the values just allocated to one worktree of `repo`, ahead of the
+    /// repo recording all of its worktrees when it saves
+    pub fn reserve(&mut self, repo: &Path, worktree: &str, values: &HashMap<String, String>) {
+        self.allocations
+            .retain(|allocation| allocation.repo != repo || allocation.worktree != worktree);
+        self.allocations.push(Allocation {
+            repo: repo.to_path_buf(),
+            worktree: worktree.to_string(),
+            values: values.clone().into_iter().collect(),
+        });
+        self.allocations
+            .sort_by(|a, b| (&a.repo, &a.worktree).cmp(&(&b.repo, &b.worktree)));
+    }
+
+    /// Ports held by the worktrees of repositories other than `repo`
+    pub fn ports_used_outside(&self, repo: &Path) -> HashSet<u16> {
+        self.allocations
+            .iter()
+            .filter(|allocation| allocation.repo != repo)
+            .flat_map(|allocation| allocation.values.values())
+            .flat_map(|value| VariableAllocator::numbers_in(value))
+            .collect()
+    }
+
+    /// Drop allocations whose repository or worktree no longer exists, returning them
+    ///
+    /// Repositories whose branches.toml can't be read keep their allocations,
+    /// as the problem may well be passing.
+    pub fn gc(&mut self) -> Vec<Allocation> {
+        let mut worktrees_by_repo: HashMap<PathBuf, RepoWorktrees> = HashMap::new();
+        let (kept, removed) =
+            std::mem::take(&mut self.allocations)
+                .into_iter()
+                .partition(|allocation| {
+                    match worktrees_by_repo
+                        .entry(allocation.repo.clone())
+                        .or_insert_with(|| configured_worktrees(&allocation.repo))
+                    {
+                        RepoWorktrees::Gone => false,
+                        RepoWorktrees::Unreadable => true,
+                        RepoWorktrees::Present(names) => names.contains(&allocation.worktree),
+                    }
+                });
+        self.allocations = kept;
+        removed
+    }
+}
+
+/// What a repository's branches.toml says about its worktrees
+enum RepoWorktrees {
+    /// The repository or its branches.toml no longer exists
+    Gone,
+    /// branches.toml exists but couldn't be read or parsed
+    Unreadable,
+    Present(HashSet<String>),
+}
+
+fn configured_worktrees(repo: &Path) -> RepoWorktrees {
+    let path = repo.join(".vibetree").join("branches.toml");
+    if !path.exists() {
+        return RepoWorktrees::Gone;
+    }
+    let worktrees = fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(toml::from_str::<VibeTreeBranchesConfig>(&content)?));
+    match worktrees {
+        Ok(config) => RepoWorktrees::Present(config.worktrees.into_keys().collect()),
+        Err(e) => {
+            warn!(
+                "Keeping the allocations of {}, as {} can't be read: {}",
+                repo.display(),
+                path.display(),
+                e
+            );
+            RepoWorktrees::Unreadable
+        }
+    }
+}
+
+/// Print the registry's allocations
+pub fn list() -> Result<RegistryResult> {
+    let path = Registry::path().context("The registry is turned off (VIBETREE_REGISTRY=off)")?;
+    let registry = Registry::load(&path)?;
+    info!("Registry at {}", path.display());
+    for allocation in &registry.allocations {
+        let values = allocation
+            .values
+            .iter()
+            .map(|(variable, value)| format!("{}={}", variable, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        info!(
+            "  {} {} {}",
+            allocation.repo.display(),
+            allocation.worktree,
+            values
+        );
+    }
+    Ok(RegistryResult {
+        path,
+        allocations: registry.allocations,
+    })
+}
+
+/// Remove allocations of repositories and worktrees that no longer exist
+pub fn gc(dry_run: bool) -> Result<RegistryGcResult> {
+    let path = Registry::path().context("The registry is turned off (VIBETREE_REGISTRY=off)")?;
+    let removed = if dry_run {
+        Registry::load(&path)?.gc()
+    } else {
+        Registry::update(&path, Registry::gc)?
+    };
+    for allocation in &removed {
+        info!(
+            "{} {} from {}",
+            if dry_run { "Would remove" } else { "Removed" },
+            allocation.worktree,
+            allocation.repo.display()
+        );
+    }
+    if removed.is_empty() {
+        info!("No stale allocations");
+    }
+    Ok(RegistryGcResult { dry_run, removed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn worktrees(values: &[(&str, &str)]) -> HashMap<String, WorktreeConfig> {
+        values
+            .iter()
+            .map(|(name, port)| {
+                let worktree = WorktreeConfig {
+                    values: HashMap::from([("PORT".to_string(), port.to_string())]),
+                    ..Default::default()
+                };
+                (name.to_string(), worktree)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_replaces_repo_and_reserves_other_repos_ports() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("registry.toml");
+        let (one, two) = (Path::new("/repos/one"), Path::new("/repos/two"));
+
+        Registry::update(&path, |registry| {
+            registry.record(one, &worktrees(&[("main", "5432"), ("feature", "5433")]));
+            registry.record(two, &worktrees(&[("main", "5434")]));
+        })?;
+        Registry::update(&path, |registry| {
+            registry.record(one, &worktrees(&[("main", "5432")]))
+        })?;
+
+        let registry = Registry::load(&path)?;
+        assert_eq!(registry.allocations.len(), 2);
+        assert_eq!(registry.ports_used_outside(two), HashSet::from([5432]));
+        assert_eq!(registry.ports_used_outside(one), HashSet::from([5434]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve_holds_ports_until_the_repo_records() {
+        let (one, two) = (Path::new("/repos/one"), Path::new("/repos/two"));
+        let mut registry = Registry::default();
+        registry.record(one, &worktrees(&[("main", "5432")]));
+
+        let values = HashMap::from([("PORT".to_string(), "5433".to_string())]);
+        registry.reserve(one, "feature", &values);
+        registry.reserve(one, "feature", &values);
+        assert_eq!(registry.allocations.len(), 2);
+        assert_eq!(registry.ports_used_outside(two), HashSet::from([5432, 5433]));
+
+        // A rolled back add drops the reservation when the repo next records
+        registry.record(one, &worktrees(&[("main", "5432")]));
+        assert_eq!(registry.ports_used_outside(two), HashSet::from([5432]));
+    }
+
+    #[test]
+    fn test_gc_drops_missing_repos_and_worktrees() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo = temp_dir.path().join("repo");
+        fs::create_dir_all(repo.join(".vibetree"))?;
+        fs::write(
+            repo.join(".vibetree").join("branches.toml"),
+            "version = \"1\"\n\n[worktrees.main.values]\nPORT = \"5432\"\n",
+        )?;
+
+        let mut registry = Registry::default();
+        registry.record(&repo, &worktrees(&[("main", "5432"), ("gone", "5433")]));
+        registry.record(
+            &temp_dir.path().join("deleted"),
+            &worktrees(&[("main", "5434")]),
+        );
+
+        let removed = registry.gc();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(registry.allocations.len(), 1);
+        assert_eq!(registry.allocations[0].worktree, "main");
+
+        // An unreadable branches.toml keeps the repo's allocations
+        fs::write(repo.join(".vibetree").join("branches.toml"), "not [toml")?;
+        registry.record(&repo, &worktrees(&[("main", "5432"), ("feature", "5433")]));
+        assert!(registry.gc().is_empty());
+        assert_eq!(registry.allocations.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::ResourceConfig;
+use crate::placeholders::expand;
+
+/// Creates and destroys the `[[resources]]` of a worktree, such as its database
+///
+/// Commands run through `sh` with the worktree's values in their environment.
+/// `${VAR}` placeholders naming a value are also replaced up front, so plans
+/// show the commands as they will run.
+pub struct ResourceManager<'a> {
+    resource: &'a ResourceConfig,
+    values: &'a HashMap<String, String>,
+}
+
+impl<'a> ResourceManager<'a> {
+    pub fn new(resource: &'a ResourceConfig, values: &'a HashMap<String, String>) -> Self {
+        Self { resource, values }
+    }
+
+    /// The `create` command with the worktree's values filled in
+    pub fn create_command(&self) -> String {
+        expand(&self.resource.create, self.values)
+    }
+
+    /// The `destroy` command with the worktree's values filled in
+    pub fn destroy_command(&self) -> String {
+        expand(&self.resource.destroy, self.values)
+    }
+
+    /// Run the `exists` check; without one the resource is assumed not to exist
+    pub fn exists(&self, cwd: &Path) -> Result<bool> {
+        let Some(check) = &self.resource.exists else {
+            return Ok(false);
+        };
+        let status = command(&expand(check, self.values), self.values, cwd)
+            .output()
+            .with_context(|| format!("Failed to run exists check of '{}'", self.resource.name))?
+            .status;
+        Ok(status.success())
+    }
+
+    /// Create the resource unless its `exists` check finds it already there
+    ///
+    /// Returns whether it was created, as opposed to found.
+    pub fn provision(&self, cwd: &Path) -> Result<bool> {
+        if self.exists(cwd)? {
+            info!("Resource '{}' already exists", self.resource.name);
+            return Ok(false);
+        }
+        run(&self.create_command(), self.values, cwd)?;
+        info!("Created resource '{}'", self.resource.name);
+        Ok(true)
+    }
+}
+
+/// Run a resource command in `cwd` with the worktree's values in its environment
+pub fn run(command_line: &str, values: &HashMap<String, String>, cwd: &Path) -> Result<()> {
+    debug!("Running: {}", command_line);
+    let output = command(command_line, values, cwd)
+        .output()
+        .with_context(|| format!("Failed to run: {}", command_line))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "'{}' failed with {}: {}",
+            command_line,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn command(command_line: &str, values: &HashMap<String, String>, cwd: &Path) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(command_line)
+        .current_dir(cwd)
+        .envs(values);
+    command
+}
@@ -0,0 +1,334 @@
+//! Versions of vibetree's file formats, and the migrations between them
+//!
+//! `vibetree.toml` and `.vibetree/branches.toml` record the version of their
+//! format in `version`. A file written by an older vibetree is migrated one
+//! version at a time. The shared `vibetree.toml` is only migrated in memory as
+//! it loads, and rewritten by `vibetree migrate`; the local `branches.toml` is
+//! rewritten as it loads. Either way a copy of the old file is saved to
+//! `.vibetree/backups` first. A file written by a newer vibetree is refused
+//! rather than misread.
+//!
+//! To change a format, append a [`Migration`] to its schema: the current
+//! version is always one more than the number of migrations.
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, value};
+
+use crate::output::MigrateResult;
+
+/// One step from a version of a file format to the next
+pub struct Migration {
+    /// What the step changes, shown by `vibetree migrate --dry-run`
+    pub description: &'static str,
+    pub apply: fn(&mut DocumentMut) -> Result<()>,
+}
+
+/// The versions of one file format
+pub struct Schema {
+    pub file: &'static str,
+    /// `migrations[0]` goes from version 1 to 2, `migrations[1]` from 2 to 3, and so on
+    pub migrations: &'static [Migration],
+}
+
+/// Format of the shared vibetree.toml
+pub const PROJECT: Schema = Schema {
+    file: "vibetree.toml",
+    migrations: &[],
+};
+
+/// Format of the local .vibetree/branches.toml
+pub const BRANCHES: Schema = Schema {
+    file: "branches.toml",
+    migrations: &[],
+};
+
+/// What migrating a file did, or would do in a dry run
+#[derive(Debug, Clone, Serialize)]
+pub struct FileMigration {
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+    /// Descriptions of the migrations applied, oldest first
+    pub migrations: Vec<String>,
+    /// Copy of the file from before the migration
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+}
+
+impl FileMigration {
+    pub fn is_current(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+impl Schema {
+    pub const fn current(&self) -> u32 {
+        self.migrations.len() as u32 + 1
+    }
+
+    /// Version a document was written with; files from before versioning are version 1
+    pub fn version(&self, document: &DocumentMut, source: &str) -> Result<u32> {
+        let version = match document.get("version") {
+            None => return Ok(1),
+            Some(item) => match (item.as_str(), item.as_integer()) {
+                (Some(version), _) => version.parse().ok(),
+                (_, Some(version)) => u32::try_from(version).ok(),
+                _ => None,
+            },
+        };
+        version
+            .filter(|version| *version >= 1)
+            .with_context(|| format!("Invalid version in {}: {}", source, document["version"]))
+    }
+
+    /// Migrate a document to the current version in place, returning what was applied
+    ///
+    /// Fails for documents from a newer vibetree.
+    pub fn upgrade(&self, document: &mut DocumentMut, source: &str) -> Result<Vec<String>> {
+        let version = self.version(document, source)?;
+        if version > self.current() {
+            bail!(
+                "{} uses format version {}, but vibetree {} only understands up to version {}. \
+                 Upgrade vibetree to work with this repository.",
+                source,
+                version,
+                crate::VERSION,
+                self.current()
+            );
+        }
+
+        let mut applied = Vec::new();
+        for (from, migration) in (version..).zip(&self.migrations[version as usize - 1..]) {
+            (migration.apply)(document).with_context(|| {
+                format!(
+                    "Failed to migrate {} from version {} to {}",
+                    source,
+                    from,
+                    from + 1
+                )
+            })?;
+            applied.push(migration.description.to_string());
+        }
+        if !applied.is_empty() {
+            let current = self.current().to_string();
+            match document.get_mut("version").and_then(Item::as_value_mut) {
+                Some(existing) => {
+                    let decor = existing.decor().clone();
+                    *existing = current.into();
+                    *existing.decor_mut() = decor;
+                }
+                None => {
+                    document.insert("version", value(current));
+                }
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Parse a file's content, migrating it in memory
+    ///
+    /// For copies vibetree doesn't own, like other checkouts and past revisions,
+    /// and for the shared vibetree.toml, which only `vibetree migrate` rewrites.
+    pub fn parse<T: DeserializeOwned>(&self, content: &str, source: &str) -> Result<T> {
+        let mut document = content
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", source))?;
+        self.upgrade(&mut document, source)?;
+        toml::from_str(&document.to_string()).with_context(|| format!("Failed to parse {}", source))
+    }
+
+    /// Load a file, first migrating it on disk if it is from an older vibetree
+    ///
+    /// Only for files local to this checkout, like branches.toml.
+    pub fn load<T: DeserializeOwned>(&self, path: &Path, backup_dir: &Path) -> Result<T> {
+        let (document, migration) = self.migrate_file(path, backup_dir, false)?;
+        if !migration.is_current() {
+            info!(
+                "Migrated {} from version {} to {}",
+                path.display(),
+                migration.from,
+                migration.to
+            );
+        }
+        toml::from_str(&document.to_string())
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Bring a file to the current version, saving a copy of the old one to `backup_dir`
+    ///
+    /// A dry run only works out what would change.
+    pub fn migrate_file(
+        &self,
+        path: &Path,
+        backup_dir: &Path,
+        dry_run: bool,
+    ) -> Result<(DocumentMut, FileMigration)> {
+        let source = path.display().to_string();
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", source))?;
+        let mut document = content
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", source))?;
+        let from = self.version(&document, &source)?;
+        let migrations = self.upgrade(&mut document, &source)?;
+
+        let mut migration = FileMigration {
+            path: path.to_path_buf(),
+            from,
+            to: from.max(self.current()),
+            migrations,
+            backup: None,
+        };
+        if migration.is_current() || dry_run {
+            return Ok((document, migration));
+        }
+
+        fs::create_dir_all(backup_dir).with_context(|| {
+            format!(
+                "Failed to create backup directory: {}",
+                backup_dir.display()
+            )
+        })?;
+        let backup = backup_dir.join(format!("{}.v{}", self.file, from));
+        fs::write(&backup, &content)
+            .with_context(|| format!("Failed to back up {} to {}", source, backup.display()))?;
+        fs::write(path, document.to_string())
+            .with_context(|| format!("Failed to write {}", source))?;
+        migration.backup = Some(backup);
+        Ok((document, migration))
+    }
+}
+
+/// Migrate the repository's vibetree.toml and .vibetree/branches.toml, or with
+/// `dry_run` only report what would change
+pub fn migrate(vibetree_parent: &Path, dry_run: bool) -> Result<MigrateResult> {
+    let vibetree_dir = vibetree_parent.join(".vibetree");
+    let backup_dir = vibetree_dir.join("backups");
+    let files = [
+        (&PROJECT, vibetree_parent.join("vibetree.toml")),
+        (&BRANCHES, vibetree_dir.join("branches.toml")),
+    ];
+
+    let mut migrated = Vec::new();
+    for (schema, path) in files {
+        if !path.exists() {
+            continue;
+        }
+        let (_, migration) = schema.migrate_file(&path, &backup_dir, dry_run)?;
+        if migration.is_current() {
+            info!(
+                "{} is at the current version {}",
+                path.display(),
+                migration.to
+            );
+        } else {
+            info!(
+                "{} {} from version {} to {}:",
+                if dry_run { "Would migrate" } else { "Migrated" },
+                path.display(),
+                migration.from,
+                migration.to
+            );
+            for description in &migration.migrations {
+                info!("  - {}", description);
+            }
+            if let Some(backup) = &migration.backup {
+                info!("  Backup saved to {}", backup.display());
+            }
+        }
+        migrated.push(migration);
+    }
+    Ok(MigrateResult {
+        dry_run,
+        files: migrated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn rename_ports(document: &mut DocumentMut) -> Result<()> {
+        if let Some(ports) = document.remove("ports") {
+            document.insert("values", ports);
+        }
+        Ok(())
+    }
+
+    fn add_layout(document: &mut DocumentMut) -> Result<()> {
+        document.insert("layout", value("default"));
+        Ok(())
+    }
+
+    const TEST: Schema = Schema {
+        file: "test.toml",
+        migrations: &[
+            Migration {
+                description: "Rename ports to values",
+                apply: rename_ports,
+            },
+            Migration {
+                description: "Add layout",
+                apply: add_layout,
+            },
+        ],
+    };
+
+    #[test]
+    fn test_upgrade_runs_the_remaining_chain() -> Result<()> {
+        assert_eq!(TEST.current(), 3);
+
+        let mut document: DocumentMut = "version = \"1\" # format\nports = 1\n".parse()?;
+        let applied = TEST.upgrade(&mut document, "test.toml")?;
+        assert_eq!(applied, ["Rename ports to values", "Add layout"]);
+        assert_eq!(
+            document.to_string(),
+            "version = \"3\" # format\nvalues = 1\nlayout = \"default\"\n"
+        );
+
+        let mut document: DocumentMut = "version = \"2\"\nvalues = 1\n".parse()?;
+        assert_eq!(TEST.upgrade(&mut document, "test.toml")?, ["Add layout"]);
+
+        let mut document: DocumentMut = "version = \"3\"\n".parse()?;
+        assert!(TEST.upgrade(&mut document, "test.toml")?.is_empty());
+        assert_eq!(document.to_string(), "version = \"3\"\n");
+
+        let mut document: DocumentMut = "version = \"4\"\n".parse()?;
+        let error = TEST.upgrade(&mut document, "test.toml").unwrap_err();
+        assert!(error.to_string().contains("Upgrade vibetree"));
+
+        let mut document: DocumentMut = "version = \"one\"\n".parse()?;
+        assert!(TEST.upgrade(&mut document, "test.toml").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_file_backs_up_unless_dry_run() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("test.toml");
+        let backup_dir = temp_dir.path().join("backups");
+        let original = "# Allocations\nversion = \"1\"\nports = 1\n";
+        fs::write(&path, original)?;
+
+        let (_, migration) = TEST.migrate_file(&path, &backup_dir, true)?;
+        assert_eq!((migration.from, migration.to), (1, 3));
+        assert!(migration.backup.is_none());
+        assert_eq!(fs::read_to_string(&path)?, original);
+
+        let (_, migration) = TEST.migrate_file(&path, &backup_dir, false)?;
+        let backup = migration.backup.unwrap();
+        assert_eq!(backup, backup_dir.join("test.toml.v1"));
+        assert_eq!(fs::read_to_string(&backup)?, original);
+        assert!(fs::read_to_string(&path)?.starts_with("# Allocations\nversion = \"3\"\n"));
+
+        let (_, migration) = TEST.migrate_file(&path, &backup_dir, false)?;
+        assert!(migration.is_current());
+        Ok(())
+    }
+}
@@ -0,0 +1,468 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::config::{ReadyCheck, ServiceConfig, VibeTreeConfig};
+use crate::output::{ServiceStatus, ServicesResult, VibeTreeError};
+use crate::placeholders::expand_all;
+
+/// Seconds `up` waits for a ready check when the service sets no `ready_timeout`
+const DEFAULT_READY_TIMEOUT: u64 = 60;
+
+/// How long `down` waits after SIGTERM before killing a service
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Runs a worktree's `[[services]]` in the background
+///
+/// Each service runs in its own process group, with its PID and output kept
+/// under `.vibetree/services/<worktree>/`.
+pub struct ServiceManager<'a> {
+    config: &'a VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> ServiceManager<'a> {
+    pub fn new(config: &'a VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Start the named services, or all of them, and wait until they are ready
+    ///
+    /// Services that are already running are left alone. A service that exits
+    /// or fails its ready check is stopped and reported with the end of its log.
+    pub fn up(&self, name: &str, only: &[String]) -> Result<ServicesResult> {
+        let values = self.worktree_values(name)?;
+        let worktree_path = self.config.get_worktree_path(self.vibetree_parent, name);
+        let state_dir = self.state_dir(name);
+        fs::create_dir_all(&state_dir)
+            .with_context(|| format!("Failed to create {}", state_dir.display()))?;
+
+        let mut statuses = Vec::new();
+        for service in self.select(only)? {
+            if let Some(pid) = self.running_pid(name, &service.name) {
+                info!(
+                    "Service '{}' is already running (pid {})",
+                    service.name, pid
+                );
+                statuses.push(self.service_status(name, &service.name));
+                continue;
+            }
+
+            let child = self.spawn(name, service, &worktree_path, &values)?;
+            let pid = child.id();
+            info!("Started service '{}' (pid {})", service.name, pid);
+            if let Err(e) = self.wait_ready(service, child, &values) {
+                self.stop(name, &service.name);
+                let log = self.log_path(name, &service.name);
+                return Err(e.context(format!(
+                    "Service '{}' did not start. Last lines of {}:\n{}",
+                    service.name,
+                    log.display(),
+                    tail(&log, 10)
+                )));
+            }
+            statuses.push(self.service_status(name, &service.name));
+        }
+
+        Ok(ServicesResult {
+            name: name.to_string(),
+            services: statuses,
+        })
+    }
+
+    /// Stop the named services, or all of them
+    pub fn down(&self, name: &str, only: &[String]) -> Result<ServicesResult> {
+        self.worktree_values(name)?;
+        let mut statuses = Vec::new();
+        for service in self.select(only)? {
+            if self.stop(name, &service.name) {
+                info!("Stopped service '{}'", service.name);
+            } else {
+                debug!("Service '{}' was not running", service.name);
+            }
+            statuses.push(self.service_status(name, &service.name));
+        }
+        Ok(ServicesResult {
+            name: name.to_string(),
+            services: statuses,
+        })
+    }
+
+    /// Stop whatever is running for a worktree, e.g. before it is removed
+    pub fn stop_all(&self, name: &str) {
+        for service in &self.config.project_config.services {
+            if self.stop(name, &service.name) {
+                info!("Stopped service '{}'", service.name);
+            }
+        }
+    }
+
+    /// Delete a worktree's PID files and logs once it is removed
+    pub fn forget(&self, name: &str) {
+        let state_dir = self.state_dir(name);
+        if state_dir.exists()
+            && let Err(e) = fs::remove_dir_all(&state_dir)
+        {
+            warn!("Failed to remove {}: {}", state_dir.display(), e);
+        }
+    }
+
+    /// State of every configured service of a worktree
+    pub fn status(&self, name: &str) -> Vec<ServiceStatus> {
+        self.config
+            .project_config
+            .services
+            .iter()
+            .map(|service| self.service_status(name, &service.name))
+            .collect()
+    }
+
+    /// Print the logs of one service or all of them, prefixed with the service
+    /// name when there are several
+    ///
+    /// With `follow` this keeps printing new output until interrupted.
+    pub fn logs(
+        &self,
+        name: &str,
+        only: Option<&str>,
+        follow: bool,
+        lines: Option<usize>,
+    ) -> Result<()> {
+        self.worktree_values(name)?;
+        let only: Vec<String> = only.map(str::to_string).into_iter().collect();
+        let services = self.select(&only)?;
+        let prefixed = services.len() > 1;
+
+        let mut logs: Vec<(String, PathBuf, u64)> = Vec::new();
+        for service in services {
+            let path = self.log_path(name, &service.name);
+            let content = fs::read_to_string(&path).unwrap_or_default();
+            let shown: Vec<&str> = content.lines().collect();
+            let skip = lines.map_or(0, |lines| shown.len().saturating_sub(lines));
+            for line in &shown[skip..] {
+                print_log_line(&service.name, line, prefixed);
+            }
+            logs.push((service.name.clone(), path, content.len() as u64));
+        }
+
+        if !follow {
+            return Ok(());
+        }
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            for (service, path, offset) in logs.iter_mut() {
+                let Ok(mut file) = File::open(&*path) else {
+                    continue;
+                };
+                let length = file.metadata().map(|m| m.len()).unwrap_or(0);
+                // The log was truncated, e.g. by a restart
+                if length < *offset {
+                    *offset = 0;
+                }
+                if length == *offset {
+                    continue;
+                }
+                file.seek(SeekFrom::Start(*offset))?;
+                let mut new = String::new();
+                file.read_to_string(&mut new)?;
+                // Leave a partly written line for the next round
+                let complete = new.rfind('\n').map_or(0, |end| end + 1);
+                for line in new[..complete].lines() {
+                    print_log_line(service, line, prefixed);
+                }
+                *offset += complete as u64;
+            }
+        }
+    }
+
+    fn worktree_values(&self, name: &str) -> Result<HashMap<String, String>> {
+        let worktree = self
+            .config
+            .branches_config
+            .worktrees
+            .get(name)
+            .ok_or_else(|| VibeTreeError::not_found(format!("Worktree '{}' not found", name)))?;
+        Ok(worktree.values.clone())
+    }
+
+    /// Configured services, narrowed to `only` when it names any
+    fn select(&self, only: &[String]) -> Result<Vec<&'a ServiceConfig>> {
+        let services = &self.config.project_config.services;
+        if services.is_empty() {
+            anyhow::bail!(VibeTreeError::not_found(
+                "No [[services]] configured in vibetree.toml"
+            ));
+        }
+        if let Some(unknown) = only
+            .iter()
+            .find(|name| !services.iter().any(|service| &service.name == *name))
+        {
+            anyhow::bail!(VibeTreeError::not_found(format!(
+                "Service '{}' is not configured",
+                unknown
+            )));
+        }
+        Ok(services
+            .iter()
+            .filter(|service| only.is_empty() || only.contains(&service.name))
+            .collect())
+    }
+
+    fn state_dir(&self, name: &str) -> PathBuf {
+        self.vibetree_parent
+            .join(".vibetree")
+            .join("services")
+            .join(name)
+    }
+
+    fn pid_path(&self, name: &str, service: &str) -> PathBuf {
+        self.state_dir(name).join(format!("{}.pid", service))
+    }
+
+    fn log_path(&self, name: &str, service: &str) -> PathBuf {
+        self.state_dir(name).join(format!("{}.log", service))
+    }
+
+    fn service_status(&self, name: &str, service: &str) -> ServiceStatus {
+        let pid = self.running_pid(name, service);
+        ServiceStatus {
+            name: service.to_string(),
+            running: pid.is_some(),
+            pid,
+            log: self.log_path(name, service),
+        }
+    }
+
+    /// PID of the service if its process group is still alive
+    ///
+    /// The group outlives the command when it leaves processes behind. While
+    /// the command itself is alive, it must be the process that was started,
+    /// not a later one given the same PID.
+    fn running_pid(&self, name: &str, service: &str) -> Option<u32> {
+        let recorded = fs::read_to_string(self.pid_path(name, service)).ok()?;
+        let mut fields = recorded.split_whitespace();
+        let pid = fields.next()?.parse::<u32>().ok()?;
+        let started = fields.next().and_then(|field| field.parse::<u64>().ok());
+        if !group_alive(pid) {
+            return None;
+        }
+        if is_running(pid) {
+            let leader = unsafe { libc::getpgid(pid as i32) } == pid as i32;
+            let same = match (started, start_time(pid)) {
+                (Some(recorded), Some(current)) => recorded == current,
+                _ => true,
+            };
+            if !leader || !same {
+                debug!(
+                    "PID {} of service '{}' belongs to another process",
+                    pid, service
+                );
+                return None;
+            }
+        }
+        Some(pid)
+    }
+
+    fn spawn(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+        worktree_path: &Path,
+        values: &HashMap<String, String>,
+    ) -> Result<Child> {
+        let log_path = self.log_path(name, &service.name);
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .with_context(|| format!("Failed to open {}", log_path.display()))?;
+        let cwd = match &service.cwd {
+            Some(cwd) => worktree_path.join(cwd),
+            None => worktree_path.to_path_buf(),
+        };
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(&service.command)
+            .current_dir(&cwd)
+            .envs(values)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone().context("Failed to open log file")?)
+            .stderr(log)
+            // Its own process group, so `down` stops everything the command started
+            .process_group(0)
+            .spawn()
+            .with_context(|| format!("Failed to start service '{}'", service.name))?;
+
+        let pid_path = self.pid_path(name, &service.name);
+        let recorded = match start_time(child.id()) {
+            Some(started) => format!("{} {}", child.id(), started),
+            None => child.id().to_string(),
+        };
+        fs::write(&pid_path, recorded)
+            .with_context(|| format!("Failed to write {}", pid_path.display()))?;
+        Ok(child)
+    }
+
+    /// Wait for the service's ready check, failing if it exits first or times out
+    fn wait_ready(
+        &self,
+        service: &ServiceConfig,
+        mut child: Child,
+        values: &HashMap<String, String>,
+    ) -> Result<()> {
+        let Some(check) = &service.ready else {
+            return Ok(());
+        };
+        let timeout = Duration::from_secs(service.ready_timeout.unwrap_or(DEFAULT_READY_TIMEOUT));
+        let deadline = Instant::now() + timeout;
+        debug!("Waiting for service '{}' to be ready", service.name);
+
+        loop {
+            if let Some(status) = child.try_wait()? {
+                anyhow::bail!("Exited with {}", status);
+            }
+            if is_ready(check, values)? {
+                info!("Service '{}' is ready", service.name);
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!("Not ready after {} seconds", timeout.as_secs());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Stop a service's process group, returning whether it was running
+    fn stop(&self, name: &str, service: &str) -> bool {
+        let pid_path = self.pid_path(name, service);
+        let running = self.running_pid(name, service);
+        if let Some(pid) = running {
+            let group = -(pid as i32);
+            unsafe { libc::kill(group, libc::SIGTERM) };
+            let deadline = Instant::now() + STOP_TIMEOUT;
+            while group_alive(pid) && Instant::now() < deadline {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            if group_alive(pid) {
+                warn!("Service '{}' ignored SIGTERM, killing it", service);
+                unsafe { libc::kill(group, libc::SIGKILL) };
+            }
+        }
+        if pid_path.exists()
+            && let Err(e) = fs::remove_file(&pid_path)
+        {
+            warn!("Failed to remove {}: {}", pid_path.display(), e);
+        }
+        running.is_some()
+    }
+}
+
+/// Reap a service started by this process that has exited, which otherwise
+/// lingers as a zombie
+fn reap(pid: u32) {
+    unsafe { libc::waitpid(pid as i32, std::ptr::null_mut(), libc::WNOHANG) };
+}
+
+/// Whether a process is alive
+fn is_running(pid: u32) -> bool {
+    reap(pid);
+    (unsafe { libc::kill(pid as i32, 0) }) == 0
+}
+
+/// Whether any process of the group led by `pid` is alive
+fn group_alive(pid: u32) -> bool {
+    reap(pid);
+    (unsafe { libc::kill(-(pid as i32), 0) }) == 0
+}
+
+/// When a process started, in clock ticks since boot, which tells it apart
+/// from a later process given the same PID; `None` without `/proc`
+fn start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name before it is in parentheses and may contain spaces;
+    // the start time is the 22nd field, the 20th after the name
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+fn is_ready(check: &ReadyCheck, values: &HashMap<String, String>) -> Result<bool> {
+    match check {
+        ReadyCheck::Tcp(address) => {
+            let address = expand_all(address, values, "ready check")?;
+            let address = if address.contains(':') {
+                address
+            } else {
+                format!("127.0.0.1:{}", address)
+            };
+            let Ok(mut addresses) = address.to_socket_addrs() else {
+                return Ok(false);
+            };
+            Ok(addresses.any(|address| {
+                TcpStream::connect_timeout(&address, Duration::from_secs(1)).is_ok()
+            }))
+        }
+        ReadyCheck::Http(url) => {
+            let url = expand_all(url, values, "ready check")?;
+            let output = Command::new("curl")
+                .args([
+                    "-s",
+                    "-o",
+                    "/dev/null",
+                    "-w",
+                    "%{http_code}",
+                    "--max-time",
+                    "2",
+                ])
+                .arg(&url)
+                .output()
+                .context("Failed to run curl, which HTTP ready checks need")?;
+            Ok(String::from_utf8_lossy(&output.stdout).trim() == "200")
+        }
+    }
+}
+
+fn print_log_line(service: &str, line: &str, prefixed: bool) {
+    if prefixed {
+        println!("{} | {}", service, line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// The last `lines` lines of a log file
+fn tail(path: &Path, lines: usize) -> String {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let all: Vec<&str> = content.lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tcp_ready_check() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let values = HashMap::from([("PORT".to_string(), port.to_string())]);
+
+        assert!(is_ready(&ReadyCheck::Tcp("${PORT}".to_string()), &values)?);
+        drop(listener);
+        assert!(!is_ready(&ReadyCheck::Tcp("${PORT}".to_string()), &values)?);
+        Ok(())
+    }
+}
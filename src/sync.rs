@@ -1,11 +1,14 @@
 use anyhow::{Context, Result};
 use log::{info, warn};
+use serde::Serialize;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::config::VibeTreeConfig;
+use crate::config::{ResourceState, VibeTreeConfig};
 use crate::env::EnvFileGenerator;
 use crate::git::{DiscoveredWorktree, GitManager};
+use crate::output::RepairResult;
+use crate::resources::ResourceManager;
 
 pub struct SyncManager<'a> {
     config: &'a mut VibeTreeConfig,
@@ -21,7 +24,7 @@ impl<'a> SyncManager<'a> {
     }
 
     /// Synchronize configuration and discover orphaned worktrees
-    pub fn sync(&mut self, dry_run: bool) -> Result<()> {
+    pub fn sync(&mut self, dry_run: bool) -> Result<RepairResult> {
         info!("Synchronizing vibetree configuration");
 
         let repo_path = GitManager::find_repo_root(self.vibetree_parent)
@@ -38,16 +41,20 @@ impl<'a> SyncManager<'a> {
 
         // Discover all git worktrees
         let discovered_worktrees = GitManager::discover_worktrees(&repo_path)?;
-        let branches_dir = self
-            .vibetree_parent
-            .join(&self.config.project_config.branches_dir);
+        let branches_dir = self.vibetree_parent.join(self.config.branches_dir());
 
         let sync_plan = self.analyze_sync_needs(&discovered_worktrees, &branches_dir)?;
 
+        self.report_config_drift();
+
         if !sync_plan.needs_changes() {
             info!("Configuration is synchronized");
             self.update_env_files(&branches_dir)?;
-            return Ok(());
+            return Ok(RepairResult {
+                dry_run,
+                plan: sync_plan,
+                errors: Vec::new(),
+            });
         }
 
         // Report what would be done
@@ -55,19 +62,38 @@ impl<'a> SyncManager<'a> {
 
         if dry_run {
             info!("Dry run - no changes made");
-            return Ok(());
+            return Ok(RepairResult {
+                dry_run,
+                plan: sync_plan,
+                errors: Vec::new(),
+            });
         }
 
         // Apply changes
-        self.apply_sync_changes(sync_plan, &branches_dir)?;
+        let errors = self.apply_sync_changes(sync_plan.clone(), &branches_dir)?;
 
-        Ok(())
+        Ok(RepairResult {
+            dry_run,
+            plan: sync_plan,
+            errors,
+        })
+    }
+
+    /// Work out what `sync` would change, without pruning or modifying anything
+    pub fn plan(&self) -> Result<SyncPlan> {
+        let repo_path = GitManager::find_repo_root(self.vibetree_parent)
+            .context("Not inside a git repository")?;
+
+        let discovered_worktrees = GitManager::discover_worktrees(&repo_path)?;
+        let branches_dir = self.vibetree_parent.join(self.config.branches_dir());
+
+        self.analyze_sync_needs(&discovered_worktrees, &branches_dir)
     }
 
     fn analyze_sync_needs(
         &self,
         discovered_worktrees: &[DiscoveredWorktree],
-        branches_dir: &PathBuf,
+        branches_dir: &Path,
     ) -> Result<SyncPlan> {
         let mut plan = SyncPlan::new();
 
@@ -102,9 +128,16 @@ impl<'a> SyncManager<'a> {
                 // Root worktree is always treated as "main" in vibetree config
                 if is_root_worktree {
                     let config_key = &self.config.project_config.main_branch;
-                    if !self.config.branches_config.worktrees.contains_key(config_key) {
-                        plan.orphaned_worktrees
-                            .push((config_key.clone(), discovered.path.clone()));
+                    if !self
+                        .config
+                        .branches_config
+                        .worktrees
+                        .contains_key(config_key)
+                    {
+                        plan.orphaned_worktrees.push(OrphanedWorktree {
+                            name: config_key.clone(),
+                            path: discovered.path.clone(),
+                        });
                     }
                 } else if is_branch_worktree
                     && !self
@@ -113,14 +146,16 @@ impl<'a> SyncManager<'a> {
                         .worktrees
                         .contains_key(branch_name)
                 {
-                    plan.orphaned_worktrees
-                        .push((branch_name.clone(), discovered.path.clone()));
+                    plan.orphaned_worktrees.push(OrphanedWorktree {
+                        name: branch_name.clone(),
+                        path: discovered.path.clone(),
+                    });
                 }
             }
         }
 
         // Check for missing worktrees (in config but not in git)
-        for (branch_name, _) in &self.config.branches_config.worktrees {
+        for branch_name in self.config.branches_config.worktrees.keys() {
             // Simply check if this branch exists anywhere in git worktrees
             let found = discovered_worktrees
                 .iter()
@@ -131,6 +166,23 @@ impl<'a> SyncManager<'a> {
             }
         }
 
+        // Check for resources that failed or were never created
+        for (branch_name, worktree_config) in &self.config.branches_config.worktrees {
+            if *branch_name == self.config.project_config.main_branch
+                || plan.missing_worktrees.contains(branch_name)
+            {
+                continue;
+            }
+            for resource in &self.config.project_config.resources {
+                if worktree_config.resources.get(&resource.name) != Some(&ResourceState::Created) {
+                    plan.pending_resources.push(PendingResource {
+                        worktree: branch_name.clone(),
+                        resource: resource.name.clone(),
+                    });
+                }
+            }
+        }
+
         // Check for config mismatches (variable changes)
         // Skip worktrees that are already marked as missing
         for (branch_name, worktree_config) in &self.config.branches_config.worktrees {
@@ -139,14 +191,24 @@ impl<'a> SyncManager<'a> {
                 continue;
             }
 
-            // Check if all configured variables exist in current project config
-            let current_var_names: std::collections::HashSet<_> = self
+            // Each worktree is compared against the vibetree.toml in its own checkout
+            let variables = match self
                 .config
-                .project_config
-                .variables
-                .iter()
-                .map(|v| &v.name)
-                .collect();
+                .worktree_variables(self.vibetree_parent, branch_name)
+            {
+                Ok(variables) => variables,
+                Err(e) => {
+                    warn!(
+                        "Failed to read vibetree.toml for '{}': {}. Skipping variable check.",
+                        branch_name, e
+                    );
+                    continue;
+                }
+            };
+
+            // Check if all configured variables exist in the worktree's project config
+            let current_var_names: std::collections::HashSet<_> =
+                variables.iter().map(|v| &v.name).collect();
             let worktree_var_names: std::collections::HashSet<_> =
                 worktree_config.values.keys().collect();
 
@@ -158,7 +220,8 @@ impl<'a> SyncManager<'a> {
 
             // Check if variable definitions changed by re-allocating and comparing values
             // This catches changes like switching from bare numbers to templates
-            match self.config.project_config.allocate_values(
+            match crate::allocator::VariableAllocator::allocate_values(
+                &variables,
                 branch_name,
                 &self.config.branches_config.worktrees,
             ) {
@@ -181,11 +244,15 @@ impl<'a> SyncManager<'a> {
         Ok(plan)
     }
 
-    fn apply_sync_changes(&mut self, plan: SyncPlan, branches_dir: &PathBuf) -> Result<()> {
+    fn apply_sync_changes(&mut self, plan: SyncPlan, branches_dir: &Path) -> Result<Vec<String>> {
         let mut sync_errors = Vec::new();
 
         // Add orphaned worktrees to config
-        for (branch_name, worktree_path) in plan.orphaned_worktrees {
+        for OrphanedWorktree {
+            name: branch_name,
+            path: worktree_path,
+        } in plan.orphaned_worktrees
+        {
             info!(
                 "Adding orphaned worktree '{}' to configuration",
                 branch_name
@@ -194,8 +261,21 @@ impl<'a> SyncManager<'a> {
             let ports = if branch_name == self.config.project_config.main_branch {
                 self.add_main_worktree(&branch_name, &mut sync_errors)?
             } else {
-                // For other worktrees, allocate ports normally
-                match self.config.add_worktree(branch_name.clone(), None) {
+                // For other worktrees, allocate from the variables in their own checkout
+                let variables = match self.config.variables_in_checkout(&worktree_path) {
+                    Ok(variables) => variables,
+                    Err(e) => {
+                        sync_errors.push(format!(
+                            "Failed to read vibetree.toml for '{}': {}",
+                            branch_name, e
+                        ));
+                        continue;
+                    }
+                };
+                match self
+                    .config
+                    .add_worktree_with_variables(branch_name.clone(), None, &variables)
+                {
                     Ok(ports) => ports,
                     Err(e) => {
                         sync_errors
@@ -239,10 +319,30 @@ impl<'a> SyncManager<'a> {
         // Update config mismatches and regenerate env files for all worktrees
         for branch_name in plan.config_mismatches {
             info!("Updating variable configuration for '{}'", branch_name);
-            match self
+            let variables = match self
                 .config
-                .add_or_update_worktree(branch_name.clone(), None)
+                .worktree_variables(self.vibetree_parent, &branch_name)
             {
+                Ok(variables) => variables,
+                Err(e) => {
+                    sync_errors.push(format!(
+                        "Failed to read vibetree.toml for '{}': {}",
+                        branch_name, e
+                    ));
+                    continue;
+                }
+            };
+            let values = self
+                .config
+                .reallocated_values(&branch_name, &variables)
+                .and_then(|values| {
+                    self.config.add_or_update_worktree_with_variables(
+                        branch_name.clone(),
+                        Some(values),
+                        &variables,
+                    )
+                });
+            match values {
                 Ok(ports) => {
                     // Update env file with new port configuration
                     let worktree_path = if branch_name == self.config.project_config.main_branch {
@@ -277,6 +377,11 @@ impl<'a> SyncManager<'a> {
         // Also regenerate env files for all worktrees that had their ports changed
         self.regenerate_all_env_files(branches_dir, &mut sync_errors)?;
 
+        // Retry resources after the values they are templated with are up to date
+        for pending in plan.pending_resources {
+            self.provision_resource(&pending, branches_dir, &mut sync_errors);
+        }
+
         // Save configuration
         if let Err(e) = self.config.save() {
             sync_errors.push(format!("Failed to save configuration: {}", e));
@@ -289,12 +394,73 @@ impl<'a> SyncManager<'a> {
                 "Synchronization completed with {} errors:",
                 sync_errors.len()
             );
-            for error in sync_errors {
+            for error in &sync_errors {
                 warn!("{}", error);
             }
         }
 
-        Ok(())
+        Ok(sync_errors)
+    }
+
+    fn provision_resource(
+        &mut self,
+        pending: &PendingResource,
+        branches_dir: &Path,
+        sync_errors: &mut Vec<String>,
+    ) {
+        let Some(resource) = self
+            .config
+            .project_config
+            .resources
+            .iter()
+            .find(|r| r.name == pending.resource)
+        else {
+            return;
+        };
+        let Some(worktree) = self
+            .config
+            .branches_config
+            .worktrees
+            .get_mut(&pending.worktree)
+        else {
+            return;
+        };
+
+        info!(
+            "Creating resource '{}' for '{}'",
+            pending.resource, pending.worktree
+        );
+        let cwd = branches_dir.join(&pending.worktree);
+        let state = match ResourceManager::new(resource, &worktree.values).provision(&cwd) {
+            Ok(_) => ResourceState::Created,
+            Err(e) => {
+                sync_errors.push(format!(
+                    "Failed to create resource '{}' for '{}': {}",
+                    pending.resource, pending.worktree, e
+                ));
+                ResourceState::Failed
+            }
+        };
+        worktree.resources.insert(pending.resource.clone(), state);
+    }
+
+    /// Report worktrees whose vibetree.toml defines different variables than main's
+    fn report_config_drift(&self) {
+        let drift = self.config.config_drift(self.vibetree_parent);
+        if drift.is_empty() {
+            return;
+        }
+
+        info!(
+            "Worktrees with vibetree.toml differing from '{}':",
+            self.config.project_config.main_branch
+        );
+        for (branch_name, drift) in drift {
+            match drift {
+                Ok(drift) => info!("  {}: {}", branch_name, drift.summary()),
+                Err(e) => warn!("  {}: failed to read vibetree.toml: {}", branch_name, e),
+            }
+        }
     }
 
     fn add_main_worktree(
@@ -306,11 +472,8 @@ impl<'a> SyncManager<'a> {
         // Remove existing main branch config temporarily if it exists
         let existing_main = self.config.branches_config.worktrees.remove(branch_name);
 
-        // Allocate values for main branch
-        let main_values = match self.config.project_config.allocate_values(
-            branch_name,
-            &self.config.branches_config.worktrees,
-        ) {
+        // Allocate values for main branch, clear of other repositories' ports too
+        let main_values = match self.config.main_branch_values(branch_name, true) {
             Ok(values) => values,
             Err(e) => {
                 // Restore the existing main config if allocation failed
@@ -338,7 +501,7 @@ impl<'a> SyncManager<'a> {
         }
     }
 
-    fn update_env_files(&self, branches_dir: &PathBuf) -> Result<()> {
+    fn update_env_files(&self, branches_dir: &Path) -> Result<()> {
         // Even if no config changes, ensure all env files are up to date
         let mut env_errors = Vec::new();
         for (branch_name, worktree_config) in &self.config.branches_config.worktrees {
@@ -350,17 +513,17 @@ impl<'a> SyncManager<'a> {
             let env_file_path = self.config.get_env_file_path(&worktree_path);
 
             // Always regenerate env files to ensure they're current
-            if worktree_path.exists() {
-                if let Err(e) = EnvFileGenerator::generate_env_file(
+            if worktree_path.exists()
+                && let Err(e) = EnvFileGenerator::generate_env_file(
                     &env_file_path,
                     branch_name,
                     &worktree_config.values,
-                ) {
-                    env_errors.push(format!(
-                        "Failed to update env file for '{}': {}",
-                        branch_name, e
-                    ));
-                }
+                )
+            {
+                env_errors.push(format!(
+                    "Failed to update env file for '{}': {}",
+                    branch_name, e
+                ));
             }
         }
 
@@ -379,7 +542,7 @@ impl<'a> SyncManager<'a> {
 
     fn regenerate_all_env_files(
         &self,
-        branches_dir: &PathBuf,
+        branches_dir: &Path,
         sync_errors: &mut Vec<String>,
     ) -> Result<()> {
         for (branch_name, worktree_config) in &self.config.branches_config.worktrees {
@@ -391,28 +554,46 @@ impl<'a> SyncManager<'a> {
             let env_file_path = self.config.get_env_file_path(&worktree_path);
 
             // Only update if the env file exists or if the worktree directory exists
-            if env_file_path.exists() || worktree_path.exists() {
-                if let Err(e) = EnvFileGenerator::generate_env_file(
+            if (env_file_path.exists() || worktree_path.exists())
+                && let Err(e) = EnvFileGenerator::generate_env_file(
                     &env_file_path,
                     branch_name,
                     &worktree_config.values,
-                ) {
-                    sync_errors.push(format!(
-                        "Failed to update env file for '{}': {}",
-                        branch_name, e
-                    ));
-                }
+                )
+            {
+                sync_errors.push(format!(
+                    "Failed to update env file for '{}': {}",
+                    branch_name, e
+                ));
             }
         }
         Ok(())
     }
 }
 
-#[derive(Debug)]
-struct SyncPlan {
-    orphaned_worktrees: Vec<(String, PathBuf)>,
-    missing_worktrees: Vec<String>,
-    config_mismatches: Vec<String>,
+/// Changes `repair` makes to bring the configuration in line with git
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPlan {
+    /// Git worktrees that are not in the configuration yet
+    pub orphaned_worktrees: Vec<OrphanedWorktree>,
+    /// Configured worktrees that no longer exist in git
+    pub missing_worktrees: Vec<String>,
+    /// Worktrees whose allocated values no longer match their variables
+    pub config_mismatches: Vec<String>,
+    /// Resources of worktrees that failed or were never created
+    pub pending_resources: Vec<PendingResource>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedWorktree {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingResource {
+    pub worktree: String,
+    pub resource: String,
 }
 
 impl SyncPlan {
@@ -421,13 +602,15 @@ impl SyncPlan {
             orphaned_worktrees: Vec::new(),
             missing_worktrees: Vec::new(),
             config_mismatches: Vec::new(),
+            pending_resources: Vec::new(),
         }
     }
 
-    fn needs_changes(&self) -> bool {
+    pub fn needs_changes(&self) -> bool {
         !self.orphaned_worktrees.is_empty()
             || !self.missing_worktrees.is_empty()
             || !self.config_mismatches.is_empty()
+            || !self.pending_resources.is_empty()
     }
 
     fn report(&self) {
@@ -435,8 +618,8 @@ impl SyncPlan {
 
         if !self.orphaned_worktrees.is_empty() {
             info!("  Orphaned worktrees to add to config:");
-            for (branch, path) in &self.orphaned_worktrees {
-                info!("    {} ({})", branch, path.display());
+            for orphan in &self.orphaned_worktrees {
+                info!("    {} ({})", orphan.name, orphan.path.display());
             }
         }
 
@@ -453,5 +636,12 @@ impl SyncPlan {
                 info!("    {}", branch);
             }
         }
+
+        if !self.pending_resources.is_empty() {
+            info!("  Resources to create:");
+            for pending in &self.pending_resources {
+                info!("    {} ({})", pending.resource, pending.worktree);
+            }
+        }
     }
 }
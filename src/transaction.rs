@@ -0,0 +1,156 @@
+use anyhow::Result;
+use log::{debug, warn};
+#[cfg(any(test, feature = "failpoints"))]
+use std::cell::RefCell;
+
+use crate::config::VibeTreeConfig;
+
+type UndoAction<'a> = Box<dyn FnOnce(&mut VibeTreeConfig) -> Result<()> + 'a>;
+
+/// Undo actions for the steps of a multi-step operation
+///
+/// Each step that changes something registers how to undo it. When a later
+/// step fails, the undo actions run newest first, so the repository and the
+/// allocations end up as they were before the operation started.
+#[derive(Default)]
+pub struct Transaction<'a> {
+    undo: Vec<(String, UndoAction<'a>)>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Run `steps`, undoing every registered step if they fail
+    ///
+    /// The error of the failed step is returned; if some undo actions failed
+    /// too, it says which.
+    pub fn run<T>(
+        config: &mut VibeTreeConfig,
+        steps: impl FnOnce(&mut VibeTreeConfig, &mut Transaction<'a>) -> Result<T>,
+    ) -> Result<T> {
+        let mut transaction = Transaction::default();
+        match steps(config, &mut transaction) {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                let failed = transaction.rollback(config);
+                if failed.is_empty() {
+                    Err(error)
+                } else {
+                    Err(error.context(format!(
+                        "Rollback incomplete, could not undo: {}",
+                        failed.join(", ")
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Register how to undo a step that just completed
+    pub fn on_rollback(
+        &mut self,
+        step: impl Into<String>,
+        undo: impl FnOnce(&mut VibeTreeConfig) -> Result<()> + 'a,
+    ) {
+        self.undo.push((step.into(), Box::new(undo)));
+    }
+
+    /// Undo the registered steps newest first, returning the ones that failed
+    ///
+    /// A failed undo action doesn't stop the remaining ones from running.
+    fn rollback(self, config: &mut VibeTreeConfig) -> Vec<String> {
+        let mut failed = Vec::new();
+        for (step, undo) in self.undo.into_iter().rev() {
+            debug!("Rolling back: {}", step);
+            if let Err(e) = undo(config) {
+                warn!("Failed to roll back {}: {}", step, e);
+                failed.push(step);
+            }
+        }
+        failed
+    }
+}
+
+#[cfg(any(test, feature = "failpoints"))]
+thread_local! {
+    static INJECTED_FAILURE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Make [`fail_point`] fail at `point` on the current thread, or at no point
+/// with `None` - for testing rollbacks
+#[cfg(any(test, feature = "failpoints"))]
+#[doc(hidden)]
+pub fn inject_failure(point: Option<&str>) {
+    INJECTED_FAILURE.with(|failure| *failure.borrow_mut() = point.map(str::to_string));
+}
+
+/// Fail if a failure was injected at this point, between two steps of an operation
+#[cfg(any(test, feature = "failpoints"))]
+pub fn fail_point(point: &str) -> Result<()> {
+    INJECTED_FAILURE.with(|failure| {
+        if failure.borrow().as_deref() == Some(point) {
+            anyhow::bail!("Injected failure at {}", point);
+        }
+        Ok(())
+    })
+}
+
+/// Failures can only be injected in builds with the `failpoints` feature
+#[cfg(not(any(test, feature = "failpoints")))]
+#[inline(always)]
+pub fn fail_point(_point: &str) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_rollback_runs_in_reverse_and_continues_after_failures() {
+        let mut config = VibeTreeConfig::default();
+        let undone = RefCell::new(Vec::new());
+
+        let result: Result<()> = Transaction::run(&mut config, |_, tx| {
+            tx.on_rollback("first", |_| {
+                undone.borrow_mut().push("first");
+                Ok(())
+            });
+            tx.on_rollback("second", |_| anyhow::bail!("stuck"));
+            tx.on_rollback("third", |_| {
+                undone.borrow_mut().push("third");
+                Ok(())
+            });
+            anyhow::bail!("step failed")
+        });
+
+        let error = result.unwrap_err();
+        assert_eq!(*undone.borrow(), vec!["third", "first"]);
+        assert!(error.to_string().contains("could not undo: second"));
+        assert_eq!(error.root_cause().to_string(), "step failed");
+    }
+
+    #[test]
+    fn test_success_keeps_changes() -> Result<()> {
+        let mut config = VibeTreeConfig::default();
+        let undone = RefCell::new(false);
+
+        Transaction::run(&mut config, |_, tx| {
+            tx.on_rollback("step", |_| {
+                *undone.borrow_mut() = true;
+                Ok(())
+            });
+            Ok(())
+        })?;
+        assert!(!*undone.borrow());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_point() {
+        assert!(fail_point("add:branch").is_ok());
+        inject_failure(Some("add:branch"));
+        assert!(fail_point("add:branch").is_err());
+        assert!(fail_point("add:worktree").is_ok());
+        inject_failure(None);
+        assert!(fail_point("add:branch").is_ok());
+    }
+}
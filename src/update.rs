@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+use crate::config::VibeTreeConfig;
+use crate::git::GitManager;
+use crate::merge::{self, MergeOperations};
+use crate::output::{MergeStrategy, UpdateResult, UpdateStatus, VibeTreeError, WorktreeUpdate};
+
+/// Options for `vibetree update`
+#[derive(Debug, Clone, Default)]
+pub struct UpdateOptions {
+    /// Worktree to update; every worktree when unset
+    pub branch: Option<String>,
+    /// Rebase onto the main branch instead of merging it
+    pub rebase: bool,
+    /// Skip fetching from the remote first
+    pub no_fetch: bool,
+    /// Only predict the outcome in memory
+    pub dry_run: bool,
+}
+
+pub struct UpdateManager<'a> {
+    config: &'a VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> UpdateManager<'a> {
+    pub fn new(config: &'a VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Bring worktree branches up to date with the main branch
+    ///
+    /// Each branch is merged or rebased inside its own worktree. Dirty
+    /// worktrees and those with an operation in progress are skipped, and an
+    /// update that conflicts is aborted so the worktree is left as it was.
+    pub fn update(&self, options: UpdateOptions) -> Result<UpdateResult> {
+        let repo_path = GitManager::find_repo_root(self.vibetree_parent)
+            .context("Not inside a git repository")?;
+        let main_branch = &self.config.project_config.main_branch;
+        let strategy = if options.rebase {
+            MergeStrategy::Rebase
+        } else {
+            MergeStrategy::Merge
+        };
+
+        let names = match &options.branch {
+            Some(name) if name == main_branch => {
+                anyhow::bail!("Cannot update the main branch '{}' from itself", name)
+            }
+            Some(name) if !self.config.branches_config.worktrees.contains_key(name) => {
+                anyhow::bail!(VibeTreeError::not_found(format!(
+                    "Worktree '{}' not found",
+                    name
+                )))
+            }
+            Some(name) => vec![name.clone()],
+            None => {
+                let mut names: Vec<_> = self
+                    .config
+                    .branches_config
+                    .worktrees
+                    .keys()
+                    .filter(|name| *name != main_branch)
+                    .cloned()
+                    .collect();
+                names.sort();
+                names
+            }
+        };
+
+        if !options.no_fetch {
+            self.fetch(&repo_path);
+        }
+        let base = Self::base_branch(&repo_path, main_branch)?;
+        info!("Updating from {}", base);
+
+        let operations = MergeOperations::load(self.vibetree_parent)?;
+        let mut worktrees = Vec::new();
+        for name in names {
+            let update = if operations.merges.contains_key(&name) {
+                skipped(&name, "merge in progress")
+            } else {
+                self.update_worktree(&repo_path, &name, &base, strategy, options.dry_run)?
+            };
+            log_update(&update, options.dry_run);
+            worktrees.push(update);
+        }
+
+        let result = UpdateResult {
+            base,
+            strategy,
+            dry_run: options.dry_run,
+            worktrees,
+        };
+
+        let conflicted: Vec<&str> = result
+            .worktrees
+            .iter()
+            .filter(|w| w.status == UpdateStatus::Conflicts)
+            .map(|w| w.name.as_str())
+            .collect();
+        if !conflicted.is_empty() && !options.dry_run {
+            return Err(VibeTreeError::conflict(format!(
+                "Could not update {} worktree(s) without conflicts: {}",
+                conflicted.len(),
+                conflicted.join(", ")
+            ))
+            .with_details(serde_json::to_value(&result)?)
+            .into());
+        }
+
+        Ok(result)
+    }
+
+    /// Fetch the configured remote, if there is one; failures only warn so
+    /// worktrees can still be updated from the local main branch
+    fn fetch(&self, repo_path: &Path) {
+        let remote = self.config.project_config.forge.remote();
+        match GitManager::remote_url(repo_path, remote) {
+            Ok(Some(_)) => {
+                info!("Fetching from {}", remote);
+                if let Err(e) = GitManager::fetch(repo_path, remote) {
+                    warn!("{}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    /// The main branch's upstream when it contains the local main branch,
+    /// otherwise the local main branch itself
+    fn base_branch(repo_path: &Path, main_branch: &str) -> Result<String> {
+        if let Some(upstream) = GitManager::upstream_branch(repo_path, main_branch)?
+            && GitManager::is_ancestor(repo_path, main_branch, &upstream)?
+        {
+            return Ok(upstream);
+        }
+        Ok(main_branch.to_string())
+    }
+
+    fn update_worktree(
+        &self,
+        repo_path: &Path,
+        name: &str,
+        base: &str,
+        strategy: MergeStrategy,
+        dry_run: bool,
+    ) -> Result<WorktreeUpdate> {
+        let path = self.config.get_worktree_path(self.vibetree_parent, name);
+        let validation = GitManager::validate_worktree_state(&path)?;
+        if !validation.exists {
+            return Ok(skipped(name, "directory missing"));
+        }
+        if !validation.is_git_worktree {
+            return Ok(skipped(name, "not a git worktree"));
+        }
+        let Some(branch) = validation.branch_name else {
+            return Ok(skipped(name, "no branch checked out"));
+        };
+        if GitManager::operation_in_progress(&path)? {
+            return Ok(skipped(name, "operation in progress"));
+        }
+        if GitManager::has_uncommitted_changes(&path)? {
+            return Ok(skipped(name, "uncommitted changes"));
+        }
+
+        if GitManager::is_ancestor(repo_path, base, &branch)? {
+            return Ok(status(name, UpdateStatus::UpToDate, Vec::new()));
+        }
+
+        // Without commits of its own the branch can simply be fast-forwarded
+        if GitManager::is_ancestor(repo_path, &branch, base)? {
+            if !dry_run {
+                GitManager::merge_rebased_branch(repo_path, base, &branch, Some(&path), None)?;
+            }
+            return Ok(status(name, UpdateStatus::Updated, Vec::new()));
+        }
+
+        let prediction = match strategy {
+            MergeStrategy::Rebase => {
+                GitManager::predict_rebase_conflicts(repo_path, &branch, base)?
+            }
+            _ => GitManager::predict_merge_conflicts(repo_path, base, &branch)?,
+        };
+        if dry_run || !prediction.is_clean() {
+            let update_status = if prediction.is_clean() {
+                UpdateStatus::Updated
+            } else {
+                UpdateStatus::Conflicts
+            };
+            return Ok(status(name, update_status, prediction.paths));
+        }
+
+        // The prediction was clean, but abort anyway if git disagrees
+        let conflicts = if strategy == MergeStrategy::Rebase {
+            let conflicts = GitManager::start_rebase(&path, base)?;
+            if !conflicts.is_empty() {
+                GitManager::abort_rebase(&path)?;
+            }
+            conflicts
+        } else {
+            let conflicts = GitManager::start_merge(&path, base, false)?;
+            if conflicts.is_empty() {
+                GitManager::commit_merge(&path, &merge::default_merge_message(base, &branch))?;
+            } else {
+                GitManager::abort_merge(&path)?;
+            }
+            conflicts
+        };
+
+        if conflicts.is_empty() {
+            Ok(status(name, UpdateStatus::Updated, Vec::new()))
+        } else {
+            Ok(status(name, UpdateStatus::Conflicts, conflicts))
+        }
+    }
+}
+
+fn status(name: &str, status: UpdateStatus, conflicts: Vec<String>) -> WorktreeUpdate {
+    WorktreeUpdate {
+        name: name.to_string(),
+        status,
+        conflicts,
+        reason: None,
+    }
+}
+
+fn skipped(name: &str, reason: &str) -> WorktreeUpdate {
+    WorktreeUpdate {
+        name: name.to_string(),
+        status: UpdateStatus::Skipped,
+        conflicts: Vec::new(),
+        reason: Some(reason.to_string()),
+    }
+}
+
+fn log_update(update: &WorktreeUpdate, dry_run: bool) {
+    match (update.status, dry_run) {
+        (UpdateStatus::UpToDate, _) => info!("  {}: up to date", update.name),
+        (UpdateStatus::Updated, false) => info!("  {}: updated", update.name),
+        (UpdateStatus::Updated, true) => info!("  {}: would update", update.name),
+        (UpdateStatus::Conflicts, dry_run) => warn!(
+            "  {}: {} ({})",
+            update.name,
+            if dry_run {
+                "would conflict"
+            } else {
+                "conflicts, aborted"
+            },
+            update.conflicts.join(", ")
+        ),
+        (UpdateStatus::Skipped, _) => warn!(
+            "  {}: skipped, {}",
+            update.name,
+            update.reason.as_deref().unwrap_or_default()
+        ),
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use log::{error, warn};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
 use crate::config::{VariableConfig, VibeTreeConfig};
 use crate::ports::PortManager;
@@ -10,13 +12,29 @@ pub struct ConfigValidator;
 impl ConfigValidator {
     /// Validate the entire configuration for consistency and conflicts
     pub fn validate_config(config: &VibeTreeConfig) -> Result<ValidationResult> {
+        Self::validate(config, None)
+    }
+
+    /// Validate the configuration, comparing each worktree's values with the variables
+    /// defined in its own checkout's vibetree.toml rather than the root one
+    pub fn validate_config_in(
+        config: &VibeTreeConfig,
+        vibetree_parent: &Path,
+    ) -> Result<ValidationResult> {
+        Self::validate(config, Some(vibetree_parent))
+    }
+
+    fn validate(
+        config: &VibeTreeConfig,
+        vibetree_parent: Option<&Path>,
+    ) -> Result<ValidationResult> {
         let mut result = ValidationResult::new();
 
         // Validate project configuration
         Self::validate_project_config(&config.project_config.variables, &mut result);
 
         // Validate worktree configurations
-        Self::validate_worktree_configs(config, &mut result);
+        Self::validate_worktree_configs(config, vibetree_parent, &mut result);
 
         // Validate value allocations
         Self::validate_value_allocations(config, &mut result);
@@ -24,6 +42,13 @@ impl ConfigValidator {
         Ok(result)
     }
 
+    /// Validate variable definitions on their own, before they are written to vibetree.toml
+    pub fn validate_variables(variables: &[VariableConfig]) -> ValidationResult {
+        let mut result = ValidationResult::new();
+        Self::validate_project_config(variables, &mut result);
+        result
+    }
+
     /// Validate project-level variable configuration
     fn validate_project_config(variables: &[VariableConfig], result: &mut ValidationResult) {
         // Check for duplicate variable names
@@ -66,13 +91,13 @@ impl ConfigValidator {
                         // Check for system reserved ports if it's a port type
                         if let Some(crate::config::VariableType::Port) = variable.r#type {
                             let reserved_ports = PortManager::get_system_reserved_ports();
-                            if let Ok(port) = u16::try_from(*num) {
-                                if reserved_ports.contains(&port) {
-                                    result.add_warning(format!(
-                                        "Variable '{}' uses system reserved port {}",
-                                        variable.name, port
-                                    ));
-                                }
+                            if let Ok(port) = u16::try_from(*num)
+                                && reserved_ports.contains(&port)
+                            {
+                                result.add_warning(format!(
+                                    "Variable '{}' uses system reserved port {}",
+                                    variable.name, port
+                                ));
                             }
                         }
                     }
@@ -100,13 +125,13 @@ impl ConfigValidator {
             }
 
             // Validate branch pattern if present
-            if let Some(ref branch_pattern) = variable.branch {
-                if let Err(e) = regex::Regex::new(branch_pattern) {
-                    result.add_error(format!(
-                        "Invalid regex pattern for variable '{}': {}",
-                        variable.name, e
-                    ));
-                }
+            if let Some(ref branch_pattern) = variable.branch
+                && let Err(e) = regex::Regex::new(branch_pattern)
+            {
+                result.add_error(format!(
+                    "Invalid regex pattern for variable '{}': {}",
+                    variable.name, e
+                ));
             }
 
             // Validate variable name format
@@ -120,15 +145,32 @@ impl ConfigValidator {
     }
 
     /// Validate worktree configurations
-    fn validate_worktree_configs(config: &VibeTreeConfig, result: &mut ValidationResult) {
-        let project_var_names: HashSet<_> = config
-            .project_config
-            .variables
-            .iter()
-            .map(|v| &v.name)
-            .collect();
-
+    fn validate_worktree_configs(
+        config: &VibeTreeConfig,
+        vibetree_parent: Option<&Path>,
+        result: &mut ValidationResult,
+    ) {
         for (worktree_name, worktree_config) in &config.branches_config.worktrees {
+            let variables = match vibetree_parent {
+                Some(parent) => match config.worktree_variables(parent, worktree_name) {
+                    Ok(variables) => variables,
+                    Err(e) => {
+                        result.add_error(format!(
+                            "Failed to read vibetree.toml for worktree '{}': {}",
+                            worktree_name, e
+                        ));
+                        continue;
+                    }
+                },
+                None => config.project_config.variables.clone(),
+            };
+
+            // Variables whose branch pattern doesn't match are never allocated here
+            let project_var_names: HashSet<_> = variables
+                .iter()
+                .filter(|v| Self::applies_to_branch(v, worktree_name))
+                .map(|v| &v.name)
+                .collect();
             let worktree_var_names: HashSet<_> = worktree_config.values.keys().collect();
 
             // Check if worktree has variables that don't exist in project config
@@ -173,7 +215,7 @@ impl ConfigValidator {
                 if value.parse::<u16>().is_ok() {
                     value_usage
                         .entry(value.clone())
-                        .or_insert_with(Vec::new)
+                        .or_default()
                         .push(format!("{}:{}", worktree_name, var_name));
                 }
             }
@@ -191,8 +233,19 @@ impl ConfigValidator {
         }
     }
 
+    /// Whether a variable's branch pattern matches a worktree, treating invalid
+    /// patterns as matching since they are reported separately
+    fn applies_to_branch(variable: &VariableConfig, branch_name: &str) -> bool {
+        match &variable.branch {
+            Some(pattern) => regex::Regex::new(pattern)
+                .map(|re| re.is_match(branch_name))
+                .unwrap_or(true),
+            None => true,
+        }
+    }
+
     /// Check if a variable name follows typical environment variable conventions
-    fn is_valid_env_var_name(name: &str) -> bool {
+    pub(crate) fn is_valid_env_var_name(name: &str) -> bool {
         if name.is_empty() {
             return false;
         }
@@ -222,7 +275,7 @@ impl ConfigValidator {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ValidationResult {
     pub errors: Vec<String>,
     pub warnings: Vec<String>,
@@ -331,6 +384,13 @@ mod tests {
             main_branch: "main".to_string(),
             branches_dir: "branches".to_string(),
             env_file_path: ".vibetree/env".to_string(),
+            merge: Default::default(),
+            forge: Default::default(),
+            fork: Default::default(),
+            layout: Default::default(),
+            services: Vec::new(),
+            compose: Default::default(),
+            resources: Vec::new(),
         };
 
         // Create two worktrees with conflicting value assignments
@@ -347,12 +407,14 @@ mod tests {
                     "branch1".to_string(),
                     WorktreeConfig {
                         values: worktree1_values,
+                        ..Default::default()
                     },
                 ),
                 (
                     "branch2".to_string(),
                     WorktreeConfig {
                         values: worktree2_values,
+                        ..Default::default()
                     },
                 ),
             ]),
@@ -367,4 +429,34 @@ mod tests {
                 .any(|e| e.contains("Value 5432 is used by multiple services"))
         );
     }
+
+    #[test]
+    fn test_branch_scoped_variables_are_not_missing() {
+        let mut config = VibeTreeConfig::default();
+        config.project_config.variables = vec![
+            VariableConfig {
+                name: "POSTGRES_PORT".to_string(),
+                value: Some(toml::Value::Integer(5432)),
+                r#type: Some(crate::config::VariableType::Port),
+                branch: None,
+            },
+            VariableConfig {
+                name: "PREVIEW_URL".to_string(),
+                value: Some(toml::Value::String("preview".to_string())),
+                r#type: None,
+                branch: Some("^preview/".to_string()),
+            },
+        ];
+        config.branches_config.worktrees.insert(
+            "feature".to_string(),
+            WorktreeConfig {
+                values: HashMap::from([("POSTGRES_PORT".to_string(), "5433".to_string())]),
+                ..Default::default()
+            },
+        );
+
+        let result = ConfigValidator::validate_config(&config).unwrap();
+        assert!(result.is_valid());
+        assert!(!result.warnings.iter().any(|w| w.contains("PREVIEW_URL")));
+    }
 }
@@ -0,0 +1,173 @@
+use anyhow::{Result, bail};
+use log::{info, warn};
+use std::path::PathBuf;
+
+use crate::config::{VariableConfig, VariableType, VibeTreeConfig};
+use crate::output::{self, VariableEditResult, VibeTreeError};
+use crate::project_file::ProjectFile;
+use crate::sync::SyncManager;
+use crate::validation::ConfigValidator;
+
+/// Value of a variable given on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VariableValue {
+    /// A port allocated starting from this one
+    Port(u16),
+    /// A number allocated starting from this one
+    Int(u16),
+    /// A template like "app_{int:1}" or a static string
+    Template(String),
+}
+
+impl VariableValue {
+    fn into_config(self, name: &str, branch: Option<String>) -> VariableConfig {
+        let (value, r#type) = match self {
+            VariableValue::Port(port) => {
+                (toml::Value::Integer(port as i64), Some(VariableType::Port))
+            }
+            VariableValue::Int(number) => {
+                (toml::Value::Integer(number as i64), Some(VariableType::Int))
+            }
+            VariableValue::Template(template) => (toml::Value::String(template), None),
+        };
+        VariableConfig {
+            name: name.to_string(),
+            value: Some(value),
+            r#type,
+            branch,
+        }
+    }
+}
+
+/// Edits the `[[variables]]` of vibetree.toml, keeping the rest of the file as written
+pub struct VariableManager<'a> {
+    config: &'a mut VibeTreeConfig,
+    vibetree_parent: &'a PathBuf,
+}
+
+impl<'a> VariableManager<'a> {
+    pub fn new(config: &'a mut VibeTreeConfig, vibetree_parent: &'a PathBuf) -> Self {
+        Self {
+            config,
+            vibetree_parent,
+        }
+    }
+
+    /// Define a new variable
+    pub fn add(
+        &mut self,
+        name: &str,
+        value: VariableValue,
+        branch: Option<String>,
+        yes: bool,
+    ) -> Result<VariableEditResult> {
+        if self.find(name).is_some() {
+            bail!(VibeTreeError::conflict(format!(
+                "Variable '{}' already exists; use `vibetree var set` to change it",
+                name
+            )));
+        }
+        let variable = value.into_config(name, branch);
+        self.edit(yes, |file| file.add_variable(&variable))
+    }
+
+    /// Change the value of a variable, and its branch pattern when one is given
+    pub fn set(
+        &mut self,
+        name: &str,
+        value: VariableValue,
+        branch: Option<String>,
+        yes: bool,
+    ) -> Result<VariableEditResult> {
+        let Some(existing) = self.find(name) else {
+            bail!(VibeTreeError::not_found(format!(
+                "Variable '{}' does not exist",
+                name
+            )));
+        };
+        let variable = value.into_config(name, branch.or_else(|| existing.branch.clone()));
+        self.edit(yes, |file| file.update_variable(&variable).map(|_| ()))
+    }
+
+    /// Remove a variable
+    pub fn remove(&mut self, name: &str, yes: bool) -> Result<VariableEditResult> {
+        if self.find(name).is_none() {
+            bail!(VibeTreeError::not_found(format!(
+                "Variable '{}' does not exist",
+                name
+            )));
+        }
+        self.edit(yes, |file| {
+            file.remove_variable(name);
+            Ok(())
+        })
+    }
+
+    fn find(&self, name: &str) -> Option<&VariableConfig> {
+        self.config
+            .project_config
+            .variables
+            .iter()
+            .find(|variable| variable.name == name)
+    }
+
+    /// Apply `change` to vibetree.toml, then offer to repair the worktrees it affects
+    fn edit(
+        &mut self,
+        yes: bool,
+        change: impl FnOnce(&mut ProjectFile) -> Result<()>,
+    ) -> Result<VariableEditResult> {
+        let path = self.vibetree_parent.join("vibetree.toml");
+        let mut file = ProjectFile::load(&path)?;
+        change(&mut file)?;
+
+        let variables = file.config()?.variables;
+        let validation = ConfigValidator::validate_variables(&variables);
+        if !validation.is_valid() {
+            bail!(
+                "Invalid variable configuration: {}",
+                validation.errors.join("; ")
+            );
+        }
+        for warning in &validation.warnings {
+            warn!("{}", warning);
+        }
+
+        file.save()?;
+        info!("Updated {}", path.display());
+        self.config.project_config.variables = variables.clone();
+
+        let mut sync_manager = SyncManager::new(self.config, self.vibetree_parent);
+        let outdated = sync_manager.plan()?.config_mismatches;
+        if outdated.is_empty() {
+            return Ok(VariableEditResult {
+                variables,
+                outdated,
+                repair: None,
+            });
+        }
+
+        info!("Worktrees with outdated values:");
+        for name in &outdated {
+            info!("  {}", name);
+        }
+        if !yes {
+            let question = format!("Repair {} worktree(s) now?", outdated.len());
+            if !output::confirm(&question, "--yes")? {
+                info!("Run `vibetree repair` to update them later");
+                return Ok(VariableEditResult {
+                    variables,
+                    outdated,
+                    repair: None,
+                });
+            }
+        }
+
+        let repair = sync_manager.sync(false)?;
+        Ok(VariableEditResult {
+            variables,
+            outdated,
+            repair: Some(repair),
+        })
+    }
+}
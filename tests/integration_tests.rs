@@ -1,11 +1,19 @@
 use anyhow::Result;
+use std::cell::RefCell;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Once;
 use tempfile::TempDir;
-use vibetree::{config, EnvFileGenerator, GitManager, VariableConfig, VibeTreeApp};
+use vibetree::output::error_kind;
+use vibetree::{
+    Action, CarryOptions, CopyMode, EnvFileGenerator, ErrorKind, ForkOptions, GitManager,
+    InitOptions, ListOptions, MergeOptions, MergeStrategy, Origin, OutputFormat, PrState,
+    PullRequestOptions, RemoveOptions, ResourceState, UpdateOptions, UpdateStatus, VariableConfig,
+    VariableValue, VibeTreeApp, VibeTreeError, WorkspaceCloser, config, schema, transaction,
+};
 
 // Set up test environment once - skip shell spawning in tests
 static INIT: Once = Once::new();
@@ -25,6 +33,8 @@ fn setup_test_env() {
             // Skip system port availability checks in tests (concurrent tests may
             // temporarily occupy ports, causing false "port in use" errors)
             std::env::set_var("VIBETREE_TESTING", "1");
+            // Let tests choose the editor through each repository's core.editor
+            std::env::remove_var("GIT_EDITOR");
         }
     });
 }
@@ -42,6 +52,19 @@ struct IntegrationTestSetup {
     repo_path: PathBuf,
     /// Unique port base for this test (each test gets 100 ports starting here)
     port_base: u16,
+    /// Worktrees whose windows, tabs and sessions removal closed, as (name, path)
+    closed: Rc<RefCell<Vec<(String, PathBuf)>>>,
+}
+
+/// Records what removal closes rather than closing a developer's own windows
+struct RecordingCloser(Rc<RefCell<Vec<(String, PathBuf)>>>);
+
+impl WorkspaceCloser for RecordingCloser {
+    fn close(&self, name: &str, path: &Path) {
+        self.0
+            .borrow_mut()
+            .push((name.to_string(), path.to_path_buf()));
+    }
 }
 
 impl IntegrationTestSetup {
@@ -109,13 +132,16 @@ impl IntegrationTestSetup {
             temp_dir,
             repo_path,
             port_base: get_unique_port_base(),
+            closed: Rc::default(),
         })
     }
 
     /// Create a VibeTreeApp instance for this test setup
     fn create_app(&self) -> Result<VibeTreeApp> {
         // Use with_parent to avoid global environment variable conflicts
-        VibeTreeApp::with_parent(self.repo_path.clone())
+        let mut app = VibeTreeApp::with_parent(self.repo_path.clone())?;
+        app.set_workspace_closer(Box::new(RecordingCloser(self.closed.clone())));
+        Ok(app)
     }
 
     /// Get a variable spec with a unique port for this test
@@ -305,8 +331,19 @@ fn test_complete_vibetree_workflow() -> Result<()> {
         false, // don't keep branch
     )?;
 
-    // Verify worktree was removed
+    // Verify worktree was removed, and its windows closed
     assert!(!setup.worktree_exists("feature-payments"));
+    assert_eq!(
+        *setup.closed.borrow(),
+        [(
+            "feature-payments".to_string(),
+            setup
+                .repo_path
+                .join(".vibetree")
+                .join("branches")
+                .join("feature-payments")
+        )]
+    );
     assert_eq!(app.get_worktrees().len(), 2); // main + feature-auth
     assert!(!app.get_worktrees().contains_key("feature-payments"));
 
@@ -829,7 +866,7 @@ fn test_switch_to_existing_worktree() -> Result<()> {
     app.add_worktree("feature-branch".to_string(), None, None, false, false)?;
 
     // Test switching to the worktree should succeed
-    let result = app.switch_to_worktree("feature-branch".to_string());
+    let result = app.switch_to_worktree("feature-branch".to_string(), false);
     assert!(result.is_ok());
 
     Ok(())
@@ -844,7 +881,7 @@ fn test_switch_to_nonexistent_worktree() -> Result<()> {
     app.init(vec![setup.var("web", 0)])?;
 
     // Test switching to non-existent worktree should fail
-    let result = app.switch_to_worktree("nonexistent-branch".to_string());
+    let result = app.switch_to_worktree("nonexistent-branch".to_string(), false);
     assert!(result.is_err());
     assert!(result.unwrap_err().to_string().contains("does not exist"));
 
@@ -1118,7 +1155,9 @@ fn test_merge_nonexistent_branch() -> Result<()> {
         false,
     );
     assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("does not exist"));
+    let err = result.unwrap_err();
+    assert!(err.to_string().contains("does not exist"));
+    assert_eq!(error_kind(&err), Some(ErrorKind::NotFound));
 
     Ok(())
 }
@@ -1165,7 +1204,9 @@ fn test_merge_detects_uncommitted_changes() -> Result<()> {
         false,
     );
     assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("Uncommitted changes"));
+    let err = result.unwrap_err();
+    assert!(err.to_string().contains("Uncommitted changes"));
+    assert_eq!(error_kind(&err), Some(ErrorKind::DirtyTree));
 
     Ok(())
 }
@@ -1215,7 +1256,2163 @@ fn test_merge_detects_conflicts() -> Result<()> {
         false,
     );
     assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("conflicts"));
+    let err = result.unwrap_err();
+    assert!(err.to_string().contains("conflicts"));
+    assert_eq!(error_kind(&err), Some(ErrorKind::Conflict));
+    let details = err
+        .downcast_ref::<VibeTreeError>()
+        .and_then(|e| e.details.clone())
+        .unwrap();
+    assert_eq!(details["conflicts"], serde_json::json!(["conflict.txt"]));
+
+    // Rebase prediction finds the same file without touching the worktree
+    let result = app.merge_worktree("conflict-feature".to_string(), None, false, true, false);
+    assert_eq!(error_kind(&result.unwrap_err()), Some(ErrorKind::Conflict));
+    assert_eq!(
+        fs::read_to_string(worktree_path.join("conflict.txt"))?,
+        "feature version"
+    );
+
+    Ok(())
+}
+
+/// Commit a file in the given worktree directory
+fn commit_in(path: &std::path::Path, file: &str, contents: &str, message: &str) -> Result<()> {
+    fs::write(path.join(file), contents)?;
+    Command::new("git")
+        .args(["add", "."])
+        .current_dir(path)
+        .output()?;
+    Command::new("git")
+        .args(["commit", "-m", message])
+        .current_dir(path)
+        .output()?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_into_target_in_another_worktree() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    app.add_worktree("release-1.2".to_string(), None, None, false, false)?;
+    app.add_worktree("fix".to_string(), None, None, false, false)?;
+
+    let branches = setup.repo_path.join(".vibetree").join("branches");
+    commit_in(
+        &branches.join("release-1.2"),
+        "release.txt",
+        "release",
+        "Release",
+    )?;
+    commit_in(&branches.join("fix"), "fix.txt", "fix", "Fix")?;
+
+    let result = app.merge_worktree(
+        "fix".to_string(),
+        Some("release-1.2".to_string()),
+        false,
+        false,
+        false,
+    )?;
+    assert!(!result.already_merged);
+
+    // The merge happened in the release worktree; the root stays on main
+    assert!(branches.join("release-1.2").join("fix.txt").exists());
+    assert!(!setup.repo_path.join("fix.txt").exists());
+    assert_eq!(
+        setup.run_git_cmd(&["branch", "--show-current"])?.trim(),
+        "main"
+    );
+
+    // Uncommitted changes where the target lives block the merge
+    commit_in(&branches.join("fix"), "more.txt", "more", "More")?;
+    fs::write(branches.join("release-1.2").join("release.txt"), "dirty")?;
+    let result = app.merge_worktree(
+        "fix".to_string(),
+        Some("release-1.2".to_string()),
+        false,
+        true,
+        false,
+    );
+    assert_eq!(error_kind(&result.unwrap_err()), Some(ErrorKind::DirtyTree));
+
+    // Rebasing fast-forwards the target in its worktree
+    setup.run_git_cmd(&["-C", ".vibetree/branches/release-1.2", "checkout", "."])?;
+    app.merge_worktree(
+        "fix".to_string(),
+        Some("release-1.2".to_string()),
+        false,
+        true,
+        false,
+    )?;
+    assert!(branches.join("release-1.2").join("more.txt").exists());
+    assert_eq!(
+        setup.run_git_cmd(&["rev-parse", "release-1.2"])?,
+        setup.run_git_cmd(&["rev-parse", "fix"])?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_into_target_not_checked_out() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    // A release branch with its own commit that isn't checked out anywhere
+    setup.run_git_cmd(&["checkout", "-b", "release-1.2"])?;
+    commit_in(&setup.repo_path, "release.txt", "release", "Release")?;
+    setup.run_git_cmd(&["checkout", "main"])?;
+
+    app.add_worktree("fix".to_string(), None, None, false, false)?;
+    let fix_path = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join("fix");
+    commit_in(&fix_path, "fix.txt", "fix", "Fix")?;
+
+    // Diverged, so the ref gets an in-memory merge commit
+    app.merge_worktree(
+        "fix".to_string(),
+        Some("release-1.2".to_string()),
+        false,
+        false,
+        false,
+    )?;
+    assert_eq!(
+        setup.run_git_cmd(&["rev-parse", "release-1.2^2"])?,
+        setup.run_git_cmd(&["rev-parse", "fix"])?
+    );
+    let files = setup.run_git_cmd(&["ls-tree", "--name-only", "release-1.2"])?;
+    assert!(files.lines().any(|f| f == "fix.txt"));
+    assert!(files.lines().any(|f| f == "release.txt"));
+    assert_eq!(
+        setup.run_git_cmd(&["branch", "--show-current"])?.trim(),
+        "main"
+    );
+    assert!(!setup.repo_path.join("fix.txt").exists());
+
+    // Rebasing onto the updated release branch fast-forwards the ref
+    commit_in(&fix_path, "more.txt", "more", "More")?;
+    app.merge_worktree(
+        "fix".to_string(),
+        Some("release-1.2".to_string()),
+        false,
+        true,
+        false,
+    )?;
+    assert_eq!(
+        setup.run_git_cmd(&["rev-parse", "release-1.2"])?,
+        setup.run_git_cmd(&["rev-parse", "fix"])?
+    );
+    assert_eq!(
+        setup.run_git_cmd(&["branch", "--show-current"])?.trim(),
+        "main"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_commit_messages() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[merge]\ntrailer = \"Co-authored-by: {{name}} <{{email}}>\"\n",
+            fs::read_to_string(setup.config_path())?
+        ),
+    )?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    let mut app = setup.create_app()?;
+
+    app.add_worktree("squashed".to_string(), None, None, false, false)?;
+    let branches = setup.repo_path.join(".vibetree").join("branches");
+    let squashed = branches.join("squashed");
+    commit_in(&squashed, "one.txt", "one", "Add one")?;
+    fs::write(squashed.join("two.txt"), "two")?;
+    Command::new("git")
+        .args(["add", "."])
+        .current_dir(&squashed)
+        .output()?;
+    Command::new("git")
+        .args([
+            "-c",
+            "user.name=Ann",
+            "-c",
+            "user.email=ann@example.com",
+            "commit",
+            "-m",
+            "Add two",
+        ])
+        .current_dir(&squashed)
+        .output()?;
+
+    // Squash without a message lists the subjects and credits other authors
+    let result = app.merge_worktree("squashed".to_string(), None, true, false, false)?;
+    let expected = "Squash merge branch 'squashed' into main\n\n* Add one\n* Add two\n\n\
+                    Co-authored-by: Ann <ann@example.com>";
+    assert_eq!(result.message.as_deref(), Some(expected));
+    assert_eq!(
+        setup.run_git_cmd(&["log", "-1", "--format=%B"])?.trim(),
+        expected
+    );
+
+    // --no-ff creates a merge commit with the given message even when fast-forwarding works
+    app.add_worktree("no-ff".to_string(), None, None, false, false)?;
+    commit_in(&branches.join("no-ff"), "three.txt", "three", "Add three")?;
+    app.merge_worktree_with_options(
+        "no-ff".to_string(),
+        MergeOptions {
+            message: Some("Land no-ff".to_string()),
+            no_ff: true,
+            ..MergeOptions::default()
+        },
+    )?;
+    assert_eq!(
+        setup
+            .run_git_cmd(&["log", "-1", "--format=%s %P"])?
+            .split_whitespace()
+            .count(),
+        4,
+        "expected a merge commit with two parents"
+    );
+    assert_eq!(
+        setup.run_git_cmd(&["log", "-1", "--format=%s"])?.trim(),
+        "Land no-ff"
+    );
+
+    // --edit runs the git editor on the message
+    setup.run_git_cmd(&["config", "core.editor", "sed -i s/Merge/Edited/"])?;
+    app.add_worktree("edited".to_string(), None, None, false, false)?;
+    commit_in(&branches.join("edited"), "four.txt", "four", "Add four")?;
+    let result = app.merge_worktree_with_options(
+        "edited".to_string(),
+        MergeOptions {
+            edit: true,
+            no_ff: true,
+            ..MergeOptions::default()
+        },
+    )?;
+    assert_eq!(
+        result.message.as_deref(),
+        Some("Edited branch 'edited' into main")
+    );
+    assert_eq!(
+        setup.run_git_cmd(&["log", "-1", "--format=%B"])?.trim(),
+        "Edited branch 'edited' into main"
+    );
+
+    Ok(())
+}
+
+/// Make the worktree's branch and the main branch change conflict.txt differently
+fn make_conflict(setup: &IntegrationTestSetup, name: &str) -> Result<()> {
+    let branch_path = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join(name);
+    let main_version = format!("main version for {}", name);
+    commit_in(
+        &setup.repo_path,
+        "conflict.txt",
+        &main_version,
+        "Main conflict",
+    )?;
+    commit_in(&branch_path, "conflict.txt", name, "Feature conflict")
+}
+
+#[test]
+fn test_merge_keep_conflicts_continue_and_abort() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    let mut app = setup.create_app()?;
+    let operations = setup.repo_path.join(".vibetree").join("operations.toml");
+    let branches = setup.repo_path.join(".vibetree").join("branches");
+
+    // A kept merge conflict is resolved in the target worktree and continued
+    app.add_worktree("kept".to_string(), None, None, false, false)?;
+    make_conflict(&setup, "kept")?;
+    let keep = MergeOptions {
+        keep_conflicts: true,
+        ..MergeOptions::default()
+    };
+    let err = app
+        .merge_worktree_with_options("kept".to_string(), keep.clone())
+        .unwrap_err();
+    assert_eq!(error_kind(&err), Some(ErrorKind::Conflict));
+    let details = err
+        .downcast_ref::<VibeTreeError>()
+        .and_then(|e| e.details.clone())
+        .unwrap();
+    assert_eq!(details["in_progress"], serde_json::json!(true));
+    assert!(operations.exists());
+    assert!(fs::read_to_string(setup.repo_path.join("conflict.txt"))?.contains("<<<<<<<"));
+
+    let data = app.collect_worktree_data()?;
+    let kept = data.iter().find(|d| d.name == "kept").unwrap();
+    assert_eq!(kept.operation.as_deref(), Some("merging into main"));
+
+    // Starting the same merge again or continuing before resolving is refused
+    let again = app.merge_worktree_with_options("kept".to_string(), keep.clone());
+    assert_eq!(error_kind(&again.unwrap_err()), Some(ErrorKind::Conflict));
+    let early = app.continue_merge(None, false, false);
+    assert_eq!(error_kind(&early.unwrap_err()), Some(ErrorKind::Conflict));
+
+    fs::write(setup.repo_path.join("conflict.txt"), "resolved")?;
+    setup.run_git_cmd(&["add", "conflict.txt"])?;
+    let result = app.continue_merge(None, false, false)?;
+    assert_eq!(result.strategy, MergeStrategy::Merge);
+    assert_eq!(
+        result.message.as_deref(),
+        Some("Merge branch 'kept' into main")
+    );
+    // The merge stopped with conflicts, so it was never a fast-forward
+    assert_eq!(
+        result.plan.actions,
+        [Action::Merge {
+            branch: "kept".to_string(),
+            into: "main".to_string(),
+            strategy: MergeStrategy::Merge,
+            message: result.message.clone(),
+            no_ff: true,
+        }]
+    );
+    assert!(!operations.exists());
+    let subject = setup.run_git_cmd(&["log", "-1", "--format=%s"])?;
+    assert_eq!(subject.trim(), "Merge branch 'kept' into main");
+
+    // An aborted squash merge restores the target worktree
+    app.add_worktree("aborted".to_string(), None, None, false, false)?;
+    make_conflict(&setup, "aborted")?;
+    let head = setup.run_git_cmd(&["rev-parse", "HEAD"])?;
+    let squash = MergeOptions {
+        squash: true,
+        ..keep.clone()
+    };
+    assert!(
+        app.merge_worktree_with_options("aborted".to_string(), squash)
+            .is_err()
+    );
+    let result = app.abort_merge(Some("aborted".to_string()))?;
+    assert_eq!(result.strategy, MergeStrategy::Squash);
+    assert!(!operations.exists());
+    assert_eq!(setup.run_git_cmd(&["rev-parse", "HEAD"])?, head);
+    assert_eq!(
+        fs::read_to_string(setup.repo_path.join("conflict.txt"))?,
+        "main version for aborted"
+    );
+    assert!(app.abort_merge(None).is_err());
+
+    // A kept rebase conflict is resolved in the branch's worktree
+    app.add_worktree("rebased".to_string(), None, None, false, false)?;
+    let rebased = branches.join("rebased");
+    make_conflict(&setup, "rebased")?;
+    let rebase = MergeOptions {
+        rebase: true,
+        ..keep
+    };
+    assert!(
+        app.merge_worktree_with_options("rebased".to_string(), rebase)
+            .is_err()
+    );
+    assert!(fs::read_to_string(rebased.join("conflict.txt"))?.contains("<<<<<<<"));
+
+    fs::write(rebased.join("conflict.txt"), "rebased")?;
+    Command::new("git")
+        .args(["add", "conflict.txt"])
+        .current_dir(&rebased)
+        .output()?;
+    let result = app.continue_merge(Some("rebased".to_string()), true, false)?;
+    assert_eq!(result.strategy, MergeStrategy::Rebase);
+    assert!(matches!(
+        result.plan.actions[..],
+        [Action::Merge { no_ff: false, .. }]
+    ));
+    assert!(result.removed);
+    assert!(!operations.exists());
+    assert_eq!(
+        fs::read_to_string(setup.repo_path.join("conflict.txt"))?,
+        "rebased"
+    );
+
+    Ok(())
+}
+
+// ============================================================================
+// Per-worktree config tests
+// ============================================================================
+
+#[test]
+fn test_worktree_config_adds_branch_variable() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    app.add_worktree("feature-search".to_string(), None, None, false, false)?;
+
+    // The feature branch adds a variable to its own vibetree.toml
+    let worktree_path = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join("feature-search");
+    let mut branch_config = fs::read_to_string(worktree_path.join("vibetree.toml"))?;
+    branch_config.push_str(&format!(
+        "\n[[variables]]\nname = \"ELASTIC_PORT\"\nvalue = {}\ntype = \"port\"\n",
+        setup.port_base + 10
+    ));
+    fs::write(worktree_path.join("vibetree.toml"), branch_config)?;
+    Command::new("git")
+        .args(["commit", "-am", "Add elasticsearch"])
+        .current_dir(&worktree_path)
+        .output()?;
+
+    // Repair allocates the branch's new variable without touching main
+    app.repair(false)?;
+    assert!(
+        app.get_worktrees()["feature-search"]
+            .values
+            .contains_key("ELASTIC_PORT")
+    );
+    assert!(
+        !app.get_worktrees()["main"]
+            .values
+            .contains_key("ELASTIC_PORT")
+    );
+    assert!(
+        setup
+            .read_env_file("feature-search")?
+            .contains("ELASTIC_PORT=")
+    );
+
+    // List reports the drift from main's config
+    let worktree_data = app.collect_worktree_data()?;
+    let feature = worktree_data
+        .iter()
+        .find(|w| w.name == "feature-search")
+        .unwrap();
+    let drift = feature
+        .config_drift
+        .as_ref()
+        .expect("drift should be reported");
+    assert_eq!(drift.added, vec!["ELASTIC_PORT".to_string()]);
+    let main = worktree_data.iter().find(|w| w.name == "main").unwrap();
+    assert!(main.config_drift.is_none());
+
+    // A worktree created from the feature branch picks up its variables too
+    app.add_worktree(
+        "feature-search-ui".to_string(),
+        Some("feature-search".to_string()),
+        None,
+        false,
+        false,
+    )?;
+    assert!(
+        app.get_worktrees()["feature-search-ui"]
+            .values
+            .contains_key("ELASTIC_PORT")
+    );
+
+    Ok(())
+}
+
+// ============================================================================
+// Prune command tests
+// ============================================================================
+
+#[test]
+fn test_prune_merged_and_missing_worktrees() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    let branches_dir = setup.repo_path.join(".vibetree").join("branches");
+    let commit_file = |name: &str| -> Result<()> {
+        let worktree_path = branches_dir.join(name);
+        fs::write(worktree_path.join(format!("{}.txt", name)), name)?;
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(&worktree_path)
+            .output()?;
+        Command::new("git")
+            .args(["commit", "-m", name])
+            .current_dir(&worktree_path)
+            .output()?;
+        Ok(())
+    };
+
+    for name in [
+        "merged-feature",
+        "unmerged-feature",
+        "missing-feature",
+        "dirty-merged",
+        "fresh",
+    ] {
+        app.add_worktree(name.to_string(), None, None, false, false)?;
+    }
+
+    commit_file("merged-feature")?;
+    app.merge_worktree("merged-feature".to_string(), None, false, false, false)?;
+    commit_file("dirty-merged")?;
+    app.merge_worktree("dirty-merged".to_string(), None, false, false, false)?;
+    fs::write(branches_dir.join("dirty-merged").join("wip.txt"), "wip")?;
+    commit_file("unmerged-feature")?;
+    fs::remove_dir_all(branches_dir.join("missing-feature"))?;
+
+    // Dry run changes nothing
+    app.prune(vibetree::PruneCriteria::default(), true, true)?;
+    assert_eq!(app.get_worktrees().len(), 6);
+
+    app.prune(vibetree::PruneCriteria::default(), true, false)?;
+
+    let worktrees = app.get_worktrees();
+    assert!(!worktrees.contains_key("merged-feature"));
+    assert!(!worktrees.contains_key("missing-feature"));
+    assert!(worktrees.contains_key("unmerged-feature"));
+    assert!(
+        worktrees.contains_key("dirty-merged"),
+        "dirty worktrees are skipped"
+    );
+    assert!(
+        worktrees.contains_key("fresh"),
+        "branches without commits aren't merged"
+    );
+    assert!(worktrees.contains_key("main"));
+    assert!(!setup.worktree_exists("merged-feature"));
+
+    // Merged branches are deleted, branches of missing worktrees are kept
+    assert!(!GitManager::branch_exists(
+        &setup.repo_path,
+        "merged-feature"
+    )?);
+    assert!(GitManager::branch_exists(
+        &setup.repo_path,
+        "missing-feature"
+    )?);
+
+    // The release was persisted
+    let reloaded = setup.create_app()?;
+    assert!(!reloaded.get_worktrees().contains_key("merged-feature"));
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_keeps_unmerged_branches_whose_upstream_is_gone() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    // Both branches track an upstream that has since been deleted, but only one
+    // of them was merged
+    let branches_dir = setup.repo_path.join(".vibetree").join("branches");
+    for name in ["gone-merged", "gone-unmerged"] {
+        app.add_worktree(name.to_string(), None, None, false, false)?;
+        commit_in(&branches_dir.join(name), "work.txt", name, name)?;
+        setup.run_git_cmd(&["config", &format!("branch.{}.remote", name), "origin"])?;
+        setup.run_git_cmd(&[
+            "config",
+            &format!("branch.{}.merge", name),
+            &format!("refs/heads/{}", name),
+        ])?;
+    }
+    app.merge_worktree("gone-merged".to_string(), None, false, false, false)?;
+    let unmerged_oid = setup.run_git_cmd(&["rev-parse", "gone-unmerged"])?;
+
+    let criteria = vibetree::PruneCriteria {
+        gone: true,
+        ..Default::default()
+    };
+    let result = app.prune(criteria.clone(), true, true)?;
+    assert_eq!(result.candidates.len(), 2);
+    assert!(result.candidates.iter().all(|candidate| {
+        candidate.reasons == [vibetree::PruneReason::UpstreamGone]
+            && candidate.delete_branch == (candidate.name == "gone-merged")
+    }));
+    assert_eq!(result.removals.len(), 2);
+    assert!(result.removals.iter().all(|removal| !removal.removed));
+    assert!(setup.worktree_exists("gone-merged"));
+
+    let result = app.prune(criteria, true, false)?;
+    assert_eq!(result.removals.len(), 2);
+    assert!(!setup.worktree_exists("gone-merged"));
+    assert!(!setup.worktree_exists("gone-unmerged"));
+    assert!(!GitManager::branch_exists(&setup.repo_path, "gone-merged")?);
+    assert_eq!(
+        setup.run_git_cmd(&["rev-parse", "gone-unmerged"])?,
+        unmerged_oid
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_stale_ignores_commits_the_branch_was_created_from() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    let old_commit = |dir: &Path, message: &str| -> Result<()> {
+        let output = Command::new("git")
+            .args(["commit", "--allow-empty", "-m", message])
+            .env("GIT_AUTHOR_DATE", "2000-01-01T00:00:00")
+            .env("GIT_COMMITTER_DATE", "2000-01-01T00:00:00")
+            .current_dir(dir)
+            .output()?;
+        assert!(output.status.success());
+        Ok(())
+    };
+    old_commit(&setup.repo_path, "Add vibetree config")?;
+
+    // Both branches start from a years-old commit, but only one has old work of its own
+    app.add_worktree("fresh".to_string(), None, None, false, false)?;
+    app.add_worktree("abandoned".to_string(), None, None, false, false)?;
+    old_commit(
+        &setup.repo_path.join(".vibetree/branches/abandoned"),
+        "Old work",
+    )?;
+
+    let criteria = vibetree::PruneCriteria {
+        stale_days: Some(30),
+        ..Default::default()
+    };
+    app.prune(criteria, true, false)?;
+
+    let worktrees = app.get_worktrees();
+    assert!(worktrees.contains_key("fresh"));
+    assert!(!worktrees.contains_key("abandoned"));
+
+    Ok(())
+}
+
+// ============================================================================
+// List command tests
+// ============================================================================
+
+#[test]
+fn test_list_git_status_and_filters() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    app.add_worktree("feature-a".to_string(), None, None, false, false)?;
+    app.add_worktree("feature-b".to_string(), None, None, false, false)?;
+
+    let feature_a = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join("feature-a");
+    fs::write(feature_a.join("a.txt"), "a")?;
+    Command::new("git")
+        .args(["add", "."])
+        .current_dir(&feature_a)
+        .output()?;
+    Command::new("git")
+        .args(["commit", "-m", "Add feature a"])
+        .current_dir(&feature_a)
+        .output()?;
+    fs::write(feature_a.join("wip.txt"), "wip")?;
+
+    let data = app.collect_worktree_data()?;
+    let names: Vec<_> = data.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["feature-a", "feature-b", "main"],
+        "sorted by name"
+    );
+
+    let a = data.iter().find(|d| d.name == "feature-a").unwrap();
+    assert_eq!(a.branch.as_deref(), Some("feature-a"));
+    assert_eq!(a.dirty, Some(true));
+    assert_eq!(a.ahead_main, Some(1));
+    assert_eq!(a.behind_main, Some(0));
+    assert_eq!(a.last_commit_subject.as_deref(), Some("Add feature a"));
+
+    let b = data.iter().find(|d| d.name == "feature-b").unwrap();
+    assert_eq!(b.dirty, Some(false));
+    assert_eq!(b.ahead_main, Some(0));
+
+    let dirty = app.select_worktree_data(&ListOptions {
+        dirty: true,
+        ..Default::default()
+    })?;
+    assert_eq!(dirty.len(), 1);
+    assert_eq!(dirty[0].name, "feature-a");
+
+    let filtered = app.select_worktree_data(&ListOptions {
+        filter: Some("-b$".to_string()),
+        ..Default::default()
+    })?;
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].name, "feature-b");
+
+    // Custom columns, including a variable, render without error
+    app.list_worktrees_with_options(
+        Some(OutputFormat::Table),
+        &ListOptions {
+            columns: Some(vec![
+                "name".to_string(),
+                "branch".to_string(),
+                "POSTGRES".to_string(),
+            ]),
+            ..Default::default()
+        },
+    )?;
+
+    let unknown_column = app.list_worktrees_with_options(
+        Some(OutputFormat::Table),
+        &ListOptions {
+            columns: Some(vec!["bogus".to_string()]),
+            ..Default::default()
+        },
+    );
+    assert!(unknown_column.is_err());
+
+    let bad_regex = app.select_worktree_data(&ListOptions {
+        filter: Some("(".to_string()),
+        ..Default::default()
+    });
+    assert!(bad_regex.is_err());
+
+    Ok(())
+}
+
+// ============================================================================
+// Structured output tests
+// ============================================================================
+
+#[test]
+fn test_commands_return_structured_results() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    let init = app.init(vec![setup.var("postgres", 0)])?;
+    assert_eq!(init.variables.len(), 1);
+    assert_eq!(init.values.len(), 1);
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    let dry_run = app.add_worktree("feature".to_string(), None, None, true, false)?;
+    assert!(dry_run.dry_run);
+    assert!(!app.get_worktrees().contains_key("feature"));
+
+    let added = app.add_worktree("feature".to_string(), None, None, false, false)?;
+    assert!(!added.dry_run);
+    assert_eq!(
+        added.path,
+        setup.repo_path.join(".vibetree/branches/feature")
+    );
+    assert_eq!(added.values, app.get_worktrees()["feature"].values);
+
+    let duplicate = app
+        .add_worktree("feature".to_string(), None, None, false, false)
+        .unwrap_err();
+    assert_eq!(error_kind(&duplicate), Some(ErrorKind::Conflict));
+
+    fs::write(added.path.join("feature.txt"), "feature")?;
+    Command::new("git")
+        .args(["add", "."])
+        .current_dir(&added.path)
+        .output()?;
+    Command::new("git")
+        .args(["commit", "-m", "Add feature"])
+        .current_dir(&added.path)
+        .output()?;
+
+    let merged = app.merge_worktree("feature".to_string(), None, false, false, true)?;
+    assert_eq!(merged.into, "main");
+    assert_eq!(merged.strategy, MergeStrategy::Merge);
+    assert!(!merged.already_merged);
+    assert!(merged.removed);
+
+    let repair = app.repair(true)?;
+    assert!(repair.dry_run);
+    assert!(repair.errors.is_empty());
+
+    // Results serialize to the JSON printed by --output json
+    let json = serde_json::to_value(&repair)?;
+    assert_eq!(json["dry_run"], true);
+    assert!(json["plan"]["orphaned_worktrees"].is_array());
+
+    let missing = app
+        .remove_worktree_for_test("feature".to_string(), true, false)
+        .unwrap_err();
+    assert_eq!(error_kind(&missing), Some(ErrorKind::NotFound));
+    assert_eq!(vibetree::output::exit_code(&missing), 3);
+
+    Ok(())
+}
+
+// ============================================================================
+// Check command tests
+// ============================================================================
+
+#[test]
+fn test_check_reports_and_fixes_problems() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    app.add_worktree("no-env".to_string(), None, None, false, false)?;
+    app.add_worktree("stale-env".to_string(), None, None, false, false)?;
+    app.add_worktree("gone".to_string(), None, None, false, false)?;
+    assert!(app.check(false)?.issues.is_empty());
+
+    let branches_dir = setup.repo_path.join(".vibetree").join("branches");
+    fs::remove_file(branches_dir.join("no-env/.vibetree/env"))?;
+    fs::write(branches_dir.join("stale-env/.vibetree/env"), "POSTGRES=1\n")?;
+    fs::remove_dir_all(branches_dir.join("gone"))?;
+    fs::write(setup.repo_path.join(".gitignore"), "target/\n")?;
+
+    let report = app.check(false)?;
+    assert!(!report.is_ok());
+    assert_eq!(report.error_count(), 3);
+    for name in ["no-env", "stale-env", "gone"] {
+        assert!(
+            report
+                .issues
+                .iter()
+                .any(|i| i.worktree.as_deref() == Some(name) && i.fix.is_some()),
+            "expected a fixable issue for '{}'",
+            name
+        );
+    }
+    assert!(
+        report
+            .issues
+            .iter()
+            .any(|i| i.message.contains(".gitignore"))
+    );
+
+    let fixed = app.check(true)?;
+    assert!(fixed.is_ok());
+    assert!(fixed.issues.iter().all(|i| i.fixed || i.fix.is_none()));
+
+    assert!(app.check(false)?.issues.is_empty());
+    assert!(!app.get_worktrees().contains_key("gone"));
+    assert!(branches_dir.join("no-env/.vibetree/env").exists());
+
+    Ok(())
+}
+
+// ============================================================================
+// Push and pull request tests
+// ============================================================================
+
+/// Minimal stand-in for the GitHub pulls API
+///
+/// Records created pull requests and returns them from the list endpoint.
+/// Returns the base URL and the request bodies received for creation.
+fn start_mock_github() -> Result<(
+    String,
+    std::sync::Arc<std::sync::Mutex<Vec<serde_json::Value>>>,
+)> {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    let created: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+    let state = Arc::clone(&created);
+    let html_base = base_url.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut created = state.lock().unwrap();
+            let pull = |request: &serde_json::Value, number: usize| {
+                serde_json::json!({
+                    "number": number,
+                    "html_url": format!("{}/acme/widgets/pull/{}", html_base, number),
+                    "title": request["title"],
+                    "state": "open",
+                    "draft": request["draft"],
+                    "merged_at": null,
+                })
+            };
+            let response = if request_line.starts_with("POST /repos/acme/widgets/pulls ") {
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                created.push(request.clone());
+                pull(&request, created.len())
+            } else if request_line.starts_with("GET /repos/acme/widgets/pulls?") {
+                serde_json::Value::Array(
+                    created
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, r)| {
+                            request_line.contains(&format!("acme:{} ", r["head"].as_str().unwrap()))
+                        })
+                        .map(|(i, r)| pull(r, i + 1))
+                        .collect(),
+                )
+            } else {
+                serde_json::json!({ "message": "Not Found" })
+            };
+
+            let body = response.to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    Ok((base_url, created))
+}
+
+#[test]
+fn test_push_and_pull_request() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    let (api_url, created) = start_mock_github()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[forge]\nmethod = \"api\"\napi_url = \"{}\"\nrepository = \"acme/widgets\"\n",
+            fs::read_to_string(setup.config_path())?,
+            api_url
+        ),
+    )?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    let remote = TempDir::new()?;
+    Command::new("git")
+        .args(["init", "--bare"])
+        .current_dir(remote.path())
+        .output()?;
+    setup.run_git_cmd(&["remote", "add", "origin", &remote.path().to_string_lossy()])?;
+
+    let mut app = setup.create_app()?;
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    let worktree_path = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join("feature");
+    commit_in(&worktree_path, "feature.txt", "feature", "Add feature")?;
+
+    // Push sets the upstream
+    let pushed = app.push_worktree("feature", false)?;
+    assert_eq!(pushed.upstream, "origin/feature");
+    assert_eq!(
+        setup
+            .run_git_cmd(&["rev-parse", "--abbrev-ref", "feature@{upstream}"])?
+            .trim(),
+        "origin/feature"
+    );
+
+    // The first call creates the pull request from the commit subject
+    let result = app.pull_request("feature", PullRequestOptions::default())?;
+    assert!(result.created);
+    assert_eq!(result.pull_request.number, 1);
+    assert_eq!(result.pull_request.state, PrState::Open);
+    {
+        let created = created.lock().unwrap();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0]["head"], "feature");
+        assert_eq!(created[0]["base"], "main");
+        assert_eq!(created[0]["title"], "Add feature");
+    }
+
+    // The second finds the open one instead of creating another
+    let result = app.pull_request("feature", PullRequestOptions::default())?;
+    assert!(!result.created);
+    assert_eq!(created.lock().unwrap().len(), 1);
+
+    // list --pr shows the pull request
+    let data = app.select_worktree_data(&ListOptions {
+        pr: true,
+        ..Default::default()
+    })?;
+    let feature = data.iter().find(|d| d.name == "feature").unwrap();
+    assert_eq!(feature.pull_request.as_ref().map(|pr| pr.number), Some(1));
+
+    // A dry run of remove shows the open pull request, unless forced
+    let options = RemoveOptions {
+        dry_run: true,
+        ..RemoveOptions::default()
+    };
+    let result = app.remove_worktree_with_options("feature".to_string(), options)?;
+    assert_eq!(result.open_pull_request.map(|pr| pr.number), Some(1));
+    let options = RemoveOptions {
+        force: true,
+        dry_run: true,
+        ..RemoveOptions::default()
+    };
+    let result = app.remove_worktree_with_options("feature".to_string(), options)?;
+    assert!(result.open_pull_request.is_none());
+
+    // Removing still works with an open pull request; it only warns
+    app.remove_worktree_for_test("feature".to_string(), false, false)?;
+
+    let missing = app.pull_request("missing", PullRequestOptions::default());
+    assert_eq!(error_kind(&missing.unwrap_err()), Some(ErrorKind::NotFound));
+
+    Ok(())
+}
+
+// ============================================================================
+// Update Command Tests
+// ============================================================================
+
+#[test]
+fn test_update_worktrees_from_main() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    let mut app = setup.create_app()?;
+    let branches = setup.repo_path.join(".vibetree").join("branches");
+
+    for name in ["behind", "clashing", "dirty", "fresh"] {
+        app.add_worktree(name.to_string(), None, None, false, false)?;
+    }
+    commit_in(
+        &branches.join("behind"),
+        "behind.txt",
+        "behind",
+        "Behind work",
+    )?;
+    commit_in(
+        &branches.join("clashing"),
+        "shared.txt",
+        "clashing",
+        "Clash",
+    )?;
+    fs::write(branches.join("dirty").join("wip.txt"), "wip")?;
+
+    // Main moves on through a remote, so update has to fetch it
+    let remote = TempDir::new()?;
+    Command::new("git")
+        .args(["init", "--bare"])
+        .current_dir(remote.path())
+        .output()?;
+    let remote_url = remote.path().to_string_lossy().to_string();
+    setup.run_git_cmd(&["remote", "add", "origin", &remote_url])?;
+    setup.run_git_cmd(&["push", "-u", "origin", "main"])?;
+    let other = TempDir::new()?;
+    Command::new("git")
+        .args(["clone", &remote_url, "."])
+        .current_dir(other.path())
+        .output()?;
+    for (key, value) in [("user.name", "Other"), ("user.email", "other@example.com")] {
+        Command::new("git")
+            .args(["config", key, value])
+            .current_dir(other.path())
+            .output()?;
+    }
+    commit_in(other.path(), "shared.txt", "main", "Main work")?;
+    Command::new("git")
+        .args(["push", "origin", "main"])
+        .current_dir(other.path())
+        .output()?;
+
+    let status_of = |result: &vibetree::UpdateResult, name: &str| {
+        result
+            .worktrees
+            .iter()
+            .find(|w| w.name == name)
+            .map(|w| w.status)
+    };
+
+    // A dry run predicts every outcome without touching the worktrees
+    let dry_run = app.update(UpdateOptions {
+        dry_run: true,
+        ..UpdateOptions::default()
+    })?;
+    assert_eq!(dry_run.base, "origin/main");
+    assert_eq!(status_of(&dry_run, "behind"), Some(UpdateStatus::Updated));
+    assert_eq!(
+        status_of(&dry_run, "clashing"),
+        Some(UpdateStatus::Conflicts)
+    );
+    assert_eq!(status_of(&dry_run, "dirty"), Some(UpdateStatus::Skipped));
+    assert_eq!(status_of(&dry_run, "fresh"), Some(UpdateStatus::Updated));
+    assert!(!branches.join("behind").join("shared.txt").exists());
+
+    // The real update merges, fast-forwards, and aborts the conflicting one
+    let err = app.update(UpdateOptions::default()).unwrap_err();
+    assert_eq!(error_kind(&err), Some(ErrorKind::Conflict));
+    let details = err
+        .downcast_ref::<VibeTreeError>()
+        .and_then(|e| e.details.clone())
+        .unwrap();
+    assert_eq!(
+        details["worktrees"][1]["conflicts"],
+        serde_json::json!(["shared.txt"])
+    );
+
+    assert!(branches.join("behind").join("shared.txt").exists());
+    assert!(branches.join("fresh").join("shared.txt").exists());
+    assert_eq!(
+        fs::read_to_string(branches.join("clashing").join("shared.txt"))?,
+        "clashing"
+    );
+    assert!(!GitManager::operation_in_progress(
+        &branches.join("clashing")
+    )?);
+    assert!(!branches.join("dirty").join("shared.txt").exists());
+    let subject = Command::new("git")
+        .args(["log", "-1", "--format=%s"])
+        .current_dir(branches.join("behind"))
+        .output()?;
+    assert_eq!(
+        String::from_utf8_lossy(&subject.stdout).trim(),
+        "Merge branch 'origin/main' into behind"
+    );
+
+    // Updated branches are now up to date, and a single branch can be rebased
+    let result = app.update(UpdateOptions {
+        branch: Some("behind".to_string()),
+        no_fetch: true,
+        ..UpdateOptions::default()
+    })?;
+    assert_eq!(status_of(&result, "behind"), Some(UpdateStatus::UpToDate));
+    assert_eq!(result.worktrees.len(), 1);
+
+    fs::remove_file(branches.join("dirty").join("wip.txt"))?;
+    let result = app.update(UpdateOptions {
+        branch: Some("dirty".to_string()),
+        rebase: true,
+        ..UpdateOptions::default()
+    })?;
+    assert_eq!(result.strategy, MergeStrategy::Rebase);
+    assert_eq!(status_of(&result, "dirty"), Some(UpdateStatus::Updated));
+    assert!(branches.join("dirty").join("shared.txt").exists());
+
+    assert!(
+        app.update(UpdateOptions {
+            branch: Some("main".to_string()),
+            ..UpdateOptions::default()
+        })
+        .is_err()
+    );
+
+    Ok(())
+}
+
+// ============================================================================
+// Carry and Move Changes Tests
+// ============================================================================
+
+#[test]
+fn test_add_carry_and_move_changes() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    commit_in(&setup.repo_path, "notes.txt", "original", "Add notes")?;
+    let mut app = setup.create_app()?;
+    let branches = setup.repo_path.join(".vibetree").join("branches");
+
+    // Staged, unstaged and untracked work started on main
+    fs::write(setup.repo_path.join("notes.txt"), "edited")?;
+    fs::write(setup.repo_path.join("staged.txt"), "staged")?;
+    setup.run_git_cmd(&["add", "staged.txt"])?;
+    fs::write(setup.repo_path.join("untracked.txt"), "untracked")?;
+
+    let carry = CarryOptions::default();
+    // A dry run plans the carry but leaves the changes where they are
+    let result =
+        app.add_worktree_carrying("carried".to_string(), None, None, true, false, Some(carry))?;
+    assert!(!result.carried);
+    assert!(
+        result
+            .plan
+            .actions
+            .iter()
+            .any(|action| matches!(action, Action::CarryChanges { .. }))
+    );
+    assert_eq!(fs::read_to_string(setup.repo_path.join("notes.txt"))?, "edited");
+
+    let result =
+        app.add_worktree_carrying("carried".to_string(), None, None, false, false, Some(carry))?;
+    assert!(result.carried);
+    let carried = branches.join("carried");
+    assert_eq!(fs::read_to_string(carried.join("notes.txt"))?, "edited");
+    let staged = Command::new("git")
+        .args(["diff", "--cached", "--name-only"])
+        .current_dir(&carried)
+        .output()?;
+    assert_eq!(String::from_utf8_lossy(&staged.stdout).trim(), "staged.txt");
+    assert!(!carried.join("untracked.txt").exists());
+
+    // Main keeps only the untracked file, and no stash is left behind
+    assert_eq!(
+        fs::read_to_string(setup.repo_path.join("notes.txt"))?,
+        "original"
+    );
+    assert!(!setup.repo_path.join("staged.txt").exists());
+    assert!(setup.repo_path.join("untracked.txt").exists());
+    assert_eq!(setup.run_git_cmd(&["stash", "list"])?, "");
+
+    let with_untracked = CarryOptions {
+        include_untracked: true,
+    };
+    let result = app.add_worktree_carrying(
+        "untracked".to_string(),
+        None,
+        None,
+        false,
+        false,
+        Some(with_untracked),
+    )?;
+    assert!(result.carried);
+    assert!(branches.join("untracked").join("untracked.txt").exists());
+    assert!(!setup.repo_path.join("untracked.txt").exists());
+
+    // Nothing left to carry is fine
+    let result =
+        app.add_worktree_carrying("empty".to_string(), None, None, false, false, Some(carry))?;
+    assert!(!result.carried);
+
+    // Changes move between existing worktrees, but only into clean ones
+    let dirty = app.move_changes("carried", "untracked", carry);
+    assert_eq!(error_kind(&dirty.unwrap_err()), Some(ErrorKind::DirtyTree));
+
+    let result = app.move_changes("carried", "empty", carry)?;
+    assert!(result.moved);
+    assert_eq!(
+        fs::read_to_string(branches.join("empty").join("notes.txt"))?,
+        "edited"
+    );
+    assert!(!GitManager::has_uncommitted_changes(&carried)?);
+
+    // A move that can't be applied is rolled back
+    commit_in(&carried, "notes.txt", "conflicting", "Change notes")?;
+    assert!(app.move_changes("empty", "carried", carry).is_err());
+    assert!(!GitManager::has_uncommitted_changes(&carried)?);
+    assert_eq!(
+        fs::read_to_string(branches.join("empty").join("notes.txt"))?,
+        "edited"
+    );
+    assert_eq!(setup.run_git_cmd(&["stash", "list"])?, "");
+
+    Ok(())
+}
+
+// ============================================================================
+// Fork Command Tests
+// ============================================================================
+
+#[test]
+fn test_fork_worktree_with_state() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[fork]\nhooks = [\"echo ${{src:POSTGRES}} ${{new:POSTGRES}} > hook.txt\"]\n",
+            fs::read_to_string(setup.config_path())?
+        ),
+    )?;
+    let gitignore = setup.repo_path.join(".gitignore");
+    let ignored = fs::read_to_string(&gitignore).unwrap_or_default();
+    fs::write(&gitignore, format!("{}build/\n", ignored))?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    let mut app = setup.create_app()?;
+
+    let source_values = app
+        .add_worktree("source".to_string(), None, None, false, false)?
+        .values;
+    let source = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join("source");
+    commit_in(&source, "work.txt", "committed", "Source work")?;
+    fs::write(source.join("work.txt"), "uncommitted")?;
+    fs::write(source.join("new.txt"), "untracked")?;
+    fs::create_dir_all(source.join("build"))?;
+    fs::write(source.join("build").join("artifact"), "built")?;
+
+    let options = ForkOptions {
+        with_changes: true,
+        copy_ignored: true,
+        copy_mode: CopyMode::Hardlink,
+        no_hooks: false,
+    };
+    let result = app.fork_worktree("source".to_string(), "forked".to_string(), options)?;
+    let forked = result.path.clone();
+
+    // The fork starts at the source's HEAD with fresh values
+    let head = |path: &std::path::Path| -> Result<String> {
+        let output = Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(path)
+            .output()?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    assert_eq!(head(&forked)?, head(&source)?);
+    assert_ne!(result.values["POSTGRES"], source_values["POSTGRES"]);
+
+    // Changes and artifacts are copied, and the source keeps its own
+    assert!(result.copied_changes);
+    assert_eq!(fs::read_to_string(forked.join("work.txt"))?, "uncommitted");
+    assert_eq!(fs::read_to_string(forked.join("new.txt"))?, "untracked");
+    assert_eq!(fs::read_to_string(source.join("work.txt"))?, "uncommitted");
+    assert_eq!(result.copied_ignored, vec!["build".to_string()]);
+    assert_eq!(
+        fs::read_to_string(forked.join("build").join("artifact"))?,
+        "built"
+    );
+
+    // The hook sees both worktrees' values
+    assert_eq!(result.hooks_run, 1);
+    assert_eq!(
+        fs::read_to_string(forked.join("hook.txt"))?.trim(),
+        format!(
+            "{} {}",
+            source_values["POSTGRES"], result.values["POSTGRES"]
+        )
+    );
+
+    // Without options only the branch is forked
+    let result = app.fork_worktree(
+        "source".to_string(),
+        "plain".to_string(),
+        ForkOptions {
+            no_hooks: true,
+            ..ForkOptions::default()
+        },
+    )?;
+    assert!(!result.copied_changes);
+    assert_eq!(result.hooks_run, 0);
+    assert_eq!(
+        fs::read_to_string(result.path.join("work.txt"))?,
+        "committed"
+    );
+    assert!(!result.path.join("build").exists());
+
+    let missing = app.fork_worktree(
+        "nope".to_string(),
+        "other".to_string(),
+        ForkOptions::default(),
+    );
+    assert_eq!(error_kind(&missing.unwrap_err()), Some(ErrorKind::NotFound));
+
+    Ok(())
+}
+
+#[test]
+fn test_fork_rolls_back_when_a_hook_fails() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[fork]\nhooks = [\"touch hook.txt && exit 1\"]\n",
+            fs::read_to_string(setup.config_path())?
+        ),
+    )?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    let mut app = setup.create_app()?;
+
+    app.add_worktree("source".to_string(), None, None, false, false)?;
+    let source = setup.repo_path.join(".vibetree/branches/source");
+    fs::write(source.join("new.txt"), "untracked")?;
+
+    let options = ForkOptions {
+        with_changes: true,
+        ..ForkOptions::default()
+    };
+    let error = app
+        .fork_worktree("source".to_string(), "forked".to_string(), options)
+        .unwrap_err();
+    assert!(format!("{:#}", error).contains("Fork hook failed"));
+
+    // The copied changes, the worktree, its branch and its allocation are all undone
+    assert!(!setup.worktree_exists("forked"));
+    assert!(!GitManager::branch_exists(&setup.repo_path, "forked")?);
+    assert!(!app.get_worktrees().contains_key("forked"));
+    assert!(!setup.create_app()?.get_worktrees().contains_key("forked"));
+    assert_eq!(fs::read_to_string(source.join("new.txt"))?, "untracked");
+
+    Ok(())
+}
+
+// ============================================================================
+// Transaction Rollback Tests
+// ============================================================================
+
+fn branch_oid(setup: &IntegrationTestSetup, branch: &str) -> Option<String> {
+    GitManager::branch_oid(&setup.repo_path, branch).ok()
+}
+
+#[test]
+fn test_failed_operations_roll_back() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    commit_in(&setup.repo_path, "notes.txt", "original", "Add notes")?;
+    let mut app = setup.create_app()?;
+    let branches = setup.repo_path.join(".vibetree").join("branches");
+
+    // A failed add leaves no allocation, branch or directory, and the carried
+    // changes back on main
+    fs::write(setup.repo_path.join("notes.txt"), "edited")?;
+    for point in [
+        "add:allocate",
+        "add:branch",
+        "add:worktree",
+        "add:carry",
+        "add:env",
+    ] {
+        transaction::inject_failure(Some(point));
+        let result = app.add_worktree_carrying(
+            "doomed".to_string(),
+            None,
+            None,
+            false,
+            false,
+            Some(CarryOptions::default()),
+        );
+        transaction::inject_failure(None);
+
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains(point), "{}: {:#}", point, error);
+        assert!(!app.get_worktrees().contains_key("doomed"), "{}", point);
+        assert!(!setup.create_app()?.get_worktrees().contains_key("doomed"));
+        assert_eq!(branch_oid(&setup, "doomed"), None, "{}", point);
+        assert!(!branches.join("doomed").exists(), "{}", point);
+        assert_eq!(
+            fs::read_to_string(setup.repo_path.join("notes.txt"))?,
+            "edited",
+            "{}",
+            point
+        );
+    }
+    setup.run_git_cmd(&["checkout", "notes.txt"])?;
+
+    // The same add goes through once nothing fails
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    let feature = branches.join("feature");
+    commit_in(&feature, "feature.txt", "feature", "Add feature")?;
+    let values = app.get_worktrees()["feature"].values.clone();
+    let feature_oid = branch_oid(&setup, "feature");
+
+    // A failed remove puts back the directory, allocation and branch
+    for point in ["remove:move_dir", "remove:release", "remove:branch"] {
+        transaction::inject_failure(Some(point));
+        let result = app.remove_worktree("feature".to_string(), true, false);
+        transaction::inject_failure(None);
+
+        assert!(result.is_err(), "{}", point);
+        assert!(feature.join("feature.txt").exists(), "{}", point);
+        assert_eq!(app.get_worktrees()["feature"].values, values, "{}", point);
+        assert_eq!(
+            setup.create_app()?.get_worktrees()["feature"].values,
+            values
+        );
+        assert_eq!(branch_oid(&setup, "feature"), feature_oid, "{}", point);
+        assert!(!GitManager::has_uncommitted_changes(&feature)?);
+    }
+
+    // A failed merge leaves both branches, and main's index, where they were
+    let main_oid = branch_oid(&setup, "main");
+    for (point, squash, rebase) in [
+        ("merge:merge", false, false),
+        ("merge:commit", true, false),
+        ("merge:merge", true, false),
+        ("merge:rebase", false, true),
+        ("merge:merge", false, true),
+    ] {
+        commit_in(&setup.repo_path, "main.txt", point, "Move main along")?;
+        let main_oid = branch_oid(&setup, "main");
+
+        transaction::inject_failure(Some(point));
+        let result = app.merge_worktree("feature".to_string(), None, squash, rebase, false);
+        transaction::inject_failure(None);
+
+        assert!(result.is_err(), "{}", point);
+        assert_eq!(branch_oid(&setup, "main"), main_oid, "{}", point);
+        assert_eq!(branch_oid(&setup, "feature"), feature_oid, "{}", point);
+        assert!(!setup.repo_path.join("feature.txt").exists(), "{}", point);
+        assert!(!GitManager::has_uncommitted_changes(&setup.repo_path)?);
+        assert!(!GitManager::has_uncommitted_changes(&feature)?);
+    }
+    assert_ne!(branch_oid(&setup, "main"), main_oid);
+
+    // And the remove goes through once nothing fails
+    app.remove_worktree("feature".to_string(), true, false)?;
+    assert!(!feature.exists());
+    assert_eq!(branch_oid(&setup, "feature"), None);
+    assert!(
+        !setup
+            .repo_path
+            .join(".vibetree")
+            .join("trash")
+            .join("feature")
+            .exists()
+    );
+    assert!(
+        !setup
+            .run_git_cmd(&["worktree", "list"])?
+            .contains("feature")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_failed_remove_keeps_resources() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let database = setup.repo_path.join("database");
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[[resources]]\nname = \"db\"\n\
+             create = \"touch '{db}'\"\ndestroy = \"rm '{db}'\"\n",
+            fs::read_to_string(setup.config_path())?,
+            db = database.display()
+        ),
+    )?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    let mut app = setup.create_app()?;
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    assert!(database.exists());
+
+    // Resources are destroyed only after every step that can be undone
+    for point in ["remove:move_dir", "remove:release", "remove:branch"] {
+        transaction::inject_failure(Some(point));
+        let result = app.remove_worktree("feature".to_string(), true, false);
+        transaction::inject_failure(None);
+
+        assert!(result.is_err(), "{}", point);
+        assert!(database.exists(), "{}", point);
+        assert_eq!(
+            app.get_worktrees()["feature"].resources["db"],
+            ResourceState::Created,
+            "{}",
+            point
+        );
+    }
+
+    app.remove_worktree("feature".to_string(), true, false)?;
+    assert!(!database.exists());
+
+    Ok(())
+}
+
+// ============================================================================
+// Dry Run Plan Tests
+// ============================================================================
+
+#[test]
+fn test_dry_runs_show_plan_without_changes() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    let gitignore = setup.repo_path.join(".gitignore");
+    let branches_toml = setup.repo_path.join(".vibetree").join("branches.toml");
+    let gitignore_before = fs::read_to_string(&gitignore).ok();
+
+    let result = app.init_with_options(
+        vec![setup.var("postgres", 0)],
+        InitOptions {
+            dry_run: true,
+            ..Default::default()
+        },
+    )?;
+    assert!(result.dry_run);
+    let main_values = result.values.clone().into_iter().collect();
+    assert_eq!(
+        result.plan.actions[..2],
+        [
+            Action::WriteConfig {
+                path: setup.repo_path.join("vibetree.toml"),
+            },
+            Action::Allocate {
+                worktree: "main".to_string(),
+                values: main_values,
+            },
+        ]
+    );
+    assert!(!branches_toml.exists());
+    assert_eq!(fs::read_to_string(&gitignore).ok(), gitignore_before);
+
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+    let mut app = setup.create_app()?;
+    let feature = setup
+        .repo_path
+        .join(".vibetree")
+        .join("branches")
+        .join("feature");
+
+    // Add
+    let result = app.add_worktree("feature".to_string(), None, None, true, false)?;
+    let values = result.values.clone().into_iter().collect();
+    assert_eq!(
+        result.plan.actions,
+        vec![
+            Action::Allocate {
+                worktree: "feature".to_string(),
+                values,
+            },
+            Action::CreateBranch {
+                name: "feature".to_string(),
+                from: None,
+            },
+            Action::CreateDir {
+                path: feature.clone(),
+                branch: "feature".to_string(),
+            },
+            Action::WriteEnvFile {
+                path: feature.join(".vibetree").join("env"),
+                worktree: "feature".to_string(),
+            },
+        ]
+    );
+    assert!(!app.get_worktrees().contains_key("feature"));
+    assert!(GitManager::branch_oid(&setup.repo_path, "feature").is_err());
+    assert!(!feature.exists());
+
+    // Executing walks the same plan
+    let added = app.add_worktree("feature".to_string(), None, None, false, false)?;
+    assert_eq!(added.plan, result.plan);
+    commit_in(&feature, "feature.txt", "feature", "Add feature")?;
+
+    // Remove
+    let options = RemoveOptions {
+        dry_run: true,
+        ..RemoveOptions::default()
+    };
+    let result = app.remove_worktree_with_options("feature".to_string(), options)?;
+    assert!(!result.removed);
+    assert_eq!(
+        result.plan.to_string().lines().collect::<Vec<_>>(),
+        vec![
+            format!("  remove directory {}", feature.display()),
+            format!(
+                "  release POSTGRES={} from 'feature'",
+                app.get_worktrees()["feature"].values["POSTGRES"]
+            ),
+            "  delete branch 'feature'".to_string(),
+        ]
+    );
+    assert!(feature.join("feature.txt").exists());
+    assert!(app.get_worktrees().contains_key("feature"));
+
+    // Merge, including the removal afterwards
+    let main_oid = GitManager::branch_oid(&setup.repo_path, "main")?;
+    let options = MergeOptions {
+        squash: true,
+        remove_after: true,
+        dry_run: true,
+        ..MergeOptions::default()
+    };
+    let result = app.merge_worktree_with_options("feature".to_string(), options.clone())?;
+    assert!(result.dry_run);
+    assert!(result.conflicts.is_empty());
+    assert_eq!(
+        result.plan.actions[0],
+        Action::Merge {
+            branch: "feature".to_string(),
+            into: "main".to_string(),
+            strategy: MergeStrategy::Squash,
+            message: None,
+            no_ff: false,
+        }
+    );
+    assert_eq!(result.plan.actions.len(), 4);
+    assert_eq!(GitManager::branch_oid(&setup.repo_path, "main")?, main_oid);
+    assert!(feature.exists());
+
+    // Predicted conflicts are reported, still without changing anything
+    commit_in(&setup.repo_path, "feature.txt", "main", "Conflict")?;
+    let result = app.merge_worktree_with_options("feature".to_string(), options)?;
+    assert_eq!(result.conflicts, vec!["feature.txt".to_string()]);
+    assert_eq!(result.plan.actions.len(), 1);
+    assert!(!GitManager::has_uncommitted_changes(&setup.repo_path)?);
+
+    Ok(())
+}
+
+// ============================================================================
+// Service Tests
+// ============================================================================
+
+#[test]
+fn test_services_up_and_down() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("web", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[[services]]\nname = \"web\"\ncommand = \"echo listening on $WEB && exec sleep 30\"\n\n\
+             [[services]]\nname = \"broken\"\ncommand = \"exit 3\"\nready = {{ tcp = \"${{WEB}}\" }}\n\n\
+             [[services]]\nname = \"detached\"\ncommand = \"sleep 30 &\"\n",
+            fs::read_to_string(setup.config_path())?
+        ),
+    )?;
+    let mut app = setup.create_app()?;
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    let port = app.get_worktrees()["feature"].values["WEB"].clone();
+
+    let result = app.services_up("feature", &["web".to_string()])?;
+    assert!(result.services[0].running);
+    let log = result.services[0].log.clone();
+    for _ in 0..40 {
+        if fs::read_to_string(&log).unwrap_or_default().contains(&port) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert_eq!(
+        fs::read_to_string(&log)?.trim(),
+        format!("listening on {}", port)
+    );
+
+    let data = app.collect_worktree_data()?;
+    let feature = data.iter().find(|d| d.name == "feature").unwrap();
+    assert!(
+        feature
+            .services
+            .iter()
+            .any(|s| s.name == "web" && s.running)
+    );
+    assert!(
+        feature
+            .services
+            .iter()
+            .any(|s| s.name == "broken" && !s.running)
+    );
+
+    // A service that exits before its ready check passes fails `up`
+    let err = app.services_up("feature", &[]).unwrap_err();
+    assert!(format!("{:#}", err).contains("Service 'broken' did not start"));
+
+    // Running while what its command left behind is
+    let result = app.services_up("feature", &["detached".to_string()])?;
+    assert!(result.services[0].running);
+
+    let result = app.services_down("feature", &[])?;
+    assert!(result.services.iter().all(|s| !s.running));
+    assert!(app.services_up("feature", &["db".to_string()]).is_err());
+
+    // A PID file naming some other process doesn't make it the service's
+    let state_dir = setup.repo_path.join(".vibetree/services/feature");
+    let mut other = Command::new("sleep").arg("30").spawn()?;
+    fs::write(state_dir.join("web.pid"), format!("{} 1", other.id()))?;
+    let data = app.collect_worktree_data()?;
+    let feature = data.iter().find(|d| d.name == "feature").unwrap();
+    assert!(feature.services.iter().all(|s| !s.running));
+    app.services_down("feature", &["web".to_string()])?;
+    assert!(other.try_wait()?.is_none());
+    other.kill()?;
+    other.wait()?;
+
+    app.remove_worktree_for_test("feature".to_string(), true, false)?;
+    assert!(!state_dir.exists());
+
+    Ok(())
+}
+
+// ============================================================================
+// Docker Compose Tests
+// ============================================================================
+
+#[test]
+fn test_remove_takes_down_compose_project() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let setup = IntegrationTestSetup::new()?;
+    // Stands in for the docker CLI, logging its arguments
+    let calls = setup.repo_path.join("docker-calls.log");
+    let docker = setup.repo_path.join("docker-stub");
+    fs::write(
+        &docker,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\ncase \"$*\" in\n  \
+             compose*ps*) echo '{{\"Name\":\"'$3'-db-1\",\"Service\":\"db\",\"State\":\"running\"}}' ;;\n  \
+             volume*) echo \"${{5##*=}}_pgdata\" ;;\nesac\n",
+            calls.display()
+        ),
+    )?;
+    fs::set_permissions(&docker, fs::Permissions::from_mode(0o755))?;
+
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[[variables]]\nname = \"COMPOSE_PROJECT_NAME\"\nvalue = \"app_{{int:1}}\"\n\n\
+             [compose]\nproject = \"COMPOSE_PROJECT_NAME\"\ndocker = \"{}\"\n",
+            fs::read_to_string(setup.config_path())?,
+            docker.display()
+        ),
+    )?;
+    let mut app = setup.create_app()?;
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    let project = app.get_worktrees()["feature"].values["COMPOSE_PROJECT_NAME"].clone();
+
+    let data = app.collect_worktree_data()?;
+    let feature = data.iter().find(|d| d.name == "feature").unwrap();
+    assert_eq!(feature.containers[0].name, format!("{}-db-1", project));
+    assert_eq!(feature.containers[0].state, "running");
+
+    // A dry run lists what would be deleted without touching it
+    let options = RemoveOptions {
+        dry_run: true,
+        ..RemoveOptions::default()
+    };
+    let result = app.remove_worktree_with_options("feature".to_string(), options)?;
+    assert_eq!(
+        result.plan.actions.last(),
+        Some(&Action::ComposeDown {
+            project: project.clone(),
+            containers: vec![format!("{}-db-1", project)],
+            volumes: vec![format!("{}_pgdata", project)],
+        })
+    );
+    assert!(!fs::read_to_string(&calls)?.contains("down"));
+
+    // Volumes are deleted only after every step that can be undone
+    transaction::inject_failure(Some("remove:branch"));
+    let result = app.remove_worktree("feature".to_string(), true, false);
+    transaction::inject_failure(None);
+    assert!(result.is_err());
+    assert!(setup.worktree_exists("feature"));
+    assert!(!fs::read_to_string(&calls)?.contains("down"));
+
+    let options = RemoveOptions {
+        force: true,
+        ..RemoveOptions::default()
+    };
+    app.remove_worktree_with_options("feature".to_string(), options)?;
+    assert!(
+        fs::read_to_string(&calls)?
+            .contains(&format!("compose -p {} down -v --remove-orphans", project))
+    );
+    assert!(!setup.worktree_exists("feature"));
+
+    // Pruning a worktree takes down its project the same way
+    app.add_worktree("missing".to_string(), None, None, false, false)?;
+    let project = app.get_worktrees()["missing"].values["COMPOSE_PROJECT_NAME"].clone();
+    fs::remove_dir_all(setup.repo_path.join(".vibetree").join("branches").join("missing"))?;
+    let criteria = vibetree::PruneCriteria {
+        missing: true,
+        ..Default::default()
+    };
+    app.prune(criteria, true, false)?;
+    assert!(!app.get_worktrees().contains_key("missing"));
+    assert!(
+        fs::read_to_string(&calls)?
+            .contains(&format!("compose -p {} down -v --remove-orphans", project))
+    );
+
+    Ok(())
+}
+
+// ============================================================================
+// Resource Tests
+// ============================================================================
+
+#[test]
+fn test_resources_created_retried_by_repair_and_destroyed() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    // Each "database" is a marker file, created only once the gate file exists
+    let databases = setup.repo_path.join("databases");
+    let gate = setup.repo_path.join("server-up");
+    fs::create_dir(&databases)?;
+
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+    fs::write(
+        setup.config_path(),
+        format!(
+            "{}\n[[variables]]\nname = \"DB_NAME\"\nvalue = \"app_{{int:1}}\"\n\n\
+             [[resources]]\nname = \"db\"\n\
+             create = \"test -e '{gate}' && touch '{dir}/${{DB_NAME}}'\"\n\
+             destroy = \"rm '{dir}/${{DB_NAME}}'\"\n\
+             exists = \"test -e '{dir}/${{DB_NAME}}'\"\n",
+            fs::read_to_string(setup.config_path())?,
+            gate = gate.display(),
+            dir = databases.display()
+        ),
+    )?;
+
+    // Committed so the worktree's own vibetree.toml has DB_NAME as well
+    setup.run_git_cmd(&["add", "vibetree.toml", ".gitignore"])?;
+    setup.run_git_cmd(&["commit", "-m", "Add vibetree config"])?;
+
+    // A failed create does not fail the add, but is recorded
+    let mut app = setup.create_app()?;
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    assert_eq!(
+        app.get_worktrees()["feature"].resources["db"],
+        ResourceState::Failed
+    );
+    assert_eq!(fs::read_dir(&databases)?.count(), 0);
+    assert!(!app.get_worktrees()["main"].resources.contains_key("db"));
+
+    // Repair retries it
+    fs::write(&gate, "")?;
+    let mut app = setup.create_app()?;
+    let result = app.repair(false)?;
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.plan.pending_resources.len(), 1);
+    let database = databases.join(&app.get_worktrees()["feature"].values["DB_NAME"]);
+    assert_eq!(
+        app.get_worktrees()["feature"].resources["db"],
+        ResourceState::Created
+    );
+    assert!(database.exists());
+    assert!(app.repair(true)?.plan.pending_resources.is_empty());
+
+    let options = RemoveOptions {
+        force: true,
+        ..RemoveOptions::default()
+    };
+    app.remove_worktree_with_options("feature".to_string(), options)?;
+    assert!(!database.exists());
+
+    // Pruning a worktree destroys its resources the same way
+    app.add_worktree("missing".to_string(), None, None, false, false)?;
+    let database = databases.join(&app.get_worktrees()["missing"].values["DB_NAME"]);
+    assert!(database.exists());
+    fs::remove_dir_all(setup.repo_path.join(".vibetree").join("branches").join("missing"))?;
+    let criteria = vibetree::PruneCriteria {
+        missing: true,
+        ..Default::default()
+    };
+    app.prune(criteria, true, false)?;
+    assert!(!app.get_worktrees().contains_key("missing"));
+    assert!(!database.exists());
+
+    Ok(())
+}
+
+// ============================================================================
+// Settings Tests
+// ============================================================================
+
+#[test]
+fn test_local_settings_override_layout_and_avoid_ports() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+
+    let base = setup.port_base;
+    fs::write(
+        setup.repo_path.join("vibetree.local.toml"),
+        format!(
+            "branches_dir = \".worktrees\"\navoid_ports = [\"{}-{}\"]\n",
+            base,
+            base + 2
+        ),
+    )?;
+
+    let mut app = setup.create_app()?;
+    let result = app.add_worktree("feature".to_string(), None, None, false, false)?;
+    assert_eq!(
+        result.path,
+        setup.repo_path.join(".worktrees").join("feature")
+    );
+    assert!(result.path.exists());
+    let port: u16 = result.values["POSTGRES"].parse()?;
+    assert!(port > base + 2, "allocated avoided port {}", port);
+
+    let shown = app.show_config(true)?;
+    let origins = shown.origins.unwrap();
+    assert_eq!(origins["branches_dir"], Origin::Local);
+    assert_eq!(origins["main_branch"], Origin::Project);
+    // The override stays out of the committed vibetree.toml
+    assert!(!fs::read_to_string(setup.config_path())?.contains(".worktrees"));
+
+    Ok(())
+}
+
+// ============================================================================
+// Variable Command Tests
+// ============================================================================
+
+#[test]
+fn test_var_commands_edit_vibetree_toml_in_place() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+
+    // Comments survive the edits and the repair that follows them
+    let config_path = setup.config_path();
+    let written = format!(
+        "# Ports for local services\n{}",
+        fs::read_to_string(&config_path)?
+    );
+    fs::write(&config_path, &written)?;
+
+    let mut app = setup.create_app()?;
+    let redis = setup.port_base + 10;
+    let result = app.add_variable("REDIS", VariableValue::Port(redis), None, true)?;
+    assert_eq!(result.variables.len(), 2);
+    assert!(result.outdated.contains(&"main".to_string()));
+    assert!(result.repair.is_some());
+    assert!(app.get_worktrees()["main"].values.contains_key("REDIS"));
+    let content = fs::read_to_string(&config_path)?;
+    assert!(content.starts_with(&written));
+    assert!(content.contains(&format!(
+        "name = \"REDIS\"\nvalue = {}\ntype = \"port\"",
+        redis
+    )));
+
+    let error = app
+        .add_variable("REDIS", VariableValue::Port(redis), None, true)
+        .unwrap_err();
+    assert_eq!(error_kind(&error), Some(ErrorKind::Conflict));
+
+    // Invalid definitions are refused before anything is written
+    assert!(
+        app.add_variable("BROKEN", VariableValue::Port(0), None, true)
+            .is_err()
+    );
+    assert!(!fs::read_to_string(&config_path)?.contains("BROKEN"));
+
+    app.set_variable("REDIS", VariableValue::Int(3), None, true)?;
+    let listed = app.list_variables();
+    let variable = listed.variables.iter().find(|v| v.name == "REDIS").unwrap();
+    assert_eq!(variable.value, Some(toml::Value::Integer(3)));
+    assert_eq!(variable.r#type, Some(config::VariableType::Int));
+
+    app.remove_variable("REDIS", true)?;
+    assert_eq!(app.list_variables().variables.len(), 1);
+    assert_eq!(fs::read_to_string(&config_path)?, written);
+    let error = app.remove_variable("REDIS", true).unwrap_err();
+    assert_eq!(error_kind(&error), Some(ErrorKind::NotFound));
+
+    // init keeps existing variables unless asked to reset them, and allocates
+    // around the worktrees that already exist
+    app.add_worktree("feature".to_string(), None, None, false, false)?;
+    let result = app.init(vec![setup.var("api", 20)])?;
+    let names: Vec<_> = result.variables.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, ["POSTGRES", "API"]);
+    let result = app.init_with_options(
+        vec![setup.var("api", 20)],
+        InitOptions {
+            reset: true,
+            ..Default::default()
+        },
+    )?;
+    let names: Vec<_> = result.variables.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, ["API"]);
+
+    Ok(())
+}
+
+// ============================================================================
+// Migration Tests
+// ============================================================================
+
+#[test]
+fn test_migrate_reports_current_files_and_newer_files_are_refused() -> Result<()> {
+    let setup = IntegrationTestSetup::new()?;
+    let mut app = setup.create_app()?;
+    app.init(vec![setup.var("postgres", 0)])?;
+
+    let result = schema::migrate(&setup.repo_path, true)?;
+    assert!(result.dry_run);
+    assert_eq!(result.files.len(), 2);
+    assert!(result.files.iter().all(|file| file.is_current()));
+    assert_eq!(result.files[0].to, schema::PROJECT.current());
+
+    // Loading the shared vibetree.toml never rewrites it; only migrate does
+    let project = setup.repo_path.join("vibetree.toml");
+    let content = fs::read_to_string(&project)?;
+    setup.create_app()?.list_worktrees(None)?;
+    assert_eq!(fs::read_to_string(&project)?, content);
+
+    // A branches.toml from a newer vibetree is refused rather than misread
+    let branches = setup.repo_path.join(".vibetree").join("branches.toml");
+    let content = fs::read_to_string(&branches)?;
+    let newer = content.replacen(
+        &format!("version = \"{}\"", schema::BRANCHES.current()),
+        "version = \"99\"",
+        1,
+    );
+    assert_ne!(newer, content);
+    fs::write(&branches, &newer)?;
+    let error = setup.create_app().err().unwrap();
+    assert!(format!("{:#}", error).contains("format version 99"));
+    assert!(schema::migrate(&setup.repo_path, true).is_err());
+    assert_eq!(fs::read_to_string(&branches)?, newer);
 
     Ok(())
 }
@@ -0,0 +1,64 @@
+//! The cross-repository registry is found through `VIBETREE_REGISTRY`, which is
+//! process-wide, so these tests run in their own binary
+
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+use vibetree::{Registry, VibeTreeApp};
+
+fn git(repo: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("git").args(args).current_dir(repo).output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Git command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+fn init_repo(repo: &Path) -> Result<VibeTreeApp> {
+    fs::create_dir_all(repo)?;
+    git(repo, &["init", "--initial-branch=main"])?;
+    git(repo, &["config", "user.name", "Test User"])?;
+    git(repo, &["config", "user.email", "test@example.com"])?;
+    fs::write(repo.join("README.md"), "# Test Repository\n")?;
+    git(repo, &["add", "."])?;
+    git(repo, &["commit", "-m", "Initial commit"])?;
+
+    let mut app = VibeTreeApp::with_parent(repo.to_path_buf())?;
+    app.init(vec!["PORT:5432".to_string()])?;
+    Ok(app)
+}
+
+#[test]
+fn test_repos_with_the_same_base_port_get_different_ports() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let registry = temp_dir.path().join("registry.toml");
+    // SAFETY: This is the only test in this binary, and it sets the variables
+    // before vibetree reads them
+    unsafe {
+        std::env::set_var("VIBETREE_SKIP_SHELL", "1");
+        std::env::set_var("VIBETREE_TESTING", "1");
+        std::env::set_var("VIBETREE_REGISTRY", &registry);
+    }
+
+    let mut one = init_repo(&temp_dir.path().join("one"))?;
+    let mut two = init_repo(&temp_dir.path().join("two"))?;
+    one.add_worktree("feature".to_string(), None, None, false, false)?;
+    two.add_worktree("feature".to_string(), None, None, false, false)?;
+
+    let mut ports: Vec<_> = [&one, &two]
+        .iter()
+        .flat_map(|app| app.get_worktrees().values().map(|w| w.values["PORT"].clone()))
+        .collect();
+    assert_eq!(ports.len(), 4);
+    ports.sort();
+    ports.dedup();
+    assert_eq!(ports, ["5432", "5433", "5434", "5435"]);
+
+    assert_eq!(Registry::load(&registry)?.allocations.len(), 4);
+    Ok(())
+}